@@ -0,0 +1,193 @@
+//! Interactive chat loop over a mock or ollama backend.
+//!
+//! The backend is picked from `STEELWOOL_PROVIDER` (`mock`, the default, or
+//! `ollama`); `OLLAMA_MODEL` names the ollama model. Type `/reset` to start
+//! over and `/quit` to leave.
+//!
+//! ```text
+//! cargo run --example chat_repl --features tokio-runtime
+//! STEELWOOL_PROVIDER=ollama OLLAMA_MODEL=llama3.2 cargo run --example chat_repl --features ollama
+//! ```
+
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+use steelwool::providers::mock::mock_text_response;
+use steelwool::{ContentType, ContextBuilder, Message, MessageRole, ProviderAdapter};
+
+#[derive(Debug, PartialEq)]
+enum Provider {
+    Mock,
+    Ollama { model: String },
+}
+
+/// Pick the backend from the values of `STEELWOOL_PROVIDER` and `OLLAMA_MODEL`
+fn provider_from_env(provider: Option<&str>, model: Option<&str>) -> Result<Provider, String> {
+    match provider.map(str::trim).unwrap_or("mock") {
+        "" | "mock" => Ok(Provider::Mock),
+        "ollama" => Ok(Provider::Ollama {
+            model: model.unwrap_or("llama3.2").to_string(),
+        }),
+        other => Err(format!(
+            "Unknown provider {}, expected mock or ollama",
+            other
+        )),
+    }
+}
+
+/// Offline stand-in that echoes the last user message
+fn echo_adapter() -> ProviderAdapter {
+    Arc::new(|context: ContextBuilder, _| {
+        let last = context
+            .history
+            .iter()
+            .rev()
+            .find(|msg| msg.role == MessageRole::User)
+            .map(|msg| msg.content.clone())
+            .unwrap_or_default();
+        Box::pin(async move { Ok(mock_text_response(&format!("You said: {}", last))) })
+    })
+}
+
+fn adapter_for(provider: &Provider) -> Result<ProviderAdapter, String> {
+    match provider {
+        Provider::Mock => Ok(echo_adapter()),
+        #[cfg(feature = "ollama")]
+        Provider::Ollama { model } => Ok(steelwool::providers::ollama::ollama_adapter_factory(
+            model.clone(),
+            None,
+        )),
+        #[cfg(not(feature = "ollama"))]
+        Provider::Ollama { .. } => Err("Rebuild with --features ollama to use ollama".to_string()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Quit,
+    Reset,
+    Say(String),
+    Nothing,
+}
+
+fn parse_command(line: &str) -> Command {
+    match line.trim() {
+        "" => Command::Nothing,
+        "/quit" | "/exit" => Command::Quit,
+        "/reset" => Command::Reset,
+        text => Command::Say(text.to_string()),
+    }
+}
+
+/// Add the user's input, send, and keep the reply in the context
+async fn chat_turn(
+    context: ContextBuilder,
+    adapter: ProviderAdapter,
+    input: String,
+) -> (ContextBuilder, String) {
+    let response = context
+        .add_message(Message {
+            role: MessageRole::User,
+            content: input,
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+        .send(adapter, 500)
+        .await;
+    let reply = response.prompt_response.message.content.clone();
+    (response.resolve_without(), reply)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), String> {
+    let provider = provider_from_env(
+        std::env::var("STEELWOOL_PROVIDER").ok().as_deref(),
+        std::env::var("OLLAMA_MODEL").ok().as_deref(),
+    )?;
+    let adapter = adapter_for(&provider)?;
+    println!(
+        "Chatting with {:?}. /reset to start over, /quit to leave.",
+        provider
+    );
+
+    let mut context = ContextBuilder::new();
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().map_err(|e| e.to_string())?;
+
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            return Ok(());
+        }
+
+        match parse_command(&line) {
+            Command::Quit => return Ok(()),
+            Command::Reset => context = context.clear(),
+            Command::Nothing => {}
+            Command::Say(input) => {
+                let (next, reply) = chat_turn(context, adapter.clone(), input).await;
+                context = next;
+                println!("{}", reply);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_selection() {
+        assert_eq!(provider_from_env(None, None), Ok(Provider::Mock));
+        assert_eq!(provider_from_env(Some(" mock "), None), Ok(Provider::Mock));
+        assert_eq!(
+            provider_from_env(Some("ollama"), Some("qwen3")),
+            Ok(Provider::Ollama {
+                model: "qwen3".to_string()
+            })
+        );
+        assert_eq!(
+            provider_from_env(Some("ollama"), None),
+            Ok(Provider::Ollama {
+                model: "llama3.2".to_string()
+            })
+        );
+        assert!(provider_from_env(Some("gpt"), None).is_err());
+
+        assert!(adapter_for(&Provider::Mock).is_ok());
+        #[cfg(not(feature = "ollama"))]
+        assert!(
+            adapter_for(&Provider::Ollama {
+                model: "qwen3".to_string()
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(parse_command("/quit\n"), Command::Quit);
+        assert_eq!(parse_command("/reset"), Command::Reset);
+        assert_eq!(parse_command("   \n"), Command::Nothing);
+        assert_eq!(parse_command(" hi \n"), Command::Say("hi".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_chat_turns_accumulate_history() {
+        let adapter = echo_adapter();
+        let (context, reply) = chat_turn(ContextBuilder::new(), adapter.clone(), "hi".into()).await;
+        assert_eq!(reply, "You said: hi");
+
+        let (context, reply) = chat_turn(context, adapter, "again".into()).await;
+        assert_eq!(reply, "You said: again");
+        assert_eq!(context.len(), 4);
+    }
+}
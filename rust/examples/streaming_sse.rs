@@ -0,0 +1,131 @@
+//! Minimal axum server streaming replies as Server-Sent Events.
+//!
+//! Each POST to `/chat` is treated as a user prompt; the reply is streamed
+//! back as `sse_events` from a scripted mock adapter.
+//!
+//! ```text
+//! cargo run --example streaming_sse --features tokio-runtime
+//! curl -N -d 'Hello' http://127.0.0.1:3000/chat
+//! ```
+
+use std::convert::Infallible;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::header;
+use axum::response::Response;
+use axum::routing::post;
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use steelwool::providers::mock::mock_streaming_adapter_factory;
+use steelwool::web::sse_events;
+use steelwool::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, StopReason,
+    StreamProviderAdapter,
+};
+
+fn delta(content: &str, stop_reason: Option<StopReason>) -> Result<PromptResponseDelta, String> {
+    Ok(PromptResponseDelta {
+        content: content.to_string(),
+        stop_reason,
+        tool_calls: None,
+        cumulative_tokens: 0,
+        token_delta: 0,
+        logprobs: None,
+    })
+}
+
+/// A `text/event-stream` response carrying `deltas`
+fn sse_response(deltas: BoxStream<'static, Result<PromptResponseDelta, String>>) -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(
+            sse_events(deltas).map(Ok::<_, Infallible>),
+        ))
+        .unwrap()
+}
+
+async fn chat(State(adapter): State<StreamProviderAdapter>, prompt: String) -> Response {
+    let context = ContextBuilder::new().add_message(Message {
+        role: MessageRole::User,
+        content: prompt,
+        content_type: ContentType::Text,
+        pinned: false,
+        assistant_tool_calls: None,
+    });
+    sse_response(context.send_streaming(adapter, 1000))
+}
+
+fn app(adapter: StreamProviderAdapter) -> Router {
+    Router::new().route("/chat", post(chat)).with_state(adapter)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let adapter = mock_streaming_adapter_factory(vec![
+        delta("Hello ", None),
+        delta("from ", None),
+        delta("steelwool", Some(StopReason::Stop)),
+    ]);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    println!("Listening on http://127.0.0.1:3000/chat");
+    axum::serve(listener, app(adapter)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_text(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_chat_streams_event_frames() {
+        let adapter = mock_streaming_adapter_factory(vec![
+            delta("Hi", None),
+            delta("!", Some(StopReason::Stop)),
+        ]);
+
+        let response = chat(State(adapter), "Hello".to_string()).await;
+
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        let body = body_text(response).await;
+        let events: Vec<&str> = body.split_terminator("\n\n").collect();
+        assert_eq!(
+            events,
+            vec![
+                "event: delta\ndata: {\"type\":\"delta\",\"content\":\"Hi\"}",
+                "event: delta\ndata: {\"type\":\"delta\",\"content\":\"!\"}",
+                "event: done\ndata: {\"type\":\"done\",\"stop_reason\":\"Stop\"}",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors_close_the_stream() {
+        let adapter = mock_streaming_adapter_factory(vec![
+            delta("Hi", None),
+            Err("upstream timed out".to_string()),
+            delta("never sent", None),
+        ]);
+
+        let body = body_text(chat(State(adapter), "Hello".to_string()).await).await;
+
+        assert!(body.contains(
+            "event: error\ndata: {\"type\":\"error\",\"message\":\"upstream timed out\"}"
+        ));
+        assert!(body.ends_with("event: done\ndata: {\"type\":\"done\",\"stop_reason\":null}\n\n"));
+        assert!(!body.contains("never sent"));
+    }
+}
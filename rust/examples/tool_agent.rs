@@ -0,0 +1,162 @@
+//! Weather agent: the model calls a `get_weather` tool until it can answer.
+//!
+//! The model is scripted, so this runs offline: it asks for the weather in
+//! the city named in the question, then answers from the tool's result.
+//!
+//! ```text
+//! cargo run --example tool_agent --features tokio-runtime -- Oslo
+//! ```
+
+use std::sync::Arc;
+
+use steelwool::providers::mock::mock_text_response;
+use steelwool::tool_loop::ParamsSchedule;
+use steelwool::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponse, ProviderAdapter, StopReason,
+    ToolCall, ToolExecuter,
+};
+
+/// Looks the city up in a fixed table
+fn weather_tool() -> ToolExecuter {
+    Arc::new(|call: ToolCall| {
+        let result = call
+            .arg_str("city")
+            .and_then(|city| match city.to_lowercase().as_str() {
+                "oslo" => Ok("4°C and sleet".to_string()),
+                "lisbon" => Ok("22°C and sunny".to_string()),
+                _ => Err(format!("No forecast for {}", city)),
+            });
+        Box::pin(async move { result })
+    })
+}
+
+/// Calls `get_weather` for `city` until a tool result comes back, then
+/// answers with it
+fn scripted_model(city: &str) -> ProviderAdapter {
+    let city = city.to_string();
+    Arc::new(move |context: ContextBuilder, _| {
+        let response = match context.history.last() {
+            Some(msg) if msg.role == MessageRole::Tool => {
+                mock_text_response(&format!("The weather in {}: {}", city, msg.content.trim()))
+            }
+            _ => PromptResponse {
+                stop_reason: StopReason::ToolCalls,
+                tool_calls: Some(vec![ToolCall {
+                    id: format!("call_{}", context.len()),
+                    name: "get_weather".to_string(),
+                    arguments: serde_json::json!({ "city": city }),
+                }]),
+                ..mock_text_response("")
+            },
+        };
+        Box::pin(async move { Ok(response) })
+    })
+}
+
+/// Run the tool loop on `question` and return the model's final answer
+async fn run_agent(
+    question: &str,
+    model: ProviderAdapter,
+    tools: ToolExecuter,
+    max_turns: usize,
+) -> Result<String, String> {
+    let context = ContextBuilder::new()
+        .add_message(Message {
+            role: MessageRole::System,
+            content: "Answer weather questions with the get_weather tool.".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+        .add_message(Message {
+            role: MessageRole::User,
+            content: question.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+        .send_tool_loop(model, tools, 500, max_turns, &ParamsSchedule::default())
+        .await?;
+
+    Ok(context
+        .history
+        .last()
+        .map(|msg| msg.content.clone())
+        .unwrap_or_default())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), String> {
+    let city = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "Oslo".to_string());
+    let question = format!("What's the weather in {}?", city);
+
+    let answer = run_agent(&question, scripted_model(&city), weather_tool(), 4).await?;
+    println!("{}", answer);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steelwool::providers::mock::mock_adapter_factory;
+
+    #[tokio::test]
+    async fn test_loop_ends_when_model_answers() {
+        let answer = run_agent(
+            "Weather in Lisbon?",
+            scripted_model("Lisbon"),
+            weather_tool(),
+            4,
+        )
+        .await
+        .unwrap();
+        assert_eq!(answer, "The weather in Lisbon: 22°C and sunny");
+    }
+
+    #[tokio::test]
+    async fn test_tool_errors_reach_the_model() {
+        let answer = run_agent(
+            "Weather on Mars?",
+            scripted_model("Mars"),
+            weather_tool(),
+            4,
+        )
+        .await
+        .unwrap();
+        assert!(answer.ends_with("No forecast for Mars"));
+    }
+
+    #[tokio::test]
+    async fn test_loop_gives_up_after_max_turns() {
+        let always_calling = mock_adapter_factory(vec![Ok(PromptResponse {
+            stop_reason: StopReason::ToolCalls,
+            tool_calls: Some(vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "get_weather".to_string(),
+                arguments: serde_json::json!({ "city": "Oslo" }),
+            }]),
+            ..mock_text_response("")
+        })]);
+
+        let err = run_agent("Weather in Oslo?", always_calling, weather_tool(), 3)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err, "Tool loop still calling tools after 3 turns");
+    }
+
+    #[tokio::test]
+    async fn test_weather_tool_needs_a_city() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            arguments: serde_json::json!({}),
+        };
+        assert_eq!(
+            weather_tool()(call).await,
+            Err("Missing argument city for get_weather".to_string())
+        );
+    }
+}
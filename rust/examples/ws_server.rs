@@ -0,0 +1,69 @@
+//! Minimal websocket server streaming `WsFrame`s.
+//!
+//! Every text message received is treated as a user prompt; the reply is
+//! streamed back as JSON frames from a scripted mock adapter.
+//!
+//! ```text
+//! cargo run --example ws_server --features ws-example
+//! ```
+
+use futures::{SinkExt, StreamExt};
+use steelwool::providers::mock::mock_streaming_adapter_factory;
+use steelwool::web::ws_frames;
+use steelwool::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, StopReason,
+};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+fn delta(content: &str, stop_reason: Option<StopReason>) -> Result<PromptResponseDelta, String> {
+    Ok(PromptResponseDelta {
+        content: content.to_string(),
+        stop_reason,
+        tool_calls: None,
+        cumulative_tokens: 0,
+        token_delta: 0,
+        logprobs: None,
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let adapter = mock_streaming_adapter_factory(vec![
+        delta("Hello ", None),
+        delta("from ", None),
+        delta("steelwool", Some(StopReason::Stop)),
+    ]);
+
+    let listener = TcpListener::bind("127.0.0.1:9001").await?;
+    println!("Listening on ws://127.0.0.1:9001");
+
+    while let Ok((tcp, _)) = listener.accept().await {
+        let adapter = adapter.clone();
+
+        tokio::spawn(async move {
+            let Ok(mut socket) = tokio_tungstenite::accept_async(tcp).await else {
+                return;
+            };
+
+            while let Some(Ok(WsMessage::Text(prompt))) = socket.next().await {
+                let context = ContextBuilder::new().add_message(Message {
+                    role: MessageRole::User,
+                    content: prompt.to_string(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                });
+
+                let mut frames = Box::pin(ws_frames(context.send_streaming(adapter.clone(), 1000)));
+                while let Some(frame) = frames.next().await {
+                    if socket.send(WsMessage::text(frame)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::determinism::{Determinism, STABLE_HASH_SEED, stable_hash};
+use crate::{ContextBuilder, ProviderAdapter, UnresolvedResponse};
+
+/// Provenance source and metadata key naming the arm that answered
+pub const AB_ARM: &str = "ab_arm";
+
+/// Metadata key that `Assignment::StickyByConversationId` hashes
+pub const CONVERSATION_ID: &str = "conversation_id";
+
+/* ---------------------------------- Arms ---------------------------------- */
+
+/// One configuration under test
+#[derive(Clone)]
+pub struct Arm {
+    pub label: String,
+    /// Share of traffic relative to the other arms' weights
+    pub weight: f64,
+    pub adapter: ProviderAdapter,
+    /// Sent through `RequestParams::system_prompt` in place of the
+    /// conversation's system messages
+    pub system_prompt_override: Option<String>,
+}
+
+/// How `ab_test_adapter` picks an arm for a send
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Assignment {
+    /// Hash the `conversation_id` metadata tag, so every send of a
+    /// conversation goes to the same arm. A conversation whose `ab_arm` tag
+    /// names an arm keeps that arm even if the weights change.
+    StickyByConversationId,
+    /// Draw a fresh arm for every send from a generator seeded with this
+    PerRequestRandom(u64),
+}
+
+/* -------------------------------- Counters -------------------------------- */
+
+/// Usage and outcomes of one arm
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ArmStats {
+    pub sends: u64,
+    pub errors: u64,
+    pub token_usage: u64,
+    /// Outcomes reported with `ArmCounters::record_outcome`
+    pub passed: u64,
+    pub failed: u64,
+}
+
+impl ArmStats {
+    /// Share of reported outcomes that passed; 0 when none were reported
+    pub fn pass_rate(&self) -> f64 {
+        match self.passed + self.failed {
+            0 => 0.0,
+            n => self.passed as f64 / n as f64,
+        }
+    }
+}
+
+/// Per-arm counters shared by an `ab_test_adapter` and its caller. Clones
+/// share the same counts.
+#[derive(Clone, Default)]
+pub struct ArmCounters(Arc<Mutex<BTreeMap<String, ArmStats>>>);
+
+impl ArmCounters {
+    /// Counts so far, by arm label
+    pub fn snapshot(&self) -> BTreeMap<String, ArmStats> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Report whether a reply from `arm` was good, e.g. an eval verdict or
+    /// user feedback
+    pub fn record_outcome(&self, arm: &str, passed: bool) {
+        let mut counters = self.0.lock().unwrap();
+        let stats = counters.entry(arm.to_string()).or_default();
+        match passed {
+            true => stats.passed += 1,
+            false => stats.failed += 1,
+        }
+    }
+
+    /// List `arm` with zero counts until it sees traffic
+    fn track(&self, arm: &str) {
+        self.0.lock().unwrap().entry(arm.to_string()).or_default();
+    }
+
+    /// Count a send; `token_usage` is `None` when it failed
+    fn record_send(&self, arm: &str, token_usage: Option<u32>) {
+        let mut counters = self.0.lock().unwrap();
+        let stats = counters.entry(arm.to_string()).or_default();
+        stats.sends += 1;
+        match token_usage {
+            Some(tokens) => stats.token_usage += tokens as u64,
+            None => stats.errors += 1,
+        }
+    }
+}
+
+/// An A/B-testing adapter and the counters it updates
+#[derive(Clone)]
+pub struct AbTest {
+    pub adapter: ProviderAdapter,
+    pub counters: ArmCounters,
+}
+
+/* -------------------------------- Assigning ------------------------------- */
+
+/// The arm whose slice of the total weight holds `unit` (in `0..1`)
+fn weighted_pick(arms: &[Arm], unit: f64) -> Option<usize> {
+    let weight = |arm: &Arm| arm.weight.max(0.0);
+    let total: f64 = arms.iter().map(weight).sum();
+    if total <= 0.0 {
+        return None;
+    }
+
+    let target = unit * total;
+    let mut cumulative = 0.0;
+    for (index, arm) in arms.iter().enumerate() {
+        cumulative += weight(arm);
+        if target < cumulative {
+            return Some(index);
+        }
+    }
+    arms.iter().rposition(|arm| weight(arm) > 0.0)
+}
+
+fn unit_from(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn assign(
+    arms: &[Arm],
+    assignment: Assignment,
+    random: &Determinism,
+    context: &ContextBuilder,
+) -> Result<usize, String> {
+    let unit = match assignment {
+        Assignment::PerRequestRandom(_) => unit_from(random.next_u64()),
+        Assignment::StickyByConversationId => {
+            if let Some(index) = context
+                .metadata
+                .get(AB_ARM)
+                .and_then(|label| arms.iter().position(|arm| arm.label == *label))
+            {
+                return Ok(index);
+            }
+            let id = context
+                .metadata
+                .get(CONVERSATION_ID)
+                .ok_or_else(|| format!("Sticky A/B assignment needs a {} tag", CONVERSATION_ID))?;
+            // Mixed so that similar ids don't land in the same slice
+            unit_from(Determinism::seeded(stable_hash(STABLE_HASH_SEED, id.as_bytes())).next_u64())
+        }
+    };
+    weighted_pick(arms, unit).ok_or_else(|| "A/B test has no arm with positive weight".to_string())
+}
+
+/// ## `ab_test_adapter`
+/// Route each send to one of `arms` in proportion to their weights, e.g. 10%
+/// to a candidate model or prompt and 90% to the incumbent. The chosen
+/// arm's label is recorded in the response provenance under `"ab_arm"`,
+/// and its usage in the returned counters.
+///
+/// ```rust,ignore
+/// let test = ab_test_adapter(
+///     vec![
+///         Arm { label: "incumbent".into(), weight: 0.9, adapter: gpt4o, system_prompt_override: None },
+///         Arm { label: "candidate".into(), weight: 0.1, adapter: gpt5, system_prompt_override: Some(prompt) },
+///     ],
+///     Assignment::StickyByConversationId,
+/// );
+/// let context = context
+///     .with_metadata(CONVERSATION_ID, session_id)
+///     .send(test.adapter.clone(), 500)
+///     .await
+///     .record_ab_arm()
+///     .resolve_without();
+/// ```
+pub fn ab_test_adapter(arms: Vec<Arm>, assignment: Assignment) -> AbTest {
+    let arms = Arc::new(arms);
+    let counters = ArmCounters::default();
+    for arm in arms.iter() {
+        counters.track(&arm.label);
+    }
+    let random = match assignment {
+        Assignment::PerRequestRandom(seed) => Determinism::seeded(seed),
+        Assignment::StickyByConversationId => Determinism::default(),
+    };
+
+    let adapter_counters = counters.clone();
+    let adapter: ProviderAdapter = Arc::new(move |mut context: ContextBuilder, max_tokens: u32| {
+        let arms = arms.clone();
+        let counters = adapter_counters.clone();
+        let picked = assign(&arms, assignment, &random, &context);
+
+        Box::pin(async move {
+            let arm = &arms[picked?];
+            if let Some(system_prompt) = &arm.system_prompt_override {
+                context.params.system_prompt = Some(system_prompt.clone());
+            }
+
+            let result = (arm.adapter)(context, max_tokens).await;
+            counters.record_send(&arm.label, result.as_ref().ok().map(|r| r.token_usage));
+            let mut response = result?;
+            response.provenance.record(AB_ARM, arm.label.clone());
+            Ok(response)
+        })
+    });
+
+    AbTest { adapter, counters }
+}
+
+impl UnresolvedResponse {
+    /// Tag the conversation with the arm `ab_test_adapter` sent this
+    /// response to, so sticky assignment keeps it there
+    pub fn record_ab_arm(mut self) -> Self {
+        if let Some(label) = self.prompt_response.provenance.details_from(AB_ARM).last() {
+            self.context_builder
+                .metadata
+                .insert(AB_ARM.to_string(), label.to_string());
+        }
+        self
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{ContentType, ContextBuilder, Message, UnresolvedResponse};
+
+/* ---------------------------------- Rules --------------------------------- */
+
+/// One kind of sensitive text and how to find it
+#[derive(Clone)]
+pub struct AnonymizerRule {
+    /// Placeholder label, e.g. `PERSON` for `<PERSON_1>`
+    pub category: String,
+    pub pattern: Regex,
+}
+
+/// ## `AnonymizerRules`
+/// Ordered rules for `ContextBuilder::anonymize`. When matches overlap the
+/// earlier rule wins.
+///
+/// ```rust,ignore
+/// let rules = AnonymizerRules::new()
+///     .exact("PERSON", "Ada Lovelace")
+///     .pattern("EMAIL", r"[\w.+-]+@[\w-]+\.[\w.]+")?;
+/// ```
+#[derive(Clone, Default)]
+pub struct AnonymizerRules {
+    pub rules: Vec<AnonymizerRule>,
+}
+
+impl AnonymizerRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match `value` literally
+    pub fn exact(mut self, category: &str, value: &str) -> Self {
+        self.rules.push(AnonymizerRule {
+            category: category.to_string(),
+            pattern: Regex::new(&regex::escape(value)).expect("escaped literal is a valid regex"),
+        });
+        self
+    }
+
+    /// Match a regular expression
+    pub fn pattern(mut self, category: &str, pattern: &str) -> Result<Self, String> {
+        let pattern =
+            Regex::new(pattern).map_err(|e| format!("Invalid anonymizer pattern: {}", e))?;
+        self.rules.push(AnonymizerRule {
+            category: category.to_string(),
+            pattern,
+        });
+        Ok(self)
+    }
+}
+
+/* ---------------------------- AnonymizationMap ---------------------------- */
+
+/// Reversible record of which placeholder stands for which original text
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct AnonymizationMap {
+    /// placeholder -> original
+    originals: BTreeMap<String, String>,
+    /// original -> placeholder, so repeats get the same placeholder
+    placeholders: BTreeMap<String, String>,
+    /// Last number handed out per category
+    counters: BTreeMap<String, usize>,
+}
+
+impl AnonymizationMap {
+    /// The original text behind `placeholder`
+    pub fn original(&self, placeholder: &str) -> Option<&str> {
+        self.originals.get(placeholder).map(String::as_str)
+    }
+
+    /// The placeholder standing in for `original`
+    pub fn placeholder(&self, original: &str) -> Option<&str> {
+        self.placeholders.get(original).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.originals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.originals.is_empty()
+    }
+
+    /// Placeholder for `original`, minting one that doesn't already occur in
+    /// the conversation so deanonymizing can't clobber real text
+    fn placeholder_for(&mut self, category: &str, original: &str, existing: &[String]) -> String {
+        if let Some(placeholder) = self.placeholders.get(original) {
+            return placeholder.clone();
+        }
+
+        let counter = self.counters.entry(category.to_string()).or_default();
+        let placeholder = loop {
+            *counter += 1;
+            let candidate = format!("<{}_{}>", category, counter);
+            if !existing.iter().any(|text| text.contains(&candidate)) {
+                break candidate;
+            }
+        };
+
+        self.originals
+            .insert(placeholder.clone(), original.to_string());
+        self.placeholders
+            .insert(original.to_string(), placeholder.clone());
+        placeholder
+    }
+
+    fn anonymize_text(
+        &mut self,
+        text: &str,
+        rules: &AnonymizerRules,
+        existing: &[String],
+    ) -> String {
+        // Collect every match up front so later rules never see placeholders
+        let mut spans: Vec<(usize, usize, &str)> = vec![];
+        for rule in &rules.rules {
+            for found in rule.pattern.find_iter(text) {
+                let overlaps = spans
+                    .iter()
+                    .any(|(start, end, _)| found.start() < *end && *start < found.end());
+                if !found.is_empty() && !overlaps {
+                    spans.push((found.start(), found.end(), &rule.category));
+                }
+            }
+        }
+        spans.sort_by_key(|(start, _, _)| *start);
+
+        let mut out = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end, category) in spans {
+            out.push_str(&text[cursor..start]);
+            out.push_str(&self.placeholder_for(category, &text[start..end], existing));
+            cursor = end;
+        }
+        out.push_str(&text[cursor..]);
+        out
+    }
+
+    pub(crate) fn deanonymize_text(&self, text: &str) -> String {
+        self.originals
+            .iter()
+            .fold(text.to_string(), |text, (placeholder, original)| {
+                text.replace(placeholder, original)
+            })
+    }
+}
+
+/* --------------------------------- Walking -------------------------------- */
+
+/// Apply `f` to the string values of JSON content, leaving keys alone, or to
+/// the whole content when it isn't a JSON object or array
+fn map_content(content: &str, f: &mut impl FnMut(&str) -> String) -> String {
+    match serde_json::from_str::<serde_json::Value>(content) {
+        Ok(mut value @ (serde_json::Value::Object(_) | serde_json::Value::Array(_))) => {
+            map_json_strings(&mut value, f);
+            value.to_string()
+        }
+        _ => f(content),
+    }
+}
+
+fn map_json_strings(value: &mut serde_json::Value, f: &mut impl FnMut(&str) -> String) {
+    match value {
+        serde_json::Value::String(text) => *text = f(text),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|v| map_json_strings(v, f)),
+        serde_json::Value::Object(fields) => {
+            fields.values_mut().for_each(|v| map_json_strings(v, f))
+        }
+        _ => {}
+    }
+}
+
+/// Apply `f` to a tool call's argument values. Arguments sent as a JSON
+/// string (OpenAI) are walked like message content.
+fn map_arguments(arguments: &mut serde_json::Value, f: &mut impl FnMut(&str) -> String) {
+    match arguments {
+        serde_json::Value::String(raw) => *raw = map_content(raw, f),
+        other => map_json_strings(other, f),
+    }
+}
+
+/// Apply `f` to everything in `msg` that's sent to a provider as text: its
+/// content, its tool calls' arguments and its tool result. Tool call ids and
+/// names are left alone.
+fn map_message(msg: &mut Message, f: &mut impl FnMut(&str) -> String) {
+    msg.content = map_content(&msg.content, f);
+    for call in msg.assistant_tool_calls.iter_mut().flatten() {
+        map_arguments(&mut call.arguments, f);
+    }
+    match &mut msg.content_type {
+        ContentType::ToolUse(call) => map_arguments(&mut call.arguments, f),
+        ContentType::ToolResult(result) => result.result = map_content(&result.result, f),
+        _ => {}
+    }
+}
+
+/// Every piece of text `map_message` touches, for checking new placeholders
+/// against
+fn message_texts(msg: &Message) -> Vec<String> {
+    let mut texts = vec![msg.content.clone()];
+    let calls = msg.assistant_tool_calls.iter().flatten();
+    texts.extend(calls.map(|call| call.arguments.to_string()));
+    match &msg.content_type {
+        ContentType::ToolUse(call) => texts.push(call.arguments.to_string()),
+        ContentType::ToolResult(result) => texts.push(result.result.clone()),
+        _ => {}
+    }
+    texts
+}
+
+/* ------------------------------- Transforms ------------------------------- */
+
+impl ContextBuilder {
+    /// Replace sensitive text in every message with stable placeholders such
+    /// as `<PERSON_1>`, returning the map needed to undo it. Tool call
+    /// arguments and tool results are anonymized along with the content.
+    pub fn anonymize(mut self, rules: &AnonymizerRules) -> (Self, AnonymizationMap) {
+        let existing: Vec<String> = self.history.iter().flat_map(message_texts).collect();
+        let mut map = AnonymizationMap::default();
+
+        for msg in &mut self.history {
+            map_message(msg, &mut |text| map.anonymize_text(text, rules, &existing));
+        }
+
+        (self, map)
+    }
+
+    /// Put the originals back in place of `map`'s placeholders
+    pub fn deanonymize(mut self, map: &AnonymizationMap) -> Self {
+        for msg in &mut self.history {
+            map_message(msg, &mut |text| map.deanonymize_text(text));
+        }
+        self
+    }
+}
+
+impl UnresolvedResponse {
+    /// Restore originals in the model's reply, tool call arguments included.
+    /// Tool call ids and names are left untouched.
+    pub fn deanonymize(mut self, map: &AnonymizationMap) -> Self {
+        let response = &mut self.prompt_response;
+        map_message(&mut response.message, &mut |text| {
+            map.deanonymize_text(text)
+        });
+
+        for call in response.tool_calls.iter_mut().flatten() {
+            map_arguments(&mut call.arguments, &mut |text| map.deanonymize_text(text));
+        }
+
+        self.context_builder = self.context_builder.deanonymize(map);
+        self
+    }
+}
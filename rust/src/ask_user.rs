@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+
+use crate::tool_loop::{ParamsSchedule, ToolLoopRun, add_tool_results};
+use crate::{ContextBuilder, ProviderAdapter, StopReason, ToolCall, ToolDescriptor, ToolExecuter};
+
+/// Name of the tool the model calls to ask the user something
+pub const ASK_USER: &str = "ask_user";
+
+/// Descriptor to list among the model's tools so it can ask the user a
+/// clarifying question
+pub fn ask_user_tool() -> ToolDescriptor {
+    ToolDescriptor::from_json_schema(
+        ASK_USER.to_string(),
+        "Ask the user a clarifying question and wait for their answer".to_string(),
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "question": { "type": "string", "description": "The question to ask" }
+            },
+            "required": ["question"],
+            "additionalProperties": false
+        }),
+    )
+}
+
+/* -------------------------------- Outcomes -------------------------------- */
+
+/// How `send_tool_loop_with_ask_user` ended
+#[derive(Serialize, Deserialize, Clone)]
+pub enum LoopOutcome {
+    /// The model answered without calling tools
+    Finished(ContextBuilder),
+    /// The model called `ask_user`; resume with the user's answer
+    Suspended {
+        question: String,
+        resolution_snapshot: ResolutionSnapshot,
+    },
+}
+
+/// ## `ResolutionSnapshot`
+/// A tool loop paused on an `ask_user` call. Serializes, so the wait can
+/// span a process restart; request params are not kept, so pass the
+/// loop's schedule again when resuming.
+///
+/// ```rust,ignore
+/// if let LoopOutcome::Suspended { question, resolution_snapshot } = outcome {
+///     save(serde_json::to_string(&resolution_snapshot)?);
+///     // ... later, maybe in another process
+///     let outcome = resolution_snapshot
+///         .resume_with_answer(answer, adapter, tools, &schedule)
+///         .await?;
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ResolutionSnapshot {
+    /// The conversation up to and including the model's tool-calling turn
+    pub context: ContextBuilder,
+    /// Id of the `ask_user` call waiting for an answer
+    pub call_id: String,
+    /// Every call of the suspended turn, and the results of those already run
+    pub calls: Vec<ToolCall>,
+    pub results: Vec<Option<Result<String, String>>>,
+    pub max_tokens: u32,
+    /// Index of the next send, and how many the loop may make in all
+    pub next_turn: usize,
+    pub max_turns: usize,
+}
+
+impl ResolutionSnapshot {
+    /// Add `answer` as the `ask_user` call's result, alongside the turn's
+    /// other results, and carry on with the loop using `schedule`
+    pub async fn resume_with_answer(
+        mut self,
+        answer: impl Into<String>,
+        adapter: ProviderAdapter,
+        tool_executer: ToolExecuter,
+        schedule: &ParamsSchedule,
+    ) -> Result<LoopOutcome, String> {
+        let index = self
+            .calls
+            .iter()
+            .position(|call| call.id == self.call_id)
+            .ok_or_else(|| format!("Suspended call {} is not in the snapshot", self.call_id))?;
+        self.results[index] = Some(Ok(answer.into()));
+
+        let context = add_tool_results(self.context, &self.calls, &self.results);
+        let run = ToolLoopRun {
+            adapter,
+            tool_executer,
+            max_tokens: self.max_tokens,
+            schedule,
+            suspend_on: Some(ASK_USER),
+        };
+        run.run(
+            context,
+            self.next_turn..self.max_turns,
+            Some(StopReason::ToolCalls),
+        )
+        .await
+    }
+}
+
+impl ContextBuilder {
+    /// ## `send_tool_loop_with_ask_user`
+    /// `send_tool_loop` with the `ask_user` tool (see `ask_user_tool`)
+    /// registered as a suspension point: a call to it suspends the loop once
+    /// the turn's other calls have run. The outcome is then `Suspended` with the question and a snapshot
+    /// to resume from with the user's answer.
+    ///
+    /// ```rust,ignore
+    /// let outcome = context
+    ///     .send_tool_loop_with_ask_user(adapter.clone(), tools.clone(), 500, 8, &schedule)
+    ///     .await?;
+    /// let context = match outcome {
+    ///     LoopOutcome::Finished(context) => context,
+    ///     LoopOutcome::Suspended { question, resolution_snapshot } => {
+    ///         let answer = prompt_user(&question);
+    ///         // ... resume_with_answer until Finished
+    ///     }
+    /// };
+    /// ```
+    pub async fn send_tool_loop_with_ask_user(
+        self,
+        adapter: ProviderAdapter,
+        tool_executer: ToolExecuter,
+        max_tokens: u32,
+        max_turns: usize,
+        schedule: &ParamsSchedule,
+    ) -> Result<LoopOutcome, String> {
+        let run = ToolLoopRun {
+            adapter,
+            tool_executer,
+            max_tokens,
+            schedule,
+            suspend_on: Some(ASK_USER),
+        };
+        run.run(self, 0..max_turns, None).await
+    }
+}
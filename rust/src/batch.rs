@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures::StreamExt;
+use futures::stream;
+use serde::{Deserialize, Serialize};
+
+use crate::capabilities::Pricing;
+use crate::resilience::{RetryPolicy, retry_adapter};
+use crate::tokens::{HeuristicTokenCounter, TokenCounter, estimate_prompt_tokens};
+use crate::{ContextBuilder, PromptResponse, ProviderAdapter};
+
+/* --------------------------------- Options -------------------------------- */
+
+/// Stops a batch `run` from starting more items. Items already in flight
+/// finish and are checkpointed first.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Reported after every item a batch `run` sends
+#[derive(Clone, PartialEq, Debug)]
+pub struct BatchProgress {
+    pub key: String,
+    /// Why the item failed, `None` when it succeeded
+    pub error: Option<String>,
+    /// Items done so far, including those resumed from the checkpoint
+    pub finished: usize,
+    pub total: usize,
+}
+
+/// Receives a `BatchProgress` for every item a batch `run` sends
+pub type BatchProgressSink = Arc<dyn Fn(BatchProgress) + Send + Sync>;
+
+/// How a batch `run` sends its items
+#[derive(Clone)]
+pub struct BatchOptions {
+    pub max_tokens: u32,
+    /// Items in flight at once
+    pub concurrency: usize,
+    /// Retry each item's failures with `retry_adapter`
+    pub per_item_retry: Option<RetryPolicy>,
+    /// File of completed items. Items already in it are skipped, so a
+    /// cancelled or crashed run can be resumed by running it again.
+    pub checkpoint: Option<PathBuf>,
+    /// When set, the report carries an estimated cost
+    pub pricing: Option<Pricing>,
+    pub cancel: Option<CancelToken>,
+    pub progress: Option<BatchProgressSink>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            max_tokens: 1024,
+            concurrency: 4,
+            per_item_retry: None,
+            checkpoint: None,
+            pricing: None,
+            cancel: None,
+            progress: None,
+        }
+    }
+}
+
+/* --------------------------------- Report --------------------------------- */
+
+/// Outcome of a batch `run`
+#[derive(Clone, Default)]
+pub struct BatchReport {
+    /// Replies by key, including those resumed from the checkpoint
+    pub completed: BTreeMap<String, PromptResponse>,
+    /// Errors by key; failed items run again on the next run
+    pub failed: BTreeMap<String, String>,
+    /// Items skipped because the checkpoint already had them
+    pub resumed: usize,
+    /// Items left unsent because the run was cancelled
+    pub not_started: usize,
+    pub cancelled: bool,
+    /// Usage of the items sent in this run
+    pub token_usage: u64,
+    /// Estimated cost of the items sent in this run, when priced
+    pub cost: Option<f64>,
+}
+
+/* ------------------------------- Checkpoint ------------------------------- */
+
+/// One line of a checkpoint file
+#[derive(Serialize, Deserialize)]
+struct Completed {
+    key: String,
+    response: PromptResponse,
+}
+
+/// Completed items recorded at `path`. A line cut off by a crash ends the file.
+fn load_checkpoint(path: &Path) -> Result<BTreeMap<String, PromptResponse>, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(format!("Failed to open {}: {}", path.display(), e)),
+    };
+
+    let mut completed = BTreeMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        match serde_json::from_str::<Completed>(&line) {
+            Ok(item) => {
+                completed.insert(item.key, item.response);
+            }
+            Err(_) => break,
+        }
+    }
+    Ok(completed)
+}
+
+fn append_checkpoint(
+    file: &mut File,
+    path: &Path,
+    key: &str,
+    response: &PromptResponse,
+) -> Result<(), String> {
+    let line = serde_json::to_string(&Completed {
+        key: key.to_string(),
+        response: response.clone(),
+    })
+    .map_err(|e| format!("Failed to serialize checkpoint entry: {}", e))?;
+    writeln!(file, "{}", line)
+        .and_then(|_| file.flush())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/* ----------------------------------- Run ---------------------------------- */
+
+/// ## `run`
+/// Send every context through `adapter`, `options.concurrency` at a time,
+/// and collect the replies by key. A failed item is recorded in the report
+/// without stopping the others.
+///
+/// With a `checkpoint`, each reply is appended to the file as it arrives
+/// and items already in it are skipped. After `cancel` no new items start;
+/// the run returns once those in flight are checkpointed. Errors only if
+/// the checkpoint can't be read or written.
+///
+/// ```rust,ignore
+/// let cancel = CancelToken::new();
+/// let report = batch::run(tickets, adapter, &BatchOptions {
+///     concurrency: 8,
+///     checkpoint: Some("classify.ckpt".into()),
+///     cancel: Some(cancel.clone()),
+///     ..Default::default()
+/// }).await?;
+/// ```
+pub async fn run(
+    items: impl IntoIterator<Item = (String, ContextBuilder)>,
+    adapter: ProviderAdapter,
+    options: &BatchOptions,
+) -> Result<BatchReport, String> {
+    let mut report = BatchReport::default();
+    let mut checkpoint = None;
+    if let Some(path) = &options.checkpoint {
+        report.completed = load_checkpoint(path)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        checkpoint = Some((file, path));
+    }
+
+    let adapter = match &options.per_item_retry {
+        Some(policy) => retry_adapter(adapter, policy.clone()),
+        None => adapter,
+    };
+
+    let mut seen = HashSet::new();
+    let items: Vec<(String, ContextBuilder)> = items
+        .into_iter()
+        .filter(|(key, _)| seen.insert(key.clone()))
+        .collect();
+    let total = items.len();
+    let pending: Vec<_> = items
+        .into_iter()
+        .filter(|(key, _)| !report.completed.contains_key(key))
+        .collect();
+    report.resumed = total - pending.len();
+    let mut unsent = pending.len();
+
+    let cancelled = || {
+        options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+    };
+    let mut results = stream::iter(pending)
+        .take_while(|_| futures::future::ready(!cancelled()))
+        .map(|(key, context)| {
+            let adapter = adapter.clone();
+            async move {
+                let prompt = estimate_prompt_tokens(&context, &[], &HeuristicTokenCounter);
+                let response = adapter(context.outgoing(), options.max_tokens).await;
+                (key, prompt, response)
+            }
+        })
+        .buffer_unordered(options.concurrency.max(1));
+
+    while let Some((key, prompt, response)) = results.next().await {
+        unsent -= 1;
+        let error = match response {
+            Ok(response) => {
+                if let Some((file, path)) = &mut checkpoint {
+                    append_checkpoint(file, path, &key, &response)?;
+                }
+                report.token_usage += response.token_usage as u64;
+                if let Some(pricing) = options.pricing {
+                    let reply = HeuristicTokenCounter.count(&response.message.content);
+                    *report.cost.get_or_insert(0.0) += pricing.cost(prompt as u32, reply as u32);
+                }
+                report.completed.insert(key.clone(), response);
+                None
+            }
+            Err(e) => {
+                report.failed.insert(key.clone(), e.clone());
+                Some(e)
+            }
+        };
+
+        if let Some(sink) = &options.progress {
+            sink(BatchProgress {
+                key,
+                error,
+                finished: report.completed.len() + report.failed.len(),
+                total,
+            });
+        }
+    }
+
+    if let Some((file, path)) = &checkpoint {
+        file.sync_all()
+            .map_err(|e| format!("Failed to sync {}: {}", path.display(), e))?;
+    }
+    report.not_started = unsent;
+    report.cancelled = unsent > 0 && cancelled();
+    Ok(report)
+}
@@ -0,0 +1,89 @@
+use crate::tool_loop::send_turn;
+use crate::{ContextBuilder, Message, MessageRole, ProviderAdapter, UnresolvedResponse};
+
+/* -------------------------------- Branches -------------------------------- */
+
+impl ContextBuilder {
+    /// Save a copy of the current history under `name`, replacing any branch
+    /// already saved there
+    pub fn save_branch(mut self, name: &str) -> Self {
+        self.branches.insert(name.to_string(), self.history.clone());
+        self
+    }
+
+    /// Replace the current history with the branch saved under `name`. The
+    /// current history is discarded unless it was saved first.
+    pub fn checkout_branch(mut self, name: &str) -> Result<Self, String> {
+        let history = self
+            .branches
+            .get(name)
+            .ok_or_else(|| format!("No branch named {}", name))?;
+        self.history = history.clone();
+        Ok(self)
+    }
+
+    pub fn branch(&self, name: &str) -> Option<&[Message]> {
+        self.branches.get(name).map(|history| history.as_slice())
+    }
+
+    pub fn branch_names(&self) -> Vec<&str> {
+        self.branches.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Drop everything after `index`, optionally rewrite the user message at
+    /// `index`, and send again.
+    ///
+    /// Tool results that directly follow a kept model message are kept with
+    /// it, so a tool call is never separated from its results. The send
+    /// itself works like `send`, prefill included, but returns its errors;
+    /// a finalized conversation gives `CONVERSATION_FINALIZED`.
+    pub async fn regenerate_from(
+        mut self,
+        index: usize,
+        new_user_content: Option<String>,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+    ) -> Result<UnresolvedResponse, String> {
+        let Some(msg) = self.history.get_mut(index) else {
+            return Err(format!(
+                "Cannot regenerate from {}: history has {} messages",
+                index,
+                self.history.len()
+            ));
+        };
+
+        if let Some(content) = new_user_content {
+            if msg.role != MessageRole::User {
+                return Err(format!("Message {} is not a user message", index));
+            }
+            msg.content = content;
+        }
+
+        let mut keep = index + 1;
+        while self
+            .history
+            .get(keep)
+            .is_some_and(|msg| matches!(msg.role, MessageRole::Tool | MessageRole::Function))
+        {
+            keep += 1;
+        }
+        self.history.truncate(keep);
+
+        send_turn(self, &adapter, max_tokens).await
+    }
+
+    /// `regenerate_from`, keeping the pre-edit history as branch `branch_name`
+    /// so the caller can `checkout_branch` back to it
+    pub async fn regenerate_from_as_branch(
+        self,
+        index: usize,
+        new_user_content: Option<String>,
+        branch_name: &str,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+    ) -> Result<UnresolvedResponse, String> {
+        self.save_branch(branch_name)
+            .regenerate_from(index, new_user_content, adapter, max_tokens)
+            .await
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use futures::stream::{self, BoxStream, StreamExt};
+
+use crate::tokens::TokenCounter;
+use crate::{ContextBuilder, PromptResponseDelta, StopReason, StreamProviderAdapter};
+
+/* ------------------------------- TokenBudget ------------------------------ */
+
+/// ## `TokenBudget`
+/// A pool of tokens shared between any number of concurrent sends. Debits are
+/// atomic, so two streams can never both spend the last tokens.
+pub struct TokenBudget {
+    remaining: AtomicU64,
+}
+
+impl TokenBudget {
+    pub fn new(tokens: u64) -> Self {
+        TokenBudget {
+            remaining: AtomicU64::new(tokens),
+        }
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.remaining.load(Ordering::SeqCst)
+    }
+
+    /// Take up to `tokens` from the budget, returning how many were granted
+    pub fn debit(&self, tokens: u64) -> u64 {
+        let previous = self
+            .remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                Some(left.saturating_sub(tokens))
+            })
+            .unwrap_or(0);
+        previous.min(tokens)
+    }
+
+    /// Return tokens that were debited but not used
+    pub fn credit(&self, tokens: u64) {
+        self.remaining.fetch_add(tokens, Ordering::SeqCst);
+    }
+}
+
+/* ---------------------------- Budgeted Streaming -------------------------- */
+
+struct BudgetState {
+    deltas: BoxStream<'static, Result<PromptResponseDelta, String>>,
+    budget: Arc<TokenBudget>,
+    counter: Arc<dyn TokenCounter>,
+    debited: u64,
+    finished: bool,
+}
+
+fn exhausted_delta(cumulative_tokens: u32) -> PromptResponseDelta {
+    PromptResponseDelta {
+        content: String::new(),
+        stop_reason: Some(StopReason::BudgetExhausted),
+        tool_calls: None,
+        cumulative_tokens,
+        token_delta: 0,
+        logprobs: None,
+    }
+}
+
+impl ContextBuilder {
+    /// Stream a response while spending from a shared `budget`.
+    ///
+    /// Each delta is charged its estimated size from `counter`; the final usage
+    /// report, if the provider sends one, replaces the estimate. When the budget
+    /// runs dry the provider stream is dropped and the last delta carries
+    /// `StopReason::BudgetExhausted`. At most one delta's worth of content is
+    /// let through past the budget. An already empty budget yields just that
+    /// delta, without calling the adapter.
+    pub fn send_streaming_with_budget(
+        self,
+        adapter: StreamProviderAdapter,
+        max_tokens: u32,
+        budget: Arc<TokenBudget>,
+        counter: Arc<dyn TokenCounter>,
+    ) -> BoxStream<'static, Result<PromptResponseDelta, String>> {
+        // A max_tokens of 0 would mean "the model's limit" to the adapter
+        if budget.remaining() == 0 {
+            return Box::pin(stream::once(async { Ok(exhausted_delta(0)) }));
+        }
+        let max_tokens = max_tokens.min(budget.remaining().try_into().unwrap_or(u32::MAX));
+        let state = BudgetState {
+            deltas: self.send_streaming(adapter, max_tokens),
+            budget,
+            counter,
+            debited: 0,
+            finished: false,
+        };
+
+        Box::pin(stream::unfold(state, |mut state| async move {
+            if state.finished {
+                return None;
+            }
+
+            let mut delta = match state.deltas.next().await? {
+                Ok(delta) => delta,
+                Err(e) => return Some((Err(e), state)),
+            };
+
+            let reported = u64::from(delta.cumulative_tokens);
+            let charge = if reported > 0 {
+                // Reconcile estimates with what the provider actually counted
+                if reported < state.debited {
+                    state.budget.credit(state.debited - reported);
+                    state.debited = reported;
+                }
+                reported - state.debited
+            } else {
+                state.counter.count(&delta.content) as u64
+            };
+
+            let granted = state.budget.debit(charge);
+            state.debited += granted;
+
+            // Only the stream that takes the last tokens gets a partial grant,
+            // so the overshoot across all streams is at most that one delta
+            if charge > 0 && granted == 0 {
+                state.finished = true;
+                state.deltas = Box::pin(stream::empty());
+                return Some((Ok(exhausted_delta(delta.cumulative_tokens)), state));
+            }
+
+            if granted < charge {
+                state.finished = true;
+                state.deltas = Box::pin(stream::empty());
+                delta.stop_reason = Some(StopReason::BudgetExhausted);
+            }
+
+            Some((Ok(delta), state))
+        }))
+    }
+}
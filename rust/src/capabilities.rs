@@ -0,0 +1,127 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/* ------------------------------ Capabilities ------------------------------ */
+
+/// What a model/backend accepts, used to validate requests before sending
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Capabilities {
+    /// Prompt + completion tokens the model can attend to
+    pub context_length: u32,
+    pub max_output_tokens: u32,
+    pub supports_tools: bool,
+    /// Tool schemas must be strict (closed objects, every property required)
+    pub strict_tool_schemas: bool,
+}
+
+/// USD per million tokens
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct Pricing {
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+impl Pricing {
+    pub fn cost(&self, prompt_tokens: u32, completion_tokens: u32) -> f64 {
+        (prompt_tokens as f64 * self.prompt_per_million
+            + completion_tokens as f64 * self.completion_per_million)
+            / 1_000_000.0
+    }
+}
+
+/* -------------------------------- Known Models ---------------------------- */
+
+/// (model prefix, context length, max output tokens, tools, strict tool schemas)
+const KNOWN_MODELS: &[(&str, u32, u32, bool, bool)] = &[
+    ("gpt-4.1", 1_047_576, 32_768, true, true),
+    ("gpt-4o", 128_000, 16_384, true, true),
+    ("gpt-4o-mini", 128_000, 16_384, true, true),
+    ("gpt-4-turbo", 128_000, 4_096, true, true),
+    ("gpt-4", 8_192, 8_192, true, true),
+    ("gpt-3.5-turbo", 16_385, 4_096, true, true),
+    ("o1", 200_000, 100_000, true, true),
+    ("o3-mini", 200_000, 100_000, true, true),
+    ("llama3.1", 131_072, 131_072, true, false),
+    ("llama3", 8_192, 8_192, false, false),
+    ("mistral", 32_768, 32_768, true, false),
+    ("qwen2.5", 32_768, 8_192, true, false),
+];
+
+/// ## `known_capabilities`
+/// Capabilities of well-known models, matched on the longest known prefix so
+/// dated (`gpt-4o-2024-08-06`) and tagged (`llama3:8b`) names resolve too
+pub fn known_capabilities(model: &str) -> Option<Capabilities> {
+    KNOWN_MODELS
+        .iter()
+        .filter(|(prefix, ..)| model.starts_with(prefix))
+        .max_by_key(|(prefix, ..)| prefix.len())
+        .map(
+            |&(_, context_length, max_output_tokens, supports_tools, strict_tool_schemas)| {
+                Capabilities {
+                    context_length,
+                    max_output_tokens,
+                    supports_tools,
+                    strict_tool_schemas,
+                }
+            },
+        )
+}
+
+/* -------------------------------- Max Tokens ------------------------------ */
+
+static GLOBAL_DEFAULT_MAX_TOKENS: AtomicU32 = AtomicU32::new(0);
+
+/// Process-wide `max_tokens` for sends that pass 0 and whose adapter has no
+/// default of its own. 0 clears it.
+pub fn set_global_default_max_tokens(max_tokens: u32) {
+    GLOBAL_DEFAULT_MAX_TOKENS.store(max_tokens, Ordering::Relaxed);
+}
+
+/// ## `MaxTokensPolicy`
+/// How an adapter settles the `max_tokens` it was called with. A request of 0
+/// means "no preference" and takes the adapter default, then the global
+/// default, then the model's maximum. Requests above the model's maximum are
+/// clamped, or rejected when `strict`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct MaxTokensPolicy {
+    pub default_max_tokens: Option<u32>,
+    pub strict: bool,
+}
+
+impl MaxTokensPolicy {
+    /// The `max_tokens` to send to `model`, plus a note when it was clamped
+    pub fn resolve(&self, model: &str, requested: u32) -> Result<(u32, Option<String>), String> {
+        let limit = known_capabilities(model).map(|caps| caps.max_output_tokens);
+        let global = GLOBAL_DEFAULT_MAX_TOKENS.load(Ordering::Relaxed);
+
+        let max_tokens = match requested {
+            0 => self
+                .default_max_tokens
+                .or((global > 0).then_some(global))
+                .or(limit)
+                .ok_or_else(|| {
+                    format!(
+                        "No max_tokens given and no default for unknown model {}",
+                        model
+                    )
+                })?,
+            requested => requested,
+        };
+
+        match limit {
+            Some(limit) if max_tokens > limit && self.strict => Err(format!(
+                "max_tokens {} exceeds the {} output limit of {}",
+                max_tokens, model, limit
+            )),
+            Some(limit) if max_tokens > limit => Ok((
+                limit,
+                Some(format!(
+                    "clamped max_tokens {} to the {} output limit of {}",
+                    max_tokens, model, limit
+                )),
+            )),
+            _ => Ok((max_tokens, None)),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::FutureExt;
+use futures::future::{BoxFuture, Shared};
+
+use crate::ab_test::CONVERSATION_ID;
+use crate::determinism::{STABLE_HASH_SEED, stable_hash};
+use crate::runtime::timeout;
+use crate::{ContextBuilder, PromptResponse, ProviderAdapter};
+
+/// Provenance source on responses that came from joining an identical
+/// request already in flight
+pub const COALESCED: &str = "coalesced";
+
+/* --------------------------------- Options -------------------------------- */
+
+/// Configuration for `coalescing_adapter_with_options`
+#[derive(Clone, Debug)]
+pub struct CoalesceOptions {
+    /// How long a call waits on the request it joined before sending its
+    /// own instead
+    pub max_wait: Duration,
+}
+
+impl Default for CoalesceOptions {
+    fn default() -> Self {
+        CoalesceOptions {
+            max_wait: Duration::from_secs(60),
+        }
+    }
+}
+
+/* ----------------------------------- Key ---------------------------------- */
+
+/// Hash of everything that shapes a request: the rendered history, the
+/// params, `max_tokens`, and the conversation id when there is one, so
+/// two conversations that happen to say the same thing are never merged
+fn request_key(context: &ContextBuilder, max_tokens: u32) -> u64 {
+    let params = &context.params;
+    let canonical = serde_json::json!({
+        "conversation_id": context.metadata.get(CONVERSATION_ID),
+        "history": context.request_history(),
+        "prefill": params.prefill,
+        "temperature": params.temperature.map(f32::to_bits),
+        "top_p": params.top_p.map(f32::to_bits),
+        "json_mode": params.json_mode,
+        "seed": params.seed,
+        "max_tokens": max_tokens,
+    });
+    stable_hash(STABLE_HASH_SEED, canonical.to_string().as_bytes())
+}
+
+/* --------------------------------- Adapter -------------------------------- */
+
+type InFlight = Shared<BoxFuture<'static, Result<PromptResponse, String>>>;
+
+#[derive(Default)]
+struct Coalescer {
+    /// Request key to the id and future of the request in flight for it
+    in_flight: Mutex<HashMap<u64, (u64, InFlight)>>,
+    next_id: AtomicU64,
+}
+
+/// ## `coalescing_adapter`
+/// Wrap `adapter` so a call identical to one still in flight waits for that
+/// request and gets a copy of its response, rather than sending again, e.g.
+/// for a double-clicked send. See `coalescing_adapter_with_options`.
+///
+/// ```rust,ignore
+/// let adapter = coalescing_adapter(openai_adapter_factory(client, model));
+/// ```
+pub fn coalescing_adapter(adapter: ProviderAdapter) -> ProviderAdapter {
+    coalescing_adapter_with_options(adapter, CoalesceOptions::default())
+}
+
+/// ## `coalescing_adapter_with_options`
+/// `coalescing_adapter` with a configurable wait. Calls match when their
+/// rendered history, params and `max_tokens` match, and their
+/// `conversation_id` metadata too when set. Clones of the returned adapter
+/// share what's in flight. A call that joined another has a `"coalesced"`
+/// provenance note; one that waited `max_wait` without an answer sends its
+/// own request. Set `params.skip_coalescing` to always send.
+///
+/// ```rust,ignore
+/// let adapter = coalescing_adapter_with_options(
+///     adapter,
+///     CoalesceOptions { max_wait: Duration::from_secs(10) },
+/// );
+/// ```
+pub fn coalescing_adapter_with_options(
+    adapter: ProviderAdapter,
+    options: CoalesceOptions,
+) -> ProviderAdapter {
+    let coalescer = Arc::new(Coalescer::default());
+
+    Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        if context.params.skip_coalescing {
+            return adapter(context, max_tokens);
+        }
+        let key = request_key(&context, max_tokens);
+
+        let mut in_flight = coalescer.in_flight.lock().unwrap();
+        if let Some((_, request)) = in_flight.get(&key) {
+            let request = request.clone();
+            let fallback = adapter.clone();
+            let max_wait = options.max_wait;
+            return Box::pin(async move {
+                match timeout(max_wait, request).await {
+                    Ok(Ok(mut response)) => {
+                        response
+                            .provenance
+                            .record(COALESCED, "joined an identical request in flight");
+                        Ok(response)
+                    }
+                    Ok(Err(e)) => Err(e),
+                    Err(_) => fallback(context, max_tokens).await,
+                }
+            });
+        }
+
+        // The request clears its own entry, so it's gone even if the caller
+        // that started it stopped waiting
+        let id = coalescer.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = adapter(context, max_tokens);
+        let done = coalescer.clone();
+        let request: InFlight = async move {
+            let result = request.await;
+            let mut in_flight = done.in_flight.lock().unwrap();
+            if in_flight.get(&key).is_some_and(|(entry, _)| *entry == id) {
+                in_flight.remove(&key);
+            }
+            result
+        }
+        .boxed()
+        .shared();
+        in_flight.insert(key, (id, request.clone()));
+        Box::pin(request)
+    })
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{Semaphore, watch};
+use tokio::task::AbortHandle;
+
+pub use crate::summarize::SUMMARY_HEADER;
+use crate::summarize::SUMMARY_PROMPT;
+use crate::tool_gc::ToolGcPolicy;
+use crate::{ContentType, ContextBuilder, Message, MessageRole, ProviderAdapter};
+
+/* --------------------------------- Policy --------------------------------- */
+
+/// When and how a conversation is compacted
+#[derive(Clone, Debug)]
+pub struct CompactionPolicy {
+    /// Compact once the history holds more messages than this
+    pub max_messages: usize,
+    /// Most recent messages kept verbatim
+    pub keep_recent: usize,
+    pub summary_max_tokens: u32,
+    pub summary_prompt: String,
+    /// Compactions allowed to call the adapter at once
+    pub max_concurrency: usize,
+    /// Repairs tool calls and results cut off from their other half by the
+    /// summary; `None` leaves them as they are
+    pub tool_gc: Option<ToolGcPolicy>,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        CompactionPolicy {
+            max_messages: 40,
+            keep_recent: 10,
+            summary_max_tokens: 500,
+            summary_prompt: SUMMARY_PROMPT.to_string(),
+            max_concurrency: 4,
+            tool_gc: Some(ToolGcPolicy::Stub),
+        }
+    }
+}
+
+impl CompactionPolicy {
+    pub fn needs_compaction(&self, context: &ContextBuilder) -> bool {
+        context.len() > self.max_messages && context.len() > self.keep_recent
+    }
+
+    /// ## `compact`
+    /// Replace everything but the leading system messages, pinned messages
+    /// and the last `keep_recent` messages with one system message
+    /// summarizing them. An earlier summary is folded into the new one, and
+    /// tool messages orphaned by the cut are repaired per `tool_gc`.
+    pub async fn compact(
+        &self,
+        context: &ContextBuilder,
+        summarizer: ProviderAdapter,
+    ) -> Result<ContextBuilder, String> {
+        let history = &context.history;
+        let head = history
+            .iter()
+            .take_while(|m| m.role == MessageRole::System && !m.content.starts_with(SUMMARY_HEADER))
+            .count();
+        let cut = history.len().saturating_sub(self.keep_recent).max(head);
+        if history[head..cut].iter().all(|msg| msg.pinned) {
+            return Ok(context.clone());
+        }
+
+        // Pinned messages are kept verbatim rather than summarized
+        let mut summarized = context.clone();
+        summarized.history = history[..cut]
+            .iter()
+            .enumerate()
+            .filter(|(index, msg)| *index < head || !msg.pinned)
+            .map(|(_, msg)| msg.clone())
+            .collect();
+        let summary = summarized
+            .to_summary_string(summarizer, self.summary_max_tokens, &self.summary_prompt)
+            .await?;
+
+        let mut compacted = context.clone();
+        compacted.history = history[..head]
+            .iter()
+            .cloned()
+            .chain(std::iter::once(Message {
+                role: MessageRole::System,
+                content: format!("{}\n{}", SUMMARY_HEADER, summary),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            }))
+            .chain(history[head..cut].iter().filter(|msg| msg.pinned).cloned())
+            .chain(history[cut..].iter().cloned())
+            .collect();
+        Ok(match self.tool_gc {
+            Some(policy) => compacted.gc_tool_messages(policy),
+            None => compacted,
+        })
+    }
+}
+
+/* -------------------------------- Compactor ------------------------------- */
+
+struct Job {
+    /// Newest builder submitted for the conversation
+    latest: ContextBuilder,
+    /// Bumped on every submit, so a finished compaction can tell whether its
+    /// source is still current
+    generation: u64,
+    /// History the published builder was compacted from
+    published_from: Option<Vec<Message>>,
+    sender: watch::Sender<ContextBuilder>,
+    running: bool,
+    task: Option<AbortHandle>,
+}
+
+struct Inner {
+    policy: CompactionPolicy,
+    adapter: ProviderAdapter,
+    permits: Semaphore,
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+/// ## `Compactor`
+/// Compacts conversations on background tasks so summarization never adds
+/// latency to a user turn. `submit` a conversation after each turn; before
+/// the next send, `swap_in_if_ready` hands back the compacted builder if one
+/// was published for exactly the history being sent, and the current one
+/// otherwise. At most `max_concurrency` compactions call the adapter at once.
+///
+/// A compaction whose source was resubmitted while it ran is discarded and
+/// rerun on the newer history, so compaction never clobbers new messages.
+///
+/// ```rust,ignore
+/// let compactor = Compactor::spawn(CompactionPolicy::default(), summarizer);
+/// let mut compacted = compactor.submit("conv-42", context.clone());
+/// // ... later, before the next turn
+/// context = compactor.swap_in_if_ready("conv-42", context, &mut compacted);
+/// let response = context.add_message(user_message).send(adapter, 1000).await;
+/// ```
+#[derive(Clone)]
+pub struct Compactor {
+    inner: Arc<Inner>,
+}
+
+impl Compactor {
+    /// A compactor summarizing with `adapter`. Needs a tokio runtime once
+    /// conversations are submitted.
+    pub fn spawn(policy: CompactionPolicy, adapter: ProviderAdapter) -> Self {
+        Compactor {
+            inner: Arc::new(Inner {
+                permits: Semaphore::new(policy.max_concurrency.max(1)),
+                policy,
+                adapter,
+                jobs: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// ## `submit`
+    /// Queue a conversation for compaction. The receiver starts out holding
+    /// `context` marked as seen, and changes once a compacted builder is
+    /// published. Resubmitting a conversation mid-compaction makes that
+    /// compaction start over on the new history.
+    pub fn submit(
+        &self,
+        conversation_id: &str,
+        context: ContextBuilder,
+    ) -> watch::Receiver<ContextBuilder> {
+        let mut jobs = self.inner.jobs.lock().unwrap();
+        let job = jobs
+            .entry(conversation_id.to_string())
+            .or_insert_with(|| Job {
+                latest: context.clone(),
+                generation: 0,
+                published_from: None,
+                sender: watch::channel(context.clone()).0,
+                running: false,
+                task: None,
+            });
+        job.latest = context;
+        job.generation += 1;
+        let receiver = job.sender.subscribe();
+
+        if !job.running {
+            job.running = true;
+            let task = tokio::spawn(run(self.inner.clone(), conversation_id.to_string()));
+            job.task = Some(task.abort_handle());
+        }
+        receiver
+    }
+
+    /// ## `swap_in_if_ready`
+    /// The compacted builder if one is waiting in `compacted` and was made
+    /// from exactly `current`'s history; otherwise `current`. When `current`
+    /// has moved on since, it's resubmitted rather than overwritten.
+    pub fn swap_in_if_ready(
+        &self,
+        conversation_id: &str,
+        current: ContextBuilder,
+        compacted: &mut watch::Receiver<ContextBuilder>,
+    ) -> ContextBuilder {
+        if !compacted.has_changed().unwrap_or(false) {
+            return current;
+        }
+
+        let source = {
+            let jobs = self.inner.jobs.lock().unwrap();
+            jobs.get(conversation_id)
+                .and_then(|job| job.published_from.clone())
+        };
+        let ready = compacted.borrow_and_update().clone();
+        match source {
+            Some(source) if source == current.history => ready,
+            _ => {
+                self.submit(conversation_id, current.clone());
+                current
+            }
+        }
+    }
+
+    /// Whether a compaction is queued or running for the conversation
+    pub fn is_compacting(&self, conversation_id: &str) -> bool {
+        let jobs = self.inner.jobs.lock().unwrap();
+        jobs.get(conversation_id).is_some_and(|job| job.running)
+    }
+
+    /// Abort the conversation's compaction and forget it. Its receivers stop
+    /// changing.
+    pub fn abort(&self, conversation_id: &str) {
+        let job = self.inner.jobs.lock().unwrap().remove(conversation_id);
+        if let Some(task) = job.and_then(|job| job.task) {
+            task.abort();
+        }
+    }
+
+    /// Abort every compaction
+    pub fn shutdown(&self) {
+        let jobs: Vec<Job> = self
+            .inner
+            .jobs
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, job)| job)
+            .collect();
+        for task in jobs.into_iter().filter_map(|job| job.task) {
+            task.abort();
+        }
+    }
+}
+
+async fn run(inner: Arc<Inner>, conversation_id: String) {
+    loop {
+        let (source, generation) = {
+            let jobs = inner.jobs.lock().unwrap();
+            match jobs.get(&conversation_id) {
+                Some(job) => (job.latest.clone(), job.generation),
+                None => return,
+            }
+        };
+
+        let result = match inner.policy.needs_compaction(&source) {
+            true => {
+                let _permit = inner.permits.acquire().await;
+                Some(inner.policy.compact(&source, inner.adapter.clone()).await)
+            }
+            false => None,
+        };
+
+        let mut jobs = inner.jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(&conversation_id) else {
+            return;
+        };
+        // The source advanced while compacting: start over on the new history
+        if job.generation != generation {
+            continue;
+        }
+        // A failed compaction publishes nothing; the caller keeps its builder
+        if let Some(Ok(compacted)) = result {
+            job.published_from = Some(source.history);
+            job.sender.send_replace(compacted);
+        }
+        job.running = false;
+        return;
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::resilience::ResponseValidator;
+use crate::{ContentType, ContextBuilder, Message, MessageRole, PromptResponse};
+
+/* -------------------------------- Languages ------------------------------- */
+
+/// Languages `ResponseConstraints` can ask for and recognise
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LangCode {
+    En,
+    De,
+    Fr,
+    Es,
+    It,
+}
+
+impl LangCode {
+    pub const ALL: [LangCode; 5] = [
+        LangCode::En,
+        LangCode::De,
+        LangCode::Fr,
+        LangCode::Es,
+        LangCode::It,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LangCode::En => "English",
+            LangCode::De => "German",
+            LangCode::Fr => "French",
+            LangCode::Es => "Spanish",
+            LangCode::It => "Italian",
+        }
+    }
+
+    /// Frequent character trigrams, with spaces marking word edges
+    fn profile(&self) -> &'static [&'static str] {
+        match self {
+            LangCode::En => &[
+                " th", "the", "he ", " an", "and", "nd ", "ing", "ng ", " of", "of ", " to", "to ",
+                " is", "is ", "ion", " in", "in ", "ed ", "you", " yo", "at ", "hat", "tha", " wh",
+            ],
+            LangCode::De => &[
+                " de", "der", "er ", " di", "die", "ie ", " un", "und", "nd ", "ich", "ch ", "sch",
+                " ei", "ein", "en ", "den", " zu", "cht", "ist", " is", "das", " da", "nic", "ung",
+            ],
+            LangCode::Fr => &[
+                " de", "de ", "es ", " le", "les", "le ", "ent", " la", "la ", " qu", "que", "ue ",
+                " et", "et ", "des", "ion", " un", "une", " po", "our", "ous", "est", " es", "ne ",
+            ],
+            LangCode::Es => &[
+                " de", "de ", " la", "la ", " qu", "que", "ue ", " el", "el ", " lo", "los", "os ",
+                "es ", " en", "en ", "ent", "ión", " pa", "par", " co", "con", " un", "una", "ar ",
+            ],
+            LangCode::It => &[
+                " ch", "che", "he ", " di", "di ", "la ", " il", "il ", "ell", "lla", " de", "del",
+                " pe", "per", "to ", "re ", "one", "ent", " no", "non", "no ", "zio", "ato", "ere",
+            ],
+        }
+    }
+}
+
+/// Below this many trigrams the text is too short to call
+const MIN_TRIGRAMS: usize = 20;
+
+/// ## `detect_language`
+/// Best guess at the language of `text` from how many of its character
+/// trigrams are frequent in each language. `None` for short or ambiguous
+/// text.
+pub fn detect_language(text: &str) -> Option<LangCode> {
+    let padded: Vec<char> = format!(
+        " {} ",
+        text.to_lowercase()
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+    .chars()
+    .collect();
+    let trigrams: Vec<String> = padded.windows(3).map(|w| w.iter().collect()).collect();
+    if trigrams.len() < MIN_TRIGRAMS {
+        return None;
+    }
+
+    let mut scores: Vec<(LangCode, usize)> = LangCode::ALL
+        .iter()
+        .map(|lang| {
+            let profile: HashSet<&str> = lang.profile().iter().copied().collect();
+            let hits = trigrams
+                .iter()
+                .filter(|t| profile.contains(t.as_str()))
+                .count();
+            (*lang, hits)
+        })
+        .collect();
+    scores.sort_by_key(|(_, hits)| std::cmp::Reverse(*hits));
+
+    match (scores[0], scores[1]) {
+        ((_, 0), _) => None,
+        ((best, top), (_, runner_up)) if top > runner_up => Some(best),
+        _ => None,
+    }
+}
+
+/* --------------------------------- Formats -------------------------------- */
+
+/// Layout a reply should use
+#[derive(Clone, PartialEq, Debug)]
+pub enum ResponseFormat {
+    /// Only bullet points, with no introduction or closing remarks
+    Bullets { max_items: Option<usize> },
+    /// Prose without bullets or tables
+    Paragraph,
+    /// A Markdown table
+    Table,
+}
+
+fn is_bullet(line: &str) -> bool {
+    let line = line.trim_start();
+    if ["- ", "* ", "• "]
+        .iter()
+        .any(|marker| line.starts_with(marker))
+    {
+        return true;
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    digits > 0 && line[digits..].starts_with(". ")
+}
+
+fn is_table_row(line: &str) -> bool {
+    let line = line.trim();
+    line.len() > 1 && line.starts_with('|') && line.ends_with('|')
+}
+
+fn is_table(text: &str) -> bool {
+    let rows: Vec<&str> = text.lines().filter(|line| is_table_row(line)).collect();
+    rows.len() >= 2 && rows.iter().any(|row| row.contains("---"))
+}
+
+/* ------------------------------- Constraints ------------------------------ */
+
+/// ## `ResponseConstraints`
+/// Declarative requirements for a reply, e.g. "answer in German, at most 3
+/// bullet points, no preamble". Render them into the system prompt with
+/// `ContextBuilder::with_constraints` and enforce them with `validator`.
+///
+/// ```rust,ignore
+/// let constraints = ResponseConstraints {
+///     language: Some(LangCode::De),
+///     format: Some(ResponseFormat::Bullets { max_items: Some(3) }),
+///     ..Default::default()
+/// };
+/// let response = context
+///     .with_constraints(&constraints)
+///     .validate_and_retry(adapter, 300, &constraints.validator(), 3)
+///     .await?;
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ResponseConstraints {
+    pub language: Option<LangCode>,
+    pub max_words: Option<usize>,
+    pub format: Option<ResponseFormat>,
+    /// Matched case-insensitively
+    pub forbidden_phrases: Vec<String>,
+}
+
+/// One way a reply broke its constraints
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConstraintViolation {
+    WrongLanguage {
+        expected: LangCode,
+        detected: LangCode,
+    },
+    TooManyWords {
+        limit: usize,
+        found: usize,
+    },
+    /// The reply isn't laid out as asked; the string says how
+    WrongFormat(String),
+    ForbiddenPhrase(String),
+}
+
+impl ConstraintViolation {
+    /// Corrective instruction to send back to the model
+    pub fn feedback(&self) -> String {
+        match self {
+            ConstraintViolation::WrongLanguage { expected, detected } => format!(
+                "Answer in {}; the reply was in {}.",
+                expected.name(),
+                detected.name()
+            ),
+            ConstraintViolation::TooManyWords { limit, found } => {
+                format!("Use at most {} words; the reply had {}.", limit, found)
+            }
+            ConstraintViolation::WrongFormat(detail) => detail.clone(),
+            ConstraintViolation::ForbiddenPhrase(phrase) => {
+                format!("Do not use the phrase \"{}\".", phrase)
+            }
+        }
+    }
+}
+
+fn word_count(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+impl ResponseConstraints {
+    /// The constraints as instructions for the model
+    pub fn render_instructions(&self) -> String {
+        let mut lines = vec!["Response requirements:".to_string()];
+        if let Some(language) = self.language {
+            lines.push(format!("- Answer in {}.", language.name()));
+        }
+        if let Some(max_words) = self.max_words {
+            lines.push(format!("- Use at most {} words.", max_words));
+        }
+        match &self.format {
+            Some(ResponseFormat::Bullets { max_items }) => lines.push(format!(
+                "- Answer only with bullet points{}, with no introduction or closing remarks.",
+                max_items
+                    .map(|max| format!(" (at most {})", max))
+                    .unwrap_or_default()
+            )),
+            Some(ResponseFormat::Paragraph) => {
+                lines.push("- Answer in plain paragraphs, without lists or tables.".to_string())
+            }
+            Some(ResponseFormat::Table) => {
+                lines.push("- Answer with a Markdown table.".to_string())
+            }
+            None => {}
+        }
+        if !self.forbidden_phrases.is_empty() {
+            let phrases: Vec<String> = self
+                .forbidden_phrases
+                .iter()
+                .map(|phrase| format!("\"{}\"", phrase))
+                .collect();
+            lines.push(format!(
+                "- Never use these phrases: {}.",
+                phrases.join(", ")
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Every constraint `reply` breaks, in declaration order
+    pub fn violations(&self, reply: &str) -> Vec<ConstraintViolation> {
+        let mut violations = vec![];
+
+        if let Some(expected) = self.language
+            && let Some(detected) = detect_language(reply)
+            && detected != expected
+        {
+            violations.push(ConstraintViolation::WrongLanguage { expected, detected });
+        }
+
+        if let Some(limit) = self.max_words {
+            let found = word_count(reply);
+            if found > limit {
+                violations.push(ConstraintViolation::TooManyWords { limit, found });
+            }
+        }
+
+        let lines: Vec<&str> = reply.lines().filter(|l| !l.trim().is_empty()).collect();
+        let bullets = lines.iter().filter(|line| is_bullet(line)).count();
+        match &self.format {
+            Some(ResponseFormat::Bullets { max_items }) => {
+                if bullets < lines.len() {
+                    violations.push(ConstraintViolation::WrongFormat(format!(
+                        "Answer only with bullet points; remove the {} line(s) that aren't bullets.",
+                        lines.len() - bullets
+                    )));
+                }
+                if let Some(max) = max_items.filter(|max| bullets > *max) {
+                    violations.push(ConstraintViolation::WrongFormat(format!(
+                        "Use at most {} bullet points; the reply had {}.",
+                        max, bullets
+                    )));
+                }
+            }
+            Some(ResponseFormat::Paragraph) if bullets > 0 || is_table(reply) => {
+                violations.push(ConstraintViolation::WrongFormat(
+                    "Answer in plain paragraphs, without lists or tables.".to_string(),
+                ))
+            }
+            Some(ResponseFormat::Table) if !is_table(reply) => {
+                violations.push(ConstraintViolation::WrongFormat(
+                    "Format the answer as a Markdown table with a header row.".to_string(),
+                ))
+            }
+            _ => {}
+        }
+
+        let lowered = reply.to_lowercase();
+        violations.extend(
+            self.forbidden_phrases
+                .iter()
+                .filter(|phrase| lowered.contains(&phrase.to_lowercase()))
+                .map(|phrase| ConstraintViolation::ForbiddenPhrase(phrase.clone())),
+        );
+
+        violations
+    }
+
+    /// A `ResponseValidator` that rejects replies with violations, giving
+    /// one line of feedback per violation
+    pub fn validator(&self) -> ResponseValidator {
+        let constraints = self.clone();
+        Arc::new(move |response: &PromptResponse| {
+            let violations = constraints.violations(&response.message.content);
+            match violations.is_empty() {
+                true => Ok(()),
+                false => Err(violations
+                    .iter()
+                    .map(|v| format!("- {}", v.feedback()))
+                    .collect::<Vec<_>>()
+                    .join("\n")),
+            }
+        })
+    }
+}
+
+impl ContextBuilder {
+    /// Append the rendered constraints to the leading system message, adding
+    /// one if there isn't any
+    pub fn with_constraints(mut self, constraints: &ResponseConstraints) -> Self {
+        let instructions = constraints.render_instructions();
+        match self.history.first_mut() {
+            Some(system) if system.role == MessageRole::System => {
+                system.content = format!("{}\n\n{}", system.content, instructions);
+            }
+            _ => self.history.insert(
+                0,
+                Message {
+                    role: MessageRole::System,
+                    content: instructions,
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                },
+            ),
+        }
+        self
+    }
+}
@@ -0,0 +1,200 @@
+use crate::tokens::{HeuristicTokenCounter, TokenCounter};
+use crate::{ContentType, ContextBuilder, Message};
+
+/// Metadata key prefix for the reverse map; `blob_ref:REF-1` holds the text
+/// that `[REF-1]` stands for
+pub const BLOB_REF_PREFIX: &str = "blob_ref:";
+
+/* -------------------------------- Strategy -------------------------------- */
+
+/// Which occurrence of a repeated blob `deduplicate_blobs` keeps in full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlobStrategy {
+    /// Keep the earliest copy; later ones point back to it
+    KeepFirst,
+    /// Keep the most recent copy, which models tend to attend to better;
+    /// earlier ones point forward to it
+    KeepLast,
+}
+
+/// What a `deduplicate_blobs_with_report` pass replaced
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlobDedupReport {
+    /// Reference ids created, e.g. `REF-1`
+    pub references: Vec<String>,
+    /// Copies replaced by a marker
+    pub replaced: usize,
+    /// Tokens the history no longer costs, net of the labels added
+    pub tokens_saved: usize,
+}
+
+/* ---------------------------------- Dedup --------------------------------- */
+
+fn label(id: &str) -> String {
+    format!("[{}]\n", id)
+}
+
+fn marker(id: &str, strategy: BlobStrategy) -> String {
+    let kept = match strategy {
+        BlobStrategy::KeepFirst => "earlier",
+        BlobStrategy::KeepLast => "later",
+    };
+    format!("(see the text provided {} as [{}])", kept, id)
+}
+
+/// Plain text and tool results are rewritten; tool calls never are, so
+/// their JSON arguments stay intact
+fn rewritable(msg: &Message) -> bool {
+    matches!(
+        msg.content_type,
+        ContentType::Text | ContentType::ToolResult(_)
+    )
+}
+
+fn set_content(msg: &mut Message, content: String) {
+    if let ContentType::ToolResult(result) = &mut msg.content_type
+        && result.result == msg.content
+    {
+        result.result = content.clone();
+    }
+    msg.content = content;
+}
+
+impl ContextBuilder {
+    /// ## `deduplicate_blobs`
+    /// Find text of at least `min_len` bytes that appears more than once in
+    /// history, as a whole message or a paragraph of one, and keep a single
+    /// copy per `strategy`. The kept copy is labelled `[REF-n]` and the
+    /// others become a short marker pointing at it. Each blob is stored in
+    /// `metadata` under `blob_ref:REF-n` so `expand_blob_refs` can restore
+    /// the full text for exports. Tool calls are never rewritten.
+    ///
+    /// ```rust,ignore
+    /// let context = context.deduplicate_blobs(1024, BlobStrategy::KeepLast);
+    /// ```
+    pub fn deduplicate_blobs(self, min_len: usize, strategy: BlobStrategy) -> Self {
+        self.deduplicate_blobs_with_report(min_len, strategy, &HeuristicTokenCounter)
+            .0
+    }
+
+    /// ## `deduplicate_blobs_with_report`
+    /// `deduplicate_blobs`, also reporting the references made and the
+    /// tokens saved as measured by `counter`
+    pub fn deduplicate_blobs_with_report(
+        mut self,
+        min_len: usize,
+        strategy: BlobStrategy,
+        counter: &dyn TokenCounter,
+    ) -> (Self, BlobDedupReport) {
+        let tokens = |history: &[Message]| -> usize {
+            history.iter().map(|msg| counter.count(&msg.content)).sum()
+        };
+        let before = tokens(&self.history);
+
+        // Whole messages and their paragraphs, longest first so a repeated
+        // message is referenced once rather than paragraph by paragraph
+        let mut candidates: Vec<String> = vec![];
+        for msg in self.history.iter().filter(|msg| rewritable(msg)) {
+            let paragraphs = msg.content.split("\n\n");
+            for text in std::iter::once(msg.content.as_str()).chain(paragraphs) {
+                if text.trim().len() >= min_len && !candidates.iter().any(|c| c == text) {
+                    candidates.push(text.to_string());
+                }
+            }
+        }
+        candidates.sort_by_key(|text| std::cmp::Reverse(text.len()));
+
+        let mut next_ref = self
+            .metadata
+            .keys()
+            .filter(|key| key.starts_with(BLOB_REF_PREFIX))
+            .count()
+            + 1;
+        let mut report = BlobDedupReport::default();
+        for blob in candidates {
+            let copies: Vec<(usize, usize)> = self
+                .history
+                .iter()
+                .enumerate()
+                .filter(|(_, msg)| rewritable(msg))
+                .flat_map(|(index, msg)| {
+                    (0..msg.content.matches(blob.as_str()).count()).map(move |nth| (index, nth))
+                })
+                .collect();
+            if copies.len() < 2 {
+                continue;
+            }
+            let kept = match strategy {
+                BlobStrategy::KeepFirst => copies[0],
+                BlobStrategy::KeepLast => copies[copies.len() - 1],
+            };
+
+            let id = format!("REF-{}", next_ref);
+            next_ref += 1;
+            let marker = marker(&id, strategy);
+            let mut touched: Vec<usize> = copies.iter().map(|(index, _)| *index).collect();
+            touched.dedup();
+            for index in touched {
+                let content = &self.history[index].content;
+                let mut rewritten = String::with_capacity(content.len());
+                let mut last = 0;
+                for (nth, (at, _)) in content.match_indices(blob.as_str()).enumerate() {
+                    rewritten.push_str(&content[last..at]);
+                    match (index, nth) == kept {
+                        true => {
+                            rewritten.push_str(&label(&id));
+                            rewritten.push_str(&blob);
+                        }
+                        false => rewritten.push_str(&marker),
+                    }
+                    last = at + blob.len();
+                }
+                rewritten.push_str(&content[last..]);
+                set_content(&mut self.history[index], rewritten);
+            }
+
+            report.replaced += copies.len() - 1;
+            self.metadata
+                .insert(format!("{}{}", BLOB_REF_PREFIX, id), blob);
+            report.references.push(id);
+        }
+
+        // Net of the `[REF-n]` labels added to the kept copies
+        report.tokens_saved = before.saturating_sub(tokens(&self.history));
+        (self, report)
+    }
+
+    /// ## `expand_blob_refs`
+    /// Undo `deduplicate_blobs`: put the full text back wherever a reference
+    /// marker stands, drop the `[REF-n]` labels, and clear the reverse map
+    /// from `metadata`. Meant for exports that need the history verbatim.
+    pub fn expand_blob_refs(mut self) -> Self {
+        let refs: Vec<(String, String)> = self
+            .metadata
+            .iter()
+            .filter_map(|(key, blob)| {
+                key.strip_prefix(BLOB_REF_PREFIX)
+                    .map(|id| (id.to_string(), blob.clone()))
+            })
+            .collect();
+        if refs.is_empty() {
+            return self;
+        }
+
+        for msg in self.history.iter_mut().filter(|msg| rewritable(msg)) {
+            let mut content = msg.content.clone();
+            for (id, blob) in &refs {
+                content = content
+                    .replace(&label(id), "")
+                    .replace(&marker(id, BlobStrategy::KeepFirst), blob)
+                    .replace(&marker(id, BlobStrategy::KeepLast), blob);
+            }
+            if content != msg.content {
+                set_content(msg, content);
+            }
+        }
+        self.metadata
+            .retain(|key, _| !key.starts_with(BLOB_REF_PREFIX));
+        self
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+
+use crate::determinism::{STABLE_HASH_SEED, stable_hash};
+use crate::{ContextBuilder, Message};
+
+/* ----------------------------------- Ids ---------------------------------- */
+
+/// ## `MessageId`
+/// Hash of a message chained with every message before it, so equal ids
+/// mean equal histories up to that point. Only what the provider sees is
+/// hashed (role, content, content type and tool calls), so local flags like
+/// `pinned` don't change the id. Serialized as 16 hex digits.
+/// The hash catches corruption and mismatched histories; it isn't meant to
+/// stand up to a deliberate forgery.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(into = "String", try_from = "String")]
+pub struct MessageId(pub u64);
+
+impl MessageId {
+    /// Head of an empty history
+    pub const ROOT: MessageId = MessageId(STABLE_HASH_SEED);
+
+    /// Id of `message` following the message with this id
+    pub fn chain(self, message: &Message) -> MessageId {
+        let visible = (
+            &message.role,
+            &message.content,
+            &message.content_type,
+            &message.assistant_tool_calls,
+        );
+        let bytes = serde_json::to_vec(&visible).unwrap_or_default();
+        MessageId(stable_hash(self.0, &bytes))
+    }
+}
+
+impl From<MessageId> for String {
+    fn from(id: MessageId) -> String {
+        format!("{:016x}", id.0)
+    }
+}
+
+impl TryFrom<String> for MessageId {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Self, String> {
+        u64::from_str_radix(&hex, 16)
+            .map(MessageId)
+            .map_err(|e| format!("Invalid message id {:?}: {}", hex, e))
+    }
+}
+
+impl ContextBuilder {
+    /// Chained ids of the history, starting with `MessageId::ROOT` for the
+    /// empty prefix, so `message_ids()[i]` is the head after `i` messages
+    pub fn message_ids(&self) -> Vec<MessageId> {
+        let mut ids = Vec::with_capacity(self.history.len() + 1);
+        ids.push(MessageId::ROOT);
+        for msg in &self.history {
+            ids.push(ids[ids.len() - 1].chain(msg));
+        }
+        ids
+    }
+
+    /// Id of the last message, or `MessageId::ROOT` when empty
+    pub fn head(&self) -> MessageId {
+        self.history
+            .iter()
+            .fold(MessageId::ROOT, |id, msg| id.chain(msg))
+    }
+}
+
+/* --------------------------------- Deltas --------------------------------- */
+
+/// Messages appended to a history since `base`
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ContextDelta {
+    pub base: MessageId,
+    /// Head of the sender's history, to check the messages arrived intact
+    pub head: MessageId,
+    /// `None` when the sender's history doesn't extend `base` (it was edited
+    /// or never had it), so only a full resync will do
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appended: Option<Vec<Message>>,
+}
+
+/// Why `apply_delta` left the history alone
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum DeltaConflict {
+    /// The sender can't express its history as appends to the base
+    ResyncRequired,
+    /// The base isn't in this history: it was edited here, or the delta's
+    /// base was altered
+    UnknownBase(MessageId),
+    /// The appended messages don't chain from the base to the head
+    HeadMismatch { expected: MessageId, got: MessageId },
+    /// Both sides appended different messages after the base. Each suffix
+    /// is everything after the base, for the caller to merge.
+    Diverged {
+        ours: Vec<Message>,
+        theirs: Vec<Message>,
+    },
+    /// This history is finalized and read-only (`CONVERSATION_FINALIZED`)
+    Finalized,
+}
+
+impl ContextBuilder {
+    /// ## `diff_since`
+    /// The messages appended since `base_head`, e.g. the client's last known
+    /// head, to send instead of the whole history. If the history no longer
+    /// extends `base_head` the delta asks for a full resync.
+    ///
+    /// ```rust,ignore
+    /// let delta = server.diff_since(client_head);
+    /// client.apply_delta(serde_json::from_str(&wire)?)?;
+    /// ```
+    pub fn diff_since(&self, base_head: MessageId) -> ContextDelta {
+        let ids = self.message_ids();
+        let appended = ids
+            .iter()
+            .position(|id| *id == base_head)
+            .map(|start| self.history[start..].to_vec());
+
+        ContextDelta {
+            base: base_head,
+            head: ids[ids.len() - 1],
+            appended,
+        }
+    }
+
+    /// ## `apply_delta`
+    /// Append a delta's messages. Messages this history already has after
+    /// the base are skipped, so applying a delta twice, or one this side is
+    /// already ahead of, is a no-op. Differing messages on both sides are a
+    /// `Diverged` conflict; the history is only changed on `Ok`. A finalized
+    /// history takes no deltas.
+    pub fn apply_delta(&mut self, delta: ContextDelta) -> Result<(), DeltaConflict> {
+        if self.is_finalized() {
+            return Err(DeltaConflict::Finalized);
+        }
+        let theirs = delta.appended.ok_or(DeltaConflict::ResyncRequired)?;
+        let start = self
+            .message_ids()
+            .iter()
+            .position(|id| *id == delta.base)
+            .ok_or(DeltaConflict::UnknownBase(delta.base))?;
+
+        let got = theirs.iter().fold(delta.base, |id, msg| id.chain(msg));
+        if got != delta.head {
+            return Err(DeltaConflict::HeadMismatch {
+                expected: delta.head,
+                got,
+            });
+        }
+
+        let ours = &self.history[start..];
+        if ours.starts_with(&theirs) {
+            return Ok(());
+        }
+        if !theirs.starts_with(ours) {
+            return Err(DeltaConflict::Diverged {
+                ours: ours.to_vec(),
+                theirs,
+            });
+        }
+
+        let known = ours.len();
+        self.history.extend(theirs.into_iter().skip(known));
+        *self = std::mem::take(self).spill_to_store();
+        Ok(())
+    }
+}
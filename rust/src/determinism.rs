@@ -0,0 +1,148 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use futures::future::join_all;
+
+use crate::{ContextBuilder, PromptResponse, ProviderAdapter};
+
+/* --------------------------------- Hashing -------------------------------- */
+
+/// Starting state for `stable_hash`
+pub(crate) const STABLE_HASH_SEED: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a over `bytes`, continuing from `state`. Stable across runs and
+/// releases, unlike `DefaultHasher`.
+pub(crate) fn stable_hash(state: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(state, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/* ------------------------------- Determinism ------------------------------ */
+
+/// ## `Determinism`
+/// Source of every random choice (tie-breaking, jitter, shuffling) plus a
+/// `sequential` switch that makes fan-out sends run one at a time in
+/// declaration order. Clones share one generator, so a pipeline seeded with
+/// `Determinism::seeded` makes the same choices on every run.
+///
+/// The default draws its seed from the OS and runs fan-out concurrently.
+///
+/// ```rust,ignore
+/// let determinism = Determinism::seeded(42).sequential(true);
+/// let response = context
+///     .send_n_and_pick(adapter, 500, 3, determinism.pick_best_by(|r| score(r)))
+///     .await;
+/// ```
+#[derive(Clone)]
+pub struct Determinism {
+    state: Arc<Mutex<u64>>,
+    sequential: bool,
+}
+
+impl Default for Determinism {
+    fn default() -> Self {
+        Self::with_seed(RandomState::new().hash_one(SystemTime::now()))
+    }
+}
+
+impl Determinism {
+    fn with_seed(seed: u64) -> Self {
+        Determinism {
+            state: Arc::new(Mutex::new(seed)),
+            sequential: false,
+        }
+    }
+
+    /// Reproducible choices; runs sequentially
+    pub fn seeded(seed: u64) -> Self {
+        Self::with_seed(seed).sequential(true)
+    }
+
+    pub fn sequential(mut self, sequential: bool) -> Self {
+        self.sequential = sequential;
+        self
+    }
+
+    pub fn is_sequential(&self) -> bool {
+        self.sequential
+    }
+
+    /// Next value from a SplitMix64 generator
+    pub fn next_u64(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`; 0 when `n` is 0
+    pub fn below(&self, n: usize) -> usize {
+        match n {
+            0 => 0,
+            n => (self.next_u64() % n as u64) as usize,
+        }
+    }
+
+    /// Fisher-Yates shuffle
+    pub fn shuffle<T>(&self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+
+    /// `base` scaled by a random factor in `1 - spread ..= 1 + spread`
+    pub fn jitter(&self, base: Duration, spread: f64) -> Duration {
+        let unit = self.next_u64() as f64 / u64::MAX as f64;
+        base.mul_f64((1.0 + spread * (2.0 * unit - 1.0)).max(0.0))
+    }
+
+    /// A `send_n_and_pick` picker taking the highest `score`, breaking ties
+    /// with this generator rather than by arrival order
+    pub fn pick_best_by<S>(&self, score: S) -> impl Fn(Vec<PromptResponse>) -> PromptResponse
+    where
+        S: Fn(&PromptResponse) -> f64,
+    {
+        let determinism = self.clone();
+        move |mut responses: Vec<PromptResponse>| {
+            let scores: Vec<f64> = responses.iter().map(&score).collect();
+            let best = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let tied: Vec<usize> = (0..scores.len()).filter(|i| scores[*i] == best).collect();
+            let pick = match tied.is_empty() {
+                true => 0,
+                false => tied[determinism.below(tied.len())],
+            };
+            responses.swap_remove(pick)
+        }
+    }
+}
+
+impl ContextBuilder {
+    /// `send_n`, but one send at a time in order when `determinism` is
+    /// sequential
+    pub async fn send_n_with(
+        &self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        n: usize,
+        determinism: &Determinism,
+    ) -> Vec<Result<PromptResponse, String>> {
+        let context = match self.clone().prepare_send().await {
+            Ok(context) => context,
+            Err(e) => return vec![Err(e); n],
+        };
+        if !determinism.is_sequential() {
+            return join_all((0..n).map(|_| adapter(context.outgoing(), max_tokens))).await;
+        }
+
+        let mut results = Vec::with_capacity(n);
+        for _ in 0..n {
+            results.push(adapter(context.outgoing(), max_tokens).await);
+        }
+        results
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{ContextBuilder, Message};
+
+/* ---------------------------------- Diffs --------------------------------- */
+
+/// One edit between two histories. `Removed` indices refer to the old
+/// history; `Added` and `Changed` indices refer to the new one.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum MessageDiff {
+    Added {
+        index: usize,
+        message: Message,
+    },
+    Removed {
+        index: usize,
+        message: Message,
+    },
+    Changed {
+        index: usize,
+        before: Message,
+        after: Message,
+    },
+}
+
+/// Pairs of (old index, new index) for a longest common subsequence
+fn common_messages(old: &[Message], new: &[Message]) -> Vec<(usize, usize)> {
+    // lengths[i][j] = LCS length of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut pairs = vec![];
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+impl ContextBuilder {
+    /// ## `diff`
+    /// The edits that turn this history into `other`'s. Within each run of
+    /// differing messages, removals and additions at the same position are
+    /// reported as `Changed`.
+    ///
+    /// ```rust,ignore
+    /// let edits = original.diff(&edited);
+    /// let replayed = original.apply_diff(edits);
+    /// ```
+    pub fn diff(&self, other: &ContextBuilder) -> Vec<MessageDiff> {
+        let (old, new) = (&self.history, &other.history);
+        let mut anchors = common_messages(old, new);
+        anchors.push((old.len(), new.len()));
+
+        let mut edits = vec![];
+        let (mut i, mut j) = (0, 0);
+        for (next_i, next_j) in anchors {
+            let paired = (next_i - i).min(next_j - j);
+            for offset in 0..paired {
+                edits.push(MessageDiff::Changed {
+                    index: j + offset,
+                    before: old[i + offset].clone(),
+                    after: new[j + offset].clone(),
+                });
+            }
+            edits.extend((i + paired..next_i).map(|index| MessageDiff::Removed {
+                index,
+                message: old[index].clone(),
+            }));
+            edits.extend((j + paired..next_j).map(|index| MessageDiff::Added {
+                index,
+                message: new[index].clone(),
+            }));
+            (i, j) = (next_i + 1, next_j + 1);
+        }
+        edits
+    }
+
+    /// ## `apply_diff`
+    /// Patch the history with edits from `diff`: removals first (by old
+    /// index), then additions (by new index), then changes. Additions past
+    /// the end are appended; removals and changes with no message at their
+    /// index are skipped.
+    pub fn apply_diff(mut self, diff: Vec<MessageDiff>) -> Self {
+        let mut removed = vec![];
+        let mut added = vec![];
+        let mut changed = vec![];
+        for edit in diff {
+            match edit {
+                MessageDiff::Removed { index, .. } => removed.push(index),
+                MessageDiff::Added { index, message } => added.push((index, message)),
+                MessageDiff::Changed { index, after, .. } => changed.push((index, after)),
+            }
+        }
+
+        removed.sort_unstable();
+        removed.dedup();
+        for index in removed.into_iter().rev() {
+            if index < self.history.len() {
+                self.history.remove(index);
+            }
+        }
+
+        added.sort_by_key(|(index, _)| *index);
+        for (index, message) in added {
+            let index = index.min(self.history.len());
+            self.history.insert(index, message);
+        }
+
+        for (index, message) in changed {
+            if let Some(slot) = self.history.get_mut(index) {
+                *slot = message;
+            }
+        }
+
+        self
+    }
+}
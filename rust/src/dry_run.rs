@@ -0,0 +1,237 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::capabilities::{Capabilities, Pricing};
+use crate::tokens::{TokenCounter, estimate_prompt_tokens};
+use crate::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponse, Provenance, ProviderAdapter,
+    StopReason, ToolCall, ToolDescriptor,
+};
+
+/* --------------------------------- Options -------------------------------- */
+
+/// How the synthetic model behaves during a dry run
+#[derive(Clone)]
+pub struct DryRunOptions {
+    /// Reply content returned once the tool turns are used up
+    pub canned_content: String,
+    /// Completion length assumed for every call (capped by `max_tokens`)
+    pub assumed_completion_tokens: u32,
+    /// Tools the real adapter would be configured with
+    pub tools: Vec<ToolDescriptor>,
+    /// Number of calls that answer with a call to the first tool before the
+    /// canned reply, which bounds any tool loop being dry-run
+    pub assumed_tool_turns: usize,
+}
+
+impl Default for DryRunOptions {
+    fn default() -> Self {
+        DryRunOptions {
+            canned_content: "[dry run]".to_string(),
+            assumed_completion_tokens: 256,
+            tools: vec![],
+            assumed_tool_turns: 0,
+        }
+    }
+}
+
+/* --------------------------------- Report --------------------------------- */
+
+/// Estimate for a single call made through the dry-run adapter
+#[derive(Clone, PartialEq)]
+pub struct DryRunCall {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub cost: f64,
+    /// Reasons the real provider would have rejected this request
+    pub validation_errors: Vec<String>,
+}
+
+/// Shared, cloneable record of every call made through a dry-run adapter
+#[derive(Clone, Default)]
+pub struct DryRunReport {
+    calls: Arc<Mutex<Vec<DryRunCall>>>,
+}
+
+impl DryRunReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn calls(&self) -> Vec<DryRunCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    pub fn total_prompt_tokens(&self) -> u32 {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.prompt_tokens)
+            .sum()
+    }
+
+    pub fn total_completion_tokens(&self) -> u32 {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.completion_tokens)
+            .sum()
+    }
+
+    pub fn total_cost(&self) -> f64 {
+        self.calls.lock().unwrap().iter().map(|c| c.cost).sum()
+    }
+
+    /// Every validation error, in call order
+    pub fn validation_errors(&self) -> Vec<String> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|c| c.validation_errors.clone())
+            .collect()
+    }
+
+    fn push(&self, call: DryRunCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+/* -------------------------------- Validation ------------------------------ */
+
+/// Strict mode wants closed objects with every property required
+fn strict_schema_error(tool: &ToolDescriptor) -> Option<String> {
+    let schema = &tool.schema;
+    if schema["additionalProperties"] != serde_json::Value::Bool(false) {
+        return Some(format!(
+            "Tool {} schema must set additionalProperties: false",
+            tool.name
+        ));
+    }
+
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    let missing = schema["properties"]
+        .as_object()
+        .and_then(|props| props.keys().find(|key| !required.contains(&key.as_str())));
+
+    missing.map(|key| {
+        format!(
+            "Tool {} schema must list property {} as required",
+            tool.name, key
+        )
+    })
+}
+
+fn validate(
+    capabilities: &Capabilities,
+    tools: &[ToolDescriptor],
+    prompt_tokens: u32,
+    max_tokens: u32,
+) -> Vec<String> {
+    let mut errors = vec![];
+
+    if prompt_tokens.saturating_add(max_tokens) > capabilities.context_length {
+        errors.push(format!(
+            "Prompt of ~{} tokens plus max_tokens {} exceeds context length {}",
+            prompt_tokens, max_tokens, capabilities.context_length
+        ));
+    }
+    if max_tokens > capabilities.max_output_tokens {
+        errors.push(format!(
+            "max_tokens {} exceeds model output limit {}",
+            max_tokens, capabilities.max_output_tokens
+        ));
+    }
+    if !tools.is_empty() && !capabilities.supports_tools {
+        errors.push("Model does not support tools".to_string());
+    }
+    if capabilities.strict_tool_schemas {
+        errors.extend(tools.iter().filter_map(strict_schema_error));
+    }
+
+    errors
+}
+
+/* --------------------------------- Adapter -------------------------------- */
+
+/// ## `dry_run_adapter`
+/// A `ProviderAdapter` that never touches the network. Each call validates the
+/// request against `capabilities`, estimates its token usage and cost, records
+/// that in `report`, and answers with a synthetic response so the surrounding
+/// pipeline still runs end to end.
+///
+/// ```rust,ignore
+/// let report = DryRunReport::new();
+/// let adapter = dry_run_adapter(caps, pricing, Arc::new(HeuristicTokenCounter), DryRunOptions::default(), report.clone());
+/// run_batch(adapter).await;
+/// println!("~${:.2} over {} calls", report.total_cost(), report.call_count());
+/// ```
+pub fn dry_run_adapter(
+    capabilities: Capabilities,
+    pricing: Pricing,
+    counter: Arc<dyn TokenCounter>,
+    options: DryRunOptions,
+    report: DryRunReport,
+) -> ProviderAdapter {
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        let prompt_tokens =
+            estimate_prompt_tokens(&context, &options.tools, counter.as_ref()) as u32;
+        let completion_tokens = options.assumed_completion_tokens.min(max_tokens);
+
+        report.push(DryRunCall {
+            prompt_tokens,
+            completion_tokens,
+            cost: pricing.cost(prompt_tokens, completion_tokens),
+            validation_errors: validate(&capabilities, &options.tools, prompt_tokens, max_tokens),
+        });
+
+        let tool_call = options
+            .tools
+            .first()
+            .filter(|_| call < options.assumed_tool_turns)
+            .map(|tool| ToolCall {
+                id: format!("dry_run_call_{}", call),
+                name: tool.name.clone(),
+                arguments: serde_json::json!({}),
+            });
+
+        let response = PromptResponse {
+            message: Message {
+                role: MessageRole::Model,
+                content: match tool_call {
+                    Some(_) => String::new(),
+                    None => options.canned_content.clone(),
+                },
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            },
+            stop_reason: match tool_call {
+                Some(_) => StopReason::ToolCalls,
+                None => StopReason::Stop,
+            },
+            token_usage: prompt_tokens + completion_tokens,
+            tool_calls: tool_call.map(|call| vec![call]),
+            provenance: {
+                let mut provenance = Provenance::default();
+                provenance.record("dry_run", format!("call {}", call + 1));
+                provenance
+            },
+            logprobs: None,
+        };
+
+        Box::pin(async move { Ok(response) })
+    })
+}
@@ -0,0 +1,286 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, StreamProviderAdapter,
+    UnresolvedResponse,
+};
+
+/// Metadata key `restore_partial` sets on a conversation whose last model
+/// turn was cut off, to the number of deltas recovered for it
+pub const GENERATION_INTERRUPTED: &str = "generation_interrupted";
+
+/* --------------------------------- Journal -------------------------------- */
+
+/// When a `DurableStreamWriter` forces its deltas to disk. Deltas are always
+/// written straight to the file, so they survive the process dying; syncing
+/// also makes them survive the machine dying.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum FsyncPolicy {
+    #[default]
+    EveryDelta,
+    /// At most once per interval
+    Interval(Duration),
+    /// Only when the turn finishes
+    OnCompletion,
+}
+
+/// One line of a write-ahead file
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Entry {
+    Started { conversation_id: String },
+    Delta { content: String },
+}
+
+/// ## `StreamJournal`
+/// Directory of write-ahead files, one per conversation with a turn in
+/// flight. Stream each turn through a `DurableStreamWriter`; after a crash,
+/// `recover_partial` returns what had already been delivered.
+///
+/// ```rust,ignore
+/// let journal = StreamJournal::new("/var/lib/chat/wal", FsyncPolicy::Interval(Duration::from_millis(200)));
+/// if let Some(turn) = journal.recover_partial("conv-42")? {
+///     context = context.restore_partial(turn);
+/// }
+/// let writer = journal.writer("conv-42")?;
+/// let response = context.send_streaming_durable(adapter, 1000, &writer, render).await?;
+/// ```
+#[derive(Clone)]
+pub struct StreamJournal {
+    dir: PathBuf,
+    policy: FsyncPolicy,
+}
+
+impl StreamJournal {
+    pub fn new(dir: impl Into<PathBuf>, policy: FsyncPolicy) -> Self {
+        StreamJournal {
+            dir: dir.into(),
+            policy,
+        }
+    }
+
+    fn path(&self, conversation_id: &str) -> Result<PathBuf, String> {
+        if conversation_id.is_empty()
+            || conversation_id.contains(['/', '\\'])
+            || conversation_id.starts_with('.')
+        {
+            return Err(format!("Invalid conversation id: {:?}", conversation_id));
+        }
+        Ok(self.dir.join(format!("{}.wal", conversation_id)))
+    }
+
+    /// Start journaling a new turn, replacing any entry left for the
+    /// conversation
+    pub fn writer(&self, conversation_id: &str) -> Result<DurableStreamWriter, String> {
+        let path = self.path(conversation_id)?;
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let file = File::create(&path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+
+        let writer = DurableStreamWriter {
+            path,
+            policy: self.policy,
+            state: Arc::new(Mutex::new(WriterState {
+                file: Some(file),
+                last_sync: Instant::now(),
+            })),
+        };
+        writer.write(
+            &Entry::Started {
+                conversation_id: conversation_id.to_string(),
+            },
+            true,
+        )?;
+        Ok(writer)
+    }
+
+    /// ## `recover_partial`
+    /// The content delivered in an unfinished turn, if the conversation has
+    /// one. A line torn by the crash is ignored.
+    pub fn recover_partial(&self, conversation_id: &str) -> Result<Option<PartialTurn>, String> {
+        let path = self.path(conversation_id)?;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to open {}: {}", path.display(), e)),
+        };
+
+        let mut turn = PartialTurn {
+            conversation_id: conversation_id.to_string(),
+            content: String::new(),
+            deltas: 0,
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            match serde_json::from_str(&line) {
+                Ok(Entry::Delta { content }) => {
+                    turn.content.push_str(&content);
+                    turn.deltas += 1;
+                }
+                Ok(Entry::Started { .. }) => {}
+                Err(_) => break,
+            }
+        }
+        Ok(Some(turn))
+    }
+
+    /// Drop the entry for a conversation without recovering it
+    pub fn discard(&self, conversation_id: &str) -> Result<(), String> {
+        remove(&self.path(conversation_id)?)
+    }
+}
+
+fn remove(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            Err(format!("Failed to remove {}: {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/* --------------------------------- Writer --------------------------------- */
+
+struct WriterState {
+    /// `None` once finished
+    file: Option<File>,
+    last_sync: Instant,
+}
+
+/// Appends one turn's deltas to its write-ahead file. Clones share the file.
+#[derive(Clone)]
+pub struct DurableStreamWriter {
+    path: PathBuf,
+    policy: FsyncPolicy,
+    state: Arc<Mutex<WriterState>>,
+}
+
+impl DurableStreamWriter {
+    fn write(&self, entry: &Entry, force_sync: bool) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let due = force_sync
+            || match self.policy {
+                FsyncPolicy::EveryDelta => true,
+                FsyncPolicy::Interval(interval) => state.last_sync.elapsed() >= interval,
+                FsyncPolicy::OnCompletion => false,
+            };
+        let Some(file) = state.file.as_mut() else {
+            return Err("Stream journal entry is already finished".to_string());
+        };
+
+        let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        line.push('\n');
+        file.write_all(line.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))?;
+        if due {
+            file.sync_data()
+                .map_err(|e| format!("Failed to sync {}: {}", self.path.display(), e))?;
+            state.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Journal a delta's content
+    pub fn append(&self, delta: &PromptResponseDelta) -> Result<(), String> {
+        if delta.content.is_empty() {
+            return Ok(());
+        }
+        self.write(
+            &Entry::Delta {
+                content: delta.content.clone(),
+            },
+            false,
+        )
+    }
+
+    /// The turn completed and is saved elsewhere: sync and remove its entry.
+    /// Removing the file is atomic, so recovery sees either the whole entry
+    /// or none of it.
+    pub fn finish(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(file) = state.file.take() {
+            file.sync_data()
+                .map_err(|e| format!("Failed to sync {}: {}", self.path.display(), e))?;
+        }
+        remove(&self.path)
+    }
+
+    /// Wrap a streaming callback so each delta is journaled before `callback`
+    /// sees it. Journal failures are passed to `callback` as errors.
+    pub fn wrap<F>(
+        &self,
+        callback: F,
+    ) -> impl Fn(Result<PromptResponseDelta, String>) + Send + Sync + 'static
+    where
+        F: Fn(Result<PromptResponseDelta, String>) + Send + Sync + 'static,
+    {
+        let writer = self.clone();
+        move |delta: Result<PromptResponseDelta, String>| {
+            if let Ok(delta) = &delta
+                && let Err(e) = writer.append(delta)
+            {
+                callback(Err(e));
+            }
+            callback(delta)
+        }
+    }
+}
+
+/* -------------------------------- Recovery -------------------------------- */
+
+/// The part of a model turn that was delivered before the process died
+#[derive(Clone, PartialEq, Debug)]
+pub struct PartialTurn {
+    pub conversation_id: String,
+    pub content: String,
+    /// Deltas recovered
+    pub deltas: usize,
+}
+
+impl PartialTurn {
+    /// The recovered content as a model message
+    pub fn to_message(&self) -> Message {
+        Message {
+            role: MessageRole::Model,
+            content: self.content.clone(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        }
+    }
+}
+
+impl ContextBuilder {
+    /// Append a recovered partial turn as a model message, marking the
+    /// conversation with `GENERATION_INTERRUPTED`
+    pub fn restore_partial(self, turn: PartialTurn) -> Self {
+        self.add_message(turn.to_message())
+            .with_metadata(GENERATION_INTERRUPTED, turn.deltas.to_string())
+    }
+
+    /// `send_streaming_with_callback`, journaling each delta through `writer`
+    /// and finishing its entry once the stream completes
+    pub async fn send_streaming_durable<F>(
+        self,
+        adapter: StreamProviderAdapter,
+        max_tokens: u32,
+        writer: &DurableStreamWriter,
+        callback: F,
+    ) -> Result<UnresolvedResponse, String>
+    where
+        F: Fn(Result<PromptResponseDelta, String>) + Send + Sync + 'static,
+    {
+        let response = self
+            .send_streaming_with_callback(adapter, max_tokens, writer.wrap(callback))
+            .await?;
+        writer.finish()?;
+        Ok(response)
+    }
+}
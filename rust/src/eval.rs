@@ -0,0 +1,370 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+use futures::stream;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::capabilities::Pricing;
+use crate::tokens::{HeuristicTokenCounter, TokenCounter, estimate_prompt_tokens};
+use crate::{
+    ContentType, ContextBuilder, Message, MessageRole, ProviderAdapter, ReproducibilityInfo,
+};
+
+/* ---------------------------------- Cases --------------------------------- */
+
+/// What a case's reply has to satisfy
+#[derive(Clone)]
+pub enum Expectation {
+    /// Every string appears somewhere in the reply
+    ContainsAll(Vec<String>),
+    MatchesRegex(Regex),
+    /// The reply parses as JSON and the field at `path` (dot separated, with
+    /// numeric segments indexing arrays, e.g. `items.0.name`) equals `value`
+    JsonFieldEquals {
+        path: String,
+        value: serde_json::Value,
+    },
+    /// `judge` scores the reply against `rubric` on a 0-10 scale
+    JudgeScoresAtLeast {
+        rubric: String,
+        min: f64,
+        judge: ProviderAdapter,
+    },
+}
+
+/// A recorded conversation and what its next reply should look like
+#[derive(Clone)]
+pub struct EvalCase {
+    pub name: String,
+    pub context: ContextBuilder,
+    pub expected: Expectation,
+}
+
+/// How `run_eval` sends cases
+#[derive(Clone)]
+pub struct EvalOptions {
+    pub max_tokens: u32,
+    /// Cases in flight at once; 1 runs them in order
+    pub concurrency: usize,
+    /// When set, each result carries an estimated cost
+    pub pricing: Option<Pricing>,
+    pub counter: Arc<dyn TokenCounter>,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        EvalOptions {
+            max_tokens: 1024,
+            concurrency: 1,
+            pricing: None,
+            counter: Arc::new(HeuristicTokenCounter),
+        }
+    }
+}
+
+/* --------------------------------- Report --------------------------------- */
+
+/// Outcome of one case
+#[derive(Clone, PartialEq, Debug)]
+pub struct EvalResult {
+    pub name: String,
+    pub passed: bool,
+    /// Why the case failed (or the judge's reasoning when it passed)
+    pub detail: Option<String>,
+    pub reply: String,
+    pub judge_score: Option<f64>,
+    /// Tokens reported by the subject and judge adapters
+    pub token_usage: u32,
+    pub cost: Option<f64>,
+    /// What the adapter reported about reproducing the reply
+    pub reproducibility: Option<ReproducibilityInfo>,
+}
+
+/// A case whose backend changed since a baseline run, see
+/// `EvalReport::reproducibility_drift`
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReproducibilityDrift {
+    pub name: String,
+    pub detail: String,
+}
+
+/// Results of `run_eval`, in case order
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct EvalReport {
+    pub results: Vec<EvalResult>,
+}
+
+impl EvalReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+
+    pub fn failed(&self) -> Vec<&EvalResult> {
+        self.results.iter().filter(|r| !r.passed).collect()
+    }
+
+    pub fn pass_rate(&self) -> f64 {
+        match self.results.len() {
+            0 => 0.0,
+            n => self.passed() as f64 / n as f64,
+        }
+    }
+
+    pub fn total_tokens(&self) -> u32 {
+        self.results.iter().map(|r| r.token_usage).sum()
+    }
+
+    pub fn total_cost(&self) -> Option<f64> {
+        self.results.iter().map(|r| r.cost).sum()
+    }
+
+    /// Cases that claimed determinism in `baseline` but whose backend has
+    /// changed since, e.g. a new `system_fingerprint`. A differing reply
+    /// from one of these is not necessarily a regression. Cases are matched
+    /// by name.
+    pub fn reproducibility_drift(&self, baseline: &EvalReport) -> Vec<ReproducibilityDrift> {
+        self.results
+            .iter()
+            .filter_map(|result| {
+                let before = baseline
+                    .results
+                    .iter()
+                    .find(|b| b.name == result.name)?
+                    .reproducibility
+                    .as_ref()?;
+                let detail = match &result.reproducibility {
+                    Some(after) => after.drift_from(before)?,
+                    None if before.backend_claims_determinism => {
+                        "No reproducibility info reported".to_string()
+                    }
+                    None => return None,
+                };
+                Some(ReproducibilityDrift {
+                    name: result.name.clone(),
+                    detail,
+                })
+            })
+            .collect()
+    }
+}
+
+/* --------------------------------- Judging -------------------------------- */
+
+#[derive(Deserialize)]
+struct JudgeVerdict {
+    score: f64,
+    #[serde(default)]
+    reasoning: String,
+}
+
+const JUDGE_INSTRUCTIONS: &str = "You are grading an assistant's reply against a rubric. \
+Respond with only a JSON object of the form {\"score\": <number from 0 to 10>, \"reasoning\": \"<one sentence>\"}.";
+
+fn judge_context(case: &EvalCase, rubric: &str, reply: &str) -> ContextBuilder {
+    let transcript: String = case
+        .context
+        .history
+        .iter()
+        .map(|msg| format!("{}: {}\n", role_name(&msg.role), msg.content))
+        .collect();
+
+    let message = |role, content: String| Message {
+        role,
+        content,
+        content_type: ContentType::Text,
+        pinned: false,
+        assistant_tool_calls: None,
+    };
+
+    ContextBuilder::new()
+        .add_message(message(MessageRole::System, JUDGE_INSTRUCTIONS.to_string()))
+        .add_message(message(
+            MessageRole::User,
+            format!(
+                "Rubric:\n{}\n\nConversation:\n{}\nReply to grade:\n{}",
+                rubric, transcript, reply
+            ),
+        ))
+}
+
+fn role_name(role: &MessageRole) -> &'static str {
+    match role {
+        MessageRole::User => "User",
+        MessageRole::Model => "Assistant",
+        MessageRole::System => "System",
+        MessageRole::Function | MessageRole::Tool => "Tool",
+    }
+}
+
+/// Pull the verdict object out of the judge's reply, tolerating prose or
+/// code fences around it
+fn parse_verdict(reply: &str) -> Result<JudgeVerdict, String> {
+    let start = reply.find('{');
+    let end = reply.rfind('}');
+    let json = match (start, end) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => return Err(format!("Judge reply has no JSON verdict: {}", reply)),
+    };
+
+    serde_json::from_str(json).map_err(|e| format!("Judge verdict is malformed: {}", e))
+}
+
+/* ------------------------------- Expectations ----------------------------- */
+
+fn json_field<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(value, |value, segment| match value {
+            serde_json::Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+            _ => value.get(segment),
+        })
+}
+
+/// How a reply fared against its expectation
+#[derive(Default)]
+struct Check {
+    passed: bool,
+    detail: Option<String>,
+    judge_score: Option<f64>,
+    judge_tokens: u32,
+}
+
+impl Check {
+    fn pass() -> Self {
+        Check {
+            passed: true,
+            ..Default::default()
+        }
+    }
+
+    fn fail(detail: String) -> Self {
+        Check {
+            detail: Some(detail),
+            ..Default::default()
+        }
+    }
+}
+
+async fn check(case: &EvalCase, reply: &str, options: &EvalOptions) -> Check {
+    match &case.expected {
+        Expectation::ContainsAll(needles) => {
+            let missing: Vec<&str> = needles
+                .iter()
+                .filter(|needle| !reply.contains(needle.as_str()))
+                .map(String::as_str)
+                .collect();
+            match missing.is_empty() {
+                true => Check::pass(),
+                false => Check::fail(format!("Missing: {}", missing.join(", "))),
+            }
+        }
+        Expectation::MatchesRegex(pattern) => match pattern.is_match(reply) {
+            true => Check::pass(),
+            false => Check::fail(format!("Does not match /{}/", pattern.as_str())),
+        },
+        Expectation::JsonFieldEquals { path, value } => {
+            let parsed: serde_json::Value = match serde_json::from_str(reply) {
+                Ok(parsed) => parsed,
+                Err(e) => return Check::fail(format!("Reply is not JSON: {}", e)),
+            };
+            match json_field(&parsed, path) {
+                Some(found) if found == value => Check::pass(),
+                Some(found) => Check::fail(format!("{} is {}, expected {}", path, found, value)),
+                None => Check::fail(format!("{} is missing", path)),
+            }
+        }
+        Expectation::JudgeScoresAtLeast { rubric, min, judge } => {
+            let context = judge_context(case, rubric, reply);
+            let response = match judge(context, options.max_tokens).await {
+                Ok(response) => response,
+                Err(e) => return Check::fail(format!("Judge failed: {}", e)),
+            };
+
+            let check = match parse_verdict(&response.message.content) {
+                Ok(verdict) => Check {
+                    passed: verdict.score >= *min,
+                    detail: Some(match verdict.score >= *min {
+                        true => verdict.reasoning,
+                        false => format!(
+                            "Judge scored {} (< {}): {}",
+                            verdict.score, min, verdict.reasoning
+                        ),
+                    }),
+                    judge_score: Some(verdict.score),
+                    judge_tokens: 0,
+                },
+                Err(e) => Check::fail(e),
+            };
+            Check {
+                judge_tokens: response.token_usage,
+                ..check
+            }
+        }
+    }
+}
+
+/* ----------------------------------- Run ---------------------------------- */
+
+async fn run_case(case: EvalCase, adapter: ProviderAdapter, options: &EvalOptions) -> EvalResult {
+    let response = adapter(case.context.outgoing(), options.max_tokens).await;
+
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            return EvalResult {
+                name: case.name,
+                passed: false,
+                detail: Some(format!("Adapter failed: {}", e)),
+                reply: String::new(),
+                judge_score: None,
+                token_usage: 0,
+                cost: None,
+                reproducibility: None,
+            };
+        }
+    };
+
+    let reproducibility = response.provenance.reproducibility;
+    let reply = response.message.content;
+    let check = check(&case, &reply, options).await;
+
+    let cost = options.pricing.map(|pricing| {
+        let prompt = estimate_prompt_tokens(&case.context, &[], options.counter.as_ref());
+        pricing.cost(prompt as u32, options.counter.count(&reply) as u32)
+    });
+
+    EvalResult {
+        name: case.name,
+        passed: check.passed,
+        detail: check.detail,
+        reply,
+        judge_score: check.judge_score,
+        token_usage: response.token_usage + check.judge_tokens,
+        cost,
+        reproducibility,
+    }
+}
+
+/// ## `run_eval`
+/// Send every case through `adapter`, check the reply against its
+/// expectation and collect the results. Adapter and judge failures fail the
+/// case rather than the run.
+///
+/// ```rust,ignore
+/// let report = run_eval(cases, adapter, &EvalOptions { concurrency: 4, ..Default::default() }).await;
+/// assert!(report.pass_rate() > 0.9, "{:#?}", report.failed());
+/// ```
+pub async fn run_eval(
+    cases: Vec<EvalCase>,
+    adapter: ProviderAdapter,
+    options: &EvalOptions,
+) -> EvalReport {
+    let results = stream::iter(cases)
+        .map(|case| run_case(case, adapter.clone(), options))
+        .buffered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    EvalReport { results }
+}
@@ -0,0 +1,58 @@
+use futures::StreamExt;
+use futures::stream::{self, BoxStream};
+
+use crate::runtime::{self, mpsc};
+use crate::{ContextBuilder, PromptResponseDelta, StreamProviderAdapter};
+
+/* --------------------------------- Fan-out -------------------------------- */
+
+/// ## `fanout_streaming`
+/// Start a streaming send for every context/adapter pair at once, e.g. to
+/// run one benchmark prompt against several models. Pairs are matched by
+/// index; extras on the longer side are ignored.
+///
+/// Each send runs in the background on `runtime::spawn`, so a stream that
+/// isn't being read keeps receiving while the caller reads another. With
+/// `tokio-runtime` this panics when called outside a tokio runtime; without
+/// it, each send gets its own thread.
+///
+/// ```rust,ignore
+/// let streams = fanout_streaming(vec![prompt.clone(), prompt], vec![gpt, llama], 500);
+/// ```
+pub fn fanout_streaming(
+    contexts: Vec<ContextBuilder>,
+    adapters: Vec<StreamProviderAdapter>,
+    max_tokens: u32,
+) -> Vec<BoxStream<'static, Result<PromptResponseDelta, String>>> {
+    contexts
+        .into_iter()
+        .zip(adapters)
+        .map(|(context, adapter)| {
+            let (tx, rx) = mpsc::unbounded();
+            runtime::spawn(async move {
+                let mut deltas = context.send_streaming(adapter, max_tokens);
+                while let Some(delta) = deltas.next().await {
+                    // The caller dropped the stream
+                    if tx.unbounded_send(delta).is_err() {
+                        break;
+                    }
+                }
+            });
+            Box::pin(rx) as BoxStream<'static, Result<PromptResponseDelta, String>>
+        })
+        .collect()
+}
+
+/// `fanout_streaming` as one stream of `(pair index, delta)`, in the order
+/// the deltas arrive
+pub fn fanout_streaming_merged(
+    contexts: Vec<ContextBuilder>,
+    adapters: Vec<StreamProviderAdapter>,
+    max_tokens: u32,
+) -> BoxStream<'static, (usize, Result<PromptResponseDelta, String>)> {
+    let tagged = fanout_streaming(contexts, adapters, max_tokens)
+        .into_iter()
+        .enumerate()
+        .map(|(index, deltas)| deltas.map(move |delta| (index, delta)).boxed());
+    Box::pin(stream::select_all(tagged))
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::budget::TokenBudget;
+use crate::stats::ConversationStats;
+use crate::store::ConversationStore;
+use crate::{ContextBuilder, Message, ProviderAdapter, UnresolvedResponse};
+
+/// Error returned, or panicked with, when a finalized conversation is
+/// changed or sent
+pub const CONVERSATION_FINALIZED: &str = "ConversationFinalized: conversation is read-only";
+
+/// Prompt used to ask `FinalizeOpts::summarizer` for a title and summary
+pub const DEFAULT_SUMMARY_PROMPT: &str =
+    "Give this conversation a short title on the first line, then summarize it in a few sentences.";
+
+/* --------------------------------- Options -------------------------------- */
+
+/// Called once when a conversation is finalized, e.g. to flush a logger or
+/// record usage. An error stops finalization.
+pub type FinalizeHook =
+    Arc<dyn Fn(&ContextBuilder, &FinalReport) -> Result<(), String> + Send + Sync>;
+
+/// What `finalize` does besides marking the conversation read-only
+#[derive(Clone)]
+pub struct FinalizeOpts {
+    /// Save the finalized conversation under this key
+    pub store: Option<(Arc<dyn ConversationStore>, String)>,
+    /// Ask this adapter for a title and summary, with at most `u32` tokens
+    pub summarizer: Option<(ProviderAdapter, u32)>,
+    pub summary_prompt: String,
+    /// Credit tokens reserved for the conversation but not spent back to
+    /// the budget
+    pub release: Option<(Arc<TokenBudget>, u64)>,
+    /// Run in order, after the store is written
+    pub hooks: Vec<FinalizeHook>,
+}
+
+impl Default for FinalizeOpts {
+    fn default() -> Self {
+        FinalizeOpts {
+            store: None,
+            summarizer: None,
+            summary_prompt: DEFAULT_SUMMARY_PROMPT.to_string(),
+            release: None,
+            hooks: vec![],
+        }
+    }
+}
+
+/* --------------------------------- Report --------------------------------- */
+
+/// What `finalize` did, kept with the conversation once it's finalized
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct FinalReport {
+    pub stats: ConversationStats,
+    /// First line of the summarizer's reply
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    /// Key the conversation was saved under
+    pub stored_as: Option<String>,
+    /// Tokens credited back to the budget
+    pub released_tokens: u64,
+}
+
+/* -------------------------------- Finalize -------------------------------- */
+
+impl ContextBuilder {
+    /// ## `finalize`
+    /// End the conversation: optionally summarize it, save it to the store,
+    /// run the hooks and release its budget reservation, then mark it
+    /// read-only. Afterwards every send and edit is refused with
+    /// `CONVERSATION_FINALIZED`: sends that return a `Result` give it as
+    /// their error, streams as their only item, and the rest, like
+    /// `add_message` and `send`, panic with it. `try_add_message` and
+    /// `try_send` are the non-panicking forms.
+    ///
+    /// Finalizing again is a no-op that returns the same report. If any step
+    /// fails the conversation stays open, so the call can be retried; steps
+    /// already done run again then.
+    ///
+    /// ```rust,ignore
+    /// let report = context
+    ///     .finalize(FinalizeOpts {
+    ///         store: Some((store, conversation_id)),
+    ///         hooks: vec![flush_logger],
+    ///         ..FinalizeOpts::default()
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn finalize(&mut self, opts: FinalizeOpts) -> Result<FinalReport, String> {
+        if let Some(report) = &self.finalized {
+            return Ok(report.clone());
+        }
+
+        let mut report = FinalReport {
+            stats: self.stats(),
+            ..FinalReport::default()
+        };
+        if let Some((summarizer, max_tokens)) = opts.summarizer {
+            let reply = self
+                .to_summary_string(summarizer, max_tokens, &opts.summary_prompt)
+                .await?;
+            let (title, summary) = match reply.trim().split_once('\n') {
+                Some((title, summary)) => (title.trim(), summary.trim()),
+                None => (reply.trim(), ""),
+            };
+            report.title = Some(title.to_string()).filter(|t| !t.is_empty());
+            report.summary = Some(summary.to_string()).filter(|s| !s.is_empty());
+        }
+        if let Some((_, tokens)) = &opts.release {
+            report.released_tokens = *tokens;
+        }
+
+        if let Some((_, key)) = &opts.store {
+            report.stored_as = Some(key.clone());
+        }
+
+        // The stored copy is already finalized, so it loads read-only
+        let mut finalized = self.clone();
+        finalized.finalized = Some(report.clone());
+        if let Some((store, key)) = &opts.store {
+            store.save(key, &finalized)?;
+        }
+        for hook in &opts.hooks {
+            hook(&finalized, &report)?;
+        }
+        if let Some((budget, tokens)) = &opts.release {
+            budget.credit(*tokens);
+        }
+
+        self.finalized = Some(report.clone());
+        Ok(report)
+    }
+
+    /// `add_message`, returning `CONVERSATION_FINALIZED` instead of
+    /// panicking on a finalized conversation
+    pub fn try_add_message(self, msg: Message) -> Result<Self, String> {
+        self.check_open()?;
+        Ok(self.add_message(msg))
+    }
+
+    /// `send`, returning `CONVERSATION_FINALIZED` instead of panicking on a
+    /// finalized conversation
+    pub async fn try_send(
+        self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+    ) -> Result<UnresolvedResponse, String> {
+        self.check_open()?;
+        Ok(self.send(adapter, max_tokens).await)
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.finalized.is_some()
+    }
+
+    /// The report from `finalize`, once the conversation is finalized
+    pub fn final_report(&self) -> Option<&FinalReport> {
+        self.finalized.as_ref()
+    }
+
+    /// `CONVERSATION_FINALIZED` if the conversation is read-only. Every send
+    /// and edit checks this first; the fallible ones return the error.
+    pub(crate) fn check_open(&self) -> Result<(), String> {
+        match self.is_finalized() {
+            true => Err(CONVERSATION_FINALIZED.to_string()),
+            false => Ok(()),
+        }
+    }
+
+    /// Panics if the conversation is read-only, for the builder methods that
+    /// can't return an error
+    pub(crate) fn assert_open(&self) {
+        if let Err(e) = self.check_open() {
+            panic!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use crate::capabilities::Capabilities;
+use crate::tokens::{TokenCounter, estimate_prompt_tokens};
+use crate::{ContextBuilder, ProviderAdapter, ToolDescriptor};
+
+/* -------------------------------- Headroom -------------------------------- */
+
+/// How much of the model's context a send would use, from
+/// `ContextBuilder::headroom`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Headroom {
+    /// Estimated prompt size: every message, the system prompt included, plus
+    /// the tool descriptors sent with it
+    pub prompt_tokens: usize,
+    pub context_length: usize,
+    pub reserved_for_completion: usize,
+    /// Tokens left for more history once the prompt and the completion are in
+    pub remaining: usize,
+    /// The prompt and completion don't both fit, so `truncate_to_fit` with
+    /// `prompt_budget` would drop messages
+    pub would_truncate: bool,
+}
+
+impl Headroom {
+    /// Tokens the prompt may use without cutting into the completion
+    pub fn prompt_budget(&self) -> usize {
+        self.context_length
+            .saturating_sub(self.reserved_for_completion)
+    }
+}
+
+impl ContextBuilder {
+    /// ## `headroom`
+    /// Estimate how much room is left in `capabilities.context_length` after
+    /// this context, `tools` and a completion of `reserved_for_completion`
+    /// tokens, e.g. to show "~3,200 tokens left" before the user sends.
+    ///
+    /// ```rust,ignore
+    /// let headroom = context.headroom(&caps, &tools, &HeuristicTokenCounter, 1024);
+    /// if headroom.would_truncate {
+    ///     context = context.truncate_to_fit(headroom.prompt_budget(), &HeuristicTokenCounter)?;
+    /// }
+    /// ```
+    pub fn headroom(
+        &self,
+        capabilities: &Capabilities,
+        tools: &[ToolDescriptor],
+        counter: &dyn TokenCounter,
+        reserved_for_completion: u32,
+    ) -> Headroom {
+        let prompt_tokens = estimate_prompt_tokens(self, tools, counter);
+        let context_length = capabilities.context_length as usize;
+        let reserved_for_completion = reserved_for_completion as usize;
+        let used = prompt_tokens + reserved_for_completion;
+
+        Headroom {
+            prompt_tokens,
+            context_length,
+            reserved_for_completion,
+            remaining: context_length.saturating_sub(used),
+            would_truncate: used > context_length,
+        }
+    }
+}
+
+/* -------------------------------- Warnings -------------------------------- */
+
+/// Emitted before a send whose remaining headroom is under `threshold`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HeadroomWarning {
+    pub headroom: Headroom,
+    pub threshold: usize,
+}
+
+/// Receives every `HeadroomWarning` from `headroom_warning_adapter`
+pub type HeadroomWarningSink = Arc<dyn Fn(HeadroomWarning) + Send + Sync>;
+
+/// ## `headroom_warning_adapter`
+/// Wrap `adapter` so each send first measures its headroom, reserving the
+/// send's `max_tokens` for the completion, and reports a `HeadroomWarning`
+/// to `sink` when fewer than `threshold` tokens would be left. The request
+/// is sent either way.
+///
+/// ```rust,ignore
+/// let adapter = headroom_warning_adapter(adapter, caps, tools, Arc::new(HeuristicTokenCounter), 2000,
+///     Arc::new(|warning| ui.notify(format!("~{} tokens left", warning.headroom.remaining))));
+/// ```
+pub fn headroom_warning_adapter(
+    adapter: ProviderAdapter,
+    capabilities: Capabilities,
+    tools: Vec<ToolDescriptor>,
+    counter: Arc<dyn TokenCounter>,
+    threshold: usize,
+    sink: HeadroomWarningSink,
+) -> ProviderAdapter {
+    Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        let headroom = context.headroom(&capabilities, &tools, counter.as_ref(), max_tokens);
+        if headroom.remaining < threshold {
+            sink(HeadroomWarning {
+                headroom,
+                threshold,
+            });
+        }
+        adapter(context, max_tokens)
+    })
+}
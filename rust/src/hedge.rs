@@ -0,0 +1,327 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::{Either, select};
+use futures::stream::{self, BoxStream, StreamExt};
+
+use crate::runtime::sleep;
+use crate::tokens::{HeuristicTokenCounter, estimate_prompt_tokens};
+use crate::{ContextBuilder, PromptResponseDelta, ProviderAdapter, StreamProviderAdapter};
+
+/// Provenance source for whether a hedge fired and which backend won
+pub const HEDGE: &str = "hedge";
+
+/* -------------------------------- Counters -------------------------------- */
+
+/// Which adapter of a hedged pair answered
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backend {
+    Primary,
+    Secondary,
+}
+
+impl Backend {
+    fn label(self) -> &'static str {
+        match self {
+            Backend::Primary => "primary",
+            Backend::Secondary => "secondary",
+        }
+    }
+}
+
+/// Outcomes and usage of a hedged adapter
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct HedgeStats {
+    pub requests: u64,
+    /// Requests where the secondary was started
+    pub hedges_fired: u64,
+    pub primary_wins: u64,
+    pub secondary_wins: u64,
+    /// Requests where every backend that was started failed
+    pub errors: u64,
+    /// Tokens reported by winning backends only
+    pub token_usage: u64,
+    /// Estimated cost of cancelled losers: their prompt, plus whatever a
+    /// losing stream reported before it was dropped
+    pub wasted_tokens: u64,
+}
+
+/// Counters shared by a hedged adapter and its caller. Clones share the
+/// same counts.
+#[derive(Clone, Default)]
+pub struct HedgeCounters(Arc<Mutex<HedgeStats>>);
+
+impl HedgeCounters {
+    pub fn snapshot(&self) -> HedgeStats {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut HedgeStats)) {
+        f(&mut self.0.lock().unwrap())
+    }
+
+    fn record(&self, fired: bool, winner: Option<Backend>, wasted_tokens: u64) {
+        self.update(|stats| {
+            stats.requests += 1;
+            stats.hedges_fired += u64::from(fired);
+            match winner {
+                Some(Backend::Primary) => stats.primary_wins += 1,
+                Some(Backend::Secondary) => stats.secondary_wins += 1,
+                None => stats.errors += 1,
+            }
+            stats.wasted_tokens += wasted_tokens;
+        });
+    }
+}
+
+/// A hedged adapter and the counters it updates
+#[derive(Clone)]
+pub struct Hedged<A> {
+    pub adapter: A,
+    pub counters: HedgeCounters,
+}
+
+fn provenance_detail(fired: bool, winner: Backend, hedge_after: Duration) -> String {
+    match fired {
+        true => format!("fired after {:?}; {} won", hedge_after, winner.label()),
+        false => format!("not fired; {} won", winner.label()),
+    }
+}
+
+fn prompt_cost(context: &ContextBuilder) -> u64 {
+    estimate_prompt_tokens(context, &[], &HeuristicTokenCounter) as u64
+}
+
+/* -------------------------------- Requests -------------------------------- */
+
+/// ## `hedged_adapter`
+/// Send to `primary`, and if it hasn't answered within `hedge_after` send
+/// the same request to `secondary` too, e.g. to hold a latency SLO on
+/// user-facing turns. The first successful response wins and the other
+/// request is cancelled; if one fails, the other is awaited. Whether the
+/// hedge fired and who won is recorded in the response provenance under
+/// `"hedge"`, and usage in the returned counters.
+///
+/// ```rust,ignore
+/// let hedged = hedged_adapter(gpt4o, claude, Duration::from_millis(800));
+/// let response = context.send(hedged.adapter.clone(), 500).await;
+/// ```
+pub fn hedged_adapter(
+    primary: ProviderAdapter,
+    secondary: ProviderAdapter,
+    hedge_after: Duration,
+) -> Hedged<ProviderAdapter> {
+    let counters = HedgeCounters::default();
+
+    let adapter_counters = counters.clone();
+    let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        let primary_call = primary(context.clone(), max_tokens);
+        let secondary = secondary.clone();
+        let counters = adapter_counters.clone();
+
+        Box::pin(async move {
+            let timer = Box::pin(sleep(hedge_after));
+            let primary_call = match select(primary_call, timer).await {
+                Either::Left((result, _)) => {
+                    let winner = result.is_ok().then_some(Backend::Primary);
+                    counters.record(false, winner, 0);
+                    let mut response = result?;
+                    response.provenance.record(
+                        HEDGE,
+                        provenance_detail(false, Backend::Primary, hedge_after),
+                    );
+                    counters.update(|stats| stats.token_usage += response.token_usage as u64);
+                    return Ok(response);
+                }
+                Either::Right((_, primary_call)) => primary_call,
+            };
+
+            // Only a loser that's cancelled mid-request is wasted
+            let prompt = prompt_cost(&context);
+            let secondary_call = secondary(context, max_tokens);
+            let (winner, result, wasted) = match select(primary_call, secondary_call).await {
+                Either::Left((Ok(response), _)) => (Backend::Primary, Ok(response), prompt),
+                Either::Right((Ok(response), _)) => (Backend::Secondary, Ok(response), prompt),
+                Either::Left((Err(_), secondary_call)) => {
+                    (Backend::Secondary, secondary_call.await, 0)
+                }
+                Either::Right((Err(_), primary_call)) => (Backend::Primary, primary_call.await, 0),
+            };
+
+            let mut response = match result {
+                Ok(response) => response,
+                Err(e) => {
+                    counters.record(true, None, 0);
+                    return Err(e);
+                }
+            };
+            counters.record(true, Some(winner), wasted);
+            counters.update(|stats| stats.token_usage += response.token_usage as u64);
+            response
+                .provenance
+                .record(HEDGE, provenance_detail(true, winner, hedge_after));
+            Ok(response)
+        })
+    });
+
+    Hedged { adapter, counters }
+}
+
+/* -------------------------------- Streaming ------------------------------- */
+
+type DeltaStream = BoxStream<'static, Result<PromptResponseDelta, String>>;
+
+/// A stream that has started, with the deltas it sent before winning
+struct Racer {
+    backend: Backend,
+    deltas: DeltaStream,
+    buffered: Vec<Result<PromptResponseDelta, String>>,
+    tokens: u32,
+}
+
+/// What a racer's next delta means for the race
+enum Step {
+    /// Sent content, or ended without any
+    Won,
+    Failed,
+    /// Only empty deltas so far
+    Pending,
+}
+
+impl Racer {
+    fn new(backend: Backend, deltas: DeltaStream) -> Self {
+        Racer {
+            backend,
+            deltas,
+            buffered: vec![],
+            tokens: 0,
+        }
+    }
+
+    fn take(&mut self, next: Option<Result<PromptResponseDelta, String>>) -> Step {
+        match next {
+            None => Step::Won,
+            Some(Err(e)) => {
+                self.buffered.push(Err(e));
+                Step::Failed
+            }
+            Some(Ok(delta)) => {
+                self.tokens = self.tokens.max(delta.cumulative_tokens);
+                let has_content = !delta.content.is_empty();
+                self.buffered.push(Ok(delta));
+                match has_content {
+                    true => Step::Won,
+                    false => Step::Pending,
+                }
+            }
+        }
+    }
+
+    /// The buffered deltas followed by the rest of the stream, adding the
+    /// usage it reports to `counters`
+    fn into_stream(self, counters: HedgeCounters) -> DeltaStream {
+        let mut counted = self.tokens;
+        counters.update(|stats| stats.token_usage += counted as u64);
+        Box::pin(
+            stream::iter(self.buffered).chain(self.deltas.inspect(move |delta| {
+                if let Ok(delta) = delta
+                    && delta.cumulative_tokens > counted
+                {
+                    let added = delta.cumulative_tokens - counted;
+                    counted = delta.cumulative_tokens;
+                    counters.update(|stats| stats.token_usage += added as u64);
+                }
+            })),
+        )
+    }
+}
+
+/// ## `hedged_streaming_adapter`
+/// `hedged_adapter` for streams: if `primary` hasn't sent any content within
+/// `hedge_after`, `secondary` is streamed too and the first to send content
+/// wins. The loser's stream is dropped, cancelling it. Deltas carry no
+/// provenance, so outcomes are only in the returned counters.
+pub fn hedged_streaming_adapter(
+    primary: StreamProviderAdapter,
+    secondary: StreamProviderAdapter,
+    hedge_after: Duration,
+) -> Hedged<StreamProviderAdapter> {
+    let counters = HedgeCounters::default();
+
+    let adapter_counters = counters.clone();
+    let adapter: StreamProviderAdapter =
+        Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+            let mut first = Racer::new(Backend::Primary, primary(context.clone(), max_tokens));
+            let secondary = secondary.clone();
+            let counters = adapter_counters.clone();
+
+            let race = async move {
+                let mut timer = Box::pin(sleep(hedge_after));
+                while let Either::Left((next, _)) = select(first.deltas.next(), &mut timer).await {
+                    match first.take(next) {
+                        Step::Pending => continue,
+                        step => {
+                            let winner = matches!(step, Step::Won).then_some(first.backend);
+                            counters.record(false, winner, 0);
+                            return first.into_stream(counters);
+                        }
+                    }
+                }
+
+                let wasted = prompt_cost(&context);
+                let mut second = Racer::new(Backend::Secondary, secondary(context, max_tokens));
+                loop {
+                    let (step, racer, other) =
+                        match select(first.deltas.next(), second.deltas.next()).await {
+                            Either::Left((next, _)) => (first.take(next), &mut first, &mut second),
+                            Either::Right((next, _)) => {
+                                (second.take(next), &mut second, &mut first)
+                            }
+                        };
+                    match step {
+                        Step::Pending => continue,
+                        Step::Won => {
+                            let wasted = wasted + other.tokens as u64;
+                            counters.record(true, Some(racer.backend), wasted);
+                            let winner = std::mem::replace(
+                                racer,
+                                Racer::new(racer.backend, Box::pin(stream::empty())),
+                            );
+                            return winner.into_stream(counters);
+                        }
+                        // Leave the other to finish alone, errors and all
+                        Step::Failed => {
+                            let survivor = std::mem::replace(
+                                other,
+                                Racer::new(other.backend, Box::pin(stream::empty())),
+                            );
+                            return finish_alone(survivor, counters).await;
+                        }
+                    }
+                }
+            };
+
+            Box::pin(stream::once(race).flatten()) as DeltaStream
+        });
+
+    Hedged { adapter, counters }
+}
+
+/// Stream `racer` once its rival failed: it wins if it sends content or
+/// ends, and the request fails if it errors too
+async fn finish_alone(mut racer: Racer, counters: HedgeCounters) -> DeltaStream {
+    loop {
+        let next = racer.deltas.next().await;
+        match racer.take(next) {
+            Step::Pending => continue,
+            Step::Won => {
+                counters.record(true, Some(racer.backend), 0);
+                return racer.into_stream(counters);
+            }
+            Step::Failed => {
+                counters.record(true, None, 0);
+                return racer.into_stream(counters);
+            }
+        }
+    }
+}
@@ -7,23 +7,83 @@
 /* -------------------------------------------------------------------------- */
 
 /* ------------------------------ Dependencies ------------------------------ */
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Instant;
 
 use futures::StreamExt;
-use futures::stream::BoxStream;
+use futures::future::join_all;
+use futures::stream::{self, BoxStream};
 use serde::{Deserialize, Serialize};
 
 /* -------------------------------- Features -------------------------------- */
 
 pub mod providers {
+    pub mod anthropic;
+    pub mod mock;
     #[cfg(feature = "ollama")]
     pub mod ollama;
     #[cfg(feature = "openai")]
     pub mod openai;
+    #[cfg(feature = "openai")]
+    pub mod openai_images;
 }
 
+#[cfg(feature = "macros")]
+pub use steelwool_macros::{Tool, tool};
+
+/// Re-exported for code generated by `#[derive(Tool)]`
+#[doc(hidden)]
+pub use serde_json;
+
+/* --------------------------------- Modules -------------------------------- */
+
+pub mod ab_test;
+pub mod anonymize;
+pub mod ask_user;
+pub mod batch;
+pub mod branches;
+pub mod budget;
+pub mod capabilities;
+pub mod coalesce;
+#[cfg(feature = "tokio-runtime")]
+pub mod compactor;
+pub mod constraints;
+pub mod dedup;
+pub mod delta;
+pub mod determinism;
+pub mod diff;
+pub mod dry_run;
+pub mod durable;
+pub mod eval;
+pub mod fanout;
+pub mod finalize;
+pub mod headroom;
+pub mod hedge;
+pub mod persistence;
+pub mod pinning;
+pub mod postprocess;
+pub mod rag;
+pub mod refusal;
+pub mod resilience;
+pub mod response_filter;
+pub mod runtime;
+pub mod sandbox;
+pub mod scratchpad;
+pub mod simulation;
+pub mod sink;
+pub mod stats;
+pub mod store;
+pub mod summarize;
+pub mod tenant;
+pub mod tokens;
+pub mod tool_gc;
+pub mod tool_loop;
+pub mod web;
+
 /* ------------------------------- Signatures ------------------------------- */
 
 /// ## `PromptFuture`
@@ -79,6 +139,11 @@ pub type ToolExecuter = Arc<
     dyn Fn(ToolCall) -> Pin<Box<dyn Future<Output = Result<String, String>> + Send>> + Send + Sync,
 >;
 
+/// ## `ToolCallLogSink`
+/// Receives a `ToolCallLog` for every tool call run by `exec_tool_calls_with_log`,
+/// e.g. to write an audit trail
+pub type ToolCallLogSink = Arc<dyn Fn(ToolCallLog) + Send + Sync>;
+
 /* ------------------------------ Data Structs ------------------------------ */
 
 // Message
@@ -88,8 +153,15 @@ pub type ToolExecuter = Arc<
 pub struct Message {
     pub role: MessageRole,
     pub content: String,
+    #[serde(default)]
     pub content_type: ContentType,
-    // pub tool_results: Option<Vec<ToolResult>>,
+    /// Survives truncation, compaction and spilling to a store
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Tool calls a model turn made alongside its content, sent back to
+    /// providers that expect them on the assistant message (OpenAI)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assistant_tool_calls: Option<Vec<ToolCall>>,
 }
 
 // Responses
@@ -101,6 +173,128 @@ pub struct PromptResponse {
     pub stop_reason: StopReason,
     pub token_usage: u32,
     pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default)]
+    pub provenance: Provenance,
+    /// Per-token log probabilities, when the adapter was asked for them
+    #[serde(default)]
+    pub logprobs: Option<Vec<TokenLogprob>>,
+}
+
+/// Log probability of one generated token and the likeliest alternatives
+/// at its position
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    pub top_alternatives: Vec<(String, f32)>,
+}
+
+impl PromptResponse {
+    /// Average log probability of the generated tokens, if logprobs were returned
+    pub fn mean_token_logprob(&self) -> Option<f32> {
+        let logprobs = self.logprobs.as_ref().filter(|l| !l.is_empty())?;
+        Some(logprobs.iter().map(|t| t.logprob).sum::<f32>() / logprobs.len() as f32)
+    }
+
+    /// Byte ranges of `message.content` covered by tokens below `threshold`,
+    /// with adjacent tokens merged. Tokens are aligned to the end of the
+    /// content, so a prepended prefill doesn't shift them.
+    pub fn low_confidence_spans(&self, threshold: f32) -> Vec<std::ops::Range<usize>> {
+        let Some(logprobs) = &self.logprobs else {
+            return vec![];
+        };
+        let generated: usize = logprobs.iter().map(|t| t.token.len()).sum();
+        let mut offset = self.message.content.len().saturating_sub(generated);
+
+        let mut spans: Vec<std::ops::Range<usize>> = vec![];
+        for token in logprobs {
+            let range = offset..offset + token.token.len();
+            offset = range.end;
+            if token.logprob >= threshold {
+                continue;
+            }
+            match spans.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => spans.push(range),
+            }
+        }
+        spans
+    }
+}
+
+/// Record of how a response was produced, added to by wrapping adapters
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Provenance {
+    pub notes: Vec<ProvenanceNote>,
+    /// Set by adapters that know how their backend handles `params.seed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reproducibility: Option<ReproducibilityInfo>,
+}
+
+/// A single provenance entry; `source` names the component that wrote it
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProvenanceNote {
+    pub source: String,
+    pub detail: String,
+}
+
+/// How far the backend says a response can be reproduced
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct ReproducibilityInfo {
+    /// The seed sent with the request, if the backend takes one
+    pub seed_sent: Option<u64>,
+    /// Backend build or configuration the response came from, e.g. OpenAI's
+    /// `system_fingerprint`
+    pub fingerprint: Option<String>,
+    /// The backend claims the same request and seed give the same response.
+    /// Backends that ignore seeds report `false`.
+    pub backend_claims_determinism: bool,
+}
+
+impl ReproducibilityInfo {
+    /// Why a response with this info might not reproduce one recorded with
+    /// `baseline`, or `None` if nothing changed underneath it. Only checked
+    /// when the baseline claimed determinism.
+    pub fn drift_from(&self, baseline: &ReproducibilityInfo) -> Option<String> {
+        if !baseline.backend_claims_determinism {
+            return None;
+        }
+        if !self.backend_claims_determinism {
+            return Some("Backend no longer claims determinism".to_string());
+        }
+        if self.seed_sent != baseline.seed_sent {
+            return Some(format!(
+                "Seed changed from {:?} to {:?}",
+                baseline.seed_sent, self.seed_sent
+            ));
+        }
+        if self.fingerprint != baseline.fingerprint {
+            return Some(format!(
+                "Backend fingerprint changed from {} to {}",
+                baseline.fingerprint.as_deref().unwrap_or("none"),
+                self.fingerprint.as_deref().unwrap_or("none")
+            ));
+        }
+        None
+    }
+}
+
+impl Provenance {
+    pub fn record(&mut self, source: &str, detail: impl Into<String>) {
+        self.notes.push(ProvenanceNote {
+            source: source.to_string(),
+            detail: detail.into(),
+        });
+    }
+
+    /// Details recorded by `source`, in order
+    pub fn details_from(&self, source: &str) -> Vec<&str> {
+        self.notes
+            .iter()
+            .filter(|note| note.source == source)
+            .map(|note| note.detail.as_str())
+            .collect()
+    }
 }
 
 /// Prompt response delta for streaming
@@ -110,6 +304,12 @@ pub struct PromptResponseDelta {
     pub stop_reason: Option<StopReason>,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub cumulative_tokens: u32,
+    /// Tokens added since the previous delta
+    #[serde(default)]
+    pub token_delta: u32,
+    /// Log probabilities of the tokens in `content`, when requested
+    #[serde(default)]
+    pub logprobs: Option<Vec<TokenLogprob>>,
 }
 
 // Tools
@@ -123,19 +323,142 @@ pub struct ToolDescriptor {
     pub required: bool,
 }
 
+impl ToolDescriptor {
+    /// A tool described by an existing JSON Schema for its arguments, e.g.
+    /// one taken from an OpenAPI spec. The tool isn't `required`.
+    pub fn from_json_schema(name: String, description: String, schema: serde_json::Value) -> Self {
+        ToolDescriptor {
+            name,
+            description,
+            schema,
+            required: false,
+        }
+    }
+}
+
+/// ## `IntoTool`
+/// Implemented by the argument types of tools, so the type can describe
+/// the tool it's parsed from. With the `macros` feature, `#[derive(Tool)]`
+/// implements it from the fields and doc comments.
+pub trait IntoTool {
+    fn tool_descriptor() -> ToolDescriptor;
+}
+
 /// Describes a parsed tool-call
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct ToolCall {
     pub id: String,
     pub name: String,
     pub arguments: serde_json::Value,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+impl ToolCall {
+    /// The arguments as a JSON value. Providers that send arguments as a
+    /// JSON string (OpenAI does) have it parsed.
+    pub fn parsed_arguments(&self) -> Result<serde_json::Value, String> {
+        match &self.arguments {
+            serde_json::Value::String(raw) => serde_json::from_str(raw)
+                .map_err(|e| format!("Malformed arguments for {}: {}", self.name, e)),
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// The string argument `key`
+    pub fn arg_str(&self, key: &str) -> Result<String, String> {
+        self.arg(key, "a string", |value| value.as_str().map(str::to_string))
+    }
+
+    /// The integer argument `key`. Floats with a fractional part are rejected.
+    pub fn arg_i64(&self, key: &str) -> Result<i64, String> {
+        self.arg(key, "an integer", serde_json::Value::as_i64)
+    }
+
+    pub fn arg_bool(&self, key: &str) -> Result<bool, String> {
+        self.arg(key, "a boolean", serde_json::Value::as_bool)
+    }
+
+    fn arg<T>(
+        &self,
+        key: &str,
+        expected: &str,
+        read: impl Fn(&serde_json::Value) -> Option<T>,
+    ) -> Result<T, String> {
+        let arguments = self.parsed_arguments()?;
+        let value = arguments
+            .get(key)
+            .ok_or_else(|| format!("Missing argument {} for {}", key, self.name))?;
+        read(value).ok_or_else(|| {
+            format!(
+                "Argument {} for {} should be {}, got {}",
+                key, self.name, expected, value
+            )
+        })
+    }
+}
+
+/// Marks a run of messages that were flushed to a backing store under `key`
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ArchivedRange {
+    pub key: String,
+    pub len: usize,
+}
+
+/// Per-request settings that travel with a context to its adapter
+#[derive(Clone, Default, PartialEq)]
+pub struct RequestParams {
+    /// Text the assistant turn starts with, e.g. `"{"` to force JSON.
+    /// Adapters that support it continue from the prefill; the send methods
+    /// prepend it to the returned content either way.
+    pub prefill: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// Ask the backend for a single JSON object (OpenAI `response_format:
+    /// json_object`, Ollama `format: json`)
+    pub json_mode: bool,
+    /// Sent in place of the context's system messages, leaving its history
+    /// alone
+    pub system_prompt: Option<String>,
+    /// Sampling seed, for backends that take one. Adapters report what
+    /// they did with it in `Provenance::reproducibility`.
+    pub seed: Option<u64>,
+    /// Always send, even if a `coalescing_adapter` has an identical request
+    /// in flight
+    pub skip_coalescing: bool,
+}
+
+impl RequestParams {
+    /// `self` with any unset fields taken from `base`
+    pub fn over(self, base: &RequestParams) -> RequestParams {
+        RequestParams {
+            prefill: self.prefill.or_else(|| base.prefill.clone()),
+            temperature: self.temperature.or(base.temperature),
+            top_p: self.top_p.or(base.top_p),
+            json_mode: self.json_mode || base.json_mode,
+            system_prompt: self.system_prompt.or_else(|| base.system_prompt.clone()),
+            seed: self.seed.or(base.seed),
+            skip_coalescing: self.skip_coalescing || base.skip_coalescing,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct ToolResult {
     pub tool_call_id: String,
     pub result: String,
     pub error: bool,
+    /// Made up by a `simulated_executer` rather than returned by the tool
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub simulated: bool,
+}
+
+/// Audit record of one executed tool call
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ToolCallLog {
+    pub call_id: String,
+    pub tool_name: String,
+    pub arguments: serde_json::Value,
+    pub result: Result<String, String>,
+    pub duration_ms: u64,
 }
 
 /* ---------------------------------- Enums --------------------------------- */
@@ -148,17 +471,32 @@ pub enum MessageRole {
     System,
     Tool,
 }
-#[derive(PartialEq, Clone, Deserialize, Serialize)]
+#[derive(PartialEq, Clone, Default, Deserialize, Serialize)]
 pub enum ContentType {
+    #[default]
     Text,
+    /// Placeholder for messages spilled to a `ConversationStore`
+    ArchivedRange(ArchivedRange),
+    /// Model turn calling a tool; `content` holds the Anthropic `tool_use`
+    /// block for it
+    ToolUse(ToolCall),
+    /// Result of the tool call with `tool_call_id`; `content` holds the
+    /// result text
+    ToolResult(ToolResult),
 }
 #[derive(PartialEq, Clone, Deserialize, Serialize)]
 pub enum StopReason {
     Stop,
     Length,
     ContentFilter,
+    /// The model declined to answer; `content` holds its refusal
+    Refusal,
     ToolCalls,
     Null,
+    /// Cut off by a `TokenBudget` rather than by the provider
+    BudgetExhausted,
+    /// The stream failed before the provider finished, see `StreamFailure`
+    Interrupted,
 }
 
 /* ----------------------------- ContextBuilder ----------------------------- */
@@ -184,15 +522,97 @@ pub enum StopReason {
 ///
 /// - `transform_with`: Applies a custom transformation function to the builder
 /// - `add_message`: Adds a message to the context's history
+/// - `from_plain_text`: Builds a context from paragraphs or a `User:`/`Assistant:` transcript
 /// - `send`: Sends the context to an LLM and returns an `UnresolvedResponse`
+/// - `with_prefill`: Starts the next assistant turn with the given text
+/// - `send_tool_loop`: Sends and runs tools until the model answers
+/// - `send_n`/`send_n_and_pick`: Concurrent sends, optionally picking the best reply
 /// - `send_streaming`: Sends the context and returns a stream of response deltas
 /// - `send_streaming_with_callback`: Streams with a callback for each delta
-#[derive(Serialize, Deserialize, Clone)]
+/// - `with_backing_store`/`hydrate`: Spill old history to a `ConversationStore`
+/// - `with_rolling_summary_window`: Summarize old history before each send
+/// - `save_branch`/`checkout_branch`/`regenerate_from`: Edit and branch history
+/// - `anonymize`/`deanonymize`: Swap sensitive text for reversible placeholders
+/// - `finalize`: End the conversation and make it read-only
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct ContextBuilder {
     pub history: Vec<Message>,
+    #[serde(skip)]
+    pub(crate) backing_store: Option<store::BackingStore>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) branches: BTreeMap<String, Vec<Message>>,
+    #[serde(skip)]
+    pub params: RequestParams,
+    /// Free-form tags kept with the conversation, e.g. `conversation_id`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// State shared between steps and tools, kept out of prompts
+    #[serde(default, skip_serializing_if = "scratchpad::Scratchpad::is_empty")]
+    pub scratchpad: scratchpad::Scratchpad,
+    /// Set by `finalize`; a finalized conversation is read-only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) finalized: Option<finalize::FinalReport>,
+    /// Set by `with_rolling_summary_window`
+    #[serde(skip)]
+    pub summarize_strategy: Option<summarize::SummarizeStrategy>,
 }
 
 impl ContextBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Import an unstructured document: each paragraph (separated by blank
+    /// lines) becomes a text message with `default_role`. A line starting
+    /// with `User:`, `Assistant:` or `System:` starts a message with that
+    /// role instead, running until the next such line or blank line.
+    pub fn from_plain_text(text: &str, default_role: MessageRole) -> Self {
+        const PREFIXES: [(&str, MessageRole); 3] = [
+            ("User:", MessageRole::User),
+            ("Assistant:", MessageRole::Model),
+            ("System:", MessageRole::System),
+        ];
+
+        let mut context = Self::new();
+        let mut current: Option<(MessageRole, Vec<&str>)> = None;
+        let flush = |context: &mut Self, current: &mut Option<(MessageRole, Vec<&str>)>| {
+            if let Some((role, lines)) = current.take() {
+                let content = lines.join("\n").trim().to_string();
+                if !content.is_empty() {
+                    context.history.push(Message {
+                        role,
+                        content,
+                        content_type: ContentType::Text,
+                        pinned: false,
+                        assistant_tool_calls: None,
+                    });
+                }
+            }
+        };
+
+        for line in text.lines() {
+            if line.trim().is_empty() {
+                flush(&mut context, &mut current);
+                continue;
+            }
+
+            let prefixed = PREFIXES
+                .iter()
+                .find_map(|(prefix, role)| Some((role.clone(), line.strip_prefix(prefix)?)));
+            match (prefixed, &mut current) {
+                (Some((role, rest)), _) => {
+                    flush(&mut context, &mut current);
+                    current = Some((role, vec![rest]));
+                }
+                (None, Some((_, lines))) => lines.push(line),
+                (None, None) => current = Some((default_role.clone(), vec![line])),
+            }
+        }
+        flush(&mut context, &mut current);
+
+        context
+    }
+
     pub fn transform_with<F>(self, transformer: F) -> Self
     where
         F: FnOnce(Self) -> Self, // pass ownership down the chain
@@ -200,49 +620,665 @@ impl ContextBuilder {
         transformer(self)
     }
 
-    pub fn add_message(mut self, msg: Message) -> Self {
+    /// Append `msg`. System messages are pinned as they're added.
+    pub fn add_message(mut self, mut msg: Message) -> Self {
+        self.assert_open();
+        if msg.role == MessageRole::System {
+            msg.pinned = true;
+        }
         self.history.push(msg);
+        self.spill_to_store()
+    }
+
+    /// ## `add_tool_use_message`
+    /// Add the model turn that made `tool_call`, with its content as an
+    /// Anthropic `tool_use` block. Anthropic expects this turn right before
+    /// the tool's result in multi-turn tool use.
+    ///
+    /// ```rust,ignore
+    /// let context = context.add_tool_use_message(call.clone()).add_message(result);
+    /// ```
+    pub fn add_tool_use_message(self, tool_call: ToolCall) -> Self {
+        let input = tool_call
+            .parsed_arguments()
+            .unwrap_or_else(|_| tool_call.arguments.clone());
+        let block = serde_json::json!({
+            "type": "tool_use",
+            "id": tool_call.id,
+            "name": tool_call.name,
+            "input": input,
+        });
+
+        self.add_message(Message {
+            role: MessageRole::Model,
+            content: block.to_string(),
+            content_type: ContentType::ToolUse(tool_call),
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    /// Add the result of the tool call `tool_call_id` as a tool message.
+    /// `is_error` marks a failed call, for providers that report it.
+    pub fn add_tool_result_message(
+        self,
+        tool_call_id: &str,
+        content: String,
+        is_error: bool,
+    ) -> Self {
+        self.add_message(Message {
+            role: MessageRole::Tool,
+            content: content.clone(),
+            content_type: ContentType::ToolResult(ToolResult {
+                tool_call_id: tool_call_id.to_string(),
+                result: content,
+                error: is_error,
+                simulated: false,
+            }),
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    /// Append copies of all of `other`'s messages, leaving `other` usable,
+    /// e.g. to graft one shared prefix onto many builders
+    pub fn extend_from_context(mut self, other: &ContextBuilder) -> Self {
+        self.assert_open();
+        self.history.extend(other.history.iter().cloned());
+        self.spill_to_store()
+    }
+
+    /// ## `inject_before_last_user_message`
+    /// Insert `msg` right before the latest user message, e.g. retrieved
+    /// documents for the question just asked. Appended if there's no user
+    /// message yet. System messages are pinned as with `add_message`.
+    ///
+    /// ```rust,ignore
+    /// let context = context.inject_before_last_user_message(retrieved_docs);
+    /// ```
+    pub fn inject_before_last_user_message(mut self, mut msg: Message) -> Self {
+        let Some(index) = self
+            .history
+            .iter()
+            .rposition(|m| m.role == MessageRole::User)
+        else {
+            return self.add_message(msg);
+        };
+        self.assert_open();
+        if msg.role == MessageRole::System {
+            msg.pinned = true;
+        }
+        self.history.insert(index, msg);
+        self.spill_to_store()
+    }
+
+    /// Rotate history left so the message at `n` comes first (wrapping `n`)
+    pub fn rotate_messages(mut self, n: usize) -> Self {
+        self.assert_open();
+        if !self.history.is_empty() {
+            let len = self.history.len();
+            self.history.rotate_left(n % len);
+        }
+        self
+    }
+
+    /// Exchange the messages at `i` and `j`; unchanged if either is out of range
+    pub fn swap_messages(mut self, i: usize, j: usize) -> Self {
+        self.assert_open();
+        if i < self.history.len() && j < self.history.len() {
+            self.history.swap(i, j);
+        }
+        self
+    }
+
+    /// Drop every message but keep the rest of the builder (backing store,
+    /// saved branches, request params) for reuse
+    pub fn clear(mut self) -> Self {
+        self.assert_open();
+        self.history.clear();
+        self
+    }
+
+    /// Start the next assistant turn with `prefill`. Cleared once sent.
+    pub fn with_prefill(mut self, prefill: impl Into<String>) -> Self {
+        self.params.prefill = Some(prefill.into());
+        self
+    }
+
+    /// Set the metadata tag `key` to `value`
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Reverse the message order, e.g. for reflection prompts that re-read
+    /// the conversation backwards
+    pub fn reverse(mut self) -> Self {
+        self.assert_open();
+        self.history.reverse();
+        self
+    }
+
+    /// Rewrite every message's content with `f`, e.g. to trim whitespace or
+    /// normalize line endings. Content types and roles are left alone.
+    pub fn map_content(mut self, f: impl Fn(&str) -> String) -> Self {
+        self.assert_open();
+        for msg in &mut self.history {
+            msg.content = f(&msg.content);
+        }
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// The message at `index`, or `None` past the end of history
+    pub fn message_at(&self, index: usize) -> Option<&Message> {
+        self.history.get(index)
+    }
+
+    /// Whether any message in history has `role`
+    pub fn contains_role(&self, role: MessageRole) -> bool {
+        self.history.iter().any(|msg| msg.role == role)
+    }
+
+    /// Whether two adjacent messages share a role, which providers that need
+    /// strict user/assistant alternation (Anthropic, Gemini) reject
+    pub fn has_consecutive_same_role(&self) -> bool {
+        self.history
+            .windows(2)
+            .any(|pair| pair[0].role == pair[1].role)
+    }
+
+    /// Join runs of adjacent same-role text messages into one, separated by
+    /// blank lines, for providers that need strict alternation. System
+    /// messages and tool messages are left as they are; a merged message is
+    /// pinned if any part was.
+    pub fn merge_consecutive_same_role(mut self) -> Self {
+        self.assert_open();
+        let mut merged: Vec<Message> = Vec::with_capacity(self.history.len());
+        for msg in std::mem::take(&mut self.history) {
+            let mergeable = |m: &Message| {
+                m.role != MessageRole::System && matches!(m.content_type, ContentType::Text)
+            };
+            match merged.last_mut() {
+                Some(last) if last.role == msg.role && mergeable(last) && mergeable(&msg) => {
+                    last.content.push_str("\n\n");
+                    last.content.push_str(&msg.content);
+                    last.pinned |= msg.pinned;
+                }
+                _ => merged.push(msg),
+            }
+        }
+        self.history = merged;
+        self
+    }
+
+    /// The last `n` messages with `role`, oldest first
+    pub fn last_n_by_role(&self, role: MessageRole, n: usize) -> Vec<&Message> {
+        let mut messages: Vec<&Message> = self
+            .history
+            .iter()
+            .rev()
+            .filter(|msg| msg.role == role)
+            .take(n)
+            .collect();
+        messages.reverse();
+        messages
+    }
+
+    /// Messages in segments running from a `from_role` message to the next
+    /// `to_role` message, both included, e.g. every user→model→tool
+    /// exchange with `(User, Tool)`. A `from_role` message before the
+    /// segment is closed starts it over; an unclosed segment is left out.
+    pub fn messages_between_roles(
+        &self,
+        from_role: MessageRole,
+        to_role: MessageRole,
+    ) -> Vec<&Message> {
+        let mut messages = vec![];
+        let mut start = None;
+        for (index, msg) in self.history.iter().enumerate() {
+            match start {
+                Some(first) if msg.role == to_role => {
+                    messages.extend(&self.history[first..=index]);
+                    start = None;
+                }
+                _ if msg.role == from_role => start = Some(index),
+                _ => {}
+            }
+        }
+        messages
+    }
+
+    /// Pair messages with `other`'s by index, padding the shorter side with
+    /// `None`, e.g. to compare two branches side by side
+    pub fn zip_with<'a>(
+        &'a self,
+        other: &'a ContextBuilder,
+    ) -> impl Iterator<Item = (Option<&'a Message>, Option<&'a Message>)> {
+        let len = self.history.len().max(other.history.len());
+        (0..len).map(move |i| (self.history.get(i), other.history.get(i)))
+    }
+
+    /// Keep only the messages at indices `[start, end)`, clamped to history
+    pub fn messages_in_range(mut self, start: usize, end: usize) -> Self {
+        let end = end.min(self.history.len());
+        let start = start.min(end);
+        self.history = self.history.drain(start..end).collect();
         self
     }
 
+    /// The step every send takes before calling its adapter: refuses a
+    /// finalized conversation, then applies the rolling summary if one is
+    /// set. Sends continue from the returned context.
+    pub(crate) async fn prepare_send(self) -> Result<Self, String> {
+        self.check_open()?;
+        self.apply_rolling_summary().await
+    }
+
+    /// The context as adapters should see it, without archive markers or
+    /// the scratchpad
+    pub(crate) fn outgoing(&self) -> Self {
+        let mut context = self.clone();
+        context
+            .history
+            .retain(|msg| !matches!(msg.content_type, ContentType::ArchivedRange(_)));
+        context.scratchpad = scratchpad::Scratchpad::default();
+        context.summarize_strategy = None;
+        context
+    }
+
+    /// History as adapters should render it: with `params.system_prompt`
+    /// set, the system messages are replaced by that one prompt
+    pub fn request_history(&self) -> Cow<'_, [Message]> {
+        let Some(system_prompt) = &self.params.system_prompt else {
+            return Cow::Borrowed(&self.history);
+        };
+        let system = Message {
+            role: MessageRole::System,
+            content: system_prompt.clone(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        };
+        std::iter::once(system)
+            .chain(
+                self.history
+                    .iter()
+                    .filter(|msg| msg.role != MessageRole::System)
+                    .cloned(),
+            )
+            .collect()
+    }
+
     pub async fn send(
-        self,
+        mut self,
         adapter: ProviderAdapter, // Accept a boxed Send adapter
         max_tokens: u32,
     ) -> UnresolvedResponse {
-        let prompt_response = adapter(self.clone(), max_tokens).await;
+        self.assert_open();
+        self = self
+            .prepare_send()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to get summary: {}", e));
+        let prompt_response = adapter(self.outgoing(), max_tokens).await;
+        let mut prompt_response =
+            prompt_response.unwrap_or_else(|e| panic!("Failed to get PromptResponse: {}", e));
+
+        if let Some(prefill) = self.params.prefill.take() {
+            prompt_response.message.content.insert_str(0, &prefill);
+        }
+
         UnresolvedResponse {
-            prompt_response: prompt_response
-                .unwrap_or_else(|e| panic!("Failed to get PromptResponse: {}", e)),
+            prompt_response,
             context_builder: self,
         }
     }
 
+    /// ## `send_with_json_mode`
+    /// Send with the backend's JSON mode on and parse the reply. Errors if the
+    /// request fails or the model's content isn't valid JSON.
+    ///
+    /// ```rust,ignore
+    /// let verdict = context.send_with_json_mode(adapter, 200).await?;
+    /// let score = verdict["score"].as_f64();
+    /// ```
+    pub async fn send_with_json_mode(
+        self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+    ) -> Result<serde_json::Value, String> {
+        let context = self.prepare_send().await?;
+        let json_adapter: ProviderAdapter =
+            Arc::new(move |mut context: ContextBuilder, max_tokens: u32| {
+                context.params.json_mode = true;
+                adapter(context, max_tokens)
+            });
+
+        let mut response = json_adapter(context.outgoing(), max_tokens).await?;
+        if let Some(prefill) = &context.params.prefill {
+            response.message.content.insert_str(0, prefill);
+        }
+
+        serde_json::from_str(response.message.content.trim())
+            .map_err(|e| format!("Model returned invalid JSON: {}", e))
+    }
+
+    /// Send only if `condition` holds; otherwise skip the provider and return
+    /// an empty response that stopped normally
+    pub async fn conditional_send(
+        self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        condition: bool,
+    ) -> UnresolvedResponse {
+        self.assert_open();
+        if condition {
+            return self.send(adapter, max_tokens).await;
+        }
+
+        UnresolvedResponse {
+            prompt_response: PromptResponse {
+                message: Message {
+                    role: MessageRole::Model,
+                    content: String::new(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                },
+                stop_reason: StopReason::Stop,
+                token_usage: 0,
+                tool_calls: None,
+                provenance: Provenance::default(),
+                logprobs: None,
+            },
+            context_builder: self,
+        }
+    }
+
+    /// Send the same context `n` times concurrently, returning every result
+    pub async fn send_n(
+        self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        n: usize,
+    ) -> Vec<Result<PromptResponse, String>> {
+        let context = match self.prepare_send().await {
+            Ok(context) => context,
+            Err(e) => return vec![Err(e); n],
+        };
+        join_all((0..n).map(|_| adapter(context.outgoing(), max_tokens))).await
+    }
+
+    /// `send_n` for continuing each reply: one result per send, in order,
+    /// with each success as its own `UnresolvedResponse` on a copy of this
+    /// context and each failure as its error in the same place. Works with
+    /// any adapter, as the sends are separate requests.
+    pub async fn send_n_concurrent(
+        mut self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        n: usize,
+    ) -> Vec<Result<UnresolvedResponse, String>> {
+        self = match self.prepare_send().await {
+            Ok(context) => context,
+            Err(e) => return (0..n).map(|_| Err(e.clone())).collect(),
+        };
+        let results = join_all((0..n).map(|_| adapter(self.outgoing(), max_tokens))).await;
+        let prefill = self.params.prefill.take();
+
+        results
+            .into_iter()
+            .map(|result| {
+                let mut prompt_response = result?;
+                if let Some(prefill) = &prefill {
+                    prompt_response.message.content.insert_str(0, prefill);
+                }
+                Ok(UnresolvedResponse {
+                    prompt_response,
+                    context_builder: self.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Best-of-n sampling: send `n` times concurrently and let `picker` choose
+    /// which of the successful responses to continue with
+    pub async fn send_n_and_pick<F>(
+        self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        n: usize,
+        picker: F,
+    ) -> UnresolvedResponse
+    where
+        F: Fn(Vec<PromptResponse>) -> PromptResponse,
+    {
+        self.assert_open();
+        let mut context = self
+            .prepare_send()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to get summary: {}", e));
+        let mut responses = vec![];
+        let mut last_error = None;
+
+        let results = join_all((0..n).map(|_| adapter(context.outgoing(), max_tokens))).await;
+        let prefill = context.params.prefill.take();
+        for result in results {
+            match result {
+                Ok(response) => responses.push(response),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        if responses.is_empty() {
+            panic!(
+                "Failed to get PromptResponse: {}",
+                last_error.unwrap_or_else(|| "no completions requested".to_string())
+            );
+        }
+
+        let mut prompt_response = picker(responses);
+        if let Some(prefill) = &prefill {
+            prompt_response.message.content.insert_str(0, prefill);
+        }
+        UnresolvedResponse {
+            prompt_response,
+            context_builder: context,
+        }
+    }
+
+    /// ## `send_all_and_join`
+    /// Send this context to every adapter concurrently and add each reply as
+    /// its own model message, in `adapters` order, e.g. so the next prompt
+    /// can have the models vote. Adapters that fail are left out; panics if
+    /// they all do.
+    ///
+    /// ```rust,ignore
+    /// let context = context
+    ///     .send_all_and_join(vec![gpt, claude, llama], 500)
+    ///     .await
+    ///     .add_message(user_msg("Which answer is most accurate?"));
+    /// ```
+    pub async fn send_all_and_join(
+        mut self,
+        adapters: Vec<ProviderAdapter>,
+        max_tokens: u32,
+    ) -> ContextBuilder {
+        self.assert_open();
+        self = self
+            .prepare_send()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to get summary: {}", e));
+        let results = join_all(
+            adapters
+                .iter()
+                .map(|adapter| adapter(self.outgoing(), max_tokens)),
+        )
+        .await;
+        let prefill = self.params.prefill.take();
+
+        let mut last_error = None;
+        let mut replies = vec![];
+        for result in results {
+            match result {
+                Ok(response) => replies.push(response.message),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        if replies.is_empty() {
+            panic!(
+                "Failed to get PromptResponse: {}",
+                last_error.unwrap_or_else(|| "no adapters given".to_string())
+            );
+        }
+
+        replies.into_iter().fold(self, |context, mut message| {
+            if let Some(prefill) = &prefill {
+                message.content.insert_str(0, prefill);
+            }
+            context.add_message(message)
+        })
+    }
+
+    /// Ask `summarizer` to summarize the conversation using `prompt` as a final
+    /// user message. The prompt and reply are not added to this context.
+    pub async fn to_summary_string(
+        &self,
+        summarizer: ProviderAdapter,
+        max_tokens: u32,
+        prompt: &str,
+    ) -> Result<String, String> {
+        // Pushed directly: `add_message` would panic on a finalized context
+        // and spill or roll up the history on the way
+        let mut context = self.outgoing();
+        context.history.push(Message {
+            role: MessageRole::User,
+            content: prompt.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        });
+
+        summarizer(context, max_tokens)
+            .await
+            .map(|response| response.message.content)
+    }
+
+    /// ## `summarize_to_system_message`
+    /// Replace the whole history with one system message summarizing it,
+    /// written by `summarizer`. The most compressed form of a conversation,
+    /// at the cost of its turn-by-turn detail; `CompactionPolicy::compact`
+    /// keeps recent turns instead. The rest of the builder is kept, as with
+    /// `clear`. Panics if the summarizer fails, like `send`.
+    ///
+    /// ```rust,ignore
+    /// let context = context.summarize_to_system_message(summarizer, 500).await;
+    /// ```
+    pub async fn summarize_to_system_message(
+        self,
+        summarizer: ProviderAdapter,
+        max_tokens: u32,
+    ) -> Self {
+        let summary = self
+            .to_summary_string(
+                summarizer,
+                max_tokens,
+                "Summarize this conversation. Keep every fact, decision and open question \
+                    needed to continue it.",
+            )
+            .await
+            .unwrap_or_else(|e| panic!("Failed to get summary: {}", e));
+
+        self.clear().add_message(Message {
+            role: MessageRole::System,
+            content: summary,
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
     /// Stream a response from a provider, returning the raw stream for custom handling
     pub fn send_streaming(
         self,
         adapter: StreamProviderAdapter,
         max_tokens: u32,
     ) -> BoxStream<'static, Result<PromptResponseDelta, String>> {
-        adapter(self.clone(), max_tokens)
+        // Preparing may write a summary first, so the request starts once
+        // the stream is polled
+        Box::pin(
+            stream::once(self.prepare_send()).flat_map(move |prepared| match prepared {
+                Ok(context) => context.stream_prepared(adapter.clone(), max_tokens),
+                Err(e) => Box::pin(stream::once(async move { Err(e) })),
+            }),
+        )
     }
 
-    /// Stream a response from a provider with a callback for each delta
-    pub async fn send_streaming_with_callback<F>(
+    fn stream_prepared(
         self,
         adapter: StreamProviderAdapter,
         max_tokens: u32,
+    ) -> BoxStream<'static, Result<PromptResponseDelta, String>> {
+        let deltas = adapter(self.outgoing(), max_tokens);
+
+        // The prefill goes out first so the streamed text reads as one reply
+        match self.params.prefill {
+            Some(prefill) => Box::pin(
+                stream::once(async move {
+                    Ok(PromptResponseDelta {
+                        content: prefill,
+                        stop_reason: None,
+                        tool_calls: None,
+                        cumulative_tokens: 0,
+                        token_delta: 0,
+                        logprobs: None,
+                    })
+                })
+                .chain(deltas),
+            ),
+            None => deltas,
+        }
+    }
+
+    /// Stream a response from a provider with a callback for each delta.
+    ///
+    /// If a delta errors the stream is abandoned and the `StreamFailure`
+    /// carries what had arrived so far, stopped with
+    /// `StopReason::Interrupted`. An error on the very first delta still
+    /// returns a partial response: its content is just the prefill (if
+    /// any) and `StreamFailure::deltas` is 0.
+    pub async fn send_streaming_with_callback<F>(
+        mut self,
+        adapter: StreamProviderAdapter,
+        max_tokens: u32,
         callback: F,
-    ) -> Result<UnresolvedResponse, String>
+    ) -> Result<UnresolvedResponse, StreamFailure>
     where
         F: Fn(Result<PromptResponseDelta, String>) + Send + Sync + 'static,
     {
-        let stream = adapter(self.clone(), max_tokens);
+        // A refused send fails like a stream erroring at once
+        let stream = match self.clone().prepare_send().await {
+            Ok(context) => {
+                self = context;
+                adapter(self.outgoing(), max_tokens)
+            }
+            Err(e) => Box::pin(stream::once(async move { Err(e) })),
+        };
 
         // Collect the stream into a complete PromptResponse
-        let mut content = String::new();
+        let mut content = self.params.prefill.take().unwrap_or_default();
         let mut final_stop_reason = StopReason::Null;
         let mut tool_calls: Option<Vec<ToolCall>> = None;
+        let mut logprobs: Option<Vec<TokenLogprob>> = None;
+        let mut deltas = 0;
+        let mut error = None;
 
         // Process each delta
         let mut stream = Box::pin(stream);
@@ -251,6 +1287,8 @@ impl ContextBuilder {
             callback(delta_result.clone());
 
             if let Ok(delta) = delta_result {
+                deltas += 1;
+
                 // Append content
                 content.push_str(&delta.content);
 
@@ -262,12 +1300,19 @@ impl ContextBuilder {
                     }
                 }
 
+                // Logprobs arrive a few tokens per delta
+                if let Some(tokens) = delta.logprobs {
+                    logprobs.get_or_insert_with(Vec::new).extend(tokens);
+                }
+
                 // Stop
                 if let Some(reason) = delta.stop_reason {
-                    final_stop_reason = reason;
+                    merge_stop_reason(&mut final_stop_reason, reason);
                 }
             } else if let Err(err) = delta_result {
-                return Err(err);
+                error = Some(err);
+                final_stop_reason = StopReason::Interrupted;
+                break;
             }
         }
 
@@ -276,6 +1321,8 @@ impl ContextBuilder {
             role: MessageRole::Model,
             content,
             content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: tool_calls.clone(),
         };
 
         let prompt_response = PromptResponse {
@@ -283,13 +1330,211 @@ impl ContextBuilder {
             stop_reason: final_stop_reason,
             token_usage: 0,
             tool_calls,
+            provenance: Provenance::default(),
+            logprobs,
         };
 
         // Return as UnresolvedResponse for consistent API
-        Ok(UnresolvedResponse {
+        let response = UnresolvedResponse {
             prompt_response,
             context_builder: self,
-        })
+        };
+        match error {
+            Some(error) => Err(StreamFailure {
+                error,
+                partial: Box::new(response),
+                deltas,
+            }),
+            None => Ok(response),
+        }
+    }
+
+    /// ## `send_streaming_to_channel`
+    /// Stream a response into a tokio channel, e.g. one drained by a
+    /// WebSocket or SSE handler. Every delta is forwarded, errors included,
+    /// and the stream is dropped early once the receiver is gone.
+    ///
+    /// ```rust,ignore
+    /// let (sender, mut receiver) = tokio::sync::mpsc::channel(32);
+    /// tokio::spawn(context.send_streaming_to_channel(adapter, 500, sender));
+    /// while let Some(delta) = receiver.recv().await { /* ... */ }
+    /// ```
+    #[cfg(feature = "tokio-runtime")]
+    pub async fn send_streaming_to_channel(
+        self,
+        adapter: StreamProviderAdapter,
+        max_tokens: u32,
+        sender: tokio::sync::mpsc::Sender<Result<PromptResponseDelta, String>>,
+    ) {
+        let mut deltas = self.send_streaming(adapter, max_tokens);
+        while let Some(delta) = deltas.next().await {
+            if sender.send(delta).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// ## `send_streaming_with_progress`
+    /// Stream a response, calling `on_progress(tokens_so_far, stop_reason)`
+    /// after each delta, e.g. to drive a progress bar. `stop_reason` stays
+    /// `None` until the final delta. The count is the provider's cumulative
+    /// total when it reports one, otherwise the sum of `token_delta`s.
+    /// Errors pass through without a callback.
+    ///
+    /// ```rust,ignore
+    /// let deltas = context.send_streaming_with_progress(adapter, 500, move |tokens, _| {
+    ///     bar.set_position(tokens as u64);
+    /// });
+    /// ```
+    pub fn send_streaming_with_progress<F>(
+        self,
+        adapter: StreamProviderAdapter,
+        max_tokens: u32,
+        on_progress: F,
+    ) -> BoxStream<'static, Result<PromptResponseDelta, String>>
+    where
+        F: Fn(usize, Option<StopReason>) + Send + Sync + 'static,
+    {
+        let mut tokens = 0;
+        Box::pin(
+            self.send_streaming(adapter, max_tokens)
+                .inspect(move |delta| {
+                    if let Ok(delta) = delta {
+                        tokens = (tokens + delta.token_delta as usize)
+                            .max(delta.cumulative_tokens as usize);
+                        on_progress(tokens, delta.stop_reason.clone());
+                    }
+                }),
+        )
+    }
+
+    /// ## `send_reactive_streaming`
+    /// Stream a reply, then hand the collected response to `on_response`. If it
+    /// returns a message, the reply and that message are added to the context
+    /// and the model is streamed again, up to `max_iters` replies. Deltas from
+    /// every reply come out of one stream, in order; an error ends it.
+    ///
+    /// ```rust,ignore
+    /// let deltas = context.send_reactive_streaming(
+    ///     adapter,
+    ///     |response| match response.message.content.contains("DONE") {
+    ///         true => None,
+    ///         false => Some(user_message("Keep going")),
+    ///     },
+    ///     500,
+    ///     4,
+    /// );
+    /// ```
+    pub fn send_reactive_streaming<F>(
+        self,
+        adapter: StreamProviderAdapter,
+        on_response: F,
+        max_tokens: u32,
+        max_iters: usize,
+    ) -> BoxStream<'static, Result<PromptResponseDelta, String>>
+    where
+        F: Fn(PromptResponse) -> Option<Message> + Send + Sync + 'static,
+    {
+        struct Reactive<F> {
+            context: ContextBuilder,
+            adapter: StreamProviderAdapter,
+            on_response: F,
+            current: Option<BoxStream<'static, Result<PromptResponseDelta, String>>>,
+            iters: usize,
+            content: String,
+            stop_reason: StopReason,
+            tool_calls: Option<Vec<ToolCall>>,
+            logprobs: Option<Vec<TokenLogprob>>,
+            token_usage: u32,
+            done: bool,
+        }
+
+        let state = Reactive {
+            context: self,
+            adapter,
+            on_response,
+            current: None,
+            iters: 0,
+            content: String::new(),
+            stop_reason: StopReason::Null,
+            tool_calls: None,
+            logprobs: None,
+            token_usage: 0,
+            done: false,
+        };
+
+        Box::pin(stream::unfold(state, move |mut state| async move {
+            loop {
+                if state.done {
+                    return None;
+                }
+
+                let current = match &mut state.current {
+                    Some(current) => current,
+                    None if state.iters >= max_iters => return None,
+                    None => {
+                        state.iters += 1;
+                        let context = state.context.clone();
+                        // The prefill only leads the first reply
+                        state.context.params.prefill = None;
+                        state
+                            .current
+                            .insert(context.send_streaming(state.adapter.clone(), max_tokens))
+                    }
+                };
+
+                match current.next().await {
+                    Some(Ok(delta)) => {
+                        state.content.push_str(&delta.content);
+                        if let Some(calls) = delta.tool_calls.clone() {
+                            state.tool_calls.get_or_insert_with(Vec::new).extend(calls);
+                        }
+                        if let Some(tokens) = delta.logprobs.clone() {
+                            state.logprobs.get_or_insert_with(Vec::new).extend(tokens);
+                        }
+                        if let Some(reason) = delta.stop_reason.clone() {
+                            merge_stop_reason(&mut state.stop_reason, reason);
+                        }
+                        state.token_usage = state.token_usage.max(delta.cumulative_tokens);
+                        return Some((Ok(delta), state));
+                    }
+                    Some(Err(e)) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                    None => {
+                        state.current = None;
+                        let tool_calls = state.tool_calls.take();
+                        let message = Message {
+                            role: MessageRole::Model,
+                            content: std::mem::take(&mut state.content),
+                            content_type: ContentType::Text,
+                            pinned: false,
+                            assistant_tool_calls: tool_calls.clone(),
+                        };
+                        let response = PromptResponse {
+                            message: message.clone(),
+                            stop_reason: std::mem::replace(
+                                &mut state.stop_reason,
+                                StopReason::Null,
+                            ),
+                            token_usage: std::mem::take(&mut state.token_usage),
+                            tool_calls,
+                            provenance: Provenance::default(),
+                            logprobs: state.logprobs.take(),
+                        };
+
+                        match (state.on_response)(response) {
+                            Some(follow_up) => {
+                                state.context =
+                                    state.context.add_message(message).add_message(follow_up);
+                            }
+                            None => return None,
+                        }
+                    }
+                }
+            }
+        }))
     }
 }
 
@@ -327,6 +1572,7 @@ impl ContextBuilder {
 /// - `resolve`: Executes any tool calls and returns the updated context
 /// - `resolve_with_retry`: Handles tool calls with retry logic
 /// - `resolve_without`: Adds the response to context without handling tool calls
+/// - `or_else`: Like `resolve_without`, with a fallback for empty responses
 /// - `exec_tool_calls`: Executes tool calls and adds results to context
 /// - `resolve_with`/`resolve_with_sync`: Custom resolution with async/sync functions
 /// - `transform_with`/`transform_with_sync`: Custom transformations returning `Self`
@@ -336,6 +1582,97 @@ pub struct UnresolvedResponse {
     pub context_builder: ContextBuilder,
 }
 
+/// ## `StreamFailure`
+/// A stream that errored partway through. `partial` holds the content and
+/// tool calls received before the error, stopped with
+/// `StopReason::Interrupted`, so the turn can still be kept or resumed.
+///
+/// ```rust,ignore
+/// match context.send_streaming_with_callback(adapter, 1000, print_delta).await {
+///     Ok(response) => response.resolve_without(),
+///     Err(failure) => failure.partial.resolve_without(),
+/// }
+/// ```
+#[derive(Clone)]
+pub struct StreamFailure {
+    pub error: String,
+    pub partial: Box<UnresolvedResponse>,
+    /// Deltas received before the error; 0 if the first one failed
+    pub deltas: usize,
+}
+
+impl std::fmt::Debug for StreamFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamFailure")
+            .field("error", &self.error)
+            .field("deltas", &self.deltas)
+            .field("partial", &self.partial.prompt_response.message.content)
+            .finish()
+    }
+}
+
+/// Callers that only want the error can keep using `?` on `String` results
+impl From<StreamFailure> for String {
+    fn from(failure: StreamFailure) -> Self {
+        failure.error
+    }
+}
+
+/// Fold a streamed delta's stop reason into the one collected so far. A
+/// later `Stop`, e.g. on a trailing usage-only delta, doesn't undo
+/// `ToolCalls`.
+pub(crate) fn merge_stop_reason(current: &mut StopReason, next: StopReason) {
+    if !(*current == StopReason::ToolCalls && next == StopReason::Stop) {
+        *current = next;
+    }
+}
+
+/// How one tool call's result reads in the tool message
+pub(crate) fn tool_result_text(call: &ToolCall, result: &Result<String, String>) -> String {
+    match result {
+        Ok(output) => output.clone(),
+        Err(err) => format!("Error in tool call {} of {}: {}", call.id, call.name, err),
+    }
+}
+
+/// Tool calls written out as JSON, see `try_parse_tool_calls_from_content`
+fn tool_calls_in_text(content: &str) -> Option<Vec<ToolCall>> {
+    let text = content.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|fenced| fenced.strip_suffix("```"))
+        .unwrap_or(text);
+
+    let items = match serde_json::from_str(text.trim()).ok()? {
+        serde_json::Value::Array(items) if !items.is_empty() => items,
+        object @ serde_json::Value::Object(_) => vec![object],
+        _ => return None,
+    };
+
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let name = item.get("name")?.as_str()?.to_string();
+            let arguments = item
+                .get("arguments")
+                .or_else(|| item.get("parameters"))
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({}));
+            let id = match item.get("id").and_then(|id| id.as_str()) {
+                Some(id) => id.to_string(),
+                None => format!("call_{}", i),
+            };
+            Some(ToolCall {
+                id,
+                name,
+                arguments,
+            })
+        })
+        .collect()
+}
+
 impl UnresolvedResponse {
     pub async fn resolve(self, tool_executer: ToolExecuter) -> ContextBuilder {
         let unresolved_response = self.exec_tool_calls(tool_executer).await;
@@ -343,11 +1680,78 @@ impl UnresolvedResponse {
     }
 
     pub fn resolve_without(self) -> ContextBuilder {
-        self.context_builder
-            .add_message(self.prompt_response.message)
+        self.promote(None)
+    }
+
+    /// ## `promote`
+    /// Add the model's response to the context, then `additional_message` if
+    /// given, e.g. a follow-up instruction. `promote(None)` is `resolve_without`.
+    ///
+    /// ```rust,ignore
+    /// let context = response.promote(Some(user_msg("Now make it shorter.")));
+    /// ```
+    pub fn promote(self, additional_message: Option<Message>) -> ContextBuilder {
+        let context = self
+            .context_builder
+            .add_message(self.prompt_response.message);
+        match additional_message {
+            Some(message) => context.add_message(message),
+            None => context,
+        }
+    }
+
+    /// Resolve normally, unless the model produced nothing usable (a `Null`
+    /// stop reason or empty content), in which case `fallback` decides the context
+    pub fn or_else<F>(self, fallback: F) -> ContextBuilder
+    where
+        F: FnOnce() -> ContextBuilder,
+    {
+        if self.prompt_response.stop_reason == StopReason::Null
+            || self.prompt_response.message.content.is_empty()
+        {
+            return fallback();
+        }
+        self.resolve_without()
+    }
+
+    /// ## `try_parse_tool_calls_from_content`
+    /// For models that write tool calls as JSON text instead of using native
+    /// tool calling (common on Ollama): if the whole content, optionally in a
+    /// code fence, is a tool call object or an array of them, move them into
+    /// `tool_calls` and stop with `ToolCalls`. Each call needs a `name`, and
+    /// takes its `arguments` (or `parameters`) and `id` when present.
+    /// Anything else, or a response that already has tool calls, is
+    /// returned unchanged.
+    ///
+    /// ```rust,ignore
+    /// // Content: [{"name": "get_weather", "arguments": {"city": "Oslo"}}]
+    /// let context = response.try_parse_tool_calls_from_content().resolve(tools).await;
+    /// ```
+    pub fn try_parse_tool_calls_from_content(mut self) -> Self {
+        let response = &mut self.prompt_response;
+        if response.tool_calls.as_ref().is_some_and(|c| !c.is_empty()) {
+            return self;
+        }
+
+        let Some(calls) = tool_calls_in_text(&response.message.content) else {
+            return self;
+        };
+        response.message.assistant_tool_calls = Some(calls.clone());
+        response.tool_calls = Some(calls);
+        response.stop_reason = StopReason::ToolCalls;
+        self
     }
 
     pub async fn exec_tool_calls(self, tool_executer: ToolExecuter) -> Self {
+        self.exec_tool_calls_with_log(tool_executer, None).await
+    }
+
+    /// `exec_tool_calls`, sending a `ToolCallLog` to `log_sink` after each call
+    pub async fn exec_tool_calls_with_log(
+        self,
+        tool_executer: ToolExecuter,
+        log_sink: Option<ToolCallLogSink>,
+    ) -> Self {
         let mut unresolved_response = self.clone();
 
         // Add the current message to the context
@@ -362,31 +1766,30 @@ impl UnresolvedResponse {
             return unresolved_response;
         }
 
-        // Resolve tool calls
-        let mut tool_res = String::new();
-
+        // One tool message per call, so providers can match each result to
+        // its call id
         if let Some(tool_calls) = self.prompt_response.tool_calls.clone() {
             for tool_call in tool_calls {
+                let started = Instant::now();
                 let result = tool_executer(tool_call.clone()).await;
-                match result {
-                    Ok(output) => tool_res.push_str(&output),
-                    Err(err) => tool_res.push_str(&format!(
-                        "Error in tool call {} of {}: {}",
-                        tool_call.id, tool_call.name, err
-                    )),
+                unresolved_response.context_builder =
+                    unresolved_response.context_builder.add_tool_result_message(
+                        &tool_call.id,
+                        tool_result_text(&tool_call, &result),
+                        result.is_err(),
+                    );
+                if let Some(sink) = &log_sink {
+                    sink(ToolCallLog {
+                        call_id: tool_call.id,
+                        tool_name: tool_call.name,
+                        arguments: tool_call.arguments,
+                        result,
+                        duration_ms: started.elapsed().as_millis() as u64,
+                    });
                 }
-                tool_res.push('\n'); // Add a newline after each result
             }
         }
 
-        // Add tool response message to the context
-        unresolved_response.context_builder =
-            unresolved_response.context_builder.add_message(Message {
-                role: MessageRole::Tool,
-                content_type: ContentType::Text,
-                content: tool_res,
-            });
-
         unresolved_response
     }
 
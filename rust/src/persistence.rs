@@ -0,0 +1,185 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ContextBuilder;
+
+/* ------------------------------ Format Version ---------------------------- */
+
+/// Version written by `to_versioned_json`. Bare `ContextBuilder` JSON from
+/// before the envelope existed loads as version 0.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// On-disk wrapper that records which format a conversation was written in
+#[derive(Serialize, Deserialize)]
+pub struct ConversationEnvelope {
+    pub format_version: u32,
+    pub context: ContextBuilder,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Required,
+    /// Added after the first format; reported when absent
+    Defaulted,
+    /// Skipped by the current serializer when empty, so absence is normal
+    Optional,
+}
+
+/// Fields each persisted struct knows about. Update alongside the structs.
+const ENVELOPE_FIELDS: &[(&str, Field)] = &[
+    ("format_version", Field::Required),
+    ("context", Field::Required),
+];
+const CONTEXT_FIELDS: &[(&str, Field)] = &[
+    ("history", Field::Required),
+    ("branches", Field::Optional),
+    ("metadata", Field::Optional),
+    ("scratchpad", Field::Optional),
+    ("finalized", Field::Optional),
+];
+const MESSAGE_FIELDS: &[(&str, Field)] = &[
+    ("role", Field::Required),
+    ("content", Field::Required),
+    ("content_type", Field::Defaulted),
+    ("pinned", Field::Optional),
+    ("assistant_tool_calls", Field::Optional),
+];
+
+/* ----------------------------- MigrationReport ---------------------------- */
+
+/// What changed while loading a conversation written by another version.
+/// Paths use `[]` for every element of a list, e.g. `history[].content_type`.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct MigrationReport {
+    pub source_version: u32,
+    /// Absent fields that were populated with defaults
+    pub defaulted_fields: Vec<String>,
+    /// Fields this version doesn't know, dropped on load
+    pub ignored_fields: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Report for a conversation that needed no migration
+    pub fn current() -> Self {
+        MigrationReport {
+            source_version: FORMAT_VERSION,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.defaulted_fields.is_empty() && self.ignored_fields.is_empty()
+    }
+}
+
+fn join(path: &str, field: &str) -> String {
+    match path {
+        "" => field.to_string(),
+        _ => format!("{}.{}", path, field),
+    }
+}
+
+#[derive(Default)]
+struct Inspection {
+    defaulted: BTreeSet<String>,
+    ignored: BTreeSet<String>,
+}
+
+impl Inspection {
+    fn object(&mut self, value: &Value, path: &str, fields: &[(&str, Field)]) {
+        let Some(object) = value.as_object() else {
+            return;
+        };
+
+        for (field, kind) in fields {
+            if *kind == Field::Defaulted && !object.contains_key(*field) {
+                self.defaulted.insert(join(path, field));
+            }
+        }
+        for key in object.keys() {
+            if !fields.iter().any(|(field, _)| field == key) {
+                self.ignored.insert(join(path, key));
+            }
+        }
+    }
+
+    fn messages(&mut self, value: &Value, path: &str) {
+        for msg in value.as_array().into_iter().flatten() {
+            self.object(msg, path, MESSAGE_FIELDS);
+        }
+    }
+
+    fn context(&mut self, value: &Value, path: &str) {
+        self.object(value, path, CONTEXT_FIELDS);
+
+        self.messages(&value["history"], &join(path, "history[]"));
+        for branch in value["branches"]
+            .as_object()
+            .into_iter()
+            .flat_map(|b| b.values())
+        {
+            self.messages(branch, &join(path, "branches[][]"));
+        }
+    }
+}
+
+/* --------------------------------- Loading -------------------------------- */
+
+impl ContextBuilder {
+    /// Serialize wrapped in a `ConversationEnvelope` at the current version
+    pub fn to_versioned_json(&self) -> Result<String, String> {
+        serde_json::to_string(&ConversationEnvelope {
+            format_version: FORMAT_VERSION,
+            context: self.clone(),
+        })
+        .map_err(|e| format!("Failed to serialize conversation: {}", e))
+    }
+
+    /// Load a conversation from `to_versioned_json` output (or bare
+    /// `ContextBuilder` JSON), reporting every field that had to be defaulted
+    /// or was ignored along the way
+    pub fn load_with_report(json: &str) -> Result<(ContextBuilder, MigrationReport), String> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|e| format!("Failed to parse conversation: {}", e))?;
+
+        let versioned = value.get("format_version").is_some();
+        let (source_version, context_value) = match versioned {
+            true => (
+                value["format_version"]
+                    .as_u64()
+                    .ok_or("format_version must be a number")? as u32,
+                value["context"].clone(),
+            ),
+            false => (0, value.clone()),
+        };
+
+        if source_version > FORMAT_VERSION {
+            return Err(format!(
+                "Conversation format version {} is newer than supported version {}",
+                source_version, FORMAT_VERSION
+            ));
+        }
+
+        let mut inspection = Inspection::default();
+        if versioned {
+            inspection.object(&value, "", ENVELOPE_FIELDS);
+            inspection.context(&context_value, "context");
+        } else {
+            inspection.context(&context_value, "");
+        }
+
+        let context: ContextBuilder = serde_json::from_value(context_value)
+            .map_err(|e| format!("Failed to load conversation: {}", e))?;
+
+        Ok((
+            context,
+            MigrationReport {
+                source_version,
+                defaulted_fields: inspection.defaulted.into_iter().collect(),
+                ignored_fields: inspection.ignored.into_iter().collect(),
+            },
+        ))
+    }
+}
@@ -0,0 +1,122 @@
+use crate::tokens::{MESSAGE_OVERHEAD_TOKENS, TokenCounter};
+use crate::{ContextBuilder, MessageRole};
+
+/// Prefix of the error `truncate_to_fit` returns when the pinned messages
+/// alone are over the budget
+pub const PINNED_EXCEED_BUDGET: &str = "PinnedExceedBudget";
+
+/* --------------------------------- Pinning -------------------------------- */
+
+impl ContextBuilder {
+    /// Pin the message at `index` so truncation, compaction and spilling to a
+    /// store leave it in place. Out-of-range indices are ignored.
+    pub fn pin_message(mut self, index: usize) -> Self {
+        self.assert_open();
+        if let Some(msg) = self.history.get_mut(index) {
+            msg.pinned = true;
+        }
+        self
+    }
+
+    /// Pin the most recent message
+    pub fn pin_last(self) -> Self {
+        match self.history.len() {
+            0 => self,
+            len => self.pin_message(len - 1),
+        }
+    }
+
+    /// Clear every pin, e.g. when a new phase of the conversation starts.
+    /// System messages are unpinned too.
+    pub fn unpin_all(mut self) -> Self {
+        self.assert_open();
+        for msg in &mut self.history {
+            msg.pinned = false;
+        }
+        self
+    }
+
+    pub fn pin_all(mut self) -> Self {
+        self.assert_open();
+        for msg in &mut self.history {
+            msg.pinned = true;
+        }
+        self
+    }
+
+    /// Pin every message with `role`
+    pub fn pin_by_role(mut self, role: MessageRole) -> Self {
+        self.assert_open();
+        for msg in self.history.iter_mut().filter(|msg| msg.role == role) {
+            msg.pinned = true;
+        }
+        self
+    }
+
+    /// Keep the `n` most recent messages, plus any older pinned ones
+    pub fn truncate_to_last_n(mut self, n: usize) -> Self {
+        self.assert_open();
+        let cutoff = self.history.len().saturating_sub(n);
+        let mut index = 0;
+        self.history.retain(|msg| {
+            index += 1;
+            index > cutoff || msg.pinned
+        });
+        self
+    }
+
+    /// ## `truncate_to_fit`
+    /// Drop the oldest unpinned messages until the history fits in
+    /// `max_tokens`. Pinned messages are counted first and always kept; the
+    /// rest of the budget goes to the most recent unpinned messages. Errors
+    /// with `PINNED_EXCEED_BUDGET` if the pinned messages alone don't fit.
+    ///
+    /// ```rust,ignore
+    /// let context = context.pin_message(0).truncate_to_fit(8000, &HeuristicTokenCounter)?;
+    /// ```
+    pub fn truncate_to_fit(
+        mut self,
+        max_tokens: usize,
+        counter: &dyn TokenCounter,
+    ) -> Result<Self, String> {
+        self.check_open()?;
+        let costs: Vec<usize> = self
+            .history
+            .iter()
+            .map(|msg| counter.count(&msg.content) + MESSAGE_OVERHEAD_TOKENS)
+            .collect();
+        let pinned: usize = self
+            .history
+            .iter()
+            .zip(&costs)
+            .filter(|(msg, _)| msg.pinned)
+            .map(|(_, cost)| cost)
+            .sum();
+        if pinned > max_tokens {
+            return Err(format!(
+                "{}: pinned messages need {} tokens, over the budget of {}",
+                PINNED_EXCEED_BUDGET, pinned, max_tokens
+            ));
+        }
+
+        // Newest first, keep unpinned messages until one doesn't fit
+        let mut remaining = max_tokens - pinned;
+        let mut keep = vec![true; self.history.len()];
+        let mut full = false;
+        for index in (0..self.history.len()).rev() {
+            if self.history[index].pinned {
+                continue;
+            }
+            if full || costs[index] > remaining {
+                full = true;
+                keep[index] = false;
+            } else {
+                remaining -= costs[index];
+            }
+        }
+
+        let mut keep = keep.into_iter();
+        self.history.retain(|_| keep.next().unwrap());
+        Ok(self)
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::{ToolCall, ToolExecuter};
+
+/* ------------------------------- Signatures ------------------------------- */
+
+/// ## `ResultPostProcessor`
+/// Reshapes a tool's JSON output into the text the model sees, e.g. a
+/// markdown table for query results. Returning `Err` fails the call.
+pub type ResultPostProcessor = Arc<dyn Fn(Value) -> Result<String, String> + Send + Sync>;
+
+/// Told about results that skipped post-processing, with the reason
+pub type PostProcessWarningSink = Arc<dyn Fn(&ToolCall, &str) + Send + Sync>;
+
+/* --------------------------------- Wrapper -------------------------------- */
+
+/// ## `post_process_results`
+/// Wrap `executer` so each successful result is passed through the processor
+/// registered for its tool's name. Tools without one, and errors, pass
+/// through unchanged. Output that isn't JSON skips post-processing and is
+/// reported to `warnings`.
+///
+/// Wrap the result in `sandbox` to have its size limits apply to the
+/// processed text rather than the raw output.
+///
+/// ```rust,ignore
+/// let executer = post_process_results(
+///     executer,
+///     HashMap::from([
+///         ("run_sql".to_string(), as_markdown_table(20)),
+///         ("web_search".to_string(), numbered_list("title", "url")),
+///     ]),
+///     None,
+/// );
+/// ```
+pub fn post_process_results(
+    executer: ToolExecuter,
+    processors: HashMap<String, ResultPostProcessor>,
+    warnings: Option<PostProcessWarningSink>,
+) -> ToolExecuter {
+    let processors = Arc::new(processors);
+
+    Arc::new(move |call: ToolCall| {
+        let executer = executer.clone();
+        let processors = processors.clone();
+        let warnings = warnings.clone();
+
+        Box::pin(async move {
+            let output = executer(call.clone()).await?;
+            let Some(processor) = processors.get(&call.name) else {
+                return Ok(output);
+            };
+
+            match serde_json::from_str(&output) {
+                Ok(value) => processor(value),
+                Err(e) => {
+                    if let Some(sink) = &warnings {
+                        sink(
+                            &call,
+                            &format!("Output isn't JSON, so it wasn't post-processed: {}", e),
+                        );
+                    }
+                    Ok(output)
+                }
+            }
+        })
+    })
+}
+
+/* -------------------------------- Built-ins ------------------------------- */
+
+/// Cell text for a JSON value: strings unquoted, everything else as JSON
+fn plain(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn table_cell(value: &Value) -> String {
+    plain(value).replace('|', "\\|").replace('\n', " ")
+}
+
+/// The rows of a tool result: a JSON array, or the first array field of an
+/// object such as `{"rows": [...]}`
+fn rows(value: Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Array(rows) => Ok(rows),
+        Value::Object(fields) => fields
+            .into_iter()
+            .find_map(|(_, field)| match field {
+                Value::Array(rows) => Some(rows),
+                _ => None,
+            })
+            .ok_or_else(|| "Expected an array of rows".to_string()),
+        _ => Err("Expected an array of rows".to_string()),
+    }
+}
+
+/// ## `as_markdown_table`
+/// Render an array of objects as a markdown table with a column for every
+/// key the rows use. Rows past `max_rows` are counted in a note under the
+/// table.
+pub fn as_markdown_table(max_rows: usize) -> ResultPostProcessor {
+    Arc::new(move |value: Value| {
+        let rows = rows(value)?;
+        if rows.is_empty() {
+            return Ok("_No rows_".to_string());
+        }
+
+        let mut columns: Vec<String> = vec![];
+        for row in rows.iter().take(max_rows) {
+            let Value::Object(fields) = row else {
+                return Err("Expected every row to be an object".to_string());
+            };
+            for key in fields.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+
+        let mut table = format!("| {} |\n", columns.join(" | "));
+        table.push_str(&format!("|{}\n", " --- |".repeat(columns.len())));
+        for row in rows.iter().take(max_rows) {
+            let cells: Vec<String> = columns
+                .iter()
+                .map(|column| row.get(column).map(table_cell).unwrap_or_default())
+                .collect();
+            table.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+        if rows.len() > max_rows {
+            table.push_str(&format!(
+                "\n_{} more rows not shown_\n",
+                rows.len() - max_rows
+            ));
+        }
+        Ok(table)
+    })
+}
+
+/// ## `numbered_list`
+/// Render an array of objects as a numbered list of their `key_field`,
+/// followed by `dedup_field` in parentheses. Items repeating an earlier
+/// item's `dedup_field`, e.g. the same URL, are dropped.
+///
+/// ```rust,ignore
+/// // 1. Rust 2024 edition guide (https://doc.rust-lang.org/edition-guide/)
+/// let search = numbered_list("title", "url");
+/// ```
+pub fn numbered_list(key_field: &str, dedup_field: &str) -> ResultPostProcessor {
+    let key_field = key_field.to_string();
+    let dedup_field = dedup_field.to_string();
+
+    Arc::new(move |value: Value| {
+        let mut seen = vec![];
+        let mut lines = vec![];
+        for item in rows(value)? {
+            let dedup = item.get(&dedup_field).map(plain);
+            if let Some(dedup) = &dedup {
+                if seen.contains(dedup) {
+                    continue;
+                }
+                seen.push(dedup.clone());
+            }
+
+            let key = item.get(&key_field).map(plain);
+            let line = match (key, dedup) {
+                (Some(key), Some(dedup)) if key != dedup => format!("{} ({})", key, dedup),
+                (Some(key), _) => key,
+                (None, _) => item.to_string(),
+            };
+            lines.push(format!("{}. {}", lines.len() + 1, line));
+        }
+        Ok(lines.join("\n"))
+    })
+}
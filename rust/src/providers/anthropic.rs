@@ -0,0 +1,237 @@
+use serde_json::{Value, json};
+
+use crate::{ContentType, ContextBuilder, Message, MessageRole, ToolCall, ToolDescriptor};
+
+/// The content block for a non-system message, and whether it's sent as
+/// the user
+fn content_block(msg: &Message) -> (bool, Value) {
+    match &msg.content_type {
+        ContentType::ToolUse(call) => (
+            false,
+            json!({
+                "type": "tool_use",
+                "id": call.id,
+                "name": call.name,
+                "input": call.parsed_arguments().unwrap_or_else(|_| json!({})),
+            }),
+        ),
+        ContentType::ToolResult(result) => (
+            true,
+            json!({
+                "type": "tool_result",
+                "tool_use_id": result.tool_call_id,
+                "content": result.result,
+                "is_error": result.error,
+            }),
+        ),
+        _ => (
+            msg.role != MessageRole::Model,
+            json!({ "type": "text", "text": msg.content }),
+        ),
+    }
+}
+
+/// Text of a `tool_result` block, whose content is a string or a list of
+/// text blocks
+fn tool_result_text(content: &Value) -> Result<String, String> {
+    match content {
+        Value::Null => Ok(String::new()),
+        Value::String(text) => Ok(text.clone()),
+        Value::Array(blocks) => blocks
+            .iter()
+            .map(|block| match block["type"].as_str() {
+                Some("text") => Ok(block["text"].as_str().unwrap_or_default()),
+                other => Err(format!("Unsupported tool_result block type {:?}", other)),
+            })
+            .collect::<Result<Vec<&str>, String>>()
+            .map(|texts| texts.join("\n\n")),
+        other => Err(format!("Malformed tool_result content: {}", other)),
+    }
+}
+
+fn text_message(role: MessageRole, content: String) -> Message {
+    Message {
+        role,
+        content,
+        content_type: ContentType::Text,
+        pinned: false,
+        assistant_tool_calls: None,
+    }
+}
+
+impl ContextBuilder {
+    /// ## `from_anthropic_messages_json`
+    /// Import history in the Messages API format: `system` becomes a leading
+    /// system message and each `user`/`assistant` turn becomes one message
+    /// per content block. Adjacent text blocks in a turn are joined into one
+    /// message; `tool_use` and `tool_result` blocks become tool messages.
+    /// Errors on other roles or block types.
+    ///
+    /// ```rust,ignore
+    /// let context = ContextBuilder::from_anthropic_messages_json(
+    ///     body["system"].as_str(),
+    ///     &body["messages"],
+    /// )?;
+    /// ```
+    pub fn from_anthropic_messages_json(
+        system: Option<&str>,
+        messages: &Value,
+    ) -> Result<Self, String> {
+        let turns = messages
+            .as_array()
+            .ok_or_else(|| "Anthropic messages must be an array".to_string())?;
+
+        let mut context = ContextBuilder::new();
+        if let Some(system) = system {
+            context = context.add_message(text_message(MessageRole::System, system.to_string()));
+        }
+
+        for (index, turn) in turns.iter().enumerate() {
+            let role = match turn["role"].as_str() {
+                Some("user") => MessageRole::User,
+                Some("assistant") => MessageRole::Model,
+                other => return Err(format!("Unsupported role {:?} in message {}", other, index)),
+            };
+            let blocks = match &turn["content"] {
+                Value::String(text) => vec![json!({ "type": "text", "text": text })],
+                Value::Array(blocks) => blocks.clone(),
+                other => return Err(format!("Malformed content in message {}: {}", index, other)),
+            };
+
+            let mut text: Option<String> = None;
+            for block in blocks {
+                if block["type"] != "text"
+                    && let Some(text) = text.take()
+                {
+                    context = context.add_message(text_message(role.clone(), text));
+                }
+                match block["type"].as_str() {
+                    Some("text") => {
+                        let block_text = block["text"].as_str().unwrap_or_default();
+                        match &mut text {
+                            Some(text) => {
+                                text.push_str("\n\n");
+                                text.push_str(block_text);
+                            }
+                            None => text = Some(block_text.to_string()),
+                        }
+                    }
+                    Some("tool_use") => {
+                        context = context.add_tool_use_message(ToolCall {
+                            id: block["id"].as_str().unwrap_or_default().to_string(),
+                            name: block["name"].as_str().unwrap_or_default().to_string(),
+                            arguments: block.get("input").cloned().unwrap_or_else(|| json!({})),
+                        });
+                    }
+                    Some("tool_result") => {
+                        context = context.add_tool_result_message(
+                            block["tool_use_id"].as_str().unwrap_or_default(),
+                            tool_result_text(&block["content"])?,
+                            block["is_error"].as_bool().unwrap_or(false),
+                        );
+                    }
+                    other => {
+                        return Err(format!(
+                            "Unsupported content block type {:?} in message {}",
+                            other, index
+                        ));
+                    }
+                }
+            }
+            if let Some(text) = text {
+                context = context.add_message(text_message(role, text));
+            }
+        }
+
+        Ok(context)
+    }
+
+    /// ## `to_anthropic_request_body`
+    /// The Anthropic Messages API request for this context, ready to POST to
+    /// `/v1/messages`. System messages are joined into the top-level
+    /// `system` field and tool turns become `tool_use`/`tool_result` blocks.
+    /// Adjacent turns from the same side are merged, since Anthropic needs
+    /// user and assistant to alternate; tool and function messages are sent
+    /// as the user. A prefill becomes a trailing assistant turn.
+    ///
+    /// ```rust,ignore
+    /// let body = context.to_anthropic_request_body("claude-sonnet-4-5", 1024, Some(tools));
+    /// http.post(url).json(&body).send().await?;
+    /// ```
+    pub fn to_anthropic_request_body(
+        &self,
+        model: &str,
+        max_tokens: u32,
+        tools: Option<Vec<ToolDescriptor>>,
+    ) -> Value {
+        let outgoing = self.outgoing();
+        let history = outgoing.request_history();
+
+        let system: Vec<&str> = history
+            .iter()
+            .filter(|msg| msg.role == MessageRole::System)
+            .map(|msg| msg.content.as_str())
+            .collect();
+
+        let mut messages: Vec<(bool, Vec<Value>)> = vec![];
+        let turns = history
+            .iter()
+            .filter(|msg| msg.role != MessageRole::System)
+            .map(content_block)
+            .chain(
+                self.params
+                    .prefill
+                    .iter()
+                    .map(|prefill| (false, json!({ "type": "text", "text": prefill }))),
+            );
+        for (from_user, block) in turns {
+            match messages.last_mut() {
+                Some((last_from_user, blocks)) if *last_from_user == from_user => {
+                    blocks.push(block)
+                }
+                _ => messages.push((from_user, vec![block])),
+            }
+        }
+
+        let messages: Vec<Value> = messages
+            .into_iter()
+            .map(|(from_user, blocks)| {
+                let role = if from_user { "user" } else { "assistant" };
+                // A lone text block is sent as plain string content
+                let content = match blocks.as_slice() {
+                    [block] if block["type"] == "text" => block["text"].clone(),
+                    _ => Value::Array(blocks),
+                };
+                json!({ "role": role, "content": content })
+            })
+            .collect();
+
+        let mut body = json!({
+            "model": model,
+            "max_tokens": max_tokens,
+            "messages": messages,
+        });
+        if !system.is_empty() {
+            body["system"] = Value::from(system.join("\n\n"));
+        }
+        if let Some(temperature) = self.params.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = self.params.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if let Some(tools) = tools.filter(|tools| !tools.is_empty()) {
+            body["tools"] = tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "name": tool.name,
+                        "description": tool.description,
+                        "input_schema": tool.schema,
+                    })
+                })
+                .collect();
+        }
+        body
+    }
+}
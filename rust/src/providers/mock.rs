@@ -0,0 +1,105 @@
+use futures::stream::{self, BoxStream};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponse, PromptResponseDelta,
+    Provenance, ProviderAdapter, StopReason, StreamProviderAdapter, ToolCall, ToolExecuter,
+};
+
+/// Plain text message, for building test histories
+pub fn mock_message(role: MessageRole, content: &str) -> Message {
+    Message {
+        role,
+        content: content.to_string(),
+        content_type: ContentType::Text,
+        pinned: false,
+        assistant_tool_calls: None,
+    }
+}
+
+/// Plain text model reply, for scripting mock adapters
+pub fn mock_text_response(content: &str) -> PromptResponse {
+    PromptResponse {
+        message: mock_message(MessageRole::Model, content),
+        stop_reason: StopReason::Stop,
+        token_usage: 0,
+        tool_calls: None,
+        provenance: Provenance::default(),
+        logprobs: None,
+    }
+}
+
+/// Offline adapter that replays `responses` in order, one per call. Once the
+/// script runs out the last entry is repeated.
+pub fn mock_adapter_factory(responses: Vec<Result<PromptResponse, String>>) -> ProviderAdapter {
+    let responses = Arc::new(responses);
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    Arc::new(move |_context: ContextBuilder, _max_tokens: u32| {
+        let responses = responses.clone();
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+
+        Box::pin(async move {
+            match responses.get(call).or(responses.last()) {
+                Some(response) => response.clone(),
+                None => Err("Mock adapter has no scripted responses".to_string()),
+            }
+        })
+    })
+}
+
+/// Offline streaming adapter that yields `deltas` on every call
+pub fn mock_streaming_adapter_factory(
+    deltas: Vec<Result<PromptResponseDelta, String>>,
+) -> StreamProviderAdapter {
+    Arc::new(move |_context: ContextBuilder, _max_tokens: u32| {
+        Box::pin(stream::iter(deltas.clone()))
+            as BoxStream<'static, Result<PromptResponseDelta, String>>
+    })
+}
+
+/// Fault-injecting streaming adapter: yields the first `fail_after` of
+/// `deltas`, then a single error
+pub fn failing_streaming_adapter_factory(
+    deltas: Vec<PromptResponseDelta>,
+    fail_after: usize,
+) -> StreamProviderAdapter {
+    Arc::new(move |_context: ContextBuilder, _max_tokens: u32| {
+        let mut results: Vec<Result<PromptResponseDelta, String>> =
+            deltas.iter().take(fail_after).cloned().map(Ok).collect();
+        results.push(Err(format!(
+            "Injected stream failure after {} deltas",
+            fail_after
+        )));
+        Box::pin(stream::iter(results)) as BoxStream<'static, Result<PromptResponseDelta, String>>
+    })
+}
+
+/// Wrap `adapter` so tests can see how many times it was called
+pub fn counting_adapter(adapter: ProviderAdapter) -> (ProviderAdapter, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls_clone = calls.clone();
+
+    let counted: ProviderAdapter = Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        calls_clone.fetch_add(1, Ordering::SeqCst);
+        adapter(context, max_tokens)
+    });
+
+    (counted, calls)
+}
+
+/// Tool executer that answers each call with the result registered for its
+/// tool name
+pub fn stub_tool_executer(responses: HashMap<String, String>) -> ToolExecuter {
+    let responses = Arc::new(responses);
+
+    Arc::new(move |call: ToolCall| {
+        let result = responses
+            .get(&call.name)
+            .cloned()
+            .ok_or_else(|| format!("unknown tool: {}", call.name));
+        Box::pin(async move { result })
+    })
+}
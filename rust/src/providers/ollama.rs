@@ -1,12 +1,18 @@
 use futures::stream::{self, BoxStream, StreamExt};
 use ollama_rs::Ollama;
 use ollama_rs::generation::completion::request::GenerationRequest;
+use ollama_rs::generation::options::GenerationOptions;
+use ollama_rs::generation::parameters::FormatType;
 use serde_json::json;
 use std::sync::Arc;
 
+use crate::capabilities::MaxTokensPolicy;
+use crate::resilience::{ErrorClass, ErrorClassifier, HeuristicErrorClassifier};
+use crate::response_filter::{ResponseFilter, filtering_adapter, filtering_streaming_adapter};
 use crate::{
     ContentType, ContextBuilder, Message, MessageRole, PromptResponse, PromptResponseDelta,
-    ProviderAdapter, StopReason, StreamProviderAdapter, ToolDescriptor,
+    Provenance, ProviderAdapter, ReproducibilityInfo, RequestParams, StopReason,
+    StreamProviderAdapter, ToolDescriptor,
 };
 
 /// Format a prompt for Ollama using standard JSON format
@@ -15,8 +21,8 @@ pub fn format_ollama_prompt(
     tools: &Option<Vec<ToolDescriptor>>,
 ) -> String {
     // Create a JSON object with the messages from context history
-    let messages = context
-        .history
+    let mut messages = context
+        .request_history()
         .iter()
         .map(|msg| {
             let role = match msg.role {
@@ -33,6 +39,14 @@ pub fn format_ollama_prompt(
         })
         .collect::<Vec<_>>();
 
+    // A prefill is an assistant turn the model continues from
+    if let Some(prefill) = &context.params.prefill {
+        messages.push(json!({
+            "role": "assistant",
+            "content": prefill
+        }));
+    }
+
     // Add tools if available
     let mut request = json!({
         "messages": messages
@@ -46,14 +60,83 @@ pub fn format_ollama_prompt(
     serde_json::to_string(&request).unwrap_or_default()
 }
 
+/// Configuration for the Ollama adapters
+#[derive(Clone)]
+pub struct OllamaAdapterOptions {
+    /// Default and clamping for the `max_tokens` the adapter is called with,
+    /// sent as `num_predict`
+    pub max_tokens: MaxTokensPolicy,
+    /// Strips template artifacts from replies; `template_artifacts` by default
+    pub response_filter: ResponseFilter,
+}
+
+impl Default for OllamaAdapterOptions {
+    fn default() -> Self {
+        OllamaAdapterOptions {
+            max_tokens: MaxTokensPolicy::default(),
+            response_filter: ResponseFilter::template_artifacts(),
+        }
+    }
+}
+
+/// `params.seed` as the 32-bit seed Ollama takes. Larger seeds are rejected
+/// rather than wrapped, so the seed reported in provenance is the one sent
+fn ollama_seed(params: &RequestParams) -> Result<Option<i32>, String> {
+    params
+        .seed
+        .map(|seed| {
+            i32::try_from(seed)
+                .map_err(|_| format!("Ollama takes a seed of at most {}, got {}", i32::MAX, seed))
+        })
+        .transpose()
+}
+
+/// Generation request carrying the completion limit, the context's
+/// sampling overrides and the seed from `ollama_seed`
+fn generation_request(
+    model: String,
+    prompt: String,
+    params: &RequestParams,
+    seed: Option<i32>,
+    max_tokens: u32,
+) -> GenerationRequest<'static> {
+    let mut request = GenerationRequest::new(model, prompt);
+    if params.json_mode {
+        request = request.format(FormatType::Json);
+    }
+
+    let mut options =
+        GenerationOptions::default().num_predict(max_tokens.min(i32::MAX as u32) as i32);
+    if let Some(temperature) = params.temperature {
+        options = options.temperature(temperature);
+    }
+    if let Some(top_p) = params.top_p {
+        options = options.top_p(top_p);
+    }
+    if let Some(seed) = seed {
+        options = options.seed(seed);
+    }
+    request.options(options)
+}
+
 // Non-streaming adapter factory
 pub fn ollama_adapter_factory(
     model_name: String,
     tools: Option<Vec<ToolDescriptor>>,
 ) -> ProviderAdapter {
-    Arc::new(move |context: ContextBuilder, _: u32| {
+    ollama_adapter_factory_with_options(model_name, tools, OllamaAdapterOptions::default())
+}
+
+pub fn ollama_adapter_factory_with_options(
+    model_name: String,
+    tools: Option<Vec<ToolDescriptor>>,
+    options: OllamaAdapterOptions,
+) -> ProviderAdapter {
+    let filter = options.response_filter.clone();
+    let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, max_tokens: u32| {
         let model = model_name.clone();
         let tools_clone = tools.clone();
+        let options = options.clone();
 
         Box::pin(async move {
             let ollama = Ollama::default();
@@ -61,7 +144,9 @@ pub fn ollama_adapter_factory(
             // Use the generalized formatting function
             let prompt = format_ollama_prompt(&context, &tools_clone);
 
-            let request = GenerationRequest::new(model, prompt);
+            let (max_tokens, clamped) = options.max_tokens.resolve(&model, max_tokens)?;
+            let seed = ollama_seed(&context.params)?;
+            let request = generation_request(model, prompt, &context.params, seed, max_tokens);
             let result = ollama.generate(request).await;
 
             match result {
@@ -70,15 +155,33 @@ pub fn ollama_adapter_factory(
                         role: MessageRole::Model,
                         content: generation.response,
                         content_type: ContentType::Text,
+                        pinned: false,
+                        assistant_tool_calls: None,
                     },
                     stop_reason: StopReason::Stop,
                     token_usage: 0,
                     tool_calls: None,
+                    provenance: {
+                        let mut provenance = Provenance::default();
+                        if let Some(note) = clamped {
+                            provenance.record("max_tokens", note);
+                        }
+                        // Seeded generation is repeatable on the same model
+                        // and hardware; Ollama reports no fingerprint
+                        provenance.reproducibility = Some(ReproducibilityInfo {
+                            seed_sent: seed.map(|seed| seed as u64),
+                            fingerprint: None,
+                            backend_claims_determinism: seed.is_some(),
+                        });
+                        provenance
+                    },
+                    logprobs: None,
                 }),
                 Err(e) => Err(format!("Ollama generation error: {:?}", e)),
             }
         })
-    })
+    });
+    filtering_adapter(adapter, filter)
 }
 
 // Streaming adapter factory
@@ -86,76 +189,135 @@ pub fn ollama_streaming_adapter_factory(
     model_name: String,
     tools: Option<Vec<ToolDescriptor>>,
 ) -> StreamProviderAdapter {
-    Arc::new(move |context: ContextBuilder, _: u32| {
-        let model = model_name.clone();
-        let tools_clone = tools.clone();
+    ollama_streaming_adapter_factory_with_options(
+        model_name,
+        tools,
+        OllamaAdapterOptions::default(),
+    )
+}
 
-        // Use the generalized formatting function
-        let prompt = format_ollama_prompt(&context, &tools_clone);
+pub fn ollama_streaming_adapter_factory_with_options(
+    model_name: String,
+    tools: Option<Vec<ToolDescriptor>>,
+    options: OllamaAdapterOptions,
+) -> StreamProviderAdapter {
+    let filter = options.response_filter.clone();
+    let adapter: StreamProviderAdapter =
+        Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+            let model = model_name.clone();
+            let tools_clone = tools.clone();
+            let max_tokens = match options.max_tokens.resolve(&model, max_tokens) {
+                Ok((max_tokens, _)) => max_tokens,
+                Err(e) => {
+                    return Box::pin(stream::once(async move { Err(e) }))
+                        as BoxStream<'static, Result<PromptResponseDelta, String>>;
+                }
+            };
+            let seed = match ollama_seed(&context.params) {
+                Ok(seed) => seed,
+                Err(e) => {
+                    return Box::pin(stream::once(async move { Err(e) }))
+                        as BoxStream<'static, Result<PromptResponseDelta, String>>;
+                }
+            };
 
-        // Create a boxed stream that will contain our PromptResponseDelta items
-        let stream = async move {
-            let ollama = Ollama::default();
-            let request = GenerationRequest::new(model, prompt);
-
-            match ollama.generate_stream(request).await {
-                Ok(mut response_stream) => {
-                    // Map the Ollama response stream to our PromptResponseDelta stream
-                    Box::pin(stream::poll_fn(move |cx| {
-                        response_stream.poll_next_unpin(cx).map(|opt| {
-                            match opt {
-                                Some(Ok(responses)) => {
-                                    // Process all responses in the batch
-                                    if responses.is_empty() {
-                                        return None;
-                                    }
+            // Use the generalized formatting function
+            let prompt = format_ollama_prompt(&context, &tools_clone);
+            let params = context.params.clone();
 
-                                    // Get the last response to check if it's done
-                                    let last_response = responses.last().unwrap();
-                                    let is_done = last_response.done;
-
-                                    // Combine all response texts from this batch
-                                    let content = responses
-                                        .iter()
-                                        .map(|r| r.response.clone())
-                                        .collect::<Vec<String>>()
-                                        .join("");
-
-                                    // Create a delta with the content and stop reason if done
-                                    let delta = PromptResponseDelta {
-                                        content,
-                                        stop_reason: if is_done {
-                                            Some(StopReason::Stop)
-                                        } else {
-                                            None
-                                        },
-                                        tool_calls: None, // *it does support this; todo
-                                        cumulative_tokens: 0,
-                                    };
-
-                                    Some(Ok(delta))
-                                }
-                                Some(Err(e)) => {
-                                    Some(Err(format!("Ollama streaming error: {:?}", e)))
+            // Create a boxed stream that will contain our PromptResponseDelta items
+            let stream = async move {
+                let ollama = Ollama::default();
+                let request = generation_request(model, prompt, &params, seed, max_tokens);
+
+                match ollama.generate_stream(request).await {
+                    Ok(mut response_stream) => {
+                        // Map the Ollama response stream to our PromptResponseDelta stream
+                        Box::pin(stream::poll_fn(move |cx| {
+                            response_stream.poll_next_unpin(cx).map(|opt| {
+                                match opt {
+                                    Some(Ok(responses)) => {
+                                        // Process all responses in the batch
+                                        if responses.is_empty() {
+                                            return None;
+                                        }
+
+                                        // Get the last response to check if it's done
+                                        let last_response = responses.last().unwrap();
+                                        let is_done = last_response.done;
+
+                                        // Combine all response texts from this batch
+                                        let content = responses
+                                            .iter()
+                                            .map(|r| r.response.clone())
+                                            .collect::<Vec<String>>()
+                                            .join("");
+
+                                        // Create a delta with the content and stop reason if done
+                                        let delta = PromptResponseDelta {
+                                            content,
+                                            stop_reason: if is_done {
+                                                Some(StopReason::Stop)
+                                            } else {
+                                                None
+                                            },
+                                            tool_calls: None, // *it does support this; todo
+                                            cumulative_tokens: 0,
+                                            token_delta: 0,
+                                            logprobs: None,
+                                        };
+
+                                        Some(Ok(delta))
+                                    }
+                                    Some(Err(e)) => {
+                                        Some(Err(format!("Ollama streaming error: {:?}", e)))
+                                    }
+                                    None => None,
                                 }
-                                None => None,
-                            }
-                        })
-                    }))
-                        as BoxStream<'static, Result<PromptResponseDelta, String>>
-                }
-                Err(e) => {
-                    // Return a stream with a single error if we cant start streaming (String)
-                    Box::pin(stream::once(async move {
-                        Err(format!("Failed to start Ollama stream: {:?}", e))
-                    }))
-                        as BoxStream<'static, Result<PromptResponseDelta, String>>
+                            })
+                        }))
+                            as BoxStream<'static, Result<PromptResponseDelta, String>>
+                    }
+                    Err(e) => {
+                        // Return a stream with a single error if we cant start streaming (String)
+                        Box::pin(stream::once(async move {
+                            Err(format!("Failed to start Ollama stream: {:?}", e))
+                        }))
+                            as BoxStream<'static, Result<PromptResponseDelta, String>>
+                    }
                 }
-            }
-        };
+            };
+
+            // Return a boxed stream that will resolve to our real stream
+            Box::pin(stream::once(stream).flatten())
+                as BoxStream<'static, Result<PromptResponseDelta, String>>
+        });
+    filtering_streaming_adapter(adapter, filter)
+}
 
-        // Return a boxed stream that will resolve to our real stream
-        Box::pin(stream::once(stream).flatten())
-            as BoxStream<'static, Result<PromptResponseDelta, String>>
-    })
+/* --------------------------------- Errors --------------------------------- */
+
+/// ## `OllamaErrorClassifier`
+/// Classifies the errors the Ollama adapters return: an unreachable server is
+/// `Transient`, a busy one `RateLimited`, and a model that isn't pulled or
+/// doesn't fit in memory an `InvalidRequest`. Anything else goes to
+/// `HeuristicErrorClassifier`.
+#[derive(Clone, Copy, Default)]
+pub struct OllamaErrorClassifier;
+
+impl ErrorClassifier for OllamaErrorClassifier {
+    fn classify(&self, err: &str) -> ErrorClass {
+        let lowered = err.to_lowercase();
+        if lowered.contains("server busy") || lowered.contains("maximum pending requests") {
+            ErrorClass::RateLimited { retry_after: None }
+        } else if lowered.contains("not found, try pulling")
+            || lowered.contains("requires more system memory")
+        {
+            ErrorClass::InvalidRequest
+        } else if err.contains("ReqwestError(") {
+            ErrorClass::Transient
+        } else {
+            HeuristicErrorClassifier.classify(err)
+        }
+    }
 }
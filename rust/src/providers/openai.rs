@@ -1,58 +1,402 @@
 use async_openai::Client;
+use async_openai::config::OpenAIConfig;
 use async_openai::types::{
-    ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
-    ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs,
-    ChatCompletionRequestUserMessageArgs, ChatCompletionStreamOptions, ChatCompletionTool,
-    CreateChatCompletionRequestArgs, FinishReason,
+    ChatChoiceLogprobs, ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
+    ChatCompletionStreamOptions, ChatCompletionTool, CreateChatCompletionRequestArgs,
+    CreateChatCompletionResponse, CreateChatCompletionStreamResponse, FinishReason, FunctionCall,
+    ResponseFormat,
 };
 use futures::StreamExt;
 use futures::stream::{self, BoxStream};
-use std::sync::Arc;
+use regex::Regex;
+use std::sync::{Arc, LazyLock};
 
+use crate::capabilities::MaxTokensPolicy;
+use crate::resilience::{ErrorClass, ErrorClassifier, HeuristicErrorClassifier, retry_after_hint};
+use crate::response_filter::{ResponseFilter, filtering_adapter, filtering_streaming_adapter};
+use crate::tokens::{HeuristicTokenCounter, TokenCounter, estimate_prompt_tokens};
 use crate::{
     ContentType, ContextBuilder, Message, MessageRole, PromptResponse, PromptResponseDelta,
-    ProviderAdapter, StopReason, StreamProviderAdapter, ToolCall, ToolDescriptor,
+    Provenance, ProviderAdapter, ReproducibilityInfo, StopReason, StreamProviderAdapter,
+    TokenLogprob, ToolCall, ToolDescriptor,
 };
 
+/* ------------------------------ Role Mapping ------------------------------ */
+
+/// What to do with a message whose role the backend can't accept
+#[derive(Clone, PartialEq)]
+pub enum UnsupportedRolePolicy {
+    /// Send the message as if it had another role
+    RemapTo(MessageRole),
+    /// Send the message as user text, prefixed with a readable label
+    FlattenIntoUserText,
+    /// Refuse to build the request
+    Error,
+}
+
+/// How a single `MessageRole` goes out on the wire
+#[derive(Clone, PartialEq)]
+pub enum RoleTarget {
+    /// Send under this role string
+    Send(String),
+    /// The backend doesn't accept this role
+    Unsupported(UnsupportedRolePolicy),
+}
+
+impl RoleTarget {
+    pub fn send(role: &str) -> Self {
+        RoleTarget::Send(role.to_string())
+    }
+}
+
+/// ## `RoleMapping`
+/// Table mapping each `MessageRole` to what an OpenAI-compatible backend accepts.
+///
+/// The default matches the OpenAI API. For a backend that speaks "bot"/"human"
+/// and has no tool role:
+///
+/// ```rust,ignore
+/// let mapping = RoleMapping {
+///     user: RoleTarget::send("human"),
+///     model: RoleTarget::send("bot"),
+///     tool: RoleTarget::Unsupported(UnsupportedRolePolicy::FlattenIntoUserText),
+///     function: RoleTarget::Unsupported(UnsupportedRolePolicy::FlattenIntoUserText),
+///     ..RoleMapping::default()
+/// };
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct RoleMapping {
+    pub user: RoleTarget,
+    pub model: RoleTarget,
+    pub system: RoleTarget,
+    pub function: RoleTarget,
+    pub tool: RoleTarget,
+}
+
+impl Default for RoleMapping {
+    fn default() -> Self {
+        RoleMapping {
+            user: RoleTarget::send("user"),
+            model: RoleTarget::send("assistant"),
+            system: RoleTarget::send("system"),
+            function: RoleTarget::send("tool"),
+            tool: RoleTarget::send("tool"),
+        }
+    }
+}
+
+impl RoleMapping {
+    pub fn target(&self, role: &MessageRole) -> &RoleTarget {
+        match role {
+            MessageRole::User => &self.user,
+            MessageRole::Model => &self.model,
+            MessageRole::System => &self.system,
+            MessageRole::Function => &self.function,
+            MessageRole::Tool => &self.tool,
+        }
+    }
+
+    /// Follow the table (and any remaps) until the message lands on a role the
+    /// backend accepts. Returns the role to build the message as, the outgoing
+    /// role string, and the (possibly labelled) content.
+    pub fn resolve(&self, msg: &Message) -> Result<(MessageRole, String, String), String> {
+        let mut role = msg.role.clone();
+        let mut content = msg.content.clone();
+
+        // One hop per role is enough for any mapping that terminates
+        for _ in 0..=5 {
+            match self.target(&role) {
+                RoleTarget::Send(name) => return Ok((role, name.clone(), content)),
+                RoleTarget::Unsupported(UnsupportedRolePolicy::RemapTo(next)) => {
+                    role = next.clone();
+                }
+                RoleTarget::Unsupported(UnsupportedRolePolicy::FlattenIntoUserText) => {
+                    content = format!("{}: {}", role_label(&role), content);
+                    role = MessageRole::User;
+                }
+                RoleTarget::Unsupported(UnsupportedRolePolicy::Error) => {
+                    return Err(format!(
+                        "Backend does not accept {} messages",
+                        role_label(&role)
+                    ));
+                }
+            }
+        }
+
+        Err(format!(
+            "Role mapping for {} messages never reaches a supported role",
+            role_label(&msg.role)
+        ))
+    }
+}
+
+fn role_label(role: &MessageRole) -> &'static str {
+    match role {
+        MessageRole::User => "User",
+        MessageRole::Model => "Assistant",
+        MessageRole::System => "System",
+        MessageRole::Function => "Function result",
+        MessageRole::Tool => "Tool result",
+    }
+}
+
+/* --------------------------------- Options -------------------------------- */
+
+/// What to do with `RequestParams::prefill`, which chat completions can't express
+#[derive(Clone, Default, PartialEq)]
+pub enum PrefillPolicy {
+    /// Send the request without the prefill; it is still prepended to the
+    /// returned content by the send methods
+    #[default]
+    PrependToContent,
+    /// Refuse requests that carry a prefill
+    Error,
+}
+
+/// Request per-token log probabilities
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct LogprobOptions {
+    /// Alternatives returned per position, 0-20
+    pub top_logprobs: u8,
+}
+
+/// Configuration for the OpenAI-compatible adapters
+#[derive(Clone, Default)]
+pub struct OpenAIAdapterOptions {
+    pub role_mapping: RoleMapping,
+    pub prefill_policy: PrefillPolicy,
+    /// Default and clamping for the `max_tokens` the adapter is called with
+    pub max_tokens: MaxTokensPolicy,
+    /// When set, responses and deltas carry `logprobs`
+    pub logprobs: Option<LogprobOptions>,
+    /// Deviations of the backend from the OpenAI API
+    pub quirks: QuirkProfile,
+    /// Client settings, e.g. the API base of an OpenAI-compatible server.
+    /// `None` reads them from the environment.
+    pub client_config: Option<OpenAIConfig>,
+    /// Strips template artifacts from replies. Off by default; local
+    /// servers usually want `ResponseFilter::template_artifacts()`.
+    pub response_filter: ResponseFilter,
+}
+
+impl OpenAIAdapterOptions {
+    fn client(&self) -> Client<OpenAIConfig> {
+        match &self.client_config {
+            Some(config) => Client::with_config(config.clone()),
+            None => Client::new(),
+        }
+    }
+}
+
+/* --------------------------------- Quirks --------------------------------- */
+
+/// ## `QuirkProfile`
+/// Toggles for OpenAI-compatible servers that deviate from the OpenAI API,
+/// applied by both adapters. Start from a `QuirkPreset` and `union` in more:
+///
+/// ```rust,ignore
+/// let quirks = QuirkProfile::from(QuirkPreset::LmStudio).union(QuirkProfile {
+///     synthesize_usage_from_content: true,
+///     ..Default::default()
+/// });
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct QuirkProfile {
+    /// Don't send `stream_options`, which some servers reject
+    pub omit_stream_options: bool,
+    /// Send `max_tokens` even for o-series models, which otherwise get
+    /// `max_completion_tokens`
+    pub force_max_tokens_field: bool,
+    /// Don't mark tool schemas `strict`
+    pub no_strict_tools: bool,
+    /// Estimate usage from the prompt and reply when the server reports none
+    pub synthesize_usage_from_content: bool,
+    /// Merge every system message into one at the start of the conversation
+    pub single_system_message: bool,
+    /// The server accepts `seed` but doesn't make sampling reproducible with it
+    pub ignores_seed: bool,
+}
+
+impl QuirkProfile {
+    /// Every quirk set in either profile
+    pub fn union(self, other: QuirkProfile) -> QuirkProfile {
+        QuirkProfile {
+            omit_stream_options: self.omit_stream_options || other.omit_stream_options,
+            force_max_tokens_field: self.force_max_tokens_field || other.force_max_tokens_field,
+            no_strict_tools: self.no_strict_tools || other.no_strict_tools,
+            synthesize_usage_from_content: self.synthesize_usage_from_content
+                || other.synthesize_usage_from_content,
+            single_system_message: self.single_system_message || other.single_system_message,
+            ignores_seed: self.ignores_seed || other.ignores_seed,
+        }
+    }
+}
+
+/// Known OpenAI-compatible servers
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QuirkPreset {
+    /// The OpenAI API itself, or a server that matches it
+    Generic,
+    LmStudio,
+    Vllm,
+    /// llama.cpp's `llama-server`
+    LlamaCppServer,
+}
+
+impl From<QuirkPreset> for QuirkProfile {
+    fn from(preset: QuirkPreset) -> Self {
+        match preset {
+            QuirkPreset::Generic => QuirkProfile::default(),
+            QuirkPreset::LmStudio => QuirkProfile {
+                omit_stream_options: true,
+                no_strict_tools: true,
+                ..QuirkProfile::default()
+            },
+            QuirkPreset::Vllm => QuirkProfile {
+                force_max_tokens_field: true,
+                ..QuirkProfile::default()
+            },
+            QuirkPreset::LlamaCppServer => QuirkProfile {
+                no_strict_tools: true,
+                single_system_message: true,
+                ..QuirkProfile::default()
+            },
+        }
+    }
+}
+
+/// o-series reasoning models take `max_completion_tokens` instead of `max_tokens`
+fn is_o_series(model: &str) -> bool {
+    let mut chars = model.chars();
+    chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Merge the messages sent as `system_role` into one, placed first
+fn merge_system_messages(
+    messages: Vec<serde_json::Value>,
+    system_role: &str,
+) -> Vec<serde_json::Value> {
+    let (system, rest): (Vec<_>, Vec<_>) = messages
+        .into_iter()
+        .partition(|msg| msg["role"] == system_role);
+    if system.len() < 2 {
+        return system.into_iter().chain(rest).collect();
+    }
+
+    let content = system
+        .iter()
+        .filter_map(|msg| msg["content"].as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    std::iter::once(serde_json::json!({ "role": system_role, "content": content }))
+        .chain(rest)
+        .collect()
+}
+
+/// Usage estimated from the request and the reply, for servers that report none
+fn synthesized_usage(
+    context: &ContextBuilder,
+    tools: &Option<Vec<ToolDescriptor>>,
+    reply: &str,
+) -> u32 {
+    let tools = tools.as_deref().unwrap_or_default();
+    let prompt = estimate_prompt_tokens(context, tools, &HeuristicTokenCounter);
+    (prompt + HeuristicTokenCounter.count(reply)) as u32
+}
+
+/* ---------------------------- Request Building ---------------------------- */
+
+/// A steelwool tool call as an OpenAI assistant `tool_calls` entry, with
+/// its arguments as a JSON string
+fn openai_tool_call(call: &ToolCall) -> ChatCompletionMessageToolCall {
+    ChatCompletionMessageToolCall {
+        id: call.id.clone(),
+        r#type: async_openai::types::ChatCompletionToolType::Function,
+        function: FunctionCall {
+            name: call.name.clone(),
+            arguments: match &call.arguments {
+                serde_json::Value::String(raw) => raw.clone(),
+                other => other.to_string(),
+            },
+        },
+    }
+}
+
+/// Build the outgoing message list, with roles resolved through `role_mapping`
 pub fn build_chat_completion_message_history(
     context: &ContextBuilder,
-) -> Vec<ChatCompletionRequestMessage> {
-    let mut msg_vec: Vec<ChatCompletionRequestMessage> = vec![];
+    role_mapping: &RoleMapping,
+) -> Result<Vec<serde_json::Value>, String> {
+    let mut msg_vec: Vec<serde_json::Value> = vec![];
+
+    for msg in context.request_history().iter() {
+        let (role, role_name, content) = role_mapping.resolve(msg)?;
 
-    for msg in &context.history {
-        msg_vec.push(match msg.role {
+        let request_msg: ChatCompletionRequestMessage = match role {
             MessageRole::User => ChatCompletionRequestUserMessageArgs::default()
-                .content(msg.content.to_string())
+                .content(content)
                 .build()
                 .unwrap()
                 .into(),
-            MessageRole::Model => {
-                // TODO: This is where you would add tool calls when they happen
-                ChatCompletionRequestAssistantMessageArgs::default()
-                    .content(msg.content.to_string())
+            MessageRole::Model => match (&msg.content_type, &msg.assistant_tool_calls) {
+                (ContentType::ToolUse(call), _) => {
+                    ChatCompletionRequestAssistantMessageArgs::default()
+                        .tool_calls(vec![openai_tool_call(call)])
+                        .build()
+                        .unwrap()
+                        .into()
+                }
+                (_, Some(calls)) if !calls.is_empty() => {
+                    let mut assistant = ChatCompletionRequestAssistantMessageArgs::default();
+                    // OpenAI accepts a null content next to tool calls
+                    if !content.is_empty() {
+                        assistant.content(content);
+                    }
+                    assistant
+                        .tool_calls(calls.iter().map(openai_tool_call).collect::<Vec<_>>())
+                        .build()
+                        .unwrap()
+                        .into()
+                }
+                _ => ChatCompletionRequestAssistantMessageArgs::default()
+                    .content(content)
                     .build()
                     .unwrap()
-                    .into()
-            }
-            MessageRole::Function => ChatCompletionRequestToolMessageArgs::default()
-                .content(msg.content.to_string())
-                .build()
-                .unwrap()
-                .into(),
+                    .into(),
+            },
             MessageRole::System => ChatCompletionRequestSystemMessageArgs::default()
-                .content(msg.content.to_string())
-                .build()
-                .unwrap()
-                .into(),
-            MessageRole::Tool => ChatCompletionRequestToolMessageArgs::default()
-                .content(msg.content.to_string())
+                .content(content)
                 .build()
                 .unwrap()
                 .into(),
-        });
+            MessageRole::Function | MessageRole::Tool => {
+                let mut tool_msg = ChatCompletionRequestToolMessageArgs::default();
+                if let ContentType::ToolResult(result) = &msg.content_type {
+                    tool_msg.tool_call_id(result.tool_call_id.clone());
+                }
+                tool_msg.content(content).build().unwrap().into()
+            }
+        };
+
+        // The typed messages only know the standard role names, so the mapped
+        // name is written over the serialized one
+        let mut value = serde_json::to_value(request_msg)
+            .map_err(|e| format!("Failed to serialize OpenAI message: {}", e))?;
+        value["role"] = serde_json::Value::String(role_name);
+
+        // Consecutive tool-use turns are one assistant turn with parallel calls
+        if let (Some(previous), Some(calls)) = (msg_vec.last_mut(), value["tool_calls"].as_array())
+            && let Some(previous_calls) = previous["tool_calls"].as_array_mut()
+        {
+            previous_calls.extend(calls.iter().cloned());
+            continue;
+        }
+        msg_vec.push(value);
     }
 
-    msg_vec
+    Ok(msg_vec)
 }
 
 pub fn convert_steelwool_tools_to_openai(tools: Vec<ToolDescriptor>) -> Vec<ChatCompletionTool> {
@@ -70,87 +414,231 @@ pub fn convert_steelwool_tools_to_openai(tools: Vec<ToolDescriptor>) -> Vec<Chat
         .collect()
 }
 
+/// Build the full chat completion request body shared by both adapters
+pub fn build_chat_completion_request(
+    model: &str,
+    context: &ContextBuilder,
+    tools: Option<Vec<ToolDescriptor>>,
+    max_tokens: u32,
+    options: &OpenAIAdapterOptions,
+    stream: bool,
+) -> Result<serde_json::Value, String> {
+    if context.params.prefill.is_some() && options.prefill_policy == PrefillPolicy::Error {
+        return Err("OpenAI chat completions do not support assistant prefill".to_string());
+    }
+
+    // Format the message history into the openai lib's one
+    let request_msgs = build_chat_completion_message_history(context, &options.role_mapping)?;
+
+    // Build the request body
+    let mut request_body = CreateChatCompletionRequestArgs::default();
+    request_body.model(model);
+    if is_o_series(model) && !options.quirks.force_max_tokens_field {
+        request_body.max_completion_tokens(max_tokens);
+    } else {
+        request_body.max_tokens(max_tokens);
+    }
+
+    if let Some(temperature) = context.params.temperature {
+        request_body.temperature(temperature);
+    }
+    if let Some(top_p) = context.params.top_p {
+        request_body.top_p(top_p);
+    }
+    if let Some(seed) = context.params.seed {
+        request_body.seed(seed as i64);
+    }
+    if context.params.json_mode {
+        request_body.response_format(ResponseFormat::JsonObject);
+    }
+    if let Some(logprobs) = options.logprobs {
+        request_body.logprobs(true);
+        if logprobs.top_logprobs > 0 {
+            request_body.top_logprobs(logprobs.top_logprobs);
+        }
+    }
+
+    if stream {
+        request_body.stream(true);
+        if !options.quirks.omit_stream_options {
+            request_body.stream_options(ChatCompletionStreamOptions {
+                include_usage: true,
+            });
+        }
+    }
+
+    // Add tools if provided
+    if let Some(tools_vec) = tools {
+        request_body.tools(convert_steelwool_tools_to_openai(tools_vec));
+    }
+
+    let request = request_body
+        .build()
+        .map_err(|e| format!("Failed to build OpenAI request: {}", e))?;
+
+    let mut body = serde_json::to_value(request)
+        .map_err(|e| format!("Failed to serialize OpenAI request: {}", e))?;
+    body["messages"] = match &options.role_mapping.system {
+        RoleTarget::Send(system_role) if options.quirks.single_system_message => {
+            serde_json::Value::Array(merge_system_messages(request_msgs, system_role))
+        }
+        _ => serde_json::Value::Array(request_msgs),
+    };
+    if options.quirks.no_strict_tools
+        && let Some(tools) = body["tools"].as_array_mut()
+    {
+        for tool in tools {
+            if let Some(function) = tool["function"].as_object_mut() {
+                function.remove("strict");
+            }
+        }
+    }
+
+    Ok(body)
+}
+
+/// Content token logprobs from a response choice
+pub fn map_logprobs(logprobs: &ChatChoiceLogprobs) -> Option<Vec<TokenLogprob>> {
+    logprobs.content.as_ref().map(|tokens| {
+        tokens
+            .iter()
+            .map(|token| TokenLogprob {
+                token: token.token.clone(),
+                logprob: token.logprob,
+                top_alternatives: token
+                    .top_logprobs
+                    .iter()
+                    .map(|top| (top.token.clone(), top.logprob))
+                    .collect(),
+            })
+            .collect()
+    })
+}
+
+/// What a response's `system_fingerprint` says about reproducing it with
+/// `seed`
+pub fn reproducibility_info(
+    seed: Option<u64>,
+    system_fingerprint: Option<String>,
+    quirks: &QuirkProfile,
+) -> ReproducibilityInfo {
+    ReproducibilityInfo {
+        seed_sent: seed,
+        fingerprint: system_fingerprint,
+        backend_claims_determinism: seed.is_some() && !quirks.ignores_seed,
+    }
+}
+
+fn map_finish_reason(reason: FinishReason) -> StopReason {
+    match reason {
+        FinishReason::Stop => StopReason::Stop,
+        FinishReason::Length => StopReason::Length,
+        FinishReason::ToolCalls => StopReason::ToolCalls,
+        FinishReason::ContentFilter => StopReason::ContentFilter,
+        FinishReason::FunctionCall => StopReason::ToolCalls,
+    }
+}
+
+/* -------------------------------- Adapters -------------------------------- */
+
 // Non-streaming adapter factory
 pub fn openai_adapter_factory(
     model_name: String,
     tools: Option<Vec<ToolDescriptor>>,
 ) -> ProviderAdapter {
-    Arc::new(move |context: ContextBuilder, max_tokens : u32| -> std::pin::Pin<Box<dyn Future<Output = Result<PromptResponse, String>> + Send>> {
+    openai_adapter_factory_with_options(model_name, tools, OpenAIAdapterOptions::default())
+}
 
+pub fn openai_adapter_factory_with_options(
+    model_name: String,
+    tools: Option<Vec<ToolDescriptor>>,
+    options: OpenAIAdapterOptions,
+) -> ProviderAdapter {
+    let filter = options.response_filter.clone();
+    let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, max_tokens: u32| {
         let model = model_name.clone();
         let tools_clone = tools.clone();
+        let options = options.clone();
 
         Box::pin(async move {
-
             // Build the openai client
-            let openai_client = Client::new();
-
-            // Format the message history into the openai lib's one
-            let request_msgs = build_chat_completion_message_history(&context);
-
-            // Build the request body
-            let mut binding = CreateChatCompletionRequestArgs::default();
-            let mut request_body = binding
-                .max_tokens(max_tokens)
-                .model(model)
-                .messages(request_msgs);
-
-            // Add tools if provided
-            if let Some(tools_vec) = tools_clone {
-                request_body = request_body.tools(
-                    convert_steelwool_tools_to_openai(tools_vec)
-                );
-            }
-
-            // Build the rest of the request from the builder
-            let request = request_body
-                .build()
-                .unwrap();
+            let openai_client = options.client();
+
+            let (max_tokens, clamped) = options.max_tokens.resolve(&model, max_tokens)?;
+            let tools_clone_for_usage = tools_clone.clone();
+            let request = build_chat_completion_request(
+                &model,
+                &context,
+                tools_clone,
+                max_tokens,
+                &options,
+                false,
+            )?;
 
             // Get the response
-            let response = openai_client
+            let response: CreateChatCompletionResponse = openai_client
                 .chat()
-                .create(request)
+                .create_byot(request)
                 .await
-                .unwrap();
+                .map_err(|e| format!("OpenAI request error: {:?}", e))?;
 
             let choice = &response.choices[0];
-
-            Ok(
-                PromptResponse {
-                    message: Message {
-                        role: MessageRole::Model,
-                        content: match &choice.message.content {
-                            Some(content) => content.clone(),
-                            None => "".to_string(),
-                        },
-                        content_type: ContentType::Text
-                    },
-                    stop_reason: match choice.finish_reason {
-                        Some(reason) => match reason {
-                            async_openai::types::FinishReason::Stop => StopReason::Stop,
-                            async_openai::types::FinishReason::Length => StopReason::Length,
-                            async_openai::types::FinishReason::ToolCalls => StopReason::ToolCalls,
-                            async_openai::types::FinishReason::ContentFilter => StopReason::ContentFilter,
-                            async_openai::types::FinishReason::FunctionCall => StopReason::ToolCalls,
-                        },
-                        None => StopReason::Stop,
-                    },
-                    token_usage: response.usage.unwrap().total_tokens,
-                    tool_calls: choice.message.tool_calls.as_ref().map(|tool_calls| tool_calls
-                                .iter()
-                                .map(|tc| {
-                                    ToolCall {
-                                        id: tc.id.clone(),
-                                        name: tc.function.name.clone(),
-                                        arguments: serde_json::Value::from(tc.function.arguments.clone())
-                                    }
-                                })
-                                .collect()),
+            let mut provenance = Provenance::default();
+            if let Some(note) = clamped {
+                provenance.record("max_tokens", note);
+            }
+            provenance.reproducibility = Some(reproducibility_info(
+                context.params.seed,
+                response.system_fingerprint.clone(),
+                &options.quirks,
+            ));
+            let token_usage = match &response.usage {
+                Some(usage) => usage.total_tokens,
+                None if options.quirks.synthesize_usage_from_content => {
+                    let reply = choice.message.content.as_deref().unwrap_or_default();
+                    provenance.record("usage", "estimated from content; server reported none");
+                    synthesized_usage(&context, &tools_clone_for_usage, reply)
                 }
-            )
+                None => 0,
+            };
+
+            let tool_calls: Option<Vec<ToolCall>> =
+                choice.message.tool_calls.as_ref().map(|tool_calls| {
+                    tool_calls
+                        .iter()
+                        .map(|tc| ToolCall {
+                            id: tc.id.clone(),
+                            name: tc.function.name.clone(),
+                            arguments: serde_json::Value::from(tc.function.arguments.clone()),
+                        })
+                        .collect()
+                });
+
+            Ok(PromptResponse {
+                message: Message {
+                    role: MessageRole::Model,
+                    content: match (&choice.message.content, &choice.message.refusal) {
+                        (Some(content), _) => content.clone(),
+                        (None, Some(refusal)) => refusal.clone(),
+                        (None, None) => "".to_string(),
+                    },
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: tool_calls.clone(),
+                },
+                stop_reason: match (choice.message.refusal.is_some(), choice.finish_reason) {
+                    (true, _) => StopReason::Refusal,
+                    (false, Some(reason)) => map_finish_reason(reason),
+                    (false, None) => StopReason::Stop,
+                },
+                token_usage,
+                tool_calls,
+                provenance,
+                logprobs: choice.logprobs.as_ref().and_then(map_logprobs),
+            })
         })
-    })
+    });
+    filtering_adapter(adapter, filter)
 }
 
 // Streaming adapter factory
@@ -158,148 +646,238 @@ pub fn openai_streaming_adapter_factory(
     model_name: String,
     tools: Option<Vec<ToolDescriptor>>,
 ) -> StreamProviderAdapter {
-    Arc::new(move |context: ContextBuilder, max_tokens: u32| {
-        let model = model_name.clone();
-        let tools_clone = tools.clone();
-
-        // Format the message history into the openai lib's one
-        let request_msgs = build_chat_completion_message_history(&context);
-
-        // Build the request body
-        let mut binding = CreateChatCompletionRequestArgs::default();
-        let mut request_body = binding
-            .max_tokens(max_tokens)
-            .model(model)
-            .messages(request_msgs)
-            .stream_options(ChatCompletionStreamOptions {
-                include_usage: true,
-            });
-
-        // Add tools if provided
-        if let Some(tools_vec) = tools_clone {
-            request_body = request_body.tools(convert_steelwool_tools_to_openai(tools_vec));
-        }
-
-        let request = request_body.build().unwrap();
-
-        let stream = async move {
-            let openai_client = Client::new();
-
-            let req_stream = openai_client.chat().create_stream(request).await;
-
-            let mut prepared_tool_call: Option<ToolCall> = None;
-            let mut tool_call_buffer: Option<ToolCall> = None;
-            let mut arguments_buffer = String::new();
-
-            match req_stream {
-                Ok(mut response_stream) => {
-                    Box::pin(stream::poll_fn(move |cx| {
-                        response_stream.poll_next_unpin(cx).map(|opt| {
-                            match opt {
-                                Some(Ok(response)) => {
+    openai_streaming_adapter_factory_with_options(
+        model_name,
+        tools,
+        OpenAIAdapterOptions::default(),
+    )
+}
 
-                                    if response.choices.is_empty() {
-                                        return response.usage.map(|usage| Ok(PromptResponseDelta {
-                                                content: "".to_string(),
-                                                stop_reason: Some(StopReason::Stop),
-                                                tool_calls: None,
-                                                cumulative_tokens: usage.completion_tokens,
-                                            }));
-                                    }
+pub fn openai_streaming_adapter_factory_with_options(
+    model_name: String,
+    tools: Option<Vec<ToolDescriptor>>,
+    options: OpenAIAdapterOptions,
+) -> StreamProviderAdapter {
+    let filter = options.response_filter.clone();
+    let adapter: StreamProviderAdapter = Arc::new(
+        move |context: ContextBuilder, max_tokens: u32| {
+            let request = options
+                .max_tokens
+                .resolve(&model_name, max_tokens)
+                .and_then(|(max_tokens, _)| {
+                    build_chat_completion_request(
+                        &model_name,
+                        &context,
+                        tools.clone(),
+                        max_tokens,
+                        &options,
+                        true,
+                    )
+                });
+            let request = match request {
+                Ok(request) => request,
+                Err(e) => {
+                    return Box::pin(stream::once(async move { Err(e) }))
+                        as BoxStream<'static, Result<PromptResponseDelta, String>>;
+                }
+            };
+
+            let client = options.client();
+            let synthesize_usage = options.quirks.synthesize_usage_from_content;
+            let stream = async move {
+                let openai_client = client;
+
+                let req_stream = openai_client
+                    .chat()
+                    .create_stream_byot::<_, CreateChatCompletionStreamResponse>(request)
+                    .await;
+
+                let mut prepared_tool_call: Option<ToolCall> = None;
+                let mut tool_call_buffer: Option<ToolCall> = None;
+                let mut arguments_buffer = String::new();
+                // For servers that never send a usage chunk
+                let mut saw_usage = false;
+                let mut reply = String::new();
+                let mut ended = false;
+
+                match req_stream {
+                    Ok(mut response_stream) => {
+                        Box::pin(stream::poll_fn(move |cx| {
+                            if ended {
+                                return std::task::Poll::Ready(None);
+                            }
+                            response_stream.poll_next_unpin(cx).map(|opt| {
+                                match opt {
+                                    Some(Ok(response)) => {
+
+                                        if response.choices.is_empty() {
+                                            saw_usage |= response.usage.is_some();
+                                            // The finish reason came with the last choice, and
+                                            // earlier chunks carry no usage
+                                            return response.usage.map(|usage| Ok(PromptResponseDelta {
+                                                    content: "".to_string(),
+                                                    stop_reason: None,
+                                                    tool_calls: None,
+                                                    cumulative_tokens: usage.completion_tokens,
+                                                    token_delta: usage.completion_tokens,
+                                                    logprobs: None,
+                                                }));
+                                        }
 
-                                    let first_choice = &response.choices[0];
+                                        let first_choice = &response.choices[0];
+                                        if let Some(content) = &first_choice.delta.content {
+                                            reply.push_str(content);
+                                        }
 
-                                    // Handling the concatenation of tool calls & their args
-                                    if let Some(tool_chunks) = &first_choice.delta.tool_calls {
-                                        for chunk in tool_chunks {
+                                        // Handling the concatenation of tool calls & their args
+                                        if let Some(tool_chunks) = &first_choice.delta.tool_calls {
+                                            for chunk in tool_chunks {
 
-                                            let function = chunk.function.as_ref().unwrap();
+                                                let function = chunk.function.as_ref().unwrap();
 
-                                            // If a tool call is already in the building buffer but a NEW tool call name
-                                            // pops up, assume that there was another tool call and swap the old buffer to
-                                            // "prepared" to be sent off next round
-                                            if let Some(tool_call) = &tool_call_buffer {
-                                                if function.name.is_some() {
+                                                // If a tool call is already in the building buffer but a NEW tool call name
+                                                // pops up, assume that there was another tool call and swap the old buffer to
+                                                // "prepared" to be sent off next round
+                                                if let Some(tool_call) = &tool_call_buffer {
+                                                    if function.name.is_some() {
 
-                                                    // Move the buffers to "prepared"
-                                                    prepared_tool_call = Some(ToolCall {
-                                                        arguments: serde_json::Value::from(arguments_buffer.to_string()), ..tool_call.clone()
-                                                    });
+                                                        // Move the buffers to "prepared"
+                                                        prepared_tool_call = Some(ToolCall {
+                                                            arguments: serde_json::Value::from(arguments_buffer.to_string()), ..tool_call.clone()
+                                                        });
 
-                                                    // Reset the buffers
-                                                    tool_call_buffer = None;
-                                                    arguments_buffer = "".to_string();
+                                                        // Reset the buffers
+                                                        tool_call_buffer = None;
+                                                        arguments_buffer = "".to_string();
+                                                    }
                                                 }
-                                            }
 
-                                            // If there is no tool call in the buffer then start a new tool call
-                                            // and start a running 
-                                            if tool_call_buffer.is_none() {
-                                                tool_call_buffer = Some(
-                                                    ToolCall {
-                                                        id: chunk.id.clone().unwrap(),
-                                                        name: function.name.as_ref().unwrap().to_string(),
-                                                        arguments: serde_json::Value::Null
-                                                    });
-                                                continue;
-                                            }
+                                                // If there is no tool call in the buffer then start a new tool call
+                                                // and start a running 
+                                                if tool_call_buffer.is_none() {
+                                                    tool_call_buffer = Some(
+                                                        ToolCall {
+                                                            id: chunk.id.clone().unwrap(),
+                                                            name: function.name.as_ref().unwrap().to_string(),
+                                                            arguments: serde_json::Value::Null
+                                                        });
+                                                    continue;
+                                                }
 
-                                            // Push additional arguments onto the arguments buffer
-                                            let tmp_args_content = arguments_buffer.clone();
-                                            arguments_buffer = tmp_args_content + &function.arguments.as_ref().cloned().unwrap_or_else(|| "".to_string());
+                                                // Push additional arguments onto the arguments buffer
+                                                let tmp_args_content = arguments_buffer.clone();
+                                                arguments_buffer = tmp_args_content + &function.arguments.as_ref().cloned().unwrap_or_else(|| "".to_string());
 
+                                            }
                                         }
-                                    }
 
-                                    if let Some(FinishReason::ToolCalls) = first_choice.finish_reason {
-                                        if let Some(tool_call) = &tool_call_buffer {
-                                            // Move the buffers to "prepared"
-                                            prepared_tool_call = Some(ToolCall {
-                                                arguments: serde_json::Value::from(arguments_buffer.to_string()), ..tool_call.clone()
-                                            });
-
-                                            // Reset the buffers
-                                            tool_call_buffer = None;
-                                            arguments_buffer = "".to_string();
+                                        if let Some(FinishReason::ToolCalls) = first_choice.finish_reason {
+                                            if let Some(tool_call) = &tool_call_buffer {
+                                                // Move the buffers to "prepared"
+                                                prepared_tool_call = Some(ToolCall {
+                                                    arguments: serde_json::Value::from(arguments_buffer.to_string()), ..tool_call.clone()
+                                                });
+
+                                                // Reset the buffers
+                                                tool_call_buffer = None;
+                                                arguments_buffer = "".to_string();
+                                            }
                                         }
-                                    }
 
-                                    Some(Ok(PromptResponseDelta {
-                                        content: first_choice.delta.content.clone().unwrap_or_default(),
-                                        stop_reason: match first_choice.finish_reason {
-                                            Some(reason) => match reason {
-                                                async_openai::types::FinishReason::Stop => Some(StopReason::Stop),
-                                                async_openai::types::FinishReason::Length => Some(StopReason::Length),
-                                                async_openai::types::FinishReason::ToolCalls => Some(StopReason::ToolCalls),
-                                                async_openai::types::FinishReason::ContentFilter => Some(StopReason::ContentFilter),
-                                                async_openai::types::FinishReason::FunctionCall => Some(StopReason::ToolCalls),
+                                        Some(Ok(PromptResponseDelta {
+                                            content: first_choice.delta.content.clone().unwrap_or_default(),
+                                            stop_reason: match first_choice.finish_reason {
+                                                Some(reason) => match reason {
+                                                    async_openai::types::FinishReason::Stop => Some(StopReason::Stop),
+                                                    async_openai::types::FinishReason::Length => Some(StopReason::Length),
+                                                    async_openai::types::FinishReason::ToolCalls => Some(StopReason::ToolCalls),
+                                                    async_openai::types::FinishReason::ContentFilter => Some(StopReason::ContentFilter),
+                                                    async_openai::types::FinishReason::FunctionCall => Some(StopReason::ToolCalls),
+                                                },
+                                                None => None,
                                             },
-                                            None => None,
-                                        },
 
-                                        tool_calls: prepared_tool_call.take().map(|tool_call| vec![tool_call]),
+                                            tool_calls: prepared_tool_call.take().map(|tool_call| vec![tool_call]),
 
-                                        // async-openai only ships tokens on the final delta with an empty response (fml)
-                                        cumulative_tokens: 0,
-                                    }))
-                                },
-                                Some(Err(e)) => {
-                                    Some(Err(format!("OpenAI streaming error: {:?}", e)))
+                                            // async-openai only ships tokens on the final delta with an empty response (fml)
+                                            cumulative_tokens: 0,
+                                            token_delta: 0,
+                                            logprobs: first_choice.logprobs.as_ref().and_then(map_logprobs),
+                                        }))
+                                    },
+                                    Some(Err(e)) => {
+                                        Some(Err(format!("OpenAI streaming error: {:?}", e)))
+                                    }
+                                    None if synthesize_usage && !saw_usage => {
+                                        ended = true;
+                                        let tokens = HeuristicTokenCounter.count(&reply) as u32;
+                                        Some(Ok(PromptResponseDelta {
+                                            content: "".to_string(),
+                                            stop_reason: None,
+                                            tool_calls: None,
+                                            cumulative_tokens: tokens,
+                                            token_delta: tokens,
+                                            logprobs: None,
+                                        }))
+                                    }
+                                    None => None,
                                 }
-                                None => None,
-                            }
-                        })
+                            })
+                        }))
+                            as BoxStream<'static, Result<PromptResponseDelta, String>>
+                    }
+                    Err(e) => Box::pin(stream::once(async move {
+                        Err(format!("Failed to start OpenAI stream: {:?}", e))
                     }))
-                        as BoxStream<'static, Result<PromptResponseDelta, String>>
+                        as BoxStream<'static, Result<PromptResponseDelta, String>>,
                 }
-                Err(e) => Box::pin(stream::once(async move {
-                    Err(format!("Failed to start OpenAI stream: {:?}", e))
-                }))
-                    as BoxStream<'static, Result<PromptResponseDelta, String>>,
+            };
+
+            Box::pin(stream::once(stream).flatten())
+                as BoxStream<'static, Result<PromptResponseDelta, String>>
+        },
+    );
+    filtering_streaming_adapter(adapter, filter)
+}
+
+/* --------------------------------- Errors --------------------------------- */
+
+static API_ERROR_FIELD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\b(type|code): Some\("([^"]*)"\)"#).unwrap());
+
+/// ## `OpenAIErrorClassifier`
+/// Classifies the errors the OpenAI adapters return by the API error's
+/// `code` and `type`, then by the client error kind, falling back to
+/// `HeuristicErrorClassifier`. An exhausted quota is an `AuthFailure`: the
+/// account can't make requests until billing changes.
+#[derive(Clone, Copy, Default)]
+pub struct OpenAIErrorClassifier;
+
+impl ErrorClassifier for OpenAIErrorClassifier {
+    fn classify(&self, err: &str) -> ErrorClass {
+        let mut code = None;
+        let mut kind = None;
+        for field in API_ERROR_FIELD.captures_iter(err) {
+            match &field[1] {
+                "code" => code = Some(field[2].to_string()),
+                _ => kind = Some(field[2].to_string()),
             }
-        };
+        }
 
-        Box::pin(stream::once(stream).flatten())
-            as BoxStream<'static, Result<PromptResponseDelta, String>>
-    })
+        let rate_limited = ErrorClass::RateLimited {
+            retry_after: retry_after_hint(err),
+        };
+        match (code.as_deref(), kind.as_deref()) {
+            (Some("rate_limit_exceeded"), _) | (_, Some("requests" | "tokens")) => rate_limited,
+            (Some("insufficient_quota" | "invalid_api_key"), _) => ErrorClass::AuthFailure,
+            (_, Some("authentication_error" | "permission_error")) => ErrorClass::AuthFailure,
+            (Some("content_policy_violation" | "content_filter"), _) => ErrorClass::ContentPolicy,
+            (Some("server_error"), _) | (_, Some("server_error")) => ErrorClass::Transient,
+            (Some("context_length_exceeded" | "model_not_found"), _) => ErrorClass::InvalidRequest,
+            (_, Some("invalid_request_error")) => ErrorClass::InvalidRequest,
+            _ if err.contains("Reqwest(") || err.contains("StreamError(") => ErrorClass::Transient,
+            _ if err.contains("InvalidArgument(") => ErrorClass::InvalidRequest,
+            _ => HeuristicErrorClassifier.classify(err),
+        }
+    }
 }
@@ -0,0 +1,288 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock};
+
+use async_openai::Client;
+use async_openai::config::OpenAIConfig;
+use async_openai::types::{
+    CreateImageRequest, Image, ImageModel, ImageResponseFormat, ImageSize, ImageStyle,
+    ImagesResponse,
+};
+use regex::Regex;
+use serde_json::json;
+
+use crate::providers::openai::OpenAIErrorClassifier;
+use crate::resilience::{ErrorClass, ErrorClassifier};
+use crate::{ToolCall, ToolDescriptor, ToolExecuter};
+
+/* ------------------------------- Signatures ------------------------------- */
+
+/// ## `ImageGenerator`
+/// Sends an image request and returns the generated images, or an error
+/// string shaped like the OpenAI adapters' errors
+pub type ImageGenerator = Arc<
+    dyn Fn(
+            CreateImageRequest,
+        ) -> Pin<Box<dyn Future<Output = Result<ImagesResponse, String>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// An `ImageGenerator` calling the OpenAI images endpoint through `client`
+pub fn openai_image_generator(client: Client<OpenAIConfig>) -> ImageGenerator {
+    Arc::new(move |request: CreateImageRequest| {
+        let client = client.clone();
+        Box::pin(async move {
+            client
+                .images()
+                .create(request)
+                .await
+                .map_err(|e| format!("OpenAI request error: {:?}", e))
+        })
+    })
+}
+
+/* --------------------------------- Config --------------------------------- */
+
+/// How a generated image comes back in the tool result
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum ImageDelivery {
+    /// A hosted URL, valid for about an hour
+    #[default]
+    Url,
+    /// The PNG inline as base64, for vision models or apps that store it
+    Base64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageGenerationConfig {
+    pub tool_name: String,
+    /// `"dall-e-3"`, `"dall-e-2"` or another images model
+    pub model: String,
+    pub delivery: ImageDelivery,
+    /// Used when the model doesn't pick a size
+    pub default_size: ImageSize,
+}
+
+impl Default for ImageGenerationConfig {
+    fn default() -> Self {
+        ImageGenerationConfig {
+            tool_name: "generate_image".to_string(),
+            model: "dall-e-3".to_string(),
+            delivery: ImageDelivery::Url,
+            default_size: ImageSize::S1024x1024,
+        }
+    }
+}
+
+impl ImageGenerationConfig {
+    fn image_model(&self) -> ImageModel {
+        match self.model.as_str() {
+            "dall-e-2" => ImageModel::DallE2,
+            "dall-e-3" => ImageModel::DallE3,
+            other => ImageModel::Other(other.to_string()),
+        }
+    }
+
+    /// Sizes the model accepts
+    fn sizes(&self) -> &'static [&'static str] {
+        match self.model.as_str() {
+            "dall-e-2" => &["256x256", "512x512", "1024x1024"],
+            _ => &["1024x1024", "1792x1024", "1024x1792"],
+        }
+    }
+
+    fn supports_style(&self) -> bool {
+        self.model != "dall-e-2"
+    }
+}
+
+/* ---------------------------------- Tool ---------------------------------- */
+
+/// ## `image_generation_tool`
+/// Descriptor for a tool that draws an image from a prompt, with optional
+/// `size` and `style`. Execute its calls with `image_generation_executer`.
+///
+/// ```rust,ignore
+/// let config = ImageGenerationConfig::default();
+/// let tools = vec![image_generation_tool(&config)];
+/// let executer = image_generation_executer(openai_image_generator(Client::new()), config);
+/// ```
+pub fn image_generation_tool(config: &ImageGenerationConfig) -> ToolDescriptor {
+    let mut properties = json!({
+        "prompt": {
+            "type": "string",
+            "description": "Detailed description of the image to draw"
+        },
+        "size": {
+            "type": "string",
+            "enum": config.sizes(),
+            "description": "Width x height in pixels"
+        }
+    });
+    if config.supports_style() {
+        properties["style"] = json!({
+            "type": "string",
+            "enum": ["vivid", "natural"],
+            "description": "vivid for dramatic, hyper-real images; natural for realistic ones"
+        });
+    }
+
+    ToolDescriptor::from_json_schema(
+        config.tool_name.clone(),
+        "Generate an image from a text description".to_string(),
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": ["prompt"],
+        }),
+    )
+}
+
+/// Tool error the model can react to: a JSON object with the error's `kind`,
+/// a `message` and whether trying again may help
+pub fn image_tool_error(class: ErrorClass, message: &str) -> String {
+    let kind = match class {
+        ErrorClass::RateLimited { .. } => "rate_limited",
+        ErrorClass::Transient => "transient",
+        ErrorClass::AuthFailure => "auth_failure",
+        ErrorClass::InvalidRequest => "invalid_request",
+        ErrorClass::ContentPolicy => "content_policy",
+        ErrorClass::Unknown => "unknown",
+    };
+    json!({
+        "error": {
+            "kind": kind,
+            "message": message,
+            "retryable": class.is_retryable(),
+        }
+    })
+    .to_string()
+}
+
+static API_MESSAGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"message: "((?:[^"\\]|\\.)*)""#).unwrap());
+
+/// `image_tool_error` for an error from an `ImageGenerator`, keeping only the
+/// API's message when there is one
+pub fn map_image_error(err: &str) -> String {
+    let message = API_MESSAGE
+        .captures(err)
+        .map(|c| c[1].replace("\\\"", "\""))
+        .unwrap_or_else(|| err.to_string());
+    image_tool_error(OpenAIErrorClassifier.classify(err), &message)
+}
+
+/// The image request for a tool call's arguments
+pub fn build_image_request(
+    config: &ImageGenerationConfig,
+    arguments: &serde_json::Value,
+) -> Result<CreateImageRequest, String> {
+    let invalid = |message: String| image_tool_error(ErrorClass::InvalidRequest, &message);
+
+    // OpenAI sends arguments as a JSON string
+    let arguments = match arguments {
+        serde_json::Value::String(raw) => serde_json::from_str(raw)
+            .map_err(|e| invalid(format!("Arguments aren't valid JSON: {}", e)))?,
+        other => other.clone(),
+    };
+
+    let prompt = match arguments["prompt"].as_str() {
+        Some(prompt) if !prompt.trim().is_empty() => prompt.to_string(),
+        _ => return Err(invalid("A non-empty prompt is required".to_string())),
+    };
+
+    let size = match arguments["size"].as_str() {
+        None => config.default_size,
+        Some(size) if config.sizes().contains(&size) => {
+            serde_json::from_value(json!(size)).map_err(|e| invalid(e.to_string()))?
+        }
+        Some(size) => {
+            return Err(invalid(format!(
+                "Size {} isn't supported by {}; use one of {}",
+                size,
+                config.model,
+                config.sizes().join(", ")
+            )));
+        }
+    };
+
+    let style = match (arguments["style"].as_str(), config.supports_style()) {
+        (Some("vivid"), true) => Some(ImageStyle::Vivid),
+        (Some("natural"), true) => Some(ImageStyle::Natural),
+        (None, _) | (_, false) => None,
+        (Some(style), true) => {
+            return Err(invalid(format!(
+                "Style {} isn't supported; use vivid or natural",
+                style
+            )));
+        }
+    };
+
+    Ok(CreateImageRequest {
+        prompt,
+        model: Some(config.image_model()),
+        n: Some(1),
+        response_format: Some(match config.delivery {
+            ImageDelivery::Url => ImageResponseFormat::Url,
+            ImageDelivery::Base64 => ImageResponseFormat::B64Json,
+        }),
+        size: Some(size),
+        style,
+        ..Default::default()
+    })
+}
+
+/// The tool result for generated images: a JSON object with `"type":
+/// "image"` and either `url` or `b64_json` (plus `mime_type`), and the
+/// prompt the API actually used when it revised it
+pub fn image_tool_result(response: &ImagesResponse) -> Result<String, String> {
+    let Some(image) = response.data.first() else {
+        return Err(image_tool_error(
+            ErrorClass::Unknown,
+            "The images API returned no image",
+        ));
+    };
+    let mut result = match image.as_ref() {
+        Image::Url {
+            url,
+            revised_prompt,
+        } => json!({ "type": "image", "url": url, "revised_prompt": revised_prompt }),
+        Image::B64Json {
+            b64_json,
+            revised_prompt,
+        } => json!({
+            "type": "image",
+            "mime_type": "image/png",
+            "b64_json": b64_json.as_str(),
+            "revised_prompt": revised_prompt,
+        }),
+    };
+    if result["revised_prompt"].is_null() {
+        result.as_object_mut().unwrap().remove("revised_prompt");
+    }
+    Ok(result.to_string())
+}
+
+/// ## `image_generation_executer`
+/// Executes calls to the `image_generation_tool` with `generator`. Failures,
+/// including the API's content policy and size errors, come back as
+/// `image_tool_error` JSON. Calls to other tools are rejected, so combine it
+/// with other executers by tool name.
+pub fn image_generation_executer(
+    generator: ImageGenerator,
+    config: ImageGenerationConfig,
+) -> ToolExecuter {
+    Arc::new(move |call: ToolCall| {
+        let generator = generator.clone();
+        let config = config.clone();
+        Box::pin(async move {
+            if call.name != config.tool_name {
+                return Err(format!("unknown tool: {}", call.name));
+            }
+            let request = build_image_request(&config, &call.arguments)?;
+            let response = generator(request).await.map_err(|e| map_image_error(&e))?;
+            image_tool_result(&response)
+        })
+    })
+}
@@ -0,0 +1,123 @@
+//! Retrieval-augmented generation: embedding storage and document ingestion.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+pub mod chunking;
+
+/* -------------------------------- Embedding ------------------------------- */
+
+/// ## `EmbeddingAdapter`
+/// **Type Alias**: Embeds a batch of texts, returning one vector per input in
+/// the same order.
+pub type EmbeddingAdapter = Arc<
+    dyn Fn(Vec<String>) -> Pin<Box<dyn Future<Output = Result<Vec<Vec<f32>>, String>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/* ------------------------------- VectorStore ------------------------------ */
+
+/// An embedded piece of text and where it came from
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct VectorRecord {
+    pub id: String,
+    pub embedding: Vec<f32>,
+    pub text: String,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// ## `VectorStore`
+/// Keyed storage for embeddings with nearest-neighbour search. `upsert`
+/// replaces records with the same id, so writing the same records twice
+/// leaves the store unchanged.
+pub trait VectorStore: Send + Sync {
+    fn upsert(&self, records: Vec<VectorRecord>) -> Result<(), String>;
+    fn get(&self, id: &str) -> Result<Option<VectorRecord>, String>;
+    fn delete(&self, id: &str) -> Result<(), String>;
+    /// The `k` records most similar to `query`, best first, with their scores
+    fn search(&self, query: &[f32], k: usize) -> Result<Vec<(VectorRecord, f32)>, String>;
+    fn len(&self) -> Result<usize, String>;
+
+    fn is_empty(&self) -> Result<bool, String> {
+        Ok(self.len()? == 0)
+    }
+}
+
+/// `VectorStore` that scans every record by cosine similarity, for tests and
+/// small corpora
+#[derive(Default)]
+pub struct InMemoryVectorStore {
+    records: RwLock<BTreeMap<String, VectorRecord>>,
+}
+
+impl InMemoryVectorStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    match norm(a) * norm(b) {
+        0.0 => 0.0,
+        norms => dot / norms,
+    }
+}
+
+impl VectorStore for InMemoryVectorStore {
+    fn upsert(&self, records: Vec<VectorRecord>) -> Result<(), String> {
+        let mut stored = self
+            .records
+            .write()
+            .map_err(|e| format!("Vector store poisoned: {}", e))?;
+        for record in records {
+            stored.insert(record.id.clone(), record);
+        }
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<VectorRecord>, String> {
+        Ok(self
+            .records
+            .read()
+            .map_err(|e| format!("Vector store poisoned: {}", e))?
+            .get(id)
+            .cloned())
+    }
+
+    fn delete(&self, id: &str) -> Result<(), String> {
+        self.records
+            .write()
+            .map_err(|e| format!("Vector store poisoned: {}", e))?
+            .remove(id);
+        Ok(())
+    }
+
+    fn search(&self, query: &[f32], k: usize) -> Result<Vec<(VectorRecord, f32)>, String> {
+        let records = self
+            .records
+            .read()
+            .map_err(|e| format!("Vector store poisoned: {}", e))?;
+        let mut scored: Vec<(VectorRecord, f32)> = records
+            .values()
+            .map(|record| (record.clone(), cosine_similarity(query, &record.embedding)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(k);
+        Ok(scored)
+    }
+
+    fn len(&self) -> Result<usize, String> {
+        Ok(self
+            .records
+            .read()
+            .map_err(|e| format!("Vector store poisoned: {}", e))?
+            .len())
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use futures::StreamExt;
+use futures::stream;
+
+use crate::rag::{EmbeddingAdapter, VectorRecord, VectorStore};
+use crate::tokens::TokenCounter;
+
+/* --------------------------------- Policy --------------------------------- */
+
+/// Where `chunk_text` prefers to cut
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Boundary {
+    /// After `.`, `!` or `?` followed by whitespace
+    #[default]
+    Sentence,
+    /// At blank lines
+    Paragraph,
+    /// Anywhere between words, ignoring sentence structure
+    Fixed,
+}
+
+/// How documents are split. Sizes are measured with the `TokenCounter`
+/// passed alongside the policy.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ChunkPolicy {
+    pub max_tokens: usize,
+    /// Upper bound on the text a chunk repeats from the end of the one before
+    pub overlap_tokens: usize,
+    pub boundary: Boundary,
+}
+
+impl Default for ChunkPolicy {
+    fn default() -> Self {
+        ChunkPolicy {
+            max_tokens: 512,
+            overlap_tokens: 64,
+            boundary: Boundary::Sentence,
+        }
+    }
+}
+
+/* --------------------------------- Chunks --------------------------------- */
+
+/// A slice of a document ready to embed
+#[derive(Clone, PartialEq, Debug)]
+pub struct Chunk {
+    /// `<document id>:<start>-<end>`, or just the range for `chunk_text`, so
+    /// the same text always gets the same id
+    pub id: String,
+    pub text: String,
+    /// Position of `text` in the source document
+    pub byte_range: Range<usize>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// A document to ingest; its metadata is copied onto every chunk
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Document {
+    pub id: String,
+    pub text: String,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Byte ranges of the pieces between `boundary` cuts. The ranges are
+/// contiguous and cover `text`; whitespace after a cut belongs to the piece
+/// before it.
+fn units(text: &str, range: Range<usize>, boundary: Boundary) -> Vec<Range<usize>> {
+    let chars: Vec<(usize, char)> = text[range.clone()]
+        .char_indices()
+        .map(|(i, c)| (range.start + i, c))
+        .collect();
+
+    let mut cuts = vec![range.start];
+    let mut i = 0;
+    while i < chars.len() {
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let at_boundary = match boundary {
+            Boundary::Sentence => {
+                matches!(chars[i].1, '.' | '!' | '?') && next.is_some_and(char::is_whitespace)
+            }
+            Boundary::Paragraph => chars[i].1 == '\n' && next == Some('\n'),
+            Boundary::Fixed => chars[i].1.is_whitespace(),
+        };
+        if !at_boundary {
+            i += 1;
+            continue;
+        }
+
+        i += 1;
+        while i < chars.len() && chars[i].1.is_whitespace() {
+            i += 1;
+        }
+        if let Some((cut, _)) = chars.get(i) {
+            cuts.push(*cut);
+        }
+    }
+    cuts.push(range.end);
+
+    cuts.windows(2).map(|w| w[0]..w[1]).collect()
+}
+
+/// Split any piece over `max_tokens` at word breaks, and any word still over
+/// it at character breaks, so every piece fits on its own
+fn fitting_units(
+    text: &str,
+    boundary: Boundary,
+    max_tokens: usize,
+    counter: &dyn TokenCounter,
+) -> Vec<Range<usize>> {
+    let fits = |range: &Range<usize>| counter.count(&text[range.clone()]) <= max_tokens;
+
+    units(text, 0..text.len(), boundary)
+        .into_iter()
+        .flat_map(|unit| match fits(&unit) || boundary == Boundary::Fixed {
+            true => vec![unit],
+            false => units(text, unit, Boundary::Fixed),
+        })
+        .flat_map(|unit| {
+            if fits(&unit) {
+                return vec![unit];
+            }
+            // No usable boundary at all: fall back to as many characters as fit
+            let mut pieces = vec![];
+            let mut start = unit.start;
+            for (offset, c) in text[unit.clone()].char_indices() {
+                let end = unit.start + offset + c.len_utf8();
+                if end - start > c.len_utf8() && !fits(&(start..end)) {
+                    pieces.push(start..unit.start + offset);
+                    start = unit.start + offset;
+                }
+            }
+            pieces.push(start..unit.end);
+            pieces
+        })
+        .collect()
+}
+
+/// ## `chunk_text`
+/// Split `text` into chunks of at most `policy.max_tokens`, cutting at
+/// `policy.boundary` where possible. Each chunk after the first starts with
+/// the trailing pieces of the previous one, up to `policy.overlap_tokens`.
+///
+/// Text smaller than one chunk comes back as a single chunk; text with no
+/// boundaries is cut between words, then between characters.
+///
+/// ```rust,ignore
+/// let policy = ChunkPolicy { max_tokens: 256, overlap_tokens: 32, boundary: Boundary::Paragraph };
+/// let chunks = chunk_text(&manual, &policy, &HeuristicTokenCounter);
+/// ```
+pub fn chunk_text(text: &str, policy: &ChunkPolicy, counter: &dyn TokenCounter) -> Vec<Chunk> {
+    if text.trim().is_empty() {
+        return vec![];
+    }
+
+    let max_tokens = policy.max_tokens.max(1);
+    let units = fitting_units(text, policy.boundary, max_tokens, counter);
+    let tokens =
+        |first: usize, last: usize| counter.count(&text[units[first].start..units[last].end]);
+
+    let mut chunks = vec![];
+    let mut first = 0;
+    loop {
+        let mut end = first + 1;
+        while end < units.len() && tokens(first, end) <= max_tokens {
+            end += 1;
+        }
+
+        let start = units[first].start;
+        let stop = start + text[start..units[end - 1].end].trim_end().len();
+        chunks.push(Chunk {
+            id: format!("{}-{}", start, stop),
+            text: text[start..stop].to_string(),
+            byte_range: start..stop,
+            metadata: BTreeMap::new(),
+        });
+
+        if end == units.len() {
+            break;
+        }
+
+        // Step back over trailing pieces while they fit in the overlap, but
+        // always move forward
+        let mut next = end;
+        while next > first + 1 && tokens(next - 1, end - 1) <= policy.overlap_tokens {
+            next -= 1;
+        }
+        first = next;
+    }
+
+    chunks
+}
+
+/// `chunk_text` for a whole document: ids are prefixed with the document id
+/// and chunks carry the document's metadata plus `document_id` and
+/// `chunk_index`
+pub fn chunk_document(
+    document: &Document,
+    policy: &ChunkPolicy,
+    counter: &dyn TokenCounter,
+) -> Vec<Chunk> {
+    chunk_text(&document.text, policy, counter)
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut metadata = document.metadata.clone();
+            metadata.insert("document_id".to_string(), document.id.clone());
+            metadata.insert("chunk_index".to_string(), index.to_string());
+            Chunk {
+                id: format!("{}:{}", document.id, chunk.id),
+                metadata,
+                ..chunk
+            }
+        })
+        .collect()
+}
+
+/* --------------------------------- Ingest --------------------------------- */
+
+/// How `ingest` batches embedding calls
+#[derive(Clone, PartialEq, Debug)]
+pub struct IngestOptions {
+    /// Chunks per embedding call
+    pub batch_size: usize,
+    /// Embedding calls in flight at once
+    pub concurrency: usize,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        IngestOptions {
+            batch_size: 32,
+            concurrency: 4,
+        }
+    }
+}
+
+/// What `ingest` wrote, and which chunks it couldn't
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct IngestReport {
+    /// Ids of chunks embedded and upserted
+    pub upserted: Vec<String>,
+    /// `(chunk id, error)` for chunks whose batch failed to embed or store
+    pub failed: Vec<(String, String)>,
+}
+
+impl IngestReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+async fn ingest_batch(
+    store: &dyn VectorStore,
+    embedder: EmbeddingAdapter,
+    batch: Vec<Chunk>,
+) -> Result<Vec<String>, (Vec<String>, String)> {
+    let ids: Vec<String> = batch.iter().map(|chunk| chunk.id.clone()).collect();
+
+    let embeddings = embedder(batch.iter().map(|chunk| chunk.text.clone()).collect())
+        .await
+        .map_err(|e| (ids.clone(), format!("Embedding failed: {}", e)))?;
+    if embeddings.len() != batch.len() {
+        return Err((
+            ids,
+            format!(
+                "Embedding returned {} vectors for {} chunks",
+                embeddings.len(),
+                batch.len()
+            ),
+        ));
+    }
+
+    let records = batch
+        .into_iter()
+        .zip(embeddings)
+        .map(|(chunk, embedding)| VectorRecord {
+            id: chunk.id,
+            embedding,
+            text: chunk.text,
+            metadata: chunk.metadata,
+        })
+        .collect();
+    store
+        .upsert(records)
+        .map_err(|e| (ids.clone(), format!("Upsert failed: {}", e)))?;
+
+    Ok(ids)
+}
+
+/// ## `ingest`
+/// Chunk `documents`, embed the chunks in batches and upsert them into
+/// `store`. A failed batch is reported per chunk and the rest carry on.
+/// Chunk ids depend only on the document id and byte range, so ingesting the
+/// same documents again overwrites rather than duplicates.
+///
+/// ```rust,ignore
+/// let report = ingest(&store, embedder, docs, &ChunkPolicy::default(), &counter, &IngestOptions::default()).await;
+/// for (id, error) in &report.failed { eprintln!("{}: {}", id, error); }
+/// ```
+pub async fn ingest(
+    store: &dyn VectorStore,
+    embedder: EmbeddingAdapter,
+    documents: Vec<Document>,
+    policy: &ChunkPolicy,
+    counter: &dyn TokenCounter,
+    options: &IngestOptions,
+) -> IngestReport {
+    let chunks: Vec<Chunk> = documents
+        .iter()
+        .flat_map(|document| chunk_document(document, policy, counter))
+        .collect();
+    let batches: Vec<Vec<Chunk>> = chunks
+        .chunks(options.batch_size.max(1))
+        .map(<[Chunk]>::to_vec)
+        .collect();
+
+    let outcomes: Vec<_> = stream::iter(batches)
+        .map(|batch| ingest_batch(store, embedder.clone(), batch))
+        .buffered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut report = IngestReport::default();
+    for outcome in outcomes {
+        match outcome {
+            Ok(ids) => report.upserted.extend(ids),
+            Err((ids, error)) => report
+                .failed
+                .extend(ids.into_iter().map(|id| (id, error.clone()))),
+        }
+    }
+    report
+}
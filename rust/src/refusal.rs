@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use crate::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponse, Provenance, ProviderAdapter,
+    StopReason, UnresolvedResponse,
+};
+
+/// Provenance source adapters record content-filter details under, e.g.
+/// which category tripped or the offending span
+pub const CONTENT_FILTER: &str = "content_filter";
+
+/// Provenance source for the steps `fallback_on_refusal` took, and for the
+/// error that made `send_with_fallback` or `send_tool_loop_streaming` fall
+/// back
+pub const FALLBACK: &str = "fallback";
+
+/// Provenance source holding the original refused message, as JSON
+pub const REFUSAL: &str = "refusal";
+
+/* ---------------------------------- Steps --------------------------------- */
+
+/// ## `RefusalTransformer`
+/// Rewrites the request after a refusal, given the refused response (whose
+/// provenance carries any `"content_filter"` details)
+pub type RefusalTransformer =
+    Arc<dyn Fn(ContextBuilder, &PromptResponse) -> ContextBuilder + Send + Sync>;
+
+/// One attempt at getting a safe answer after a refusal
+#[derive(Clone)]
+pub enum FallbackStep {
+    /// Transform the request and send it again to the current adapter
+    RetryWithTransformer(RefusalTransformer),
+    /// Send the request to this adapter, which stays current for later steps
+    SwitchAdapter(ProviderAdapter),
+    /// Answer with this text without sending anything
+    StaticMessage(String),
+}
+
+impl FallbackStep {
+    fn describe(&self) -> &'static str {
+        match self {
+            FallbackStep::RetryWithTransformer(_) => "retry with transformer",
+            FallbackStep::SwitchAdapter(_) => "switch adapter",
+            FallbackStep::StaticMessage(_) => "static message",
+        }
+    }
+}
+
+impl PromptResponse {
+    /// Whether the provider filtered the reply or the model declined
+    pub fn is_refusal(&self) -> bool {
+        matches!(
+            self.stop_reason,
+            StopReason::ContentFilter | StopReason::Refusal
+        )
+    }
+}
+
+fn static_response(content: &str) -> PromptResponse {
+    PromptResponse {
+        message: Message {
+            role: MessageRole::Model,
+            content: content.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        },
+        stop_reason: StopReason::Stop,
+        token_usage: 0,
+        tool_calls: None,
+        provenance: Provenance::default(),
+        logprobs: None,
+    }
+}
+
+/* -------------------------------- Fallbacks ------------------------------- */
+
+impl UnresolvedResponse {
+    /// ## `fallback_on_refusal`
+    /// If the response was filtered or refused, run `chain` in order until a
+    /// step yields a response that isn't, sending at most once per step.
+    /// `adapter` is the adapter the response came from, which transformer
+    /// retries use until a `SwitchAdapter` step replaces it. Transforms
+    /// build on each other but only live in the retried requests; the
+    /// context is left as it was.
+    ///
+    /// The accepted response's provenance lists every step taken under
+    /// `"fallback"` and keeps the original refused message under
+    /// `"refusal"` for audit. If every step fails, the last refusal is kept.
+    ///
+    /// ```rust,ignore
+    /// let context = context
+    ///     .send(adapter.clone(), 500)
+    ///     .await
+    ///     .fallback_on_refusal(adapter, 500, vec![
+    ///         FallbackStep::RetryWithTransformer(Arc::new(strip_flagged_span)),
+    ///         FallbackStep::SwitchAdapter(moderated_model),
+    ///         FallbackStep::StaticMessage("I can't help with that.".into()),
+    ///     ])
+    ///     .await
+    ///     .resolve_without();
+    /// ```
+    pub async fn fallback_on_refusal(
+        mut self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        chain: Vec<FallbackStep>,
+    ) -> Self {
+        if !self.prompt_response.is_refusal() {
+            return self;
+        }
+
+        let original = self.prompt_response.message.clone();
+        let mut adapter = adapter;
+        let mut attempt = self.context_builder.outgoing();
+        let mut latest = self.prompt_response.clone();
+        let mut trail: Vec<String> = vec![];
+
+        for (index, step) in chain.into_iter().enumerate() {
+            let label = format!("step {} ({})", index + 1, step.describe());
+            let result = match step {
+                FallbackStep::StaticMessage(content) => Ok(static_response(&content)),
+                FallbackStep::RetryWithTransformer(transform) => {
+                    attempt = transform(attempt, &latest);
+                    adapter(attempt.clone(), max_tokens).await
+                }
+                FallbackStep::SwitchAdapter(next) => {
+                    adapter = next;
+                    adapter(attempt.clone(), max_tokens).await
+                }
+            };
+
+            match result {
+                Ok(response) if !response.is_refusal() => {
+                    trail.push(format!("{}: answered", label));
+                    latest = response;
+                    break;
+                }
+                Ok(response) => {
+                    trail.push(format!("{}: refused again", label));
+                    latest = response;
+                }
+                Err(err) => trail.push(format!("{}: failed: {}", label, err)),
+            }
+        }
+
+        let mut response = latest;
+        for step in trail {
+            response.provenance.record(FALLBACK, step);
+        }
+        response.provenance.record(
+            REFUSAL,
+            serde_json::to_string(&original).unwrap_or(original.content),
+        );
+        self.prompt_response = response;
+        self
+    }
+}
@@ -0,0 +1,620 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::refusal::FALLBACK;
+use crate::runtime::sleep;
+use crate::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponse, ProviderAdapter,
+    UnresolvedResponse,
+};
+
+/* ------------------------------- Signatures ------------------------------- */
+
+/// ## `ResponseValidator`
+/// Checks a response before it's accepted, returning the reason it was rejected
+pub type ResponseValidator = Arc<dyn Fn(&PromptResponse) -> Result<(), String> + Send + Sync>;
+
+/* ----------------------------- Classification ----------------------------- */
+
+/// What kind of failure an adapter error is, which decides whether the
+/// resilience wrappers retry it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ErrorClass {
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// Timeouts, dropped connections, overloaded or failing servers
+    Transient,
+    AuthFailure,
+    /// The request itself is wrong (bad arguments, unknown model, too long)
+    InvalidRequest,
+    ContentPolicy,
+    Unknown,
+}
+
+impl ErrorClass {
+    /// Whether the same request may succeed if sent again
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorClass::RateLimited { .. } | ErrorClass::Transient)
+    }
+}
+
+/// ## `ErrorClassifier`
+/// Turns an adapter's error string into an `ErrorClass`. Closures
+/// `Fn(&str) -> ErrorClass` are classifiers, so a custom provider can
+/// classify its own errors and fall back to `HeuristicErrorClassifier`.
+///
+/// ```rust,ignore
+/// let classifier = Arc::new(|err: &str| match err.starts_with("acme: busy") {
+///     true => ErrorClass::Transient,
+///     false => HeuristicErrorClassifier.classify(err),
+/// });
+/// ```
+pub trait ErrorClassifier: Send + Sync {
+    fn classify(&self, err: &str) -> ErrorClass;
+}
+
+impl<F> ErrorClassifier for F
+where
+    F: Fn(&str) -> ErrorClass + Send + Sync,
+{
+    fn classify(&self, err: &str) -> ErrorClass {
+        self(err)
+    }
+}
+
+static RETRY_AFTER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:retry[- ]after:?|try again in)\s*(\d+(?:\.\d+)?)\s*(ms|s)?").unwrap()
+});
+
+/// The wait an error message asks for, e.g. "Please try again in 1.5s" or
+/// "Retry-After: 20"
+pub fn retry_after_hint(err: &str) -> Option<Duration> {
+    let captures = RETRY_AFTER.captures(err)?;
+    let amount: f64 = captures[1].parse().ok()?;
+    Some(match captures.get(2).map(|unit| unit.as_str()) {
+        Some("ms") => Duration::from_secs_f64(amount / 1000.0),
+        _ => Duration::from_secs_f64(amount),
+    })
+}
+
+/// A status code leading the message or following status wording, e.g.
+/// "503 Service Unavailable" or "HTTP/1.1 429", so counts like "requested
+/// 500 tokens" aren't read as one
+static STATUS_CODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(?:^|\b(?:status|http|code)\b(?:/[\d.]+)?\D{0,3})(400|401|403|404|408|413|422|429|500|502|503|504)\b",
+    )
+    .unwrap()
+});
+
+/// Default classifier, matching status codes and common wording in error
+/// strings from any provider
+#[derive(Clone, Copy, Default)]
+pub struct HeuristicErrorClassifier;
+
+impl ErrorClassifier for HeuristicErrorClassifier {
+    fn classify(&self, err: &str) -> ErrorClass {
+        let lowered = err.to_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|needle| lowered.contains(needle));
+        let status = STATUS_CODE.captures(err).map(|c| c[1].to_string());
+        let status = status.as_deref();
+
+        if status == Some("429") || has(&["rate limit", "rate_limit", "too many requests"]) {
+            ErrorClass::RateLimited {
+                retry_after: retry_after_hint(err),
+            }
+        } else if matches!(status, Some("401" | "403"))
+            || has(&[
+                "unauthorized",
+                "forbidden",
+                "api key",
+                "api_key",
+                "authentication",
+            ])
+        {
+            ErrorClass::AuthFailure
+        } else if has(&[
+            "content policy",
+            "content_policy",
+            "content_filter",
+            "safety system",
+        ]) {
+            ErrorClass::ContentPolicy
+        } else if matches!(status, Some("408" | "500" | "502" | "503" | "504"))
+            || has(&[
+                "timeout",
+                "timed out",
+                "connection",
+                "overloaded",
+                "unavailable",
+                "temporarily",
+                "server_error",
+                "stream failed",
+            ])
+        {
+            ErrorClass::Transient
+        } else if matches!(status, Some("400" | "404" | "413" | "422"))
+            || has(&[
+                "invalid",
+                "not found",
+                "bad request",
+                "context length",
+                "too long",
+            ])
+        {
+            ErrorClass::InvalidRequest
+        } else {
+            ErrorClass::Unknown
+        }
+    }
+}
+
+/* ------------------------------- Escalation ------------------------------- */
+
+/// Caller-held handle on an `escalation_adapter`: raising it makes the next
+/// attempt move up a tier, and retry loops outside the adapter (see
+/// `validate_and_retry_with_escalation`) report their rejections through it
+#[derive(Clone, Default)]
+pub struct EscalationSignal {
+    raised: Arc<AtomicBool>,
+    validation_failures: Arc<AtomicUsize>,
+}
+
+impl EscalationSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn raise(&self) {
+        self.raised.store(true, Ordering::SeqCst);
+    }
+
+    fn take(&self) -> bool {
+        self.raised.swap(false, Ordering::SeqCst)
+    }
+
+    /// Count a response rejected outside the adapter toward the policy's
+    /// `max_validation_failures`. Kept until `reset_validation_failures`,
+    /// so every later call of the same turn starts from the count.
+    pub fn record_validation_failure(&self) {
+        self.validation_failures.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Start a new turn with no reported failures
+    pub fn reset_validation_failures(&self) {
+        self.validation_failures.store(0, Ordering::SeqCst);
+    }
+
+    pub fn validation_failures(&self) -> usize {
+        self.validation_failures.load(Ordering::SeqCst)
+    }
+}
+
+/// When `escalation_adapter` gives up on a tier. A `None` limit disables that
+/// trigger, so the failure is returned instead of retried.
+#[derive(Clone, Default)]
+pub struct EscalationPolicy {
+    /// Rejects responses; adapter errors count as validation failures too
+    pub validator: Option<ResponseValidator>,
+    pub max_validation_failures: Option<usize>,
+    /// Tool calls whose arguments aren't valid JSON
+    pub max_malformed_tool_arguments: Option<usize>,
+    pub signal: Option<EscalationSignal>,
+    /// When set, adapter errors it doesn't consider retryable are returned
+    /// at once instead of counting as validation failures
+    pub classifier: Option<Arc<dyn ErrorClassifier>>,
+}
+
+enum Failure {
+    Validation(String),
+    MalformedToolArguments(String),
+}
+
+fn malformed_tool_call(response: &PromptResponse) -> Option<String> {
+    response.tool_calls.as_ref()?.iter().find_map(|call| {
+        let serde_json::Value::String(raw) = &call.arguments else {
+            return None;
+        };
+        serde_json::from_str::<serde_json::Value>(raw)
+            .err()
+            .map(|e| format!("Malformed arguments for {}: {}", call.name, e))
+    })
+}
+
+/// ## `escalation_adapter`
+/// Wraps `tiers` (cheapest first) so that a turn which keeps failing on one
+/// model is retried on the next. Each call starts at the first tier, moved
+/// up by any validation failures reported through `signal`; the
+/// escalation chain and attempt count are recorded in the response
+/// provenance under `"escalation"`.
+///
+/// ```rust,ignore
+/// let adapter = escalation_adapter(
+///     vec![("mini".into(), cheap), ("large".into(), strong)],
+///     EscalationPolicy {
+///         validator: Some(Arc::new(|r| check_json(&r.message.content))),
+///         max_validation_failures: Some(2),
+///         ..EscalationPolicy::default()
+///     },
+/// );
+/// ```
+pub fn escalation_adapter(
+    tiers: Vec<(String, ProviderAdapter)>,
+    escalate_on: EscalationPolicy,
+) -> ProviderAdapter {
+    let tiers = Arc::new(tiers);
+
+    Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        let tiers = tiers.clone();
+        let policy = escalate_on.clone();
+
+        Box::pin(async move {
+            if tiers.is_empty() {
+                return Err("Escalation adapter has no tiers".to_string());
+            }
+
+            let mut chain: Vec<String> = vec![];
+            let mut tier = 0;
+            let mut attempts = 0;
+            let mut validation_failures = 0;
+            let mut malformed_tool_arguments = 0;
+
+            // Rejections the caller reported for this turn move it up front
+            let reported = policy
+                .signal
+                .as_ref()
+                .map_or(0, EscalationSignal::validation_failures);
+            if let Some(limit) = policy.max_validation_failures.filter(|limit| *limit > 0) {
+                validation_failures = reported;
+                while validation_failures >= limit && tier + 1 < tiers.len() {
+                    chain.push(format!(
+                        "{} -> {}: {} validation failures",
+                        tiers[tier].0,
+                        tiers[tier + 1].0,
+                        limit
+                    ));
+                    tier += 1;
+                    validation_failures -= limit;
+                }
+            }
+
+            loop {
+                if tier + 1 < tiers.len() && policy.signal.as_ref().is_some_and(|s| s.take()) {
+                    chain.push(format!(
+                        "{} -> {}: caller signal",
+                        tiers[tier].0,
+                        tiers[tier + 1].0
+                    ));
+                    tier += 1;
+                    validation_failures = 0;
+                    malformed_tool_arguments = 0;
+                }
+
+                let (label, adapter) = &tiers[tier];
+                attempts += 1;
+
+                let failure = match adapter(context.clone(), max_tokens).await {
+                    Err(e)
+                        if policy
+                            .classifier
+                            .as_ref()
+                            .is_some_and(|c| !c.classify(&e).is_retryable()) =>
+                    {
+                        return Err(e);
+                    }
+                    Err(e) => Failure::Validation(e),
+                    Ok(mut response) => {
+                        let rejected = match malformed_tool_call(&response) {
+                            Some(reason) => Some(Failure::MalformedToolArguments(reason)),
+                            None => policy
+                                .validator
+                                .as_ref()
+                                .and_then(|validate| validate(&response).err())
+                                .map(Failure::Validation),
+                        };
+
+                        match rejected {
+                            Some(failure) => failure,
+                            None => {
+                                for step in chain {
+                                    response.provenance.record("escalation", step);
+                                }
+                                response.provenance.record(
+                                    "escalation",
+                                    format!("answered by {} after {} attempts", label, attempts),
+                                );
+                                return Ok(response);
+                            }
+                        }
+                    }
+                };
+
+                let (count, limit, kind, reason) = match failure {
+                    Failure::Validation(reason) => {
+                        validation_failures += 1;
+                        (
+                            validation_failures,
+                            policy.max_validation_failures,
+                            "validation failures",
+                            reason,
+                        )
+                    }
+                    Failure::MalformedToolArguments(reason) => {
+                        malformed_tool_arguments += 1;
+                        (
+                            malformed_tool_arguments,
+                            policy.max_malformed_tool_arguments,
+                            "malformed tool arguments",
+                            reason,
+                        )
+                    }
+                };
+
+                match limit {
+                    None => return Err(reason),
+                    Some(limit) if count < limit => continue,
+                    Some(_) if tier + 1 < tiers.len() => {
+                        chain.push(format!(
+                            "{} -> {}: {} {}",
+                            label,
+                            tiers[tier + 1].0,
+                            count,
+                            kind
+                        ));
+                        tier += 1;
+                        validation_failures = 0;
+                        malformed_tool_arguments = 0;
+                    }
+                    Some(_) => {
+                        return Err(format!(
+                            "All escalation tiers failed after {} attempts: {}",
+                            attempts, reason
+                        ));
+                    }
+                }
+            }
+        })
+    })
+}
+
+/* --------------------------------- Retries -------------------------------- */
+
+/// How `retry_adapter` retries
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Sends in all, including the first
+    pub max_attempts: usize,
+    /// Doubled after each retry, up to `max_delay`
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub classifier: Arc<dyn ErrorClassifier>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            classifier: Arc::new(HeuristicErrorClassifier),
+        }
+    }
+}
+
+/// ## `retry_adapter`
+/// Wraps `adapter` so errors `policy.classifier` calls retryable are retried
+/// with exponential backoff, waiting as long as a rate limit asks when it
+/// says. Other errors, and the last retryable one, are returned unchanged.
+///
+/// ```rust,ignore
+/// let adapter = retry_adapter(
+///     openai_adapter_factory(client, "gpt-4o".into()),
+///     RetryPolicy {
+///         classifier: Arc::new(OpenAIErrorClassifier),
+///         ..RetryPolicy::default()
+///     },
+/// );
+/// ```
+pub fn retry_adapter(adapter: ProviderAdapter, policy: RetryPolicy) -> ProviderAdapter {
+    Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        let adapter = adapter.clone();
+        let policy = policy.clone();
+
+        Box::pin(async move {
+            let mut delay = policy.base_delay;
+            let mut attempt = 1;
+            loop {
+                let err = match adapter(context.clone(), max_tokens).await {
+                    Ok(response) => return Ok(response),
+                    Err(err) => err,
+                };
+                let class = policy.classifier.classify(&err);
+                if !class.is_retryable() || attempt >= policy.max_attempts {
+                    return Err(err);
+                }
+
+                let wait = match class {
+                    ErrorClass::RateLimited {
+                        retry_after: Some(retry_after),
+                    } => retry_after,
+                    _ => delay,
+                };
+                sleep(wait.min(policy.max_delay)).await;
+                delay = (delay * 2).min(policy.max_delay);
+                attempt += 1;
+            }
+        })
+    })
+}
+
+impl ContextBuilder {
+    /// ## `send_with_fallback`
+    /// Send to `primary`, and if it errors send the same request to
+    /// `fallback` once. A one-off alternative to wrapping adapters; panics
+    /// if both fail, like `send`. The fallback's response notes the
+    /// primary's error under `"fallback"` in its provenance.
+    ///
+    /// ```rust,ignore
+    /// let response = context.send_with_fallback(gpt, local_llama, 500).await;
+    /// ```
+    pub async fn send_with_fallback(
+        self,
+        primary: ProviderAdapter,
+        fallback: ProviderAdapter,
+        max_tokens: u32,
+    ) -> UnresolvedResponse {
+        self.try_send_with_fallback(primary, fallback, max_tokens)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to get PromptResponse: {}", e))
+    }
+
+    /// `send_with_fallback`, returning the fallback's error if both fail
+    pub async fn try_send_with_fallback(
+        mut self,
+        primary: ProviderAdapter,
+        fallback: ProviderAdapter,
+        max_tokens: u32,
+    ) -> Result<UnresolvedResponse, String> {
+        self.assert_open();
+        self = self.prepare_send().await?;
+        let request = self.outgoing();
+        let mut response = match primary(request.clone(), max_tokens).await {
+            Ok(response) => response,
+            Err(primary_err) => {
+                let mut response = fallback(request, max_tokens).await?;
+                response
+                    .provenance
+                    .record(FALLBACK, format!("primary failed: {}", primary_err));
+                response
+            }
+        };
+
+        if let Some(prefill) = self.params.prefill.take() {
+            response.message.content.insert_str(0, &prefill);
+        }
+        Ok(UnresolvedResponse {
+            prompt_response: response,
+            context_builder: self,
+        })
+    }
+
+    /// ## `validate_and_retry`
+    /// Send, and while `validator` rejects the reply, show the model its
+    /// rejected reply and the validator's feedback and ask again, up to
+    /// `max_attempts` sends in all. The corrective turns only live in the
+    /// retried requests; the returned response wraps the original context.
+    ///
+    /// ```rust,ignore
+    /// let response = context
+    ///     .validate_and_retry(adapter, 500, &constraints.validator(), 3)
+    ///     .await?
+    ///     .resolve_without();
+    /// ```
+    pub async fn validate_and_retry(
+        self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        validator: &ResponseValidator,
+        max_attempts: usize,
+    ) -> Result<UnresolvedResponse, String> {
+        self.validate_and_retry_inner(adapter, max_tokens, validator, max_attempts, None)
+            .await
+    }
+
+    /// ## `validate_and_retry_with_escalation`
+    /// `validate_and_retry` for an `escalation_adapter` holding `signal`:
+    /// every rejection is reported through it, so the retries move up the
+    /// adapter's tiers once they pass its `max_validation_failures`. Leave
+    /// the policy's own `validator` unset, or the adapter retries too.
+    ///
+    /// ```rust,ignore
+    /// let signal = EscalationSignal::new();
+    /// let adapter = escalation_adapter(tiers, EscalationPolicy {
+    ///     max_validation_failures: Some(2),
+    ///     signal: Some(signal.clone()),
+    ///     ..EscalationPolicy::default()
+    /// });
+    /// let response = context
+    ///     .validate_and_retry_with_escalation(adapter, 500, &validator, 5, &signal)
+    ///     .await?;
+    /// ```
+    pub async fn validate_and_retry_with_escalation(
+        self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        validator: &ResponseValidator,
+        max_attempts: usize,
+        signal: &EscalationSignal,
+    ) -> Result<UnresolvedResponse, String> {
+        signal.reset_validation_failures();
+        let result = self
+            .validate_and_retry_inner(adapter, max_tokens, validator, max_attempts, Some(signal))
+            .await;
+        signal.reset_validation_failures();
+        result
+    }
+
+    async fn validate_and_retry_inner(
+        mut self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        validator: &ResponseValidator,
+        max_attempts: usize,
+        signal: Option<&EscalationSignal>,
+    ) -> Result<UnresolvedResponse, String> {
+        self = self.prepare_send().await?;
+        let prefill = self.params.prefill.take();
+        let mut attempt = self.outgoing();
+        attempt.params.prefill = prefill.clone();
+        let mut last_feedback = String::new();
+
+        for _ in 0..max_attempts.max(1) {
+            let mut response = adapter(attempt.clone(), max_tokens).await?;
+            if let Some(prefill) = &prefill {
+                response.message.content.insert_str(0, prefill);
+            }
+
+            let feedback = match validator(&response) {
+                Ok(()) => {
+                    return Ok(UnresolvedResponse {
+                        prompt_response: response,
+                        context_builder: self,
+                    });
+                }
+                Err(feedback) => feedback,
+            };
+            if let Some(signal) = signal {
+                signal.record_validation_failure();
+            }
+
+            attempt.history.push(Message {
+                role: MessageRole::Model,
+                content: response.message.content,
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            });
+            attempt.history.push(Message {
+                role: MessageRole::User,
+                content: format!(
+                    "Your reply didn't meet the requirements:\n{}\nPlease answer again.",
+                    feedback
+                ),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            });
+            last_feedback = feedback;
+        }
+
+        Err(format!(
+            "Reply still invalid after {} attempts: {}",
+            max_attempts.max(1),
+            last_feedback
+        ))
+    }
+}
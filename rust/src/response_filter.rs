@@ -0,0 +1,354 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+use futures::stream::{self, BoxStream};
+use regex::Regex;
+
+use crate::{
+    ContextBuilder, MessageRole, PromptResponseDelta, ProviderAdapter, StreamProviderAdapter,
+};
+
+/// Provenance source listing what a `ResponseFilter` stripped
+pub const RESPONSE_FILTER: &str = "response_filter";
+
+/// Shorter messages aren't checked for echoes; a reply starting "Yes" to
+/// "Yes?" is not an echo
+const MIN_ECHO_CHARS: usize = 12;
+
+/* --------------------------------- Filter --------------------------------- */
+
+/// ## `ResponseFilter`
+/// Strips artifacts that chat templates of local models leave in replies,
+/// like a leading `<|im_start|>assistant` or a trailing `<|im_end|>`, and
+/// a copy of the last user message or a system message that the reply
+/// starts with. The default strips nothing; `template_artifacts` is the
+/// usual set for local backends.
+///
+/// ```rust,ignore
+/// let mut filter = ResponseFilter::template_artifacts();
+/// filter.leading.push(Regex::new(r"^\s*### Response:\s*")?);
+/// let adapter = filtering_adapter(adapter, filter);
+/// ```
+#[derive(Clone)]
+pub struct ResponseFilter {
+    /// Stripped from the start of a reply; anchor them with `^`
+    pub leading: Vec<Regex>,
+    /// Stripped from the end of a reply; anchor them with `$`
+    pub trailing: Vec<Regex>,
+    /// Strip an echoed copy of the last user message or a system message
+    pub strip_echoes: bool,
+    /// How alike (0-1) the start of a reply must be to a message to count
+    /// as an echo of it
+    pub echo_similarity: f64,
+    /// Streamed content held back until the start of the reply is filtered.
+    /// Longer artifacts and echoes are only caught without streaming.
+    pub stream_prefix_chars: usize,
+    /// Streamed content held back at the end so trailing artifacts can go
+    pub stream_suffix_chars: usize,
+}
+
+impl Default for ResponseFilter {
+    fn default() -> Self {
+        ResponseFilter {
+            leading: vec![],
+            trailing: vec![],
+            strip_echoes: false,
+            echo_similarity: 0.9,
+            stream_prefix_chars: 512,
+            stream_suffix_chars: 32,
+        }
+    }
+}
+
+fn patterns(sources: &[&str]) -> Vec<Regex> {
+    sources
+        .iter()
+        .map(|source| Regex::new(source).unwrap())
+        .collect()
+}
+
+impl ResponseFilter {
+    /// ChatML, Llama 3 and plain "assistant" turn markers, end-of-turn
+    /// tokens, and echoed prompts
+    pub fn template_artifacts() -> Self {
+        ResponseFilter {
+            leading: patterns(&[
+                r"^\s*<s>",
+                r"^\s*<\|im_start\|>\s*assistant\s*",
+                r"^\s*<\|start_header_id\|>\s*assistant\s*<\|end_header_id\|>\s*",
+                r"(?i)^\s*assistant:?[ \t]*\n\s*",
+            ]),
+            trailing: patterns(&[
+                r"\s*<\|im_end\|>\s*$",
+                r"\s*<\|eot_id\|>\s*$",
+                r"\s*<\|endoftext\|>\s*$",
+                r"\s*</s>\s*$",
+            ]),
+            strip_echoes: true,
+            ..ResponseFilter::default()
+        }
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_empty() && !self.strip_echoes
+    }
+
+    /// `content` with artifacts stripped, and a note for each
+    pub fn apply(&self, content: &str, context: &ContextBuilder) -> (String, Vec<String>) {
+        let (content, mut notes) = self.strip_start(content, &echo_candidates(context));
+        let (content, trailing) = self.strip_end(&content);
+        notes.extend(trailing);
+        (content, notes)
+    }
+
+    fn strip_start(&self, content: &str, echoes: &[String]) -> (String, Vec<String>) {
+        let mut rest = content;
+        let mut notes = vec![];
+        // Artifacts can stack, e.g. a turn marker before an echo
+        loop {
+            let before = rest.len();
+            for pattern in &self.leading {
+                if let Some(found) = pattern.find(rest).filter(|m| !m.is_empty()) {
+                    notes.push(format!("leading artifact {:?}", found.as_str()));
+                    rest = &rest[found.end()..];
+                }
+            }
+            let trimmed = rest.trim_start();
+            if self.strip_echoes
+                && let Some((end, similarity)) = echoes
+                    .iter()
+                    .filter_map(|echo| echoed_prefix(trimmed, echo, self.echo_similarity))
+                    .max_by_key(|(end, _)| *end)
+            {
+                notes.push(format!(
+                    "echoed prompt {:?} (similarity {:.2})",
+                    &trimmed[..end],
+                    similarity
+                ));
+                rest = trimmed[end..].trim_start();
+            }
+            if rest.len() == before {
+                return (rest.to_string(), notes);
+            }
+        }
+    }
+
+    fn strip_end(&self, content: &str) -> (String, Vec<String>) {
+        let mut rest = content;
+        let mut notes = vec![];
+        loop {
+            let before = rest.len();
+            for pattern in &self.trailing {
+                if let Some(found) = pattern.find(rest).filter(|m| !m.is_empty()) {
+                    notes.push(format!("trailing artifact {:?}", found.as_str()));
+                    rest = &rest[..found.start()];
+                }
+            }
+            if rest.len() == before {
+                return (rest.to_string(), notes);
+            }
+        }
+    }
+}
+
+/// The last user message and the system messages, which replies echo
+fn echo_candidates(context: &ContextBuilder) -> Vec<String> {
+    let last_user = context
+        .history
+        .iter()
+        .rev()
+        .find(|msg| msg.role == MessageRole::User);
+    context
+        .history
+        .iter()
+        .filter(|msg| msg.role == MessageRole::System)
+        .chain(last_user)
+        .map(|msg| msg.content.trim().to_lowercase())
+        .chain(
+            context
+                .params
+                .system_prompt
+                .iter()
+                .map(|p| p.trim().to_lowercase()),
+        )
+        .filter(|text| text.chars().count() >= MIN_ECHO_CHARS)
+        .collect()
+}
+
+/// Byte length of the prefix of `content` most like `echo`, and how alike
+/// they are, if at least `threshold`
+fn echoed_prefix(content: &str, echo: &str, threshold: f64) -> Option<(usize, f64)> {
+    let echo: Vec<char> = echo.chars().collect();
+    let slack = echo.len() / 10 + 1;
+    let prefix: Vec<(usize, char)> = content.char_indices().take(echo.len() + slack).collect();
+    if prefix.len() + slack < echo.len() {
+        return None;
+    }
+
+    // row[j] = edit distance between the echo so far and prefix[..j]
+    let mut row: Vec<usize> = (0..=prefix.len()).collect();
+    for (i, &e) in echo.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &(_, c)) in prefix.iter().enumerate() {
+            let substitution = diagonal + usize::from(c.to_lowercase().ne(e.to_lowercase()));
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    let (len, distance) = row
+        .iter()
+        .enumerate()
+        .min_by_key(|(len, distance)| (**distance, usize::MAX - len))?;
+    let similarity = 1.0 - *distance as f64 / echo.len() as f64;
+    let end = prefix
+        .get(len)
+        .map(|(index, _)| *index)
+        .unwrap_or(content.len());
+    (similarity >= threshold).then_some((end, similarity))
+}
+
+/* -------------------------------- Streaming ------------------------------- */
+
+/// Filters a reply as it streams: content is held back until enough has
+/// arrived to filter the start, and the last few characters are held back
+/// until the end
+struct DeltaFilter {
+    filter: ResponseFilter,
+    echoes: Vec<String>,
+    /// Start of the reply, not yet filtered
+    prefix: String,
+    started: bool,
+    /// Filtered content not yet released
+    held: String,
+    done: bool,
+}
+
+impl DeltaFilter {
+    fn push(&mut self, mut delta: PromptResponseDelta) -> PromptResponseDelta {
+        let ends = delta.stop_reason.is_some();
+        let content = std::mem::take(&mut delta.content);
+        delta.content = self.feed(&content, ends);
+        delta
+    }
+
+    fn feed(&mut self, content: &str, ends: bool) -> String {
+        if self.done {
+            return content.to_string();
+        }
+        if !self.started {
+            self.prefix.push_str(content);
+            if !ends && self.prefix.chars().count() < self.filter.stream_prefix_chars {
+                return String::new();
+            }
+            let (start, _) = self
+                .filter
+                .strip_start(&std::mem::take(&mut self.prefix), &self.echoes);
+            self.started = true;
+            self.held = start;
+        } else {
+            self.held.push_str(content);
+        }
+
+        if ends {
+            self.done = true;
+            return self.filter.strip_end(&std::mem::take(&mut self.held)).0;
+        }
+        let keep = self.filter.stream_suffix_chars;
+        let split = match self.held.char_indices().rev().nth(keep.saturating_sub(1)) {
+            Some((index, _)) if keep > 0 => index,
+            _ if keep > 0 => return String::new(),
+            _ => self.held.len(),
+        };
+        let released = self.held[..split].to_string();
+        self.held.drain(..split);
+        released
+    }
+
+    /// What's still held back when the stream ends without a stop reason
+    fn finish(&mut self) -> Option<PromptResponseDelta> {
+        let content = self.feed("", true);
+        (!content.is_empty()).then_some(PromptResponseDelta {
+            content,
+            stop_reason: None,
+            tool_calls: None,
+            cumulative_tokens: 0,
+            token_delta: 0,
+            logprobs: None,
+        })
+    }
+}
+
+/* -------------------------------- Wrappers -------------------------------- */
+
+/// ## `filtering_adapter`
+/// Wrap `adapter` so replies pass through `filter`. What was stripped is
+/// recorded in the response provenance under `"response_filter"`.
+///
+/// ```rust,ignore
+/// let adapter = filtering_adapter(adapter, ResponseFilter::template_artifacts());
+/// ```
+pub fn filtering_adapter(adapter: ProviderAdapter, filter: ResponseFilter) -> ProviderAdapter {
+    if filter.is_noop() {
+        return adapter;
+    }
+    let filter = Arc::new(filter);
+
+    Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        let filter = filter.clone();
+        let response = adapter(context.clone(), max_tokens);
+
+        Box::pin(async move {
+            let mut response = response.await?;
+            let (content, notes) = filter.apply(&response.message.content, &context);
+            response.message.content = content;
+            for note in notes {
+                response.provenance.record(RESPONSE_FILTER, note);
+            }
+            Ok(response)
+        })
+    })
+}
+
+/// ## `filtering_streaming_adapter`
+/// `filtering_adapter` for streams. The start of the reply is held back
+/// until `stream_prefix_chars` have arrived (or the stream ends), so an
+/// artifact split across deltas is still caught before any callback sees
+/// it. Deltas carry no provenance, so nothing records what was stripped.
+pub fn filtering_streaming_adapter(
+    adapter: StreamProviderAdapter,
+    filter: ResponseFilter,
+) -> StreamProviderAdapter {
+    if filter.is_noop() {
+        return adapter;
+    }
+
+    Arc::new(move |context: ContextBuilder, max_tokens: u32| {
+        let state = DeltaFilter {
+            echoes: echo_candidates(&context),
+            filter: filter.clone(),
+            prefix: String::new(),
+            started: false,
+            held: String::new(),
+            done: false,
+        };
+        let deltas = adapter(context, max_tokens);
+
+        Box::pin(stream::unfold(
+            (deltas, state, false),
+            |(mut deltas, mut state, ended)| async move {
+                if ended {
+                    return None;
+                }
+                match deltas.next().await {
+                    Some(Ok(delta)) => Some((Ok(state.push(delta)), (deltas, state, false))),
+                    Some(Err(e)) => Some((Err(e), (deltas, state, false))),
+                    None => state
+                        .finish()
+                        .map(|delta| (Ok(delta), (deltas, state, true))),
+                }
+            },
+        )) as BoxStream<'static, Result<PromptResponseDelta, String>>
+    })
+}
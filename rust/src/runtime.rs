@@ -0,0 +1,102 @@
+//! Executor-independent timers, tasks and channels.
+//!
+//! With `tokio-runtime` these run on tokio. Otherwise, or when
+//! `agnostic-runtime` is enabled, timers run on `async-io` and spawned tasks
+//! on their own threads, so they work under smol, async-std or
+//! `futures::executor::block_on`.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use futures::future::{Either, select};
+
+/// Bounded/unbounded channels; these are executor-independent already
+pub use futures::channel::mpsc;
+
+#[cfg(all(feature = "tokio-runtime", not(feature = "agnostic-runtime")))]
+mod imp {
+    use std::future::Future;
+    use std::time::Duration;
+
+    pub async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    pub fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(future);
+    }
+}
+
+#[cfg(any(not(feature = "tokio-runtime"), feature = "agnostic-runtime"))]
+mod imp {
+    use std::future::Future;
+    use std::time::Duration;
+
+    /// Driven by `async-io`'s timer thread, shared by every sleep
+    pub async fn sleep(duration: Duration) {
+        async_io::Timer::after(duration).await;
+    }
+
+    /// One thread per task, blocking on it until it finishes
+    pub fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        std::thread::spawn(move || futures::executor::block_on(future));
+    }
+}
+
+/// Wait for `duration` without blocking the executor
+pub async fn sleep(duration: Duration) {
+    imp::sleep(duration).await
+}
+
+/// Run `future`, giving up once `duration` has passed
+pub async fn timeout<F>(duration: Duration, future: F) -> Result<F::Output, String>
+where
+    F: Future,
+{
+    match select(Box::pin(future), Box::pin(sleep(duration))).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(format!("Timed out after {:?}", duration)),
+    }
+}
+
+/// Handle to a task started with `spawn`; resolves to its output
+pub struct JoinHandle<T> {
+    output: oneshot::Receiver<T>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.output)
+            .poll(cx)
+            .map_err(|_| "Task ended without finishing".to_string())
+    }
+}
+
+/// Run `future` in the background.
+///
+/// With `tokio-runtime` this is `tokio::spawn`, so it panics when called
+/// outside a tokio runtime. The agnostic fallback starts a thread for each
+/// task instead, which suits a handful of long-lived tasks rather than many
+/// short ones.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (done, output) = oneshot::channel();
+    imp::spawn(async move {
+        let _ = done.send(future.await);
+    });
+    JoinHandle { output }
+}
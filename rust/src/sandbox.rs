@@ -0,0 +1,209 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{ToolCall, ToolExecuter, ToolResult};
+
+/* --------------------------------- Policy --------------------------------- */
+
+/// ## `SandboxVeto`
+/// Last look at a call inside the sandbox, with the run's stats so far.
+/// Returning `Err` rejects the call with that reason.
+pub type SandboxVeto = Arc<dyn Fn(&ToolCall, &SandboxStats) -> Result<(), String> + Send + Sync>;
+
+/// Limits enforced by `sandbox` across one `SandboxRun`. `None` disables a limit.
+#[derive(Clone, Default)]
+pub struct SandboxPolicy {
+    /// Larger results are rejected rather than passed to the model
+    pub max_result_bytes: Option<usize>,
+    pub max_calls_per_run: Option<usize>,
+    /// Total wall time of every call in the run
+    pub max_cumulative_duration: Option<Duration>,
+    /// Checked against `SandboxRun::record_outbound_request`
+    pub max_outbound_requests: Option<usize>,
+    pub veto: Option<SandboxVeto>,
+}
+
+/* ---------------------------------- Runs ---------------------------------- */
+
+/// Resource use so far in a run
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct SandboxStats {
+    /// Calls that got past the limits and ran
+    pub calls: usize,
+    pub result_bytes: usize,
+    pub cumulative_duration: Duration,
+    pub outbound_requests: usize,
+    pub violations: usize,
+}
+
+#[derive(Default)]
+struct RunState {
+    stats: SandboxStats,
+    results: Vec<ToolResult>,
+}
+
+/// Shared accounting for one resolution run. Clone it into every sandboxed
+/// executor (and into tools that make network calls) so limits apply to the
+/// run as a whole.
+#[derive(Clone, Default)]
+pub struct SandboxRun {
+    state: Arc<Mutex<RunState>>,
+}
+
+impl SandboxRun {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> SandboxStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    /// Outcome of every sandboxed call, violations included, in call order
+    pub fn results(&self) -> Vec<ToolResult> {
+        self.state.lock().unwrap().results.clone()
+    }
+
+    /// Called by tools for each request they send out
+    pub fn record_outbound_request(&self) {
+        self.state.lock().unwrap().stats.outbound_requests += 1;
+    }
+
+    /// Start a fresh run, e.g. before the next user turn
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = RunState::default();
+    }
+
+    fn violation(&self, call: &ToolCall, rule: &str, detail: String) -> String {
+        let message = format!("Sandbox violation ({}): {}", rule, detail);
+        let mut state = self.state.lock().unwrap();
+        state.stats.violations += 1;
+        state.results.push(ToolResult {
+            tool_call_id: call.id.clone(),
+            result: message.clone(),
+            error: true,
+            simulated: false,
+        });
+        message
+    }
+
+    /// Check the limits that apply before a call and claim its slot. The
+    /// check and claim happen under one lock so concurrent calls can't both
+    /// take the last slot.
+    fn admit(&self, call: &ToolCall, policy: &SandboxPolicy) -> Result<(), String> {
+        let (stats, exceeded) = {
+            let mut state = self.state.lock().unwrap();
+            let stats = state.stats.clone();
+            let exceeded = exceeded_limit(&stats, policy);
+            if exceeded.is_none() {
+                state.stats.calls += 1;
+            }
+            (stats, exceeded)
+        };
+
+        if let Some((rule, detail)) = exceeded {
+            return Err(self.violation(call, rule, detail));
+        }
+
+        if let Some(veto) = &policy.veto
+            && let Err(reason) = veto(call, &stats)
+        {
+            self.state.lock().unwrap().stats.calls -= 1;
+            return Err(self.violation(call, "veto", reason));
+        }
+
+        Ok(())
+    }
+
+    fn finish(
+        &self,
+        call: &ToolCall,
+        policy: &SandboxPolicy,
+        result: Result<String, String>,
+        elapsed: Duration,
+    ) -> Result<String, String> {
+        self.state.lock().unwrap().stats.cumulative_duration += elapsed;
+
+        if let (Ok(output), Some(max)) = (&result, policy.max_result_bytes)
+            && output.len() > max
+        {
+            return Err(self.violation(
+                call,
+                "max_result_bytes",
+                format!("result of {} bytes exceeds {}", output.len(), max),
+            ));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if let Ok(output) = &result {
+            state.stats.result_bytes += output.len();
+        }
+        state.results.push(ToolResult {
+            tool_call_id: call.id.clone(),
+            result: result.clone().unwrap_or_else(|e| e),
+            error: result.is_err(),
+            simulated: false,
+        });
+        result
+    }
+}
+
+/// The first run-wide limit `stats` has already reached, as (rule, detail)
+fn exceeded_limit(stats: &SandboxStats, policy: &SandboxPolicy) -> Option<(&'static str, String)> {
+    if let Some(max) = policy.max_calls_per_run.filter(|max| stats.calls >= *max) {
+        return Some((
+            "max_calls_per_run",
+            format!("call {} exceeds the limit of {}", stats.calls + 1, max),
+        ));
+    }
+    if let Some(max) = policy
+        .max_cumulative_duration
+        .filter(|max| stats.cumulative_duration >= *max)
+    {
+        return Some((
+            "max_cumulative_duration",
+            format!("run has used {:?} of {:?}", stats.cumulative_duration, max),
+        ));
+    }
+    policy
+        .max_outbound_requests
+        .filter(|max| stats.outbound_requests >= *max)
+        .map(|max| {
+            (
+                "max_outbound_requests",
+                format!(
+                    "run has made {} of {} requests",
+                    stats.outbound_requests, max
+                ),
+            )
+        })
+}
+
+/* --------------------------------- Wrapper -------------------------------- */
+
+/// ## `sandbox`
+/// Wrap `executer` so every call is checked against `policy`, accounted in
+/// `run`, and turned into an error result when it breaks a limit.
+///
+/// ```rust,ignore
+/// let run = SandboxRun::new();
+/// let fetch = sandbox(fetch_url, SandboxPolicy { max_calls_per_run: Some(5), ..Default::default() }, &run);
+/// let context = response.resolve(fetch).await;
+/// ```
+pub fn sandbox(executer: ToolExecuter, policy: SandboxPolicy, run: &SandboxRun) -> ToolExecuter {
+    let run = run.clone();
+
+    Arc::new(move |call: ToolCall| {
+        let executer = executer.clone();
+        let policy = policy.clone();
+        let run = run.clone();
+
+        Box::pin(async move {
+            run.admit(&call, &policy)?;
+
+            let started = Instant::now();
+            let result = executer(call.clone()).await;
+            run.finish(&call, &policy, result, started.elapsed())
+        })
+    })
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ContextBuilder;
+
+/* ------------------------------- Scratchpad ------------------------------- */
+
+/// ## `Scratchpad`
+/// Conversation-scoped key/value state that steps share without putting it
+/// in the transcript, e.g. the user's selected account id. Saved with the
+/// conversation and never sent to a provider.
+///
+/// ```rust,ignore
+/// context.scratchpad.set("account_id", 42)?;
+/// let account: Option<u64> = context.scratchpad.get_as("account_id")?;
+/// ```
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(transparent)]
+pub struct Scratchpad {
+    values: BTreeMap<String, Value>,
+}
+
+impl Scratchpad {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    /// The value under `key` deserialized as `T`. Errors if it has another shape.
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, String> {
+        self.values
+            .get(key)
+            .map(|value| {
+                serde_json::from_value(value.clone())
+                    .map_err(|e| format!("Scratchpad value {} has the wrong type: {}", key, e))
+            })
+            .transpose()
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: impl Serialize) -> Result<(), String> {
+        let key = key.into();
+        let value = serde_json::to_value(value)
+            .map_err(|e| format!("Failed to serialize scratchpad value {}: {}", key, e))?;
+        self.values.insert(key, value);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.values.remove(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// A handle tools can read and write while they run. Take it before
+    /// executing tool calls and `merge_scratchpad` it back afterwards.
+    pub fn share(&self) -> SharedScratchpad {
+        SharedScratchpad {
+            state: Arc::new(Mutex::new(SharedState {
+                values: self.values.clone(),
+                writers: BTreeMap::new(),
+                writes: vec![],
+            })),
+        }
+    }
+}
+
+/* --------------------------------- Sharing -------------------------------- */
+
+/// A write made through a `SharedScratchpad`, for observability
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ScratchpadWrite {
+    /// The tool call that wrote it
+    pub call_id: String,
+    pub key: String,
+    pub value: Value,
+    /// Another call that wrote the same key since the handle was shared.
+    /// The later write wins.
+    pub conflicts_with: Option<String>,
+}
+
+struct SharedState {
+    values: BTreeMap<String, Value>,
+    /// Last call to write each key
+    writers: BTreeMap<String, String>,
+    writes: Vec<ScratchpadWrite>,
+}
+
+/// ## `SharedScratchpad`
+/// A conversation's scratchpad opened up to tools. Clone it into each tool
+/// executer; every clone sees the same values.
+///
+/// ```rust,ignore
+/// let shared = context.scratchpad.share();
+/// let tools = select_account_tool(shared.clone());
+/// let context = response.resolve(tools).await.merge_scratchpad(&shared);
+/// ```
+#[derive(Clone)]
+pub struct SharedScratchpad {
+    state: Arc<Mutex<SharedState>>,
+}
+
+impl SharedScratchpad {
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.state.lock().unwrap().values.get(key).cloned()
+    }
+
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, String> {
+        self.get(key)
+            .map(|value| {
+                serde_json::from_value(value)
+                    .map_err(|e| format!("Scratchpad value {} has the wrong type: {}", key, e))
+            })
+            .transpose()
+    }
+
+    /// Write `value` under `key` on behalf of the tool call `call_id`. If a
+    /// different call already wrote the key, this write wins and the
+    /// conflict is noted in `writes`.
+    pub fn set(&self, call_id: &str, key: &str, value: impl Serialize) -> Result<(), String> {
+        let value = serde_json::to_value(value)
+            .map_err(|e| format!("Failed to serialize scratchpad value {}: {}", key, e))?;
+
+        let mut state = self.state.lock().unwrap();
+        let conflicts_with = state
+            .writers
+            .insert(key.to_string(), call_id.to_string())
+            .filter(|previous| previous != call_id);
+        state.values.insert(key.to_string(), value.clone());
+        state.writes.push(ScratchpadWrite {
+            call_id: call_id.to_string(),
+            key: key.to_string(),
+            value,
+            conflicts_with,
+        });
+        Ok(())
+    }
+
+    /// Every write so far, in order
+    pub fn writes(&self) -> Vec<ScratchpadWrite> {
+        self.state.lock().unwrap().writes.clone()
+    }
+
+    /// Just the writes that overwrote another call's value
+    pub fn conflicts(&self) -> Vec<ScratchpadWrite> {
+        self.state
+            .lock()
+            .unwrap()
+            .writes
+            .iter()
+            .filter(|write| write.conflicts_with.is_some())
+            .cloned()
+            .collect()
+    }
+}
+
+impl ContextBuilder {
+    /// Take the values tools wrote through `shared` into this conversation's
+    /// scratchpad
+    pub fn merge_scratchpad(mut self, shared: &SharedScratchpad) -> Self {
+        let state = shared.state.lock().unwrap();
+        for key in state.writers.keys() {
+            if let Some(value) = state.values.get(key) {
+                self.scratchpad.values.insert(key.clone(), value.clone());
+            }
+        }
+        self
+    }
+}
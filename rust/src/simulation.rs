@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    ContentType, ContextBuilder, Message, MessageRole, ProviderAdapter, ToolCall, ToolCallLog,
+    ToolDescriptor, ToolExecuter, ToolResult,
+};
+
+/// Metadata key set to `"true"` on conversations whose tool results were
+/// simulated
+pub const SIMULATED_TOOLS: &str = "simulated_tools";
+
+/* --------------------------------- Sources -------------------------------- */
+
+/// Where `simulated_executer` gets its fake results
+#[derive(Clone)]
+pub enum SimulationSource {
+    /// A fixed result per tool name
+    Canned(HashMap<String, String>),
+    /// Ask `adapter` for a realistic result, given the tool's descriptor
+    /// (when listed in `tools`) and the call's arguments
+    Llm {
+        adapter: ProviderAdapter,
+        tools: Vec<ToolDescriptor>,
+        max_tokens: u32,
+    },
+    /// Replay a real run: a call gets the result of a logged call to the
+    /// same tool with the same arguments, or failing that of the first
+    /// logged call to the tool
+    Recording(Vec<ToolCallLog>),
+}
+
+impl SimulationSource {
+    /// A `Recording` from a JSONL file of `ToolCallLog`s, one per line, as
+    /// written from a `ToolCallLogSink`
+    pub fn from_log_file(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+
+        let mut logs = vec![];
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            logs.push(serde_json::from_str(&line).map_err(|e| {
+                format!(
+                    "Malformed log on line {} of {}: {}",
+                    index + 1,
+                    path.display(),
+                    e
+                )
+            })?);
+        }
+        Ok(SimulationSource::Recording(logs))
+    }
+}
+
+/// Arguments as JSON, parsing those sent as a JSON string
+fn json_arguments(arguments: &serde_json::Value) -> serde_json::Value {
+    match arguments {
+        serde_json::Value::String(raw) => {
+            serde_json::from_str(raw).unwrap_or_else(|_| arguments.clone())
+        }
+        other => other.clone(),
+    }
+}
+
+fn simulator_prompt(call: &ToolCall, tools: &[ToolDescriptor]) -> ContextBuilder {
+    let described = match tools.iter().find(|tool| tool.name == call.name) {
+        Some(tool) => format!(
+            "the tool `{}`: {}\nIts arguments follow this JSON Schema: {}",
+            tool.name, tool.description, tool.schema
+        ),
+        None => format!("the tool `{}`", call.name),
+    };
+    let arguments = call
+        .parsed_arguments()
+        .unwrap_or_else(|_| call.arguments.clone());
+
+    let message = |role: MessageRole, content: String| Message {
+        role,
+        content,
+        content_type: ContentType::Text,
+        pinned: false,
+        assistant_tool_calls: None,
+    };
+    ContextBuilder::new()
+        .add_message(message(
+            MessageRole::System,
+            format!(
+                "You are simulating {}\nReply with only the realistic output the tool would \
+                    return for the arguments you're given, with no commentary.",
+                described
+            ),
+        ))
+        .add_message(message(MessageRole::User, arguments.to_string()))
+}
+
+/* ---------------------------------- Runs ---------------------------------- */
+
+/// Record of the results a `simulated_executer` made up. Clone it to share.
+#[derive(Clone, Default)]
+pub struct SimulationRun {
+    results: Arc<Mutex<Vec<ToolResult>>>,
+}
+
+impl SimulationRun {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every simulated result, flagged `simulated`, in call order
+    pub fn results(&self) -> Vec<ToolResult> {
+        self.results.lock().unwrap().clone()
+    }
+
+    pub fn is_simulated(&self, tool_call_id: &str) -> bool {
+        self.results
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|result| result.tool_call_id == tool_call_id)
+    }
+
+    fn record(&self, call: &ToolCall, result: &Result<String, String>) {
+        self.results.lock().unwrap().push(ToolResult {
+            tool_call_id: call.id.clone(),
+            result: result.clone().unwrap_or_else(|e| e),
+            error: result.is_err(),
+            simulated: true,
+        });
+    }
+}
+
+/* --------------------------------- Wrapper -------------------------------- */
+
+/// ## `simulated_executer`
+/// A tool executer that never runs a real tool, for developing prompts
+/// against tools with side effects. Results come from `source` and are
+/// recorded in `run`; the tool descriptors sent to the model don't change,
+/// so neither does its behavior. Use `annotate_simulated` to flag the
+/// results in the transcript.
+///
+/// ```rust,ignore
+/// let run = SimulationRun::new();
+/// let executer = simulated_executer(SimulationSource::Canned(canned), &run);
+/// let context = response.resolve(executer).await.annotate_simulated(&run);
+/// ```
+pub fn simulated_executer(source: SimulationSource, run: &SimulationRun) -> ToolExecuter {
+    let source = Arc::new(source);
+    let run = run.clone();
+
+    Arc::new(move |call: ToolCall| {
+        let source = source.clone();
+        let run = run.clone();
+
+        Box::pin(async move {
+            let result = match source.as_ref() {
+                SimulationSource::Canned(results) => results
+                    .get(&call.name)
+                    .cloned()
+                    .ok_or_else(|| format!("No canned result for tool {}", call.name)),
+                SimulationSource::Llm {
+                    adapter,
+                    tools,
+                    max_tokens,
+                } => adapter(simulator_prompt(&call, tools), *max_tokens)
+                    .await
+                    .map(|response| response.message.content),
+                SimulationSource::Recording(logs) => logs
+                    .iter()
+                    .find(|log| {
+                        log.tool_name == call.name
+                            && json_arguments(&log.arguments) == json_arguments(&call.arguments)
+                    })
+                    .or_else(|| logs.iter().find(|log| log.tool_name == call.name))
+                    .map(|log| log.result.clone())
+                    .unwrap_or_else(|| Err(format!("No recorded result for tool {}", call.name))),
+            };
+            run.record(&call, &result);
+            result
+        })
+    })
+}
+
+impl ContextBuilder {
+    /// ## `annotate_simulated`
+    /// Flag the tool results `run` made up, so a dry run can't pass for a
+    /// real one: `ToolResult` messages from the run are marked `simulated`,
+    /// and the conversation is tagged `simulated_tools` in `metadata`.
+    pub fn annotate_simulated(mut self, run: &SimulationRun) -> Self {
+        let simulated: HashSet<String> = run
+            .results()
+            .into_iter()
+            .map(|result| result.tool_call_id)
+            .collect();
+        if simulated.is_empty() {
+            return self;
+        }
+
+        for msg in &mut self.history {
+            if let ContentType::ToolResult(result) = &mut msg.content_type
+                && simulated.contains(&result.tool_call_id)
+            {
+                result.simulated = true;
+            }
+        }
+        self.with_metadata(SIMULATED_TOOLS, "true")
+    }
+}
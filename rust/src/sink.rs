@@ -0,0 +1,172 @@
+use std::future::poll_fn;
+use std::task::{Context, Poll};
+
+use futures::StreamExt;
+
+use crate::{
+    ContentType, ContextBuilder, Message, MessageRole, PromptResponse, Provenance, StopReason,
+    StreamProviderAdapter, ToolCall, UnresolvedResponse, merge_stop_reason,
+};
+
+/// Bytes reserved per requested token for the collected reply
+const BYTES_PER_TOKEN: usize = 4;
+
+/// Cap on the up-front reservation, so a large `max_tokens` doesn't reserve
+/// memory a short reply never uses; past it the buffer grows by doubling
+const MAX_RESERVATION: usize = 64 * 1024;
+
+/* ---------------------------------- Sinks --------------------------------- */
+
+/// ## `ContentSink`
+/// Receives streamed content as borrowed slices of each delta, so nothing
+/// is copied per delta on the way to it
+pub trait ContentSink: Send {
+    fn write(&mut self, chunk: &str) -> Result<(), String>;
+
+    /// Push written content on to wherever it's going. Polled after every
+    /// write; sinks that only buffer are always ready.
+    fn poll_flush(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), String>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl ContentSink for String {
+    fn write(&mut self, chunk: &str) -> Result<(), String> {
+        self.push_str(chunk);
+        Ok(())
+    }
+}
+
+impl ContentSink for Vec<u8> {
+    fn write(&mut self, chunk: &str) -> Result<(), String> {
+        self.extend_from_slice(chunk.as_bytes());
+        Ok(())
+    }
+}
+
+/// Sink writing to a tokio `AsyncWrite`, through one pending buffer that's
+/// reused for every delta
+#[cfg(feature = "tokio-runtime")]
+pub struct AsyncWriteSink<W> {
+    writer: W,
+    pending: Vec<u8>,
+    written: usize,
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<W> AsyncWriteSink<W> {
+    pub fn new(writer: W) -> Self {
+        AsyncWriteSink {
+            writer,
+            pending: Vec::new(),
+            written: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<W> ContentSink for AsyncWriteSink<W>
+where
+    W: tokio::io::AsyncWrite + Unpin + Send,
+{
+    fn write(&mut self, chunk: &str) -> Result<(), String> {
+        self.pending.extend_from_slice(chunk.as_bytes());
+        Ok(())
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), String>> {
+        use std::pin::Pin;
+
+        while self.written < self.pending.len() {
+            let unwritten = &self.pending[self.written..];
+            match Pin::new(&mut self.writer).poll_write(cx, unwritten) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err("Sink writer closed".to_string())),
+                Poll::Ready(Ok(n)) => self.written += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.to_string())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.pending.clear();
+        self.written = 0;
+        Pin::new(&mut self.writer)
+            .poll_flush(cx)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/* -------------------------------- Streaming ------------------------------- */
+
+impl ContextBuilder {
+    /// ## `send_streaming_into`
+    /// Stream a reply into `sink` with as few allocations as possible, e.g.
+    /// for services running thousands of streams at once. Each delta's
+    /// content goes to the sink as a borrowed slice rather than a copy, and
+    /// the collected reply is reserved up front from `max_tokens` (capped)
+    /// and grows by doubling. A prefill is written to the sink first.
+    ///
+    /// ```rust,ignore
+    /// let mut body = String::new();
+    /// let response = context.send_streaming_into(adapter, 500, &mut body).await?;
+    /// ```
+    pub async fn send_streaming_into(
+        mut self,
+        adapter: StreamProviderAdapter,
+        max_tokens: u32,
+        sink: &mut dyn ContentSink,
+    ) -> Result<UnresolvedResponse, String> {
+        self = self.prepare_send().await?;
+        let mut deltas = adapter(self.outgoing(), max_tokens);
+
+        let prefill = self.params.prefill.take().unwrap_or_default();
+        let reservation = (max_tokens as usize)
+            .saturating_mul(BYTES_PER_TOKEN)
+            .min(MAX_RESERVATION);
+        let mut content = String::with_capacity(prefill.len() + reservation);
+        content.push_str(&prefill);
+        if !prefill.is_empty() {
+            sink.write(&prefill)?;
+            poll_fn(|cx| sink.poll_flush(cx)).await?;
+        }
+
+        let mut stop_reason = StopReason::Null;
+        let mut tool_calls: Option<Vec<ToolCall>> = None;
+        let mut token_usage = 0;
+        while let Some(delta) = deltas.next().await {
+            let delta = delta?;
+            if !delta.content.is_empty() {
+                sink.write(&delta.content)?;
+                poll_fn(|cx| sink.poll_flush(cx)).await?;
+                content.push_str(&delta.content);
+            }
+            if let Some(calls) = delta.tool_calls {
+                tool_calls.get_or_insert_with(Vec::new).extend(calls);
+            }
+            if let Some(reason) = delta.stop_reason {
+                merge_stop_reason(&mut stop_reason, reason);
+            }
+            token_usage = token_usage.max(delta.cumulative_tokens);
+        }
+
+        Ok(UnresolvedResponse {
+            prompt_response: PromptResponse {
+                message: Message {
+                    role: MessageRole::Model,
+                    content,
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: tool_calls.clone(),
+                },
+                stop_reason,
+                token_usage,
+                tool_calls,
+                provenance: Provenance::default(),
+                logprobs: None,
+            },
+            context_builder: self,
+        })
+    }
+}
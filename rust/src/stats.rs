@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::tokens::{HeuristicTokenCounter, MESSAGE_OVERHEAD_TOKENS, TokenCounter};
+use crate::{ContextBuilder, MessageRole, ToolCallLog};
+
+/* ---------------------------------- Stats --------------------------------- */
+
+/// Per-conversation aggregates, gathered in one pass by `ContextBuilder::stats`
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct ConversationStats {
+    pub message_count: usize,
+    pub user_turns: usize,
+    pub model_turns: usize,
+    /// Tool and function result messages
+    pub tool_calls: usize,
+    pub system_messages: usize,
+    /// Same estimate as `estimate_prompt_tokens` with the heuristic counter
+    pub estimated_tokens: usize,
+    /// Mean length of model messages in characters, `None` without any
+    pub avg_model_response_chars: Option<f64>,
+    /// Calls per tool name. History doesn't record tool names, so this is
+    /// `None` until filled in by `with_tool_logs`.
+    pub tool_usage: Option<BTreeMap<String, usize>>,
+}
+
+impl ConversationStats {
+    /// `self` with `tool_usage` counted from the conversation's tool call logs
+    pub fn with_tool_logs(mut self, logs: &[ToolCallLog]) -> Self {
+        let mut usage = BTreeMap::new();
+        for log in logs {
+            *usage.entry(log.tool_name.clone()).or_insert(0) += 1;
+        }
+        self.tool_usage = Some(usage);
+        self
+    }
+
+    /// ## `merge`
+    /// Aggregate stats across conversations: counts add up and the average
+    /// response length is weighted by model turns. `tool_usage` is only kept
+    /// when both sides have it, so a partial total never passes for a full one.
+    ///
+    /// ```rust,ignore
+    /// let total = conversations
+    ///     .iter()
+    ///     .fold(ConversationStats::default(), |total, c| total.merge(&c.stats()));
+    /// ```
+    pub fn merge(&self, other: &ConversationStats) -> ConversationStats {
+        let model_turns = self.model_turns + other.model_turns;
+        let response_chars = |stats: &ConversationStats| {
+            stats.avg_model_response_chars.unwrap_or(0.0) * stats.model_turns as f64
+        };
+
+        let tool_usage = match (&self.tool_usage, &other.tool_usage) {
+            (Some(ours), Some(theirs)) => {
+                let mut usage = ours.clone();
+                for (name, count) in theirs {
+                    *usage.entry(name.clone()).or_insert(0) += count;
+                }
+                Some(usage)
+            }
+            _ => None,
+        };
+
+        ConversationStats {
+            message_count: self.message_count + other.message_count,
+            user_turns: self.user_turns + other.user_turns,
+            model_turns,
+            tool_calls: self.tool_calls + other.tool_calls,
+            system_messages: self.system_messages + other.system_messages,
+            estimated_tokens: self.estimated_tokens + other.estimated_tokens,
+            avg_model_response_chars: (model_turns > 0)
+                .then(|| (response_chars(self) + response_chars(other)) / model_turns as f64),
+            tool_usage,
+        }
+    }
+}
+
+impl ContextBuilder {
+    pub fn stats(&self) -> ConversationStats {
+        let counter = HeuristicTokenCounter;
+        let mut stats = ConversationStats::default();
+        let mut response_chars = 0;
+
+        for msg in &self.history {
+            stats.message_count += 1;
+            stats.estimated_tokens += counter.count(&msg.content) + MESSAGE_OVERHEAD_TOKENS;
+            match msg.role {
+                MessageRole::User => stats.user_turns += 1,
+                MessageRole::Model => {
+                    stats.model_turns += 1;
+                    response_chars += msg.content.chars().count();
+                }
+                MessageRole::Tool | MessageRole::Function => stats.tool_calls += 1,
+                MessageRole::System => stats.system_messages += 1,
+            }
+        }
+
+        stats.avg_model_response_chars =
+            (stats.model_turns > 0).then(|| response_chars as f64 / stats.model_turns as f64);
+        stats
+    }
+}
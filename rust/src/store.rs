@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::persistence::MigrationReport;
+use crate::stats::ConversationStats;
+use crate::{ArchivedRange, ContentType, ContextBuilder, Message, MessageRole};
+
+/// Metadata key holding the store's error while spilling to a backing
+/// store fails; cleared by the next spill that succeeds
+pub const SPILL_FAILED: &str = "spill_failed";
+
+/* ---------------------------- ConversationStore --------------------------- */
+
+/// ## `ConversationStore`
+/// Keyed persistence for conversations (or archived slices of them).
+///
+/// Calls are synchronous so the store can be used from inside the builder
+/// chain, e.g. when `add_message` spills old history.
+pub trait ConversationStore: Send + Sync {
+    fn save(&self, key: &str, context: &ContextBuilder) -> Result<(), String>;
+    fn load(&self, key: &str) -> Result<Option<ContextBuilder>, String>;
+    fn delete(&self, key: &str) -> Result<(), String>;
+    fn list(&self) -> Result<Vec<String>, String>;
+
+    /// `load`, plus what had to be migrated to read the stored conversation.
+    /// Stores that persist `to_versioned_json` output should override this
+    /// with `ContextBuilder::load_with_report`.
+    fn load_with_report(
+        &self,
+        key: &str,
+    ) -> Result<Option<(ContextBuilder, MigrationReport)>, String> {
+        Ok(self
+            .load(key)?
+            .map(|context| (context, MigrationReport::current())))
+    }
+
+    /// `list`, with the stats each conversation had when it was saved.
+    /// Stores that compute stats in `save` should override this so callers
+    /// don't have to load every history; the default has none.
+    fn list_with_stats(&self) -> Result<Vec<(String, Option<ConversationStats>)>, String> {
+        Ok(self.list()?.into_iter().map(|key| (key, None)).collect())
+    }
+
+    /// Whether the conversation under `key` was saved by `finalize`, i.e.
+    /// loads read-only. Stores that index conversations can answer this
+    /// without loading the history.
+    fn is_finalized(&self, key: &str) -> Result<bool, String> {
+        Ok(self
+            .load(key)?
+            .is_some_and(|context| context.is_finalized()))
+    }
+}
+
+/// `ConversationStore` backed by a `HashMap`, for tests and short-lived processes
+#[derive(Default)]
+pub struct InMemoryConversationStore {
+    /// Each conversation with its stats as of the last save
+    conversations: RwLock<HashMap<String, (ContextBuilder, ConversationStats)>>,
+}
+
+impl InMemoryConversationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ConversationStore for InMemoryConversationStore {
+    fn save(&self, key: &str, context: &ContextBuilder) -> Result<(), String> {
+        self.conversations
+            .write()
+            .map_err(|e| format!("Conversation store poisoned: {}", e))?
+            .insert(key.to_string(), (context.clone(), context.stats()));
+        Ok(())
+    }
+
+    fn load(&self, key: &str) -> Result<Option<ContextBuilder>, String> {
+        Ok(self
+            .conversations
+            .read()
+            .map_err(|e| format!("Conversation store poisoned: {}", e))?
+            .get(key)
+            .map(|(context, _)| context.clone()))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.conversations
+            .write()
+            .map_err(|e| format!("Conversation store poisoned: {}", e))?
+            .remove(key);
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>, String> {
+        let mut keys: Vec<String> = self
+            .conversations
+            .read()
+            .map_err(|e| format!("Conversation store poisoned: {}", e))?
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn list_with_stats(&self) -> Result<Vec<(String, Option<ConversationStats>)>, String> {
+        let mut entries: Vec<(String, Option<ConversationStats>)> = self
+            .conversations
+            .read()
+            .map_err(|e| format!("Conversation store poisoned: {}", e))?
+            .iter()
+            .map(|(key, (_, stats))| (key.clone(), Some(stats.clone())))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+}
+
+/* ------------------------------ Backing Store ----------------------------- */
+
+static ARCHIVE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Spill configuration attached to a `ContextBuilder`
+#[derive(Clone)]
+pub(crate) struct BackingStore {
+    store: Arc<dyn ConversationStore>,
+    keep_in_memory: usize,
+}
+
+fn is_archive_marker(msg: &Message) -> bool {
+    matches!(msg.content_type, ContentType::ArchivedRange(_))
+}
+
+fn archive_marker(range: ArchivedRange) -> Message {
+    Message {
+        role: MessageRole::System,
+        content: format!("[{} earlier messages archived]", range.len),
+        content_type: ContentType::ArchivedRange(range),
+        pinned: false,
+        assistant_tool_calls: None,
+    }
+}
+
+fn new_archive_key() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let count = ARCHIVE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("archive-{:x}-{}", nanos, count)
+}
+
+impl ContextBuilder {
+    /// Keep at most `keep_in_memory` messages in memory, flushing older ones to
+    /// `store`. Leading system messages and pinned messages always stay in
+    /// memory; everything flushed is replaced by a single `ArchivedRange`
+    /// marker, which is never sent to a provider. `hydrate` puts pinned
+    /// messages that outlived their neighbours after the archived range.
+    pub fn with_backing_store(
+        mut self,
+        store: Arc<dyn ConversationStore>,
+        keep_in_memory: usize,
+    ) -> Self {
+        self.backing_store = Some(BackingStore {
+            store,
+            keep_in_memory,
+        });
+        self.spill_to_store()
+    }
+
+    /// Rebuild the full history by loading every archived range from the
+    /// backing store. The rest of the builder is kept; the result is
+    /// detached from the store so it won't spill again.
+    pub fn hydrate(&self) -> Result<ContextBuilder, String> {
+        let mut history = vec![];
+
+        for msg in &self.history {
+            let ContentType::ArchivedRange(range) = &msg.content_type else {
+                history.push(msg.clone());
+                continue;
+            };
+
+            let backing = self
+                .backing_store
+                .as_ref()
+                .ok_or_else(|| format!("No backing store attached to load {}", range.key))?;
+            let archived = backing
+                .store
+                .load(&range.key)?
+                .ok_or_else(|| format!("Archived range {} missing from store", range.key))?;
+            history.extend(archived.history);
+        }
+
+        let mut hydrated = self.clone();
+        hydrated.history = history;
+        hydrated.backing_store = None;
+        Ok(hydrated)
+    }
+
+    /// The error from the last failed spill to the backing store, until a
+    /// spill succeeds again. While it is set the history grows in memory.
+    pub fn spill_error(&self) -> Option<&str> {
+        self.metadata.get(SPILL_FAILED).map(String::as_str)
+    }
+
+    /// Flush the oldest messages past the in-memory window. If the store
+    /// fails the history is left untouched and the error is kept under
+    /// `SPILL_FAILED`.
+    pub(crate) fn spill_to_store(mut self) -> Self {
+        let Some(backing) = self.backing_store.clone() else {
+            return self;
+        };
+
+        let head = self
+            .history
+            .iter()
+            .take_while(|msg| msg.role == MessageRole::System && !is_archive_marker(msg))
+            .count();
+        let marker = match self.history.get(head).map(|msg| &msg.content_type) {
+            Some(ContentType::ArchivedRange(range)) => Some(range.clone()),
+            _ => None,
+        };
+        let window_start = head + usize::from(marker.is_some());
+
+        let window_len = self.history.len() - window_start;
+        if window_len <= backing.keep_in_memory {
+            return self;
+        }
+
+        // Pinned messages stay in memory and count against the window first
+        let mut excess = window_len - backing.keep_in_memory;
+        let mut evict = vec![false; self.history.len()];
+        let mut evict_end = window_start;
+        for (index, msg) in self.history.iter().enumerate().skip(window_start) {
+            if excess == 0 {
+                break;
+            }
+            evict_end = index + 1;
+            if !msg.pinned {
+                evict[index] = true;
+                excess -= 1;
+            }
+        }
+
+        // Tool results travel with the call that produced them
+        while self.history.get(evict_end).is_some_and(|msg| {
+            matches!(msg.role, MessageRole::Tool | MessageRole::Function) && !msg.pinned
+        }) {
+            evict[evict_end] = true;
+            evict_end += 1;
+        }
+        if !evict.contains(&true) {
+            return self;
+        }
+
+        // Extend the existing archive rather than stacking markers
+        let (key, mut archived) = match &marker {
+            Some(range) => match backing.store.load(&range.key) {
+                Ok(Some(archived)) => (range.key.clone(), archived.history),
+                Ok(None) => {
+                    let error = format!("Archived range {} missing from store", range.key);
+                    return self.with_metadata(SPILL_FAILED, error);
+                }
+                Err(error) => return self.with_metadata(SPILL_FAILED, error),
+            },
+            None => (new_archive_key(), vec![]),
+        };
+        archived.extend(
+            (window_start..evict_end)
+                .filter(|index| evict[*index])
+                .map(|index| self.history[index].clone()),
+        );
+        let len = archived.len();
+
+        let archive = ContextBuilder {
+            history: archived,
+            ..ContextBuilder::new()
+        };
+        if let Err(error) = backing.store.save(&key, &archive) {
+            return self.with_metadata(SPILL_FAILED, error);
+        }
+        self.metadata.remove(SPILL_FAILED);
+
+        let marker = archive_marker(ArchivedRange { key, len });
+        let kept: Vec<Message> = (window_start..evict_end)
+            .filter(|index| !evict[*index])
+            .map(|index| self.history[index].clone())
+            .collect();
+        self.history
+            .splice(head..evict_end, std::iter::once(marker).chain(kept));
+        self
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{ContentType, ContextBuilder, Message, MessageRole, ProviderAdapter};
+
+/// Starts the system message that replaces summarized messages
+pub const SUMMARY_HEADER: &str = "Summary of the earlier conversation:";
+
+/// Prompt the summarizer gets when older messages are replaced by a summary
+pub const SUMMARY_PROMPT: &str = "Summarize the conversation so far. Keep every fact, \
+    decision and open question needed to continue it.";
+
+/* ----------------------------- Rolling summary ---------------------------- */
+
+/// How `apply_rolling_summary` keeps a conversation short, see
+/// `with_rolling_summary_window`
+#[derive(Clone)]
+pub struct SummarizeStrategy {
+    pub adapter: ProviderAdapter,
+    pub max_tokens: u32,
+    /// Most recent messages kept verbatim
+    pub window: usize,
+    /// Summarize once the history holds more messages than this
+    pub threshold: usize,
+}
+
+impl ContextBuilder {
+    /// ## `with_rolling_summary_window`
+    /// Summarize old messages as the conversation grows: whenever the history
+    /// holds more than `threshold` messages, everything but the last `window`
+    /// is replaced by one system message written by `adapter`. System and
+    /// pinned messages are kept as they are, and an earlier summary is
+    /// folded into the new one.
+    ///
+    /// The summary is written by `apply_rolling_summary`, which every send,
+    /// streaming or not, runs before its request; a summarizer error fails
+    /// the send.
+    ///
+    /// ```rust,ignore
+    /// let context = ContextBuilder::new()
+    ///     .with_rolling_summary_window(summarizer, 500, 10, 40)
+    ///     .add_message(user_message)
+    ///     .apply_rolling_summary()
+    ///     .await?;
+    /// ```
+    pub fn with_rolling_summary_window(
+        mut self,
+        adapter: ProviderAdapter,
+        max_tokens: u32,
+        window: usize,
+        threshold: usize,
+    ) -> Self {
+        self.summarize_strategy = Some(SummarizeStrategy {
+            adapter,
+            max_tokens,
+            window,
+            threshold,
+        });
+        self
+    }
+
+    /// ## `apply_rolling_summary`
+    /// Apply `summarize_strategy` if the history has outgrown it. A no-op
+    /// without a strategy or below its threshold. If the summarizer fails
+    /// its error is returned and the history is left untouched.
+    pub async fn apply_rolling_summary(mut self) -> Result<Self, String> {
+        let Some(strategy) = self.summarize_strategy.clone() else {
+            return Ok(self);
+        };
+        if self.history.len() <= strategy.threshold {
+            return Ok(self);
+        }
+
+        // Tool results travel with the call that produced them
+        let mut cut = self.history.len().saturating_sub(strategy.window);
+        while self
+            .history
+            .get(cut)
+            .is_some_and(|msg| matches!(msg.role, MessageRole::Tool | MessageRole::Function))
+        {
+            cut += 1;
+        }
+
+        let is_kept = |msg: &Message| {
+            (msg.role == MessageRole::System && !msg.content.starts_with(SUMMARY_HEADER))
+                || msg.pinned
+        };
+        let (kept, summarized): (Vec<Message>, Vec<Message>) =
+            self.history[..cut].iter().cloned().partition(is_kept);
+        if summarized.is_empty() {
+            return Ok(self);
+        }
+
+        let mut prompt = ContextBuilder::new();
+        prompt.history = summarized;
+        let summary = prompt
+            .to_summary_string(strategy.adapter, strategy.max_tokens, SUMMARY_PROMPT)
+            .await?;
+
+        let recent = self.history.split_off(cut);
+        self.history = kept
+            .into_iter()
+            .chain(std::iter::once(Message {
+                role: MessageRole::System,
+                content: format!("{}\n{}", SUMMARY_HEADER, summary),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            }))
+            .chain(recent)
+            .collect();
+        Ok(self)
+    }
+}
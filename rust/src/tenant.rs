@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use futures::StreamExt;
+use futures::stream::BoxStream;
+
+use crate::anonymize::AnonymizerRules;
+use crate::budget::TokenBudget;
+use crate::stats::ConversationStats;
+use crate::store::ConversationStore;
+use crate::tokens::HeuristicTokenCounter;
+use crate::{
+    ContextBuilder, PromptResponseDelta, ProviderAdapter, StreamProviderAdapter, UnresolvedResponse,
+};
+
+/// Metadata key naming the tenant a conversation belongs to
+pub const TENANT: &str = "tenant";
+
+/* --------------------------------- Tenants -------------------------------- */
+
+/// Everything a tenant's conversations run on. Nothing here is shared with
+/// other tenants, so usage and storage can't leak between them.
+#[derive(Clone)]
+pub struct TenantContext {
+    pub adapter: ProviderAdapter,
+    /// Needed for `send_streaming_for_tenant`
+    pub streaming_adapter: Option<StreamProviderAdapter>,
+    /// Spend limit; every send reserves its `max_tokens` here
+    pub budget: Arc<TokenBudget>,
+    pub store: Arc<dyn ConversationStore>,
+    /// Applied to outgoing history, and undone on the reply
+    pub redaction: Option<AnonymizerRules>,
+}
+
+/// ## `TenantResolver`
+/// Finds the resources for a conversation from its metadata
+pub trait TenantResolver: Send + Sync {
+    fn resolve(&self, metadata: &BTreeMap<String, String>) -> Result<TenantContext, String>;
+}
+
+/// `TenantResolver` over a fixed set of tenants, keyed by the `tenant`
+/// metadata tag
+///
+/// ```rust,ignore
+/// let resolver = StaticTenantResolver::new()
+///     .with_tenant("acme", acme)
+///     .with_tenant("globex", globex);
+/// let response = context.with_metadata(TENANT, "acme").send_for_tenant(&resolver, 500).await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct StaticTenantResolver {
+    tenants: HashMap<String, TenantContext>,
+}
+
+impl StaticTenantResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tenant(mut self, tenant: impl Into<String>, context: TenantContext) -> Self {
+        self.tenants.insert(tenant.into(), context);
+        self
+    }
+}
+
+impl TenantResolver for StaticTenantResolver {
+    fn resolve(&self, metadata: &BTreeMap<String, String>) -> Result<TenantContext, String> {
+        let tenant = metadata
+            .get(TENANT)
+            .ok_or_else(|| format!("Conversation has no `{}` metadata", TENANT))?;
+        self.tenants
+            .get(tenant)
+            .cloned()
+            .ok_or_else(|| format!("Unknown tenant: {}", tenant))
+    }
+}
+
+/* ---------------------------- Namespaced Store ---------------------------- */
+
+/// ## `NamespacedStore`
+/// Gives one tenant a private view of a store shared with others: keys are
+/// prefixed with the namespace, and `list` only sees its own.
+pub struct NamespacedStore {
+    inner: Arc<dyn ConversationStore>,
+    prefix: String,
+}
+
+impl NamespacedStore {
+    pub fn new(inner: Arc<dyn ConversationStore>, namespace: &str) -> Self {
+        NamespacedStore {
+            inner,
+            prefix: format!("{}/", namespace),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+impl ConversationStore for NamespacedStore {
+    fn save(&self, key: &str, context: &ContextBuilder) -> Result<(), String> {
+        self.inner.save(&self.key(key), context)
+    }
+
+    fn load(&self, key: &str) -> Result<Option<ContextBuilder>, String> {
+        self.inner.load(&self.key(key))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.inner.delete(&self.key(key))
+    }
+
+    fn list(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .inner
+            .list()?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect())
+    }
+
+    fn list_with_stats(&self) -> Result<Vec<(String, Option<ConversationStats>)>, String> {
+        Ok(self
+            .inner
+            .list_with_stats()?
+            .into_iter()
+            .filter_map(|(key, stats)| {
+                key.strip_prefix(&self.prefix)
+                    .map(|key| (key.to_string(), stats))
+            })
+            .collect())
+    }
+}
+
+/* --------------------------------- Sending -------------------------------- */
+
+impl ContextBuilder {
+    /// The tenant named in the `tenant` metadata tag
+    pub fn tenant(&self) -> Option<&str> {
+        self.metadata.get(TENANT).map(String::as_str)
+    }
+
+    /// ## `send_for_tenant`
+    /// Send with the adapter of the tenant `resolver` finds for this
+    /// conversation. `max_tokens` is reserved from the tenant's budget (and
+    /// capped by what's left) for the duration of the request; whatever the
+    /// response didn't use is credited back. The tenant's redaction rules
+    /// are applied to the outgoing history and undone on the reply.
+    ///
+    /// Errors if the tenant is unknown, its budget is spent, or the request
+    /// fails.
+    pub async fn send_for_tenant(
+        mut self,
+        resolver: &dyn TenantResolver,
+        max_tokens: u32,
+    ) -> Result<UnresolvedResponse, String> {
+        self.assert_open();
+        let tenant = resolver.resolve(&self.metadata)?;
+        self = self.prepare_send().await?;
+        let reserved = tenant.budget.debit(u64::from(max_tokens));
+        if reserved == 0 {
+            return Err(format!(
+                "Budget exhausted for tenant: {}",
+                self.tenant_label()
+            ));
+        }
+
+        let (outgoing, map) = match &tenant.redaction {
+            Some(rules) => {
+                let (outgoing, map) = self.outgoing().anonymize(rules);
+                (outgoing, Some(map))
+            }
+            None => (self.outgoing(), None),
+        };
+
+        let mut prompt_response = match (tenant.adapter)(outgoing, reserved as u32).await {
+            Ok(response) => response,
+            Err(e) => {
+                tenant.budget.credit(reserved);
+                return Err(e);
+            }
+        };
+        tenant
+            .budget
+            .credit(reserved.saturating_sub(u64::from(prompt_response.token_usage)));
+
+        let mut context = self;
+        if let Some(prefill) = context.params.prefill.take() {
+            prompt_response.message.content.insert_str(0, &prefill);
+        }
+        let response = UnresolvedResponse {
+            prompt_response,
+            context_builder: context,
+        };
+        Ok(match map {
+            Some(map) => response.deanonymize(&map),
+            None => response,
+        })
+    }
+
+    /// ## `send_streaming_for_tenant`
+    /// `send_for_tenant` for the tenant's streaming adapter, spending from
+    /// its budget as in `send_streaming_with_budget`. Placeholders are
+    /// restored per delta, so one split across two deltas is left as is.
+    pub fn send_streaming_for_tenant(
+        self,
+        resolver: &dyn TenantResolver,
+        max_tokens: u32,
+    ) -> Result<BoxStream<'static, Result<PromptResponseDelta, String>>, String> {
+        let tenant = resolver.resolve(&self.metadata)?;
+        let adapter = tenant
+            .streaming_adapter
+            .ok_or_else(|| format!("No streaming adapter for tenant: {}", self.tenant_label()))?;
+        if tenant.budget.remaining() == 0 {
+            return Err(format!(
+                "Budget exhausted for tenant: {}",
+                self.tenant_label()
+            ));
+        }
+
+        let Some(rules) = &tenant.redaction else {
+            return Ok(self.send_streaming_with_budget(
+                adapter,
+                max_tokens,
+                tenant.budget,
+                Arc::new(HeuristicTokenCounter),
+            ));
+        };
+
+        let (outgoing, map) = self.anonymize(rules);
+        let deltas = outgoing.send_streaming_with_budget(
+            adapter,
+            max_tokens,
+            tenant.budget,
+            Arc::new(HeuristicTokenCounter),
+        );
+        Ok(Box::pin(deltas.map(move |delta| {
+            delta.map(|mut delta| {
+                delta.content = map.deanonymize_text(&delta.content);
+                delta
+            })
+        })))
+    }
+
+    /// Save to the tenant's store under `key`
+    pub fn save_for_tenant(&self, resolver: &dyn TenantResolver, key: &str) -> Result<(), String> {
+        resolver.resolve(&self.metadata)?.store.save(key, self)
+    }
+
+    /// Load `key` from `tenant`'s store
+    pub fn load_for_tenant(
+        resolver: &dyn TenantResolver,
+        tenant: &str,
+        key: &str,
+    ) -> Result<Option<ContextBuilder>, String> {
+        let metadata = BTreeMap::from([(TENANT.to_string(), tenant.to_string())]);
+        resolver.resolve(&metadata)?.store.load(key)
+    }
+
+    fn tenant_label(&self) -> &str {
+        self.tenant().unwrap_or("<none>")
+    }
+}
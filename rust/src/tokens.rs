@@ -0,0 +1,118 @@
+use crate::{ContextBuilder, ToolDescriptor};
+#[cfg(feature = "tiktoken")]
+use crate::{Message, MessageRole};
+
+/* ------------------------------ Token Counting ---------------------------- */
+
+/// Estimates how many tokens a piece of text costs for some tokenizer
+pub trait TokenCounter: Send + Sync {
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Tokenizer-free estimate of roughly four characters per token
+#[derive(Clone, Copy, Default)]
+pub struct HeuristicTokenCounter;
+
+impl TokenCounter for HeuristicTokenCounter {
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+}
+
+/// Per-message framing overhead (role markers, separators) added by chat APIs
+pub const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Estimate the prompt size of `context` plus any tool descriptors sent with it
+pub fn estimate_prompt_tokens(
+    context: &ContextBuilder,
+    tools: &[ToolDescriptor],
+    counter: &dyn TokenCounter,
+) -> usize {
+    let messages: usize = context
+        .history
+        .iter()
+        .map(|msg| counter.count(&msg.content) + MESSAGE_OVERHEAD_TOKENS)
+        .sum();
+    let tools: usize = tools
+        .iter()
+        .map(|tool| {
+            counter.count(&tool.name)
+                + counter.count(&tool.description)
+                + counter.count(&tool.schema.to_string())
+        })
+        .sum();
+
+    messages + tools
+}
+
+/* -------------------------------- tiktoken -------------------------------- */
+
+#[cfg(feature = "tiktoken")]
+impl ContextBuilder {
+    /// BPE token ids of each message's content, using the tokenizer
+    /// tiktoken associates with `model`
+    pub fn to_tiktoken_tokens(&self, model: &str) -> Result<Vec<Vec<u32>>, String> {
+        let bpe = tiktoken_rs::get_bpe_from_model(model)
+            .map_err(|e| format!("No tiktoken encoding for model {}: {}", model, e))?;
+
+        Ok(self
+            .history
+            .iter()
+            .map(|msg| bpe.encode_with_special_tokens(&msg.content))
+            .collect())
+    }
+
+    /// Split history into contexts of at most `limit` tokens each, counting
+    /// message content with `model`'s tokenizer. Leading system messages are
+    /// repeated at the head of every chunk (and count toward its limit).
+    pub fn split_by_token_limit(&self, model: &str, limit: usize) -> Result<Vec<Self>, String> {
+        let counts: Vec<usize> = self
+            .to_tiktoken_tokens(model)?
+            .iter()
+            .map(Vec::len)
+            .collect();
+
+        let head_len = self
+            .history
+            .iter()
+            .take_while(|msg| msg.role == MessageRole::System)
+            .count();
+        let head_tokens: usize = counts[..head_len].iter().sum();
+        if head_tokens > limit {
+            return Err(format!(
+                "System messages alone are {} tokens, over the limit of {}",
+                head_tokens, limit
+            ));
+        }
+
+        let chunk = |messages: &[Message]| {
+            let mut context = self.clone();
+            context.history = self.history[..head_len].to_vec();
+            context.history.extend_from_slice(messages);
+            context
+        };
+
+        let mut chunks = vec![];
+        let mut start = head_len;
+        let mut tokens = head_tokens;
+        for (i, count) in counts.iter().enumerate().skip(head_len) {
+            if head_tokens + count > limit {
+                return Err(format!(
+                    "Message {} is {} tokens, which can't fit under the limit of {}",
+                    i, count, limit
+                ));
+            }
+            if tokens + count > limit {
+                chunks.push(chunk(&self.history[start..i]));
+                start = i;
+                tokens = head_tokens;
+            }
+            tokens += count;
+        }
+        if start < self.history.len() || chunks.is_empty() {
+            chunks.push(chunk(&self.history[start..]));
+        }
+
+        Ok(chunks)
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+
+use crate::{ContentType, ContextBuilder, Message, MessageRole, ToolCall};
+
+/// Result text stubbed in for a tool call whose result is gone
+pub const RESULT_UNAVAILABLE: &str = "result unavailable";
+
+/// Tool name stubbed in for a result whose call is gone
+pub const UNKNOWN_TOOL: &str = "unknown_tool";
+
+/* --------------------------------- Policy --------------------------------- */
+
+/// What `gc_tool_messages` does with half of a tool-call/tool-result pair
+/// whose other half is missing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToolGcPolicy {
+    /// Remove the orphaned message
+    Drop,
+    /// Add the missing half: a "result unavailable" error result after an
+    /// orphaned call, or an `unknown_tool` call before an orphaned result
+    Stub,
+    /// Replace the orphaned message with a plain model message describing it
+    Narrate,
+}
+
+/* ----------------------------------- GC ----------------------------------- */
+
+fn narrate_call(call: &ToolCall) -> String {
+    format!(
+        "[Called tool {} with {}; its result is unavailable]",
+        call.name, call.arguments
+    )
+}
+
+fn narrate(msg: &Message) -> String {
+    match &msg.content_type {
+        ContentType::ToolUse(call) => narrate_call(call),
+        ContentType::ToolResult(result) => format!(
+            "[A tool call {} returned{}: {}]",
+            result.tool_call_id,
+            if result.error { " an error" } else { "" },
+            result.result
+        ),
+        _ => msg.content.clone(),
+    }
+}
+
+fn unavailable_result(call_id: &str) -> Vec<Message> {
+    ContextBuilder::new()
+        .add_tool_result_message(call_id, RESULT_UNAVAILABLE.to_string(), true)
+        .history
+}
+
+/// Apply `policy` to a model message whose `assistant_tool_calls` include
+/// the unanswered `missing`; answered calls are kept
+fn repair_model_turn(
+    mut msg: Message,
+    missing: &HashSet<String>,
+    policy: ToolGcPolicy,
+    history: &mut Vec<Message>,
+) {
+    if policy == ToolGcPolicy::Stub {
+        let stubs: Vec<Message> = msg
+            .assistant_tool_calls
+            .iter()
+            .flatten()
+            .filter(|call| missing.contains(&call.id))
+            .flat_map(|call| unavailable_result(&call.id))
+            .collect();
+        history.push(msg);
+        history.extend(stubs);
+        return;
+    }
+
+    let calls = msg.assistant_tool_calls.take().unwrap_or_default();
+    let (orphaned, kept): (Vec<ToolCall>, Vec<ToolCall>) = calls
+        .into_iter()
+        .partition(|call| missing.contains(&call.id));
+    if policy == ToolGcPolicy::Narrate {
+        let narration: Vec<String> = orphaned.iter().map(narrate_call).collect();
+        if !msg.content.is_empty() {
+            msg.content.push('\n');
+        }
+        msg.content.push_str(&narration.join("\n"));
+    }
+
+    msg.assistant_tool_calls = Some(kept).filter(|kept| !kept.is_empty());
+    if msg.assistant_tool_calls.is_some() || !msg.content.trim().is_empty() {
+        history.push(msg);
+    }
+}
+
+impl ContextBuilder {
+    /// ## `gc_tool_messages`
+    /// Find tool calls with no later result for their call id, and tool
+    /// results with no earlier call, and repair them per `policy`. Calls are
+    /// either tool-use messages or the `assistant_tool_calls` of a model
+    /// turn; for the latter only the unanswered calls are repaired, and the
+    /// turn itself is kept while it still has content or answered calls.
+    /// Histories left like this by compaction, branch edits or manual
+    /// surgery are rejected by providers that check the pairing. Complete
+    /// pairs and all other messages are left alone; pinning carries over to
+    /// narrated messages.
+    ///
+    /// ```rust,ignore
+    /// let context = context.gc_tool_messages(ToolGcPolicy::Stub);
+    /// ```
+    pub fn gc_tool_messages(mut self, policy: ToolGcPolicy) -> Self {
+        let mut called: HashSet<&str> = HashSet::new();
+        let mut answered: HashSet<&str> = HashSet::new();
+        let mut orphan_results = vec![false; self.history.len()];
+        for (index, msg) in self.history.iter().enumerate() {
+            for call in msg.assistant_tool_calls.iter().flatten() {
+                called.insert(&call.id);
+            }
+            match &msg.content_type {
+                ContentType::ToolUse(call) => {
+                    called.insert(&call.id);
+                }
+                ContentType::ToolResult(result) => {
+                    match called.contains(result.tool_call_id.as_str()) {
+                        true => {
+                            answered.insert(&result.tool_call_id);
+                        }
+                        false => orphan_results[index] = true,
+                    }
+                }
+                _ => {}
+            }
+        }
+        let orphan_calls: Vec<bool> = self
+            .history
+            .iter()
+            .map(|msg| match &msg.content_type {
+                ContentType::ToolUse(call) => !answered.contains(call.id.as_str()),
+                _ => false,
+            })
+            .collect();
+        let unanswered_turns: Vec<HashSet<String>> = self
+            .history
+            .iter()
+            .map(|msg| {
+                msg.assistant_tool_calls
+                    .iter()
+                    .flatten()
+                    .filter(|call| !answered.contains(call.id.as_str()))
+                    .map(|call| call.id.clone())
+                    .collect()
+            })
+            .collect();
+        if !orphan_results.contains(&true)
+            && !orphan_calls.contains(&true)
+            && unanswered_turns.iter().all(HashSet::is_empty)
+        {
+            return self;
+        }
+
+        let history = std::mem::take(&mut self.history);
+        for (index, msg) in history.into_iter().enumerate() {
+            if !unanswered_turns[index].is_empty() {
+                repair_model_turn(msg, &unanswered_turns[index], policy, &mut self.history);
+                continue;
+            }
+            if !orphan_calls[index] && !orphan_results[index] {
+                self.history.push(msg);
+                continue;
+            }
+
+            match policy {
+                ToolGcPolicy::Drop => {}
+                ToolGcPolicy::Narrate => self.history.push(Message {
+                    role: MessageRole::Model,
+                    content: narrate(&msg),
+                    content_type: ContentType::Text,
+                    pinned: msg.pinned,
+                    assistant_tool_calls: None,
+                }),
+                ToolGcPolicy::Stub => match &msg.content_type {
+                    ContentType::ToolUse(call) => {
+                        let stub = unavailable_result(&call.id);
+                        self.history.push(msg);
+                        self.history.extend(stub);
+                    }
+                    ContentType::ToolResult(result) => {
+                        let stub = ContextBuilder::new().add_tool_use_message(ToolCall {
+                            id: result.tool_call_id.clone(),
+                            name: UNKNOWN_TOOL.to_string(),
+                            arguments: serde_json::json!({}),
+                        });
+                        self.history.extend(stub.history);
+                        self.history.push(msg);
+                    }
+                    _ => self.history.push(msg),
+                },
+            }
+        }
+        self
+    }
+}
@@ -0,0 +1,703 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures::StreamExt;
+use futures::future::{Either, select};
+use futures::stream::FuturesUnordered;
+
+use crate::ask_user::{LoopOutcome, ResolutionSnapshot};
+use crate::refusal::FALLBACK;
+use crate::resilience::{ErrorClassifier, HeuristicErrorClassifier};
+use crate::{
+    ContextBuilder, PromptResponse, PromptResponseDelta, Provenance, ProviderAdapter,
+    RequestParams, StopReason, StreamProviderAdapter, ToolCall, ToolExecuter, UnresolvedResponse,
+    tool_result_text,
+};
+
+/* -------------------------------- Schedules ------------------------------- */
+
+/// Where a tool loop is when it's about to send
+#[derive(Clone, PartialEq)]
+pub struct TurnInfo {
+    /// 0 for the first send of the loop
+    pub turn_index: usize,
+    /// How the previous turn ended, `None` on the first turn
+    pub last_stop_reason: Option<StopReason>,
+    /// Tool results were just added and the model hasn't replied to them yet
+    pub tools_pending: bool,
+}
+
+/// ## `ParamsSchedule`
+/// Picks the request params for each send of a tool loop. The result is
+/// layered over the context's own `params`, so unset fields keep their value.
+///
+/// ```rust,ignore
+/// let schedule = ParamsSchedule::ToolPhaseThenCreative {
+///     tool_opts: RequestParams { temperature: Some(0.0), ..Default::default() },
+///     answer_opts: RequestParams { temperature: Some(0.8), ..Default::default() },
+/// };
+/// ```
+#[derive(Clone)]
+pub enum ParamsSchedule {
+    /// The same params on every turn
+    Constant(RequestParams),
+    /// `tool_opts` while the model decides which tools to call, `answer_opts`
+    /// once tool results are in and it's writing the answer
+    ToolPhaseThenCreative {
+        tool_opts: RequestParams,
+        answer_opts: RequestParams,
+    },
+    Custom(Arc<dyn Fn(&TurnInfo) -> RequestParams + Send + Sync>),
+}
+
+impl ParamsSchedule {
+    pub fn params_for(&self, turn: &TurnInfo) -> RequestParams {
+        match self {
+            ParamsSchedule::Constant(params) => params.clone(),
+            ParamsSchedule::ToolPhaseThenCreative {
+                tool_opts,
+                answer_opts,
+            } => match turn.tools_pending {
+                true => answer_opts.clone(),
+                false => tool_opts.clone(),
+            },
+            ParamsSchedule::Custom(schedule) => schedule(turn),
+        }
+    }
+}
+
+impl Default for ParamsSchedule {
+    fn default() -> Self {
+        ParamsSchedule::Constant(RequestParams::default())
+    }
+}
+
+/* ------------------------------- Speculation ------------------------------ */
+
+/// Predicted result for a tool call, or `None` to wait for the real one
+pub type ResultPredictor = Arc<dyn Fn(&ToolCall) -> Option<String> + Send + Sync>;
+
+/// Whether a real tool result (second) is close enough to the prediction
+/// (first) to keep a response built on the prediction
+pub type ResultComparator = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// ## `SpeculationPolicy`
+/// Lets `send_tool_loop_speculative` send the model's next turn while the
+/// last tool call of a turn is still running, with a predicted result in its
+/// place. If the real result doesn't `matches` the prediction, the early
+/// request is dropped and the turn is sent again with the real result.
+///
+/// ```rust,ignore
+/// // Writes almost always succeed, so don't wait for them
+/// let speculation = SpeculationPolicy::new(Arc::new(|call: &ToolCall| {
+///     (call.name == "save_note").then(|| "saved".to_string())
+/// }));
+/// ```
+#[derive(Clone)]
+pub struct SpeculationPolicy {
+    pub predict: ResultPredictor,
+    /// Exact match by default
+    pub matches: ResultComparator,
+}
+
+impl SpeculationPolicy {
+    pub fn new(predict: ResultPredictor) -> Self {
+        SpeculationPolicy {
+            predict,
+            matches: Arc::new(|predicted, actual| predicted == actual),
+        }
+    }
+
+    /// Never speculate; tools still run concurrently
+    pub fn disabled() -> Self {
+        SpeculationPolicy::new(Arc::new(|_| None))
+    }
+}
+
+impl Default for SpeculationPolicy {
+    fn default() -> Self {
+        SpeculationPolicy::disabled()
+    }
+}
+
+/// What speculation did over a `send_tool_loop_speculative` run
+#[derive(Clone, Default)]
+pub struct SpeculationReport {
+    /// Early responses that were kept
+    pub hits: usize,
+    /// Early responses thrown away because the real result differed
+    pub misses: usize,
+    /// Tokens of every response the conversation kept
+    pub tokens_used: u32,
+    /// Tokens of early responses thrown away after a miss. Requests dropped
+    /// before they finished don't report any.
+    pub tokens_wasted: u32,
+    /// One `"speculation"` note per early request
+    pub provenance: Provenance,
+}
+
+/// `context` with a tool message for each of `calls` that has a result,
+/// laid out like `exec_tool_calls` does
+pub(crate) fn add_tool_results(
+    context: ContextBuilder,
+    calls: &[ToolCall],
+    results: &[Option<Result<String, String>>],
+) -> ContextBuilder {
+    calls
+        .iter()
+        .zip(results)
+        .fold(context, |context, (call, result)| match result {
+            Some(result) => context.add_tool_result_message(
+                &call.id,
+                tool_result_text(call, result),
+                result.is_err(),
+            ),
+            None => context,
+        })
+}
+
+/// Run `calls` concurrently and add their results to `context`. Once all
+/// but one are done, sends the follow-up early if `speculation` predicts the
+/// last result, returning the early response when the prediction holds.
+async fn run_tools_speculatively(
+    context: ContextBuilder,
+    calls: Vec<ToolCall>,
+    tool_executer: &ToolExecuter,
+    adapter: &ProviderAdapter,
+    max_tokens: u32,
+    speculation: &SpeculationPolicy,
+    report: &mut SpeculationReport,
+) -> (ContextBuilder, Option<PromptResponse>) {
+    let mut results: Vec<Option<Result<String, String>>> = vec![None; calls.len()];
+    let mut running: FuturesUnordered<_> = calls
+        .iter()
+        .enumerate()
+        .map(|(index, call)| {
+            let result = tool_executer(call.clone());
+            async move { (index, result.await) }
+        })
+        .collect();
+
+    while running.len() > 1 {
+        if let Some((index, result)) = running.next().await {
+            results[index] = Some(result);
+        }
+    }
+
+    let prediction = results
+        .iter()
+        .position(Option::is_none)
+        .and_then(|index| Some((index, (speculation.predict)(&calls[index])?)));
+    let Some((index, predicted)) = prediction else {
+        while let Some((index, result)) = running.next().await {
+            results[index] = Some(result);
+        }
+        return (add_tool_results(context, &calls, &results), None);
+    };
+
+    let mut guessed = results.clone();
+    guessed[index] = Some(Ok(predicted.clone()));
+    let mut early = adapter(
+        add_tool_results(context.clone(), &calls, &guessed).outgoing(),
+        max_tokens,
+    );
+
+    // Race the last tool against the early request
+    let mut finished = None;
+    let (_, actual) = match select(running.next(), &mut early).await {
+        Either::Left((done, _)) => done.expect("one tool call still running"),
+        Either::Right((response, last)) => {
+            finished = Some(response);
+            last.await.expect("one tool call still running")
+        }
+    };
+
+    let call = &calls[index];
+    let holds = (speculation.matches)(&predicted, &tool_result_text(call, &actual));
+    results[index] = Some(actual);
+    let context = add_tool_results(context, &calls, &results);
+
+    if !holds {
+        report.misses += 1;
+        let detail = match finished {
+            Some(Ok(response)) => {
+                report.tokens_wasted += response.token_usage;
+                format!("{} tokens discarded", response.token_usage)
+            }
+            _ => "request cancelled".to_string(),
+        };
+        report.provenance.record(
+            "speculation",
+            format!("miss on {} call {}: {}", call.name, call.id, detail),
+        );
+        return (context, None);
+    }
+
+    let response = match finished {
+        Some(response) => response,
+        None => early.await,
+    };
+    match response {
+        Ok(response) => {
+            report.hits += 1;
+            report.provenance.record(
+                "speculation",
+                format!("hit on {} call {}", call.name, call.id),
+            );
+            (context, Some(response))
+        }
+        Err(e) => {
+            report.provenance.record(
+                "speculation",
+                format!(
+                    "early request for {} call {} failed: {}",
+                    call.name, call.id, e
+                ),
+            );
+            (context, None)
+        }
+    }
+}
+
+/* -------------------------------- Streaming ------------------------------- */
+
+/// What `send_tool_loop_streaming` reports as it goes, in order
+#[derive(Clone, PartialEq)]
+pub enum ToolLoopEvent {
+    /// Reply text as it streams in; never empty
+    Content { turn_index: usize, content: String },
+    /// A tool call the model made, once its turn has finished streaming
+    ToolCall { turn_index: usize, call: ToolCall },
+    /// The model's turn is complete
+    TurnEnd {
+        turn_index: usize,
+        stop_reason: StopReason,
+    },
+    ToolResult {
+        turn_index: usize,
+        call_id: String,
+        result: Result<String, String>,
+    },
+    /// The stream failed before any content and the turn was sent again
+    /// with the non-streaming adapter
+    FellBack { turn_index: usize, error: String },
+}
+
+/// ## `StreamingLoopAdapters`
+/// The adapters `send_tool_loop_streaming` sends with: `streaming` for
+/// every turn, and `fallback` for a turn whose stream fails before any
+/// content with an error `classifier` calls retryable.
+///
+/// ```rust,ignore
+/// let adapters = StreamingLoopAdapters::new(
+///     openai_streaming_adapter_factory(client.clone(), model.clone(), tools.clone()),
+///     openai_adapter_factory(client, model, tools),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct StreamingLoopAdapters {
+    pub streaming: StreamProviderAdapter,
+    pub fallback: ProviderAdapter,
+    pub classifier: Arc<dyn ErrorClassifier>,
+}
+
+impl StreamingLoopAdapters {
+    pub fn new(streaming: StreamProviderAdapter, fallback: ProviderAdapter) -> Self {
+        StreamingLoopAdapters {
+            streaming,
+            fallback,
+            classifier: Arc::new(HeuristicErrorClassifier),
+        }
+    }
+}
+
+type EventSink = Arc<dyn Fn(ToolLoopEvent) + Send + Sync>;
+
+/// `tool_executer` reporting each result to `on_event`
+fn reporting_executer(
+    tool_executer: ToolExecuter,
+    on_event: EventSink,
+    turn_index: usize,
+) -> ToolExecuter {
+    Arc::new(move |call: ToolCall| {
+        let on_event = on_event.clone();
+        let call_id = call.id.clone();
+        let result = tool_executer(call);
+        Box::pin(async move {
+            let result = result.await;
+            on_event(ToolLoopEvent::ToolResult {
+                turn_index,
+                call_id,
+                result: result.clone(),
+            });
+            result
+        })
+    })
+}
+
+/// Stream one turn, forwarding its content to `on_event`, or send it with
+/// the fallback adapter if the stream fails before any content
+async fn stream_turn(
+    context: ContextBuilder,
+    adapters: &StreamingLoopAdapters,
+    max_tokens: u32,
+    on_event: &EventSink,
+    turn_index: usize,
+) -> Result<UnresolvedResponse, String> {
+    let context = context.prepare_send().await?;
+    let content_seen = Arc::new(AtomicBool::new(false));
+    let forward = {
+        let on_event = on_event.clone();
+        let content_seen = content_seen.clone();
+        move |delta: Result<PromptResponseDelta, String>| {
+            if let Ok(delta) = delta
+                && !delta.content.is_empty()
+            {
+                content_seen.store(true, Ordering::SeqCst);
+                on_event(ToolLoopEvent::Content {
+                    turn_index,
+                    content: delta.content,
+                });
+            }
+        }
+    };
+
+    let failure = match context
+        .clone()
+        .send_streaming_with_callback(adapters.streaming.clone(), max_tokens, forward)
+        .await
+    {
+        Ok(response) => return Ok(response),
+        Err(failure) => failure,
+    };
+    if content_seen.load(Ordering::SeqCst)
+        || !adapters.classifier.classify(&failure.error).is_retryable()
+    {
+        return Err(failure.error);
+    }
+
+    on_event(ToolLoopEvent::FellBack {
+        turn_index,
+        error: failure.error.clone(),
+    });
+    let mut context = context;
+    let mut prompt_response = (adapters.fallback)(context.outgoing(), max_tokens).await?;
+    prompt_response
+        .provenance
+        .record(FALLBACK, format!("stream failed: {}", failure.error));
+    if !prompt_response.message.content.is_empty() {
+        on_event(ToolLoopEvent::Content {
+            turn_index,
+            content: prompt_response.message.content.clone(),
+        });
+    }
+    if let Some(prefill) = context.params.prefill.take() {
+        prompt_response.message.content.insert_str(0, &prefill);
+    }
+    Ok(UnresolvedResponse {
+        prompt_response,
+        context_builder: context,
+    })
+}
+
+/* ---------------------------------- Loop ---------------------------------- */
+
+/// One run of the tool loop that `send_tool_loop` and
+/// `send_tool_loop_with_ask_user` resolve with
+pub(crate) struct ToolLoopRun<'a> {
+    pub adapter: ProviderAdapter,
+    pub tool_executer: ToolExecuter,
+    pub max_tokens: u32,
+    pub schedule: &'a ParamsSchedule,
+    /// Tool whose calls suspend the loop instead of running, e.g. `ask_user`
+    pub suspend_on: Option<&'a str>,
+}
+
+impl ToolLoopRun<'_> {
+    /// Send the turns in `turns`, running tools in order after each one that
+    /// calls them. The first call to `suspend_on` suspends the loop once the
+    /// turn's other calls have run.
+    pub(crate) async fn run(
+        &self,
+        mut context: ContextBuilder,
+        turns: std::ops::Range<usize>,
+        mut last_stop_reason: Option<StopReason>,
+    ) -> Result<LoopOutcome, String> {
+        let mut base = context.params.clone();
+        let max_turns = turns.end;
+
+        for turn_index in turns {
+            let turn = TurnInfo {
+                turn_index,
+                tools_pending: last_stop_reason == Some(StopReason::ToolCalls),
+                last_stop_reason,
+            };
+            context.params = self.schedule.params_for(&turn).over(&base);
+            // The prefill only applies to the first reply
+            base.prefill = None;
+
+            let response = send_turn(context, &self.adapter, self.max_tokens).await?;
+            let stop_reason = response.prompt_response.stop_reason.clone();
+            let calls = response.prompt_response.tool_calls.clone();
+            context = response.resolve_without();
+
+            if stop_reason != StopReason::ToolCalls {
+                context.params = base;
+                return Ok(LoopOutcome::Finished(context));
+            }
+            let calls = calls.unwrap_or_default();
+
+            let mut question = None;
+            let mut results = Vec::with_capacity(calls.len());
+            for call in &calls {
+                if Some(call.name.as_str()) != self.suspend_on {
+                    results.push(Some((self.tool_executer)(call.clone()).await));
+                    continue;
+                }
+                match (&question, call.arg_str("question")) {
+                    (None, Ok(text)) => {
+                        question = Some((call.id.clone(), text));
+                        results.push(None);
+                    }
+                    (Some(_), Ok(_)) => results.push(Some(Err(
+                        "Only one question can be asked at a time".to_string(),
+                    ))),
+                    (_, Err(e)) => results.push(Some(Err(e))),
+                }
+            }
+
+            if let Some((call_id, question)) = question {
+                context.params = base;
+                return Ok(LoopOutcome::Suspended {
+                    question,
+                    resolution_snapshot: ResolutionSnapshot {
+                        context,
+                        call_id,
+                        calls,
+                        results,
+                        max_tokens: self.max_tokens,
+                        next_turn: turn_index + 1,
+                        max_turns,
+                    },
+                });
+            }
+            context = add_tool_results(context, &calls, &results);
+            last_stop_reason = Some(stop_reason);
+        }
+
+        Err(format!(
+            "Tool loop still calling tools after {} turns",
+            max_turns
+        ))
+    }
+}
+
+/// `send`, returning errors instead of panicking on them
+pub(crate) async fn send_turn(
+    context: ContextBuilder,
+    adapter: &ProviderAdapter,
+    max_tokens: u32,
+) -> Result<UnresolvedResponse, String> {
+    let mut context = context.prepare_send().await?;
+    let mut prompt_response = adapter(context.outgoing(), max_tokens).await?;
+    if let Some(prefill) = context.params.prefill.take() {
+        prompt_response.message.content.insert_str(0, &prefill);
+    }
+    Ok(UnresolvedResponse {
+        prompt_response,
+        context_builder: context,
+    })
+}
+
+impl ContextBuilder {
+    /// Send, execute any tool calls and send again until the model answers
+    /// without calling tools, using `schedule` to pick each turn's params.
+    ///
+    /// Errors if a send fails, or if the model is still calling tools after
+    /// `max_turns` sends.
+    pub async fn send_tool_loop(
+        self,
+        adapter: ProviderAdapter,
+        tool_executer: ToolExecuter,
+        max_tokens: u32,
+        max_turns: usize,
+        schedule: &ParamsSchedule,
+    ) -> Result<ContextBuilder, String> {
+        let run = ToolLoopRun {
+            adapter,
+            tool_executer,
+            max_tokens,
+            schedule,
+            suspend_on: None,
+        };
+        match run.run(self, 0..max_turns, None).await? {
+            LoopOutcome::Finished(context) => Ok(context),
+            LoopOutcome::Suspended { .. } => unreachable!("no suspension point is registered"),
+        }
+    }
+
+    /// ## `send_tool_loop_streaming`
+    /// `send_tool_loop` with every turn streamed, so the caller sees the
+    /// reply as it's written even when it comes after several tool turns.
+    /// Content, tool calls, turn ends and tool results go to `on_event` as
+    /// they happen; turns that only call tools send no content events.
+    ///
+    /// A turn whose stream fails before any content with a retryable error
+    /// is sent again with `adapters.fallback`. Errors if the stream fails
+    /// mid-reply, if the fallback fails, or if the model is still calling
+    /// tools after `max_turns` sends.
+    ///
+    /// ```rust,ignore
+    /// let context = context
+    ///     .send_tool_loop_streaming(adapters, executer, 500, 8, &schedule, |event| {
+    ///         if let ToolLoopEvent::Content { content, .. } = event {
+    ///             print!("{}", content);
+    ///         }
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn send_tool_loop_streaming<F>(
+        mut self,
+        adapters: StreamingLoopAdapters,
+        tool_executer: ToolExecuter,
+        max_tokens: u32,
+        max_turns: usize,
+        schedule: &ParamsSchedule,
+        on_event: F,
+    ) -> Result<ContextBuilder, String>
+    where
+        F: Fn(ToolLoopEvent) + Send + Sync + 'static,
+    {
+        let on_event: EventSink = Arc::new(on_event);
+        let mut base = self.params.clone();
+        let mut last_stop_reason = None;
+
+        for turn_index in 0..max_turns {
+            let turn = TurnInfo {
+                turn_index,
+                tools_pending: last_stop_reason == Some(StopReason::ToolCalls),
+                last_stop_reason,
+            };
+            self.params = schedule.params_for(&turn).over(&base);
+            base.prefill = None;
+
+            let response = stream_turn(self, &adapters, max_tokens, &on_event, turn_index).await?;
+            let stop_reason = response.prompt_response.stop_reason.clone();
+            for call in response.prompt_response.tool_calls.iter().flatten() {
+                on_event(ToolLoopEvent::ToolCall {
+                    turn_index,
+                    call: call.clone(),
+                });
+            }
+            on_event(ToolLoopEvent::TurnEnd {
+                turn_index,
+                stop_reason: stop_reason.clone(),
+            });
+
+            if stop_reason != StopReason::ToolCalls {
+                self = response.resolve_without();
+                self.params = base;
+                return Ok(self);
+            }
+
+            let executer = reporting_executer(tool_executer.clone(), on_event.clone(), turn_index);
+            self = response.resolve(executer).await;
+            last_stop_reason = Some(stop_reason);
+        }
+
+        Err(format!(
+            "Tool loop still calling tools after {} turns",
+            max_turns
+        ))
+    }
+
+    /// ## `send_tool_loop_speculative`
+    /// `send_tool_loop` with each turn's tool calls run concurrently, and the
+    /// follow-up sent before the last call finishes whenever `speculation`
+    /// predicts its result. Returns the context and what speculation did.
+    ///
+    /// ```rust,ignore
+    /// let (context, report) = context
+    ///     .send_tool_loop_speculative(adapter, executer, 500, 8, &schedule, &speculation)
+    ///     .await?;
+    /// println!("{} hits, {} tokens wasted", report.hits, report.tokens_wasted);
+    /// ```
+    pub async fn send_tool_loop_speculative(
+        mut self,
+        adapter: ProviderAdapter,
+        tool_executer: ToolExecuter,
+        max_tokens: u32,
+        max_turns: usize,
+        schedule: &ParamsSchedule,
+        speculation: &SpeculationPolicy,
+    ) -> Result<(ContextBuilder, SpeculationReport), String> {
+        let mut base = self.params.clone();
+        let mut last_stop_reason = None;
+        let mut report = SpeculationReport::default();
+        let mut prefetched = None;
+        let disabled = SpeculationPolicy::disabled();
+
+        for turn_index in 0..max_turns {
+            let turn = TurnInfo {
+                turn_index,
+                tools_pending: last_stop_reason == Some(StopReason::ToolCalls),
+                last_stop_reason,
+            };
+            self.params = schedule.params_for(&turn).over(&base);
+            base.prefill = None;
+
+            let response = match prefetched.take() {
+                Some(prompt_response) => UnresolvedResponse {
+                    prompt_response,
+                    context_builder: self,
+                },
+                None => send_turn(self, &adapter, max_tokens).await?,
+            };
+            report.tokens_used += response.prompt_response.token_usage;
+            let stop_reason = response.prompt_response.stop_reason.clone();
+            let calls = response.prompt_response.tool_calls.clone();
+
+            if stop_reason != StopReason::ToolCalls {
+                self = response.resolve_without();
+                self.params = base;
+                return Ok((self, report));
+            }
+
+            self = response.resolve_without();
+            if let Some(calls) = calls {
+                // An early follow-up goes out with the next turn's params
+                self.params = schedule
+                    .params_for(&TurnInfo {
+                        turn_index: turn_index + 1,
+                        last_stop_reason: Some(stop_reason.clone()),
+                        tools_pending: true,
+                    })
+                    .over(&base);
+                // No follow-up after the last turn, so nothing to speculate on
+                let speculation = match turn_index + 1 < max_turns {
+                    true => speculation,
+                    false => &disabled,
+                };
+                // An early follow-up skips `send_turn`, so the rolling
+                // summary goes first
+                self = self.prepare_send().await?;
+                (self, prefetched) = run_tools_speculatively(
+                    self,
+                    calls,
+                    &tool_executer,
+                    &adapter,
+                    max_tokens,
+                    speculation,
+                    &mut report,
+                )
+                .await;
+            }
+            last_stop_reason = Some(stop_reason);
+        }
+
+        Err(format!(
+            "Tool loop still calling tools after {} turns",
+            max_turns
+        ))
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{PromptResponseDelta, StopReason};
+
+/* ------------------------------ Websocket Frames -------------------------- */
+
+/// ## `WsFrame`
+/// JSON envelope for pushing a streamed response over a websocket.
+///
+/// Every frame is self-contained, so a client can render each one without
+/// remembering earlier frames:
+///
+/// ```text
+/// {"type":"delta","content":"Hel"}
+/// {"type":"tool_call","id":"call_1","name":"get_weather","arguments":{"location":"Oslo"}}
+/// {"type":"usage","cumulative_tokens":42}
+/// {"type":"error","message":"OpenAI streaming error: ..."}
+/// {"type":"done","stop_reason":"Stop"}
+/// ```
+///
+/// `done` is always the final frame, including after an `error`, in which
+/// case its `stop_reason` is whatever the stream reported before failing.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsFrame {
+    Delta {
+        content: String,
+    },
+    ToolCall {
+        id: String,
+        name: String,
+        arguments: serde_json::Value,
+    },
+    Usage {
+        cumulative_tokens: u32,
+    },
+    Error {
+        message: String,
+    },
+    Done {
+        stop_reason: Option<StopReason>,
+    },
+}
+
+impl WsFrame {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// The frame as one Server-Sent Event, named after its `type`:
+    ///
+    /// ```text
+    /// event: delta
+    /// data: {"type":"delta","content":"Hel"}
+    /// ```
+    pub fn to_sse(&self) -> String {
+        let event = match self {
+            WsFrame::Delta { .. } => "delta",
+            WsFrame::ToolCall { .. } => "tool_call",
+            WsFrame::Usage { .. } => "usage",
+            WsFrame::Error { .. } => "error",
+            WsFrame::Done { .. } => "done",
+        };
+        format!("event: {}\ndata: {}\n\n", event, self.to_json())
+    }
+}
+
+/// Client-side counterpart to `ws_frames`
+pub fn parse_ws_frame(frame: &str) -> Result<WsFrame, String> {
+    serde_json::from_str(frame).map_err(|e| format!("Invalid websocket frame: {}", e))
+}
+
+fn frames_for_delta(delta: PromptResponseDelta) -> Vec<WsFrame> {
+    let mut frames = vec![];
+
+    if !delta.content.is_empty() {
+        frames.push(WsFrame::Delta {
+            content: delta.content,
+        });
+    }
+
+    for call in delta.tool_calls.unwrap_or_default() {
+        frames.push(WsFrame::ToolCall {
+            id: call.id,
+            name: call.name,
+            arguments: call.arguments,
+        });
+    }
+
+    if delta.cumulative_tokens > 0 {
+        frames.push(WsFrame::Usage {
+            cumulative_tokens: delta.cumulative_tokens,
+        });
+    }
+
+    frames
+}
+
+struct FrameState {
+    deltas: BoxStream<'static, Result<PromptResponseDelta, String>>,
+    pending: VecDeque<WsFrame>,
+    stop_reason: Option<StopReason>,
+    finished: bool,
+}
+
+/// Convert a delta stream (e.g. from `send_streaming`) into serialized
+/// `WsFrame`s. The stream stops at the first error, after emitting the error
+/// frame and the closing `done` frame.
+pub fn ws_frames(
+    deltas: BoxStream<'static, Result<PromptResponseDelta, String>>,
+) -> impl Stream<Item = String> + Send + 'static {
+    frames(deltas).map(|frame| frame.to_json())
+}
+
+/// ## `sse_events`
+/// `ws_frames` as a `text/event-stream` body: the same frames, each
+/// serialized with `WsFrame::to_sse`
+///
+/// ```rust,ignore
+/// let body = Body::from_stream(sse_events(context.send_streaming(adapter, 500)).map(Ok::<_, Infallible>));
+/// ```
+pub fn sse_events(
+    deltas: BoxStream<'static, Result<PromptResponseDelta, String>>,
+) -> impl Stream<Item = String> + Send + 'static {
+    frames(deltas).map(|frame| frame.to_sse())
+}
+
+fn frames(
+    deltas: BoxStream<'static, Result<PromptResponseDelta, String>>,
+) -> impl Stream<Item = WsFrame> + Send + 'static {
+    let state = FrameState {
+        deltas,
+        pending: VecDeque::new(),
+        stop_reason: None,
+        finished: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(frame) = state.pending.pop_front() {
+                return Some((frame, state));
+            }
+            if state.finished {
+                return None;
+            }
+
+            match state.deltas.next().await {
+                Some(Ok(delta)) => {
+                    if let Some(reason) = delta.stop_reason.clone() {
+                        match &mut state.stop_reason {
+                            Some(current) => crate::merge_stop_reason(current, reason),
+                            None => state.stop_reason = Some(reason),
+                        }
+                    }
+                    state.pending.extend(frames_for_delta(delta));
+                }
+                Some(Err(message)) => {
+                    state.pending.push_back(WsFrame::Error { message });
+                    state.pending.push_back(WsFrame::Done {
+                        stop_reason: state.stop_reason.clone(),
+                    });
+                    state.finished = true;
+                }
+                None => {
+                    state.pending.push_back(WsFrame::Done {
+                        stop_reason: state.stop_reason.clone(),
+                    });
+                    state.finished = true;
+                }
+            }
+        }
+    })
+}
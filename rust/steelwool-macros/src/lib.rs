@@ -0,0 +1,450 @@
+//! Derive macros for steelwool. Enable them through steelwool's `macros`
+//! feature rather than depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Attribute, Data, DeriveInput, Expr, Fields, FnArg, GenericArgument, Ident, ItemFn, Lit, LitStr,
+    Pat, PathArguments, ReturnType, Type, parse_macro_input,
+};
+
+/* ---------------------------------- Tool ---------------------------------- */
+
+/// ## `#[derive(Tool)]`
+/// Implements `steelwool::IntoTool` for a struct with named fields, which
+/// are the tool's arguments. The tool is named after the struct in
+/// snake_case (override with `#[tool(name = "...")]`) and described by the
+/// struct's doc comment; each field's doc comment describes its parameter.
+/// `Option` fields aren't required.
+///
+/// ```rust,ignore
+/// /// Current weather for a city
+/// #[derive(Tool, Deserialize)]
+/// struct GetWeather {
+///     /// City name, e.g. "Oslo"
+///     city: String,
+///     days: Option<u8>,
+/// }
+///
+/// let tool = GetWeather::tool_descriptor();
+/// ```
+#[proc_macro_derive(Tool, attributes(tool))]
+pub fn derive_tool(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_tool(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_tool(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Tool can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Tool can only be derived for structs",
+            ));
+        }
+    };
+
+    let name = tool_name(input)?;
+    let params: Vec<Param> = fields
+        .iter()
+        .map(|field| Param {
+            name: field.ident.as_ref().unwrap().to_string(),
+            ty: &field.ty,
+            doc: doc_comment(&field.attrs),
+        })
+        .collect();
+    let descriptor = descriptor_tokens(&name, &doc_comment(&input.attrs), &params);
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::steelwool::IntoTool for #ident #ty_generics #where_clause {
+            fn tool_descriptor() -> ::steelwool::ToolDescriptor {
+                #descriptor
+            }
+        }
+    })
+}
+
+/// One argument of a tool
+struct Param<'a> {
+    name: String,
+    ty: &'a Type,
+    doc: String,
+}
+
+/// Expression building the `ToolDescriptor`: an object schema with a
+/// property per parameter, requiring those that aren't `Option`
+fn descriptor_tokens(name: &str, description: &str, params: &[Param]) -> TokenStream2 {
+    let mut properties = vec![];
+    let mut required = vec![];
+    for param in params {
+        let field_name = &param.name;
+        let schema = schema_for(param.ty);
+        let doc = &param.doc;
+        let schema = match doc.is_empty() {
+            true => schema,
+            false => quote! {{
+                let mut schema = #schema;
+                schema["description"] = ::steelwool::serde_json::Value::from(#doc);
+                schema
+            }},
+        };
+        properties.push(quote! {
+            properties.insert(#field_name.to_string(), #schema);
+        });
+        if !is_option(param.ty) {
+            required.push(field_name);
+        }
+    }
+
+    quote! {{
+        let mut properties = ::steelwool::serde_json::Map::new();
+        #(#properties)*
+        ::steelwool::ToolDescriptor::from_json_schema(
+            #name.to_string(),
+            #description.to_string(),
+            ::steelwool::serde_json::json!({
+                "type": "object",
+                "properties": properties,
+                "required": [#(#required),*],
+                "additionalProperties": false,
+            }),
+        )
+    }}
+}
+
+/// `#[tool(name = "...")]`, or the struct name in snake_case
+fn tool_name(input: &DeriveInput) -> syn::Result<String> {
+    let mut name = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("tool")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(name.unwrap_or_else(|| snake_case(&input.ident.to_string())))
+}
+
+fn snake_case(ident: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in ident.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Non-empty doc comment lines, trimmed
+fn doc_lines(attrs: &[Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta.require_name_value().ok()?.value {
+            Expr::Lit(expr) => match &expr.lit {
+                Lit::Str(line) => Some(line.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Doc comment lines joined into one paragraph
+fn doc_comment(attrs: &[Attribute]) -> String {
+    doc_lines(attrs).join(" ")
+}
+
+/* ------------------------------ Tool Functions ----------------------------- */
+
+/// ## `#[tool]`
+/// Turns a function into a tool. Its arguments become the tool's
+/// parameters, its doc comment the description, and a companion struct named
+/// after it in CamelCase with a `Tool` suffix gets
+/// `as_tool_descriptor()` and `as_tool_executer()`. Parameters are
+/// described by an `# Arguments` list in the doc comment. The function must
+/// return a `Result`; both sides are passed back with `to_string()`.
+/// Override the tool name with `#[tool(name = "...")]`.
+///
+/// ```rust,ignore
+/// /// Current weather for a city
+/// ///
+/// /// # Arguments
+/// /// * `city` - City name, e.g. "Oslo"
+/// #[tool]
+/// async fn get_weather(city: String, days: Option<u8>) -> Result<String, String> {
+///     Ok(format!("Sunny in {}", city))
+/// }
+///
+/// let tools = vec![GetWeatherTool::as_tool_descriptor()];
+/// let executer = GetWeatherTool::as_tool_executer();
+/// ```
+#[proc_macro_attribute]
+pub fn tool(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?.value());
+            Ok(())
+        } else {
+            Err(meta.error("expected `name = \"...\"`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+
+    let function = parse_macro_input!(item as ItemFn);
+    match expand_tool_fn(name, &function) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_tool_fn(name: Option<String>, function: &ItemFn) -> syn::Result<TokenStream2> {
+    let signature = &function.sig;
+    if !matches!(&signature.output, ReturnType::Type(_, ty) if outer_type(ty).is_some_and(|(name, _)| name == "Result"))
+    {
+        return Err(syn::Error::new_spanned(
+            signature,
+            "a #[tool] function must return a Result",
+        ));
+    }
+
+    let (description, param_docs) = split_arguments_section(&doc_lines(&function.attrs));
+    let mut params = vec![];
+    let mut idents: Vec<&Ident> = vec![];
+    for input in &signature.inputs {
+        let FnArg::Typed(arg) = input else {
+            return Err(syn::Error::new_spanned(
+                input,
+                "a #[tool] function can't take self",
+            ));
+        };
+        let Pat::Ident(pat) = arg.pat.as_ref() else {
+            return Err(syn::Error::new_spanned(
+                &arg.pat,
+                "#[tool] arguments must be plain identifiers",
+            ));
+        };
+        if matches!(arg.ty.as_ref(), Type::Reference(_)) {
+            return Err(syn::Error::new_spanned(
+                &arg.ty,
+                "#[tool] arguments must be owned types",
+            ));
+        }
+        let arg_name = pat.ident.to_string();
+        params.push(Param {
+            doc: param_docs
+                .iter()
+                .find(|(name, _)| *name == arg_name)
+                .map(|(_, doc)| doc.clone())
+                .unwrap_or_default(),
+            name: arg_name,
+            ty: &arg.ty,
+        });
+        idents.push(&pat.ident);
+    }
+
+    let fn_ident = &signature.ident;
+    let name = name.unwrap_or_else(|| fn_ident.to_string());
+    let descriptor = descriptor_tokens(&name, &description, &params);
+
+    let parse_args = params.iter().zip(&idents).map(|(param, ident)| {
+        let ty = param.ty;
+        let key = &param.name;
+        quote! {
+            let #ident: #ty = ::steelwool::serde_json::from_value(arguments[#key].clone())
+                .map_err(|e| format!("Invalid argument {} for {}: {}", #key, #name, e))?;
+        }
+    });
+    let call = match signature.asyncness {
+        Some(_) => quote!(#fn_ident(#(#idents),*).await),
+        None => quote!(#fn_ident(#(#idents),*)),
+    };
+
+    let companion = Ident::new(
+        &format!("{}Tool", camel_case(&fn_ident.to_string())),
+        fn_ident.span(),
+    );
+    let vis = &function.vis;
+    let companion_doc = format!("Tool wrapping [`{}`]", fn_ident);
+    Ok(quote! {
+        #function
+
+        #[doc = #companion_doc]
+        #vis struct #companion;
+
+        impl #companion {
+            pub fn as_tool_descriptor() -> ::steelwool::ToolDescriptor {
+                #descriptor
+            }
+
+            pub fn as_tool_executer() -> ::steelwool::ToolExecuter {
+                ::std::sync::Arc::new(|call: ::steelwool::ToolCall| {
+                    ::std::boxed::Box::pin(async move {
+                        let arguments = call.parsed_arguments()?;
+                        #(#parse_args)*
+                        #call
+                            .map(|output| output.to_string())
+                            .map_err(|e| e.to_string())
+                    })
+                })
+            }
+        }
+
+        impl ::steelwool::IntoTool for #companion {
+            fn tool_descriptor() -> ::steelwool::ToolDescriptor {
+                Self::as_tool_descriptor()
+            }
+        }
+    })
+}
+
+/// The description before an `# Arguments` heading, and the
+/// `` * `name` - description `` items listed under it
+fn split_arguments_section(lines: &[String]) -> (String, Vec<(String, String)>) {
+    let heading = lines
+        .iter()
+        .position(|line| line.trim_start_matches('#').trim() == "Arguments");
+    let Some(heading) = heading else {
+        return (lines.join(" "), vec![]);
+    };
+
+    let params = lines[heading + 1..]
+        .iter()
+        .filter_map(|line| {
+            let item = line.strip_prefix(['*', '-'])?.trim();
+            let (name, doc) = item
+                .split_once([':', '-'].as_slice())
+                .or_else(|| item.split_once(' '))?;
+            Some((
+                name.trim().trim_matches('`').to_string(),
+                doc.trim().to_string(),
+            ))
+        })
+        .collect();
+    (lines[..heading].join(" "), params)
+}
+
+fn camel_case(ident: &str) -> String {
+    ident
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/* --------------------------------- Schemas -------------------------------- */
+
+/// Last path segment's name and its first type argument
+fn outer_type(ty: &Type) -> Option<(String, Option<&Type>)> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let argument = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        }),
+        _ => None,
+    };
+    Some((segment.ident.to_string(), argument))
+}
+
+fn is_option(ty: &Type) -> bool {
+    matches!(outer_type(ty), Some((name, Some(_))) if name == "Option")
+}
+
+/// Map-like types' value type
+fn map_value(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    match &path.path.segments.last()?.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .nth(1),
+        _ => None,
+    }
+}
+
+/// Expression building the JSON Schema for `ty`. Types the mapping doesn't
+/// know are expected to implement `IntoTool` themselves.
+fn schema_for(ty: &Type) -> TokenStream2 {
+    let json = quote!(::steelwool::serde_json::json!);
+    match ty {
+        Type::Reference(reference) => return schema_for(&reference.elem),
+        Type::Slice(slice) => {
+            let items = schema_for(&slice.elem);
+            return quote!(#json({ "type": "array", "items": (#items) }));
+        }
+        Type::Array(array) => {
+            let items = schema_for(&array.elem);
+            return quote!(#json({ "type": "array", "items": (#items) }));
+        }
+        _ => {}
+    }
+
+    let Some((name, argument)) = outer_type(ty) else {
+        return quote!(#json({}));
+    };
+    match (name.as_str(), argument) {
+        ("String" | "str" | "char", _) => quote!(#json({ "type": "string" })),
+        (
+            "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+            | "usize",
+            _,
+        ) => quote!(#json({ "type": "integer" })),
+        ("f32" | "f64", _) => quote!(#json({ "type": "number" })),
+        ("bool", _) => quote!(#json({ "type": "boolean" })),
+        ("Value", _) => quote!(#json({})),
+        ("Option" | "Box", Some(inner)) => schema_for(inner),
+        ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", Some(inner)) => {
+            let items = schema_for(inner);
+            quote!(#json({ "type": "array", "items": (#items) }))
+        }
+        ("HashMap" | "BTreeMap", _) => match map_value(ty) {
+            Some(value) => {
+                let values = schema_for(value);
+                quote!(#json({ "type": "object", "additionalProperties": (#values) }))
+            }
+            None => quote!(#json({ "type": "object" })),
+        },
+        _ => quote!(<#ty as ::steelwool::IntoTool>::tool_descriptor().schema),
+    }
+}
@@ -0,0 +1,164 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::executor::block_on;
+    use steelwool::ab_test::{AB_ARM, AbTest, Arm, Assignment, CONVERSATION_ID, ab_test_adapter};
+    use steelwool::providers::mock::{mock_message, mock_text_response};
+    use steelwool::{ContextBuilder, MessageRole, ProviderAdapter};
+
+    fn context() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(MessageRole::System, "Be terse."))
+            .add_message(mock_message(MessageRole::User, "Hi"))
+    }
+
+    /// Replies with the system prompt it was sent, and 10 tokens of usage
+    fn echo_system() -> ProviderAdapter {
+        Arc::new(|context: ContextBuilder, _| {
+            let system = context
+                .request_history()
+                .iter()
+                .filter(|msg| msg.role == MessageRole::System)
+                .map(|msg| msg.content.clone())
+                .collect::<Vec<_>>()
+                .join("|");
+            Box::pin(async move {
+                let mut response = mock_text_response(&system);
+                response.token_usage = 10;
+                Ok(response)
+            })
+        })
+    }
+
+    fn split(incumbent: f64, candidate: f64, assignment: Assignment) -> AbTest {
+        ab_test_adapter(
+            vec![
+                Arm {
+                    label: "incumbent".to_string(),
+                    weight: incumbent,
+                    adapter: echo_system(),
+                    system_prompt_override: None,
+                },
+                Arm {
+                    label: "candidate".to_string(),
+                    weight: candidate,
+                    adapter: echo_system(),
+                    system_prompt_override: Some("Be thorough.".to_string()),
+                },
+            ],
+            assignment,
+        )
+    }
+
+    fn arm_of(test: &AbTest, context: &ContextBuilder) -> String {
+        let response = block_on((test.adapter)(context.clone(), 100)).unwrap();
+        response.provenance.details_from(AB_ARM)[0].to_string()
+    }
+
+    #[test]
+    fn test_sticky_assignment_is_deterministic() {
+        let test = split(0.5, 0.5, Assignment::StickyByConversationId);
+        let rerun = split(0.5, 0.5, Assignment::StickyByConversationId);
+
+        let mut seen = vec![];
+        for id in 0..20 {
+            let context = context().with_metadata(CONVERSATION_ID, format!("session-{}", id));
+            let first = arm_of(&test, &context);
+            for _ in 0..5 {
+                assert_eq!(arm_of(&test, &context), first);
+            }
+            assert_eq!(arm_of(&rerun, &context), first);
+            seen.push(first);
+        }
+        assert!(seen.contains(&"incumbent".to_string()));
+        assert!(seen.contains(&"candidate".to_string()));
+    }
+
+    #[test]
+    fn test_sticky_assignment_needs_conversation_id() {
+        let test = split(0.5, 0.5, Assignment::StickyByConversationId);
+        assert!(block_on((test.adapter)(context(), 100)).is_err());
+    }
+
+    #[test]
+    fn test_recorded_arm_survives_weight_change() {
+        let test = split(0.5, 0.5, Assignment::StickyByConversationId);
+        let context = context().with_metadata(CONVERSATION_ID, "session-1");
+        let resolved = block_on(context.send(test.adapter.clone(), 100)).record_ab_arm();
+        let arm = resolved.context_builder.metadata[AB_ARM].clone();
+
+        let (incumbent, candidate) = match arm.as_str() {
+            "incumbent" => (0.0, 1.0),
+            _ => (1.0, 0.0),
+        };
+        let reweighted = split(incumbent, candidate, Assignment::StickyByConversationId);
+        assert_eq!(arm_of(&reweighted, &resolved.resolve_without()), arm);
+    }
+
+    #[test]
+    fn test_random_assignment_follows_weights() {
+        let test = split(0.9, 0.1, Assignment::PerRequestRandom(7));
+        for _ in 0..2000 {
+            block_on((test.adapter)(context(), 100)).unwrap();
+        }
+
+        let counts = test.counters.snapshot();
+        let candidate = counts["candidate"].sends as f64 / 2000.0;
+        assert!((0.07..0.13).contains(&candidate), "{}", candidate);
+        assert_eq!(counts["incumbent"].sends + counts["candidate"].sends, 2000);
+        assert_eq!(
+            counts["candidate"].token_usage,
+            counts["candidate"].sends * 10
+        );
+
+        // The same seed makes the same picks
+        let picks = |seed| {
+            let test = split(0.9, 0.1, Assignment::PerRequestRandom(seed));
+            (0..50)
+                .map(|_| arm_of(&test, &context()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(picks(7), picks(7));
+    }
+
+    #[test]
+    fn test_override_goes_through_params() {
+        let test = split(0.0, 1.0, Assignment::PerRequestRandom(1));
+        let response = block_on((test.adapter)(context(), 100)).unwrap();
+        assert_eq!(response.message.content, "Be thorough.");
+        assert_eq!(response.provenance.details_from(AB_ARM), ["candidate"]);
+
+        let test = split(1.0, 0.0, Assignment::PerRequestRandom(1));
+        let response = block_on((test.adapter)(context(), 100)).unwrap();
+        assert_eq!(response.message.content, "Be terse.");
+    }
+
+    #[test]
+    fn test_counters_track_errors_and_outcomes() {
+        let test = ab_test_adapter(
+            vec![Arm {
+                label: "broken".to_string(),
+                weight: 1.0,
+                adapter: Arc::new(|_, _| Box::pin(async { Err("boom".to_string()) })),
+                system_prompt_override: None,
+            }],
+            Assignment::PerRequestRandom(3),
+        );
+        assert_eq!(test.counters.snapshot()["broken"].sends, 0);
+
+        assert!(block_on((test.adapter)(context(), 100)).is_err());
+        test.counters.record_outcome("broken", false);
+        test.counters.record_outcome("broken", true);
+
+        let stats = &test.counters.snapshot()["broken"];
+        assert_eq!((stats.sends, stats.errors), (1, 1));
+        assert_eq!(stats.pass_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_no_positive_weight_is_an_error() {
+        let test = split(0.0, 0.0, Assignment::PerRequestRandom(1));
+        assert!(block_on((test.adapter)(context(), 100)).is_err());
+    }
+}
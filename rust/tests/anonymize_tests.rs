@@ -0,0 +1,188 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use steelwool::anonymize::{AnonymizationMap, AnonymizerRules};
+    use steelwool::providers::mock::{mock_message, mock_text_response};
+    use steelwool::{
+        ContentType, ContextBuilder, MessageRole, PromptResponse, ProviderAdapter, StopReason,
+        ToolCall,
+    };
+
+    fn rules() -> AnonymizerRules {
+        AnonymizerRules::new()
+            .exact("PERSON", "Ada Lovelace")
+            .exact("PERSON", "Charles Babbage")
+            .pattern("EMAIL", r"[\w.+-]+@[\w-]+\.[\w.]+")
+            .unwrap()
+            .pattern("ACCOUNT", r"\b\d{8}\b")
+            .unwrap()
+    }
+
+    fn support_ticket() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(
+                MessageRole::User,
+                "I'm Ada Lovelace (ada@example.com), account 12345678.",
+            ))
+            .add_message(mock_message(
+                MessageRole::Model,
+                "Thanks Ada Lovelace, is Charles Babbage on 12345678 too?",
+            ))
+            .add_message(mock_message(
+                MessageRole::Tool,
+                r#"{"owner":"Ada Lovelace","account":"87654321"}"#,
+            ))
+    }
+
+    #[test]
+    fn test_repeated_entities_share_a_placeholder() {
+        let (context, map) = support_ticket().anonymize(&rules());
+
+        assert_eq!(
+            context.history[0].content,
+            "I'm <PERSON_1> (<EMAIL_1>), account <ACCOUNT_1>."
+        );
+        assert_eq!(
+            context.history[1].content,
+            "Thanks <PERSON_1>, is <PERSON_2> on <ACCOUNT_1> too?"
+        );
+        // JSON keys survive, only values are replaced
+        assert_eq!(
+            context.history[2].content,
+            r#"{"account":"<ACCOUNT_2>","owner":"<PERSON_1>"}"#
+        );
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.original("<EMAIL_1>"), Some("ada@example.com"));
+    }
+
+    #[test]
+    fn test_placeholders_avoid_existing_text() {
+        let context = ContextBuilder::new().add_message(mock_message(
+            MessageRole::User,
+            "Template says <PERSON_1>; fill in Ada Lovelace.",
+        ));
+
+        let (anonymized, map) = context.anonymize(&rules());
+        assert_eq!(
+            anonymized.history[0].content,
+            "Template says <PERSON_1>; fill in <PERSON_2>."
+        );
+
+        let restored = anonymized.deanonymize(&map);
+        assert_eq!(
+            restored.history[0].content,
+            "Template says <PERSON_1>; fill in Ada Lovelace."
+        );
+    }
+
+    #[test]
+    fn test_tool_arguments_and_results_are_anonymized() {
+        let lookup = ToolCall {
+            id: "call_1".to_string(),
+            name: "lookup".to_string(),
+            arguments: serde_json::json!(r#"{"email":"ada@example.com"}"#),
+        };
+        let mut asking = mock_message(MessageRole::Model, "");
+        asking.assistant_tool_calls = Some(vec![lookup]);
+        let context = ContextBuilder::new()
+            .add_message(mock_message(
+                MessageRole::User,
+                "Find Ada Lovelace's account.",
+            ))
+            .add_message(asking)
+            .add_tool_result_message("call_1", "Ada Lovelace: 12345678".to_string(), false)
+            .add_tool_use_message(ToolCall {
+                id: "toolu_1".to_string(),
+                name: "close".to_string(),
+                arguments: serde_json::json!({ "account": "12345678" }),
+            });
+
+        let (anonymized, map) = context.clone().anonymize(&rules());
+        let sent = serde_json::to_string(&anonymized.history).unwrap();
+        for original in ["Ada Lovelace", "ada@example.com", "12345678"] {
+            assert!(
+                !sent.contains(original),
+                "{} reached the provider",
+                original
+            );
+        }
+
+        let call = &anonymized.history[1].assistant_tool_calls.as_ref().unwrap()[0];
+        assert_eq!((call.id.as_str(), call.name.as_str()), ("call_1", "lookup"));
+        assert_eq!(
+            call.arguments,
+            serde_json::json!(r#"{"email":"<EMAIL_1>"}"#)
+        );
+        match &anonymized.history[2].content_type {
+            ContentType::ToolResult(result) => {
+                assert_eq!(result.tool_call_id, "call_1");
+                assert_eq!(result.result, "<PERSON_1>: <ACCOUNT_1>");
+            }
+            _ => panic!("tool result should keep its content type"),
+        }
+        match &anonymized.history[3].content_type {
+            ContentType::ToolUse(call) => {
+                assert_eq!(call.id, "toolu_1");
+                assert_eq!(call.arguments["account"], "<ACCOUNT_1>");
+            }
+            _ => panic!("tool use should keep its content type"),
+        }
+
+        let restored = anonymized.deanonymize(&map);
+        assert!(restored.history == context.history);
+    }
+
+    #[test]
+    fn test_round_trip_through_a_send() {
+        let (context, map) = support_ticket().anonymize(&rules());
+
+        // The map is what gets persisted between anonymize and deanonymize
+        let map: AnonymizationMap =
+            serde_json::from_str(&serde_json::to_string(&map).unwrap()).unwrap();
+
+        let seen = Arc::new(Mutex::new(String::new()));
+        let seen_clone = seen.clone();
+        let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            *seen_clone.lock().unwrap() = context.history[0].content.clone();
+            Box::pin(async {
+                let calls = vec![ToolCall {
+                    id: "<PERSON_1>".to_string(),
+                    name: "email".to_string(),
+                    arguments: serde_json::json!({ "to": "<EMAIL_1>" }),
+                }];
+                let mut response = mock_text_response("Emailing <PERSON_1> about <ACCOUNT_1>.");
+                response.message.assistant_tool_calls = Some(calls.clone());
+                Ok(PromptResponse {
+                    stop_reason: StopReason::ToolCalls,
+                    tool_calls: Some(calls),
+                    ..response
+                })
+            })
+        });
+
+        let response = block_on(context.send(adapter, 100)).deanonymize(&map);
+
+        assert!(!seen.lock().unwrap().contains("Ada"));
+        assert_eq!(
+            response.prompt_response.message.content,
+            "Emailing Ada Lovelace about 12345678."
+        );
+        let call = &response.prompt_response.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(call.id, "<PERSON_1>");
+        assert_eq!(call.arguments["to"], "ada@example.com");
+        let message_call = &response
+            .prompt_response
+            .message
+            .assistant_tool_calls
+            .as_ref()
+            .unwrap()[0];
+        assert_eq!(message_call.id, "<PERSON_1>");
+        assert_eq!(message_call.arguments["to"], "ada@example.com");
+        assert_eq!(
+            response.context_builder.history[0].content,
+            "I'm Ada Lovelace (ada@example.com), account 12345678."
+        );
+    }
+}
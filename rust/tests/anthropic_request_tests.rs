@@ -0,0 +1,183 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use steelwool::providers::mock::mock_message;
+    use steelwool::{ContentType, ContextBuilder, MessageRole, ToolCall, ToolDescriptor};
+
+    fn weather_tool() -> ToolDescriptor {
+        ToolDescriptor::from_json_schema(
+            "get_weather".to_string(),
+            "Current weather for a city".to_string(),
+            json!({ "type": "object", "properties": { "city": { "type": "string" } } }),
+        )
+    }
+
+    #[test]
+    fn test_plain_conversation() {
+        let context = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::System, "Be brief."))
+            .add_message(mock_message(MessageRole::System, "Use metric."))
+            .add_message(mock_message(MessageRole::User, "Hi"))
+            .add_message(mock_message(MessageRole::Model, "Hello!"));
+
+        let body = context.to_anthropic_request_body("claude-sonnet-4-5", 512, None);
+        assert_eq!(
+            body,
+            json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 512,
+                "system": "Be brief.\n\nUse metric.",
+                "messages": [
+                    { "role": "user", "content": "Hi" },
+                    { "role": "assistant", "content": "Hello!" },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_tool_turns_become_blocks() {
+        let call = |id: &str| ToolCall {
+            id: id.to_string(),
+            name: "get_weather".to_string(),
+            arguments: json!(r#"{"city":"Oslo"}"#),
+        };
+        let context = ContextBuilder::new()
+            .add_message(mock_message(
+                MessageRole::User,
+                "Weather in Oslo and Bergen?",
+            ))
+            .add_tool_use_message(call("toolu_1"))
+            .add_tool_use_message(call("toolu_2"))
+            .add_tool_result_message("toolu_1", "4C".to_string(), false)
+            .add_tool_result_message("toolu_2", "timeout".to_string(), true);
+
+        let body = context.to_anthropic_request_body("claude", 512, Some(vec![weather_tool()]));
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(
+            messages[1]["content"][1],
+            json!({
+                "type": "tool_use",
+                "id": "toolu_2",
+                "name": "get_weather",
+                "input": { "city": "Oslo" },
+            })
+        );
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(
+            messages[2]["content"][1],
+            json!({
+                "type": "tool_result",
+                "tool_use_id": "toolu_2",
+                "content": "timeout",
+                "is_error": true,
+            })
+        );
+        assert_eq!(body["tools"][0]["name"], "get_weather");
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+        assert!(body.get("system").is_none());
+    }
+
+    #[test]
+    fn test_params_and_prefill() {
+        let mut context = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::User, "One"))
+            .add_message(mock_message(MessageRole::User, "Two"))
+            .with_prefill("{");
+        context.params.temperature = Some(0.5);
+
+        let body = context.to_anthropic_request_body("claude", 100, Some(vec![]));
+        assert_eq!(
+            body["messages"],
+            json!([
+                {
+                    "role": "user",
+                    "content": [
+                        { "type": "text", "text": "One" },
+                        { "type": "text", "text": "Two" },
+                    ],
+                },
+                { "role": "assistant", "content": "{" },
+            ])
+        );
+        assert_eq!(body["temperature"], 0.5);
+        assert!(body.get("tools").is_none());
+    }
+
+    #[test]
+    fn test_import_messages_json() {
+        let messages = json!([
+            { "role": "user", "content": "Weather in Oslo?" },
+            {
+                "role": "assistant",
+                "content": [
+                    { "type": "text", "text": "Let me check." },
+                    { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Oslo" } },
+                ],
+            },
+            {
+                "role": "user",
+                "content": [
+                    {
+                        "type": "tool_result",
+                        "tool_use_id": "toolu_1",
+                        "content": [{ "type": "text", "text": "4C" }],
+                    },
+                    { "type": "text", "text": "Thanks." },
+                    { "type": "text", "text": "And tomorrow?" },
+                ],
+            },
+        ]);
+
+        let context =
+            ContextBuilder::from_anthropic_messages_json(Some("Be brief."), &messages).unwrap();
+        let roles: Vec<&MessageRole> = context.history.iter().map(|msg| &msg.role).collect();
+        assert!(
+            roles
+                == vec![
+                    &MessageRole::System,
+                    &MessageRole::User,
+                    &MessageRole::Model,
+                    &MessageRole::Model,
+                    &MessageRole::Tool,
+                    &MessageRole::User,
+                ]
+        );
+        assert_eq!(context.history[0].content, "Be brief.");
+        assert_eq!(context.history[5].content, "Thanks.\n\nAnd tomorrow?");
+        match &context.history[3].content_type {
+            ContentType::ToolUse(call) => {
+                assert_eq!(call.id, "toolu_1");
+                assert_eq!(call.arguments, json!({ "city": "Oslo" }));
+            }
+            _ => panic!("expected a tool use"),
+        }
+        match &context.history[4].content_type {
+            ContentType::ToolResult(result) => {
+                assert_eq!(result.tool_call_id, "toolu_1");
+                assert_eq!(result.result, "4C");
+                assert!(!result.error);
+            }
+            _ => panic!("expected a tool result"),
+        }
+
+        // Exporting again gives back the same turns
+        let body = context.to_anthropic_request_body("claude", 100, None);
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["messages"][0], messages[0]);
+        assert_eq!(body["messages"][1], messages[1]);
+    }
+
+    #[test]
+    fn test_import_rejects_unknown_roles_and_blocks() {
+        let role = json!([{ "role": "system", "content": "Hi" }]);
+        assert!(ContextBuilder::from_anthropic_messages_json(None, &role).is_err());
+
+        let block = json!([{ "role": "user", "content": [{ "type": "image", "source": {} }] }]);
+        assert!(ContextBuilder::from_anthropic_messages_json(None, &block).is_err());
+
+        assert!(ContextBuilder::from_anthropic_messages_json(None, &json!({})).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use steelwool::ask_user::{ASK_USER, LoopOutcome, ResolutionSnapshot, ask_user_tool};
+    use steelwool::providers::mock::{
+        mock_adapter_factory, mock_text_response, stub_tool_executer,
+    };
+    use steelwool::tool_loop::ParamsSchedule;
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, ProviderAdapter,
+        RequestParams, StopReason, ToolCall, ToolExecuter,
+    };
+
+    fn call(id: &str, name: &str, arguments: serde_json::Value) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    fn calling(calls: Vec<ToolCall>) -> PromptResponse {
+        let mut response = mock_text_response("");
+        response.message.assistant_tool_calls = Some(calls.clone());
+        PromptResponse {
+            stop_reason: StopReason::ToolCalls,
+            tool_calls: Some(calls),
+            ..response
+        }
+    }
+
+    fn question() -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Weather in Oslo?".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    fn executer() -> ToolExecuter {
+        stub_tool_executer(HashMap::from([(
+            "get_weather".to_string(),
+            "12 degrees".to_string(),
+        )]))
+    }
+
+    /// Answers from the last tool message, recording what it was sent
+    fn answering_model(seen: Arc<Mutex<Vec<String>>>) -> ProviderAdapter {
+        Arc::new(move |context: ContextBuilder, _| {
+            let last = context.history.last().unwrap().content.clone();
+            seen.lock().unwrap().push(last.clone());
+            let reply = mock_text_response(&format!("Answer from: {}", last.trim()));
+            Box::pin(async move { Ok(reply) })
+        })
+    }
+
+    #[test]
+    fn test_ask_user_tool_descriptor() {
+        let tool = ask_user_tool();
+        assert_eq!(tool.name, ASK_USER);
+        assert_eq!(tool.schema["required"], serde_json::json!(["question"]));
+    }
+
+    #[test]
+    fn test_suspend_serialize_and_resume() {
+        let model = mock_adapter_factory(vec![Ok(calling(vec![
+            call(
+                "call_1",
+                "get_weather",
+                serde_json::json!({ "location": "Oslo" }),
+            ),
+            call(
+                "call_2",
+                ASK_USER,
+                serde_json::json!({ "question": "Celsius or Fahrenheit?" }),
+            ),
+        ]))]);
+
+        let outcome = block_on(question().send_tool_loop_with_ask_user(
+            model,
+            executer(),
+            200,
+            4,
+            &ParamsSchedule::default(),
+        ))
+        .unwrap();
+        let LoopOutcome::Suspended {
+            question,
+            resolution_snapshot,
+        } = outcome
+        else {
+            panic!("loop should suspend on ask_user");
+        };
+        assert_eq!(question, "Celsius or Fahrenheit?");
+        assert_eq!(resolution_snapshot.call_id, "call_2");
+        assert_eq!(resolution_snapshot.context.len(), 2);
+
+        // Across a restart
+        let json = serde_json::to_string(&resolution_snapshot).unwrap();
+        let restored: ResolutionSnapshot = serde_json::from_str(&json).unwrap();
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let outcome = block_on(restored.resume_with_answer(
+            "Celsius",
+            answering_model(seen.clone()),
+            executer(),
+            &ParamsSchedule::default(),
+        ))
+        .unwrap();
+        let LoopOutcome::Finished(context) = outcome else {
+            panic!("loop should finish after the answer");
+        };
+
+        let transcript: Vec<(MessageRole, &str)> = context
+            .history
+            .iter()
+            .map(|msg| (msg.role.clone(), msg.content.as_str()))
+            .collect();
+        assert!(
+            transcript
+                == vec![
+                    (MessageRole::User, "Weather in Oslo?"),
+                    (MessageRole::Model, ""),
+                    (MessageRole::Tool, "12 degrees"),
+                    (MessageRole::Tool, "Celsius"),
+                    (MessageRole::Model, "Answer from: Celsius"),
+                ]
+        );
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        assert_eq!(
+            context.history[1].assistant_tool_calls.as_ref().unwrap()[1].name,
+            ASK_USER
+        );
+    }
+
+    #[test]
+    fn test_resumed_loop_can_suspend_again_and_keeps_turn_budget() {
+        let ask = |id: &str, text: &str| {
+            Ok(calling(vec![call(
+                id,
+                ASK_USER,
+                serde_json::json!({ "question": text }),
+            )]))
+        };
+        let model =
+            mock_adapter_factory(vec![ask("call_1", "Which city?"), ask("call_2", "When?")]);
+
+        let outcome = block_on(question().send_tool_loop_with_ask_user(
+            model.clone(),
+            executer(),
+            200,
+            2,
+            &ParamsSchedule::default(),
+        ))
+        .unwrap();
+        let LoopOutcome::Suspended {
+            resolution_snapshot,
+            ..
+        } = outcome
+        else {
+            panic!("loop should suspend");
+        };
+
+        let outcome = block_on(resolution_snapshot.resume_with_answer(
+            "Oslo",
+            model.clone(),
+            executer(),
+            &ParamsSchedule::default(),
+        ))
+        .unwrap();
+        let LoopOutcome::Suspended {
+            question,
+            resolution_snapshot,
+        } = outcome
+        else {
+            panic!("loop should suspend again");
+        };
+        assert_eq!(question, "When?");
+
+        // Both of the loop's two turns are used up
+        let result = block_on(resolution_snapshot.resume_with_answer(
+            "Today",
+            model,
+            executer(),
+            &ParamsSchedule::default(),
+        ));
+        assert_eq!(
+            result.err(),
+            Some("Tool loop still calling tools after 2 turns".to_string())
+        );
+    }
+
+    #[test]
+    fn test_question_without_text_is_a_tool_error() {
+        let model = mock_adapter_factory(vec![
+            Ok(calling(vec![call(
+                "call_1",
+                ASK_USER,
+                serde_json::json!({}),
+            )])),
+            Ok(mock_text_response("Never mind.")),
+        ]);
+
+        let outcome = block_on(question().send_tool_loop_with_ask_user(
+            model,
+            executer(),
+            200,
+            4,
+            &ParamsSchedule::default(),
+        ))
+        .unwrap();
+        let LoopOutcome::Finished(context) = outcome else {
+            panic!("a malformed question doesn't suspend");
+        };
+        assert!(
+            context.history[2]
+                .content
+                .contains("Missing argument question for ask_user")
+        );
+        assert_eq!(context.history[3].content, "Never mind.");
+    }
+
+    #[test]
+    fn test_resume_uses_the_schedule_and_returns_adapter_errors() {
+        let model = mock_adapter_factory(vec![Ok(calling(vec![call(
+            "call_1",
+            ASK_USER,
+            serde_json::json!({ "question": "Which city?" }),
+        )]))]);
+        let outcome = block_on(question().send_tool_loop_with_ask_user(
+            model,
+            executer(),
+            200,
+            4,
+            &ParamsSchedule::default(),
+        ))
+        .unwrap();
+        let LoopOutcome::Suspended {
+            resolution_snapshot,
+            ..
+        } = outcome
+        else {
+            panic!("loop should suspend");
+        };
+
+        let temperatures = Arc::new(Mutex::new(vec![]));
+        let temperatures_clone = temperatures.clone();
+        let failing: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            temperatures_clone
+                .lock()
+                .unwrap()
+                .push(context.params.temperature);
+            Box::pin(async { Err("503 Service Unavailable".to_string()) })
+        });
+        let schedule = ParamsSchedule::Constant(RequestParams {
+            temperature: Some(0.2),
+            ..RequestParams::default()
+        });
+
+        let result = block_on(resolution_snapshot.resume_with_answer(
+            "Oslo",
+            failing,
+            executer(),
+            &schedule,
+        ));
+        assert_eq!(result.err(), Some("503 Service Unavailable".to_string()));
+        assert_eq!(*temperatures.lock().unwrap(), vec![Some(0.2)]);
+    }
+}
@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use steelwool::batch::{self, BatchOptions, BatchProgress, CancelToken};
+    use steelwool::capabilities::Pricing;
+    use steelwool::providers::mock::mock_text_response;
+    use steelwool::resilience::RetryPolicy;
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, ProviderAdapter,
+    };
+
+    /// Drive `future` on whichever executor the runtime backend expects
+    fn run<F: Future>(future: F) -> F::Output {
+        #[cfg(all(feature = "tokio-runtime", not(feature = "agnostic-runtime")))]
+        return tokio::runtime::Runtime::new().unwrap().block_on(future);
+
+        #[cfg(any(not(feature = "tokio-runtime"), feature = "agnostic-runtime"))]
+        return futures::executor::block_on(future);
+    }
+
+    fn checkpoint(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "steelwool-batch-{}-{}.ckpt",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn items(n: usize) -> Vec<(String, ContextBuilder)> {
+        (0..n)
+            .map(|i| {
+                let key = format!("item-{}", i);
+                let context = ContextBuilder::new().add_message(Message {
+                    role: MessageRole::User,
+                    content: key.clone(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                });
+                (key, context)
+            })
+            .collect()
+    }
+
+    /// Labels each item, 10 tokens a reply, counting the calls per item.
+    /// Items listed in `flaky` fail on their first call.
+    fn classifier(calls: Arc<Mutex<HashMap<String, usize>>>, flaky: &[&str]) -> ProviderAdapter {
+        let flaky: Vec<String> = flaky.iter().map(|key| key.to_string()).collect();
+        Arc::new(move |context: ContextBuilder, _| {
+            let key = context.history[0].content.clone();
+            let mut calls = calls.lock().unwrap();
+            let count = calls.entry(key.clone()).or_insert(0);
+            *count += 1;
+            let result = match flaky.contains(&key) && *count == 1 {
+                true => Err(format!("{} timed out", key)),
+                false => Ok(PromptResponse {
+                    token_usage: 10,
+                    ..mock_text_response(&format!("label for {}", key))
+                }),
+            };
+            Box::pin(async move { result })
+        })
+    }
+
+    #[test]
+    fn test_cancel_and_resume_runs_every_item_once() {
+        let path = checkpoint("resume");
+        let calls = Arc::new(Mutex::new(HashMap::new()));
+        let cancel = CancelToken::new();
+        let events: Arc<Mutex<Vec<BatchProgress>>> = Arc::new(Mutex::new(vec![]));
+        let (sink, token) = (events.clone(), cancel.clone());
+        let options = BatchOptions {
+            concurrency: 5,
+            checkpoint: Some(path.clone()),
+            cancel: Some(cancel.clone()),
+            progress: Some(Arc::new(move |event: BatchProgress| {
+                if event.finished == 25 {
+                    token.cancel();
+                }
+                sink.lock().unwrap().push(event);
+            })),
+            ..BatchOptions::default()
+        };
+
+        let first = run(batch::run(
+            items(50),
+            classifier(calls.clone(), &[]),
+            &options,
+        ))
+        .unwrap();
+        assert!(first.cancelled);
+        assert!((25..30).contains(&first.completed.len()));
+        assert_eq!(first.completed.len() + first.not_started, 50);
+        assert_eq!(first.token_usage, 10 * first.completed.len() as u64);
+        let events = events.lock().unwrap().clone();
+        assert_eq!(events.len(), first.completed.len());
+        assert!(events.iter().all(|event| event.total == 50));
+
+        let resumed = BatchOptions {
+            cancel: None,
+            progress: None,
+            ..options
+        };
+        let second = run(batch::run(
+            items(50),
+            classifier(calls.clone(), &[]),
+            &resumed,
+        ))
+        .unwrap();
+        assert!(!second.cancelled);
+        assert_eq!(second.resumed, first.completed.len());
+        assert_eq!(second.completed.len(), 50);
+        assert_eq!(
+            second.completed["item-0"].message.content,
+            "label for item-0"
+        );
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 50);
+        assert!(calls.values().all(|count| *count == 1), "{:?}", calls);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_failures_are_isolated_and_retried() {
+        let calls = Arc::new(Mutex::new(HashMap::new()));
+        let adapter = classifier(calls.clone(), &["item-3"]);
+
+        let report = run(batch::run(
+            items(6),
+            adapter.clone(),
+            &BatchOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(report.completed.len(), 5);
+        assert_eq!(report.failed["item-3"], "item-3 timed out");
+
+        let retrying = BatchOptions {
+            per_item_retry: Some(RetryPolicy {
+                base_delay: Duration::from_millis(1),
+                ..RetryPolicy::default()
+            }),
+            pricing: Some(Pricing {
+                prompt_per_million: 1_000_000.0,
+                completion_per_million: 0.0,
+            }),
+            ..BatchOptions::default()
+        };
+        calls.lock().unwrap().clear();
+        let report = run(batch::run(items(6), adapter, &retrying)).unwrap();
+        assert!(report.failed.is_empty());
+        assert_eq!(calls.lock().unwrap()["item-3"], 2);
+        // Each prompt is "item-N" (2 tokens) plus 4 tokens of framing
+        assert_eq!(report.cost, Some(6.0 * 6.0));
+    }
+}
@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use steelwool::providers::mock::{mock_adapter_factory, mock_message, mock_text_response};
+    use steelwool::{ContextBuilder, Message, MessageRole};
+
+    fn transcript() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(MessageRole::System, "You are a travel agent."))
+            .add_message(mock_message(MessageRole::User, "Find me a flight to Oslo."))
+            .add_message(mock_message(MessageRole::Model, "Searching flights."))
+            .add_message(mock_message(MessageRole::Tool, "SK4321 09:00"))
+            .add_message(mock_message(MessageRole::Model, "SK4321 leaves at 09:00."))
+            .add_message(mock_message(MessageRole::User, "Book it for Tuesday."))
+            .add_message(mock_message(MessageRole::Model, "Booked for Tuesday."))
+    }
+
+    fn contents(history: &[Message]) -> Vec<&str> {
+        history.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_edit_mid_conversation_and_keep_branch() {
+        let adapter = mock_adapter_factory(vec![Ok(mock_text_response("Booked for Friday."))]);
+
+        let context = block_on(transcript().regenerate_from_as_branch(
+            5,
+            Some("Book it for Friday.".to_string()),
+            "tuesday",
+            adapter,
+            100,
+        ))
+        .unwrap()
+        .resolve_without();
+
+        assert_eq!(
+            contents(&context.history[4..]),
+            vec![
+                "SK4321 leaves at 09:00.",
+                "Book it for Friday.",
+                "Booked for Friday."
+            ]
+        );
+        assert_eq!(context.branch_names(), vec!["tuesday"]);
+        assert!(context.branch("tuesday").unwrap() == transcript().history.as_slice());
+
+        let restored = context.checkout_branch("tuesday").unwrap();
+        assert_eq!(
+            restored.history.last().unwrap().content,
+            "Booked for Tuesday."
+        );
+    }
+
+    #[test]
+    fn test_regenerate_keeps_tool_results_with_their_call() {
+        let adapter = mock_adapter_factory(vec![Ok(mock_text_response("Found SK4321."))]);
+
+        let unresolved = block_on(transcript().regenerate_from(2, None, adapter, 100)).unwrap();
+
+        assert_eq!(
+            contents(&unresolved.context_builder.history),
+            vec![
+                "You are a travel agent.",
+                "Find me a flight to Oslo.",
+                "Searching flights.",
+                "SK4321 09:00"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_regenerate_rejects_bad_edits() {
+        let adapter = mock_adapter_factory(vec![Ok(mock_text_response("unused"))]);
+
+        let out_of_range = block_on(transcript().regenerate_from(7, None, adapter.clone(), 100));
+        assert!(out_of_range.is_err());
+
+        let not_user =
+            block_on(transcript().regenerate_from(2, Some("edit".to_string()), adapter, 100));
+        assert_eq!(not_user.err().unwrap(), "Message 2 is not a user message");
+    }
+
+    #[test]
+    fn test_regenerate_applies_prefill() {
+        let adapter = mock_adapter_factory(vec![Ok(mock_text_response(" Friday.\"}"))]);
+
+        let unresolved = block_on(
+            transcript()
+                .with_prefill("{\"day\":")
+                .regenerate_from(5, None, adapter, 100),
+        )
+        .unwrap();
+
+        assert_eq!(
+            unresolved.prompt_response.message.content,
+            "{\"day\": Friday.\"}"
+        );
+        assert!(unresolved.context_builder.params.prefill.is_none());
+    }
+
+    #[test]
+    fn test_regenerate_refuses_finalized_conversation() {
+        use std::sync::atomic::Ordering;
+        use steelwool::finalize::{CONVERSATION_FINALIZED, FinalizeOpts};
+        use steelwool::providers::mock::counting_adapter;
+
+        let mut context = transcript();
+        block_on(context.finalize(FinalizeOpts::default())).unwrap();
+        let (adapter, calls) =
+            counting_adapter(mock_adapter_factory(vec![Ok(mock_text_response("unused"))]));
+
+        let result = block_on(context.regenerate_from(5, None, adapter, 100));
+        assert_eq!(result.err().unwrap(), CONVERSATION_FINALIZED);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+}
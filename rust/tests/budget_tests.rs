@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::executor::block_on;
+    use futures::stream::{self, BoxStream, StreamExt};
+    use steelwool::budget::TokenBudget;
+    use steelwool::tokens::HeuristicTokenCounter;
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, StopReason,
+        StreamProviderAdapter,
+    };
+
+    fn delta(content: &str) -> PromptResponseDelta {
+        PromptResponseDelta {
+            content: content.to_string(),
+            stop_reason: None,
+            tool_calls: None,
+            cumulative_tokens: 0,
+            token_delta: 0,
+            logprobs: None,
+        }
+    }
+
+    fn context() -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Tell me a long story".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    /// Streams `count` two-token deltas, counting how many were pulled
+    fn story_adapter(count: usize, pulled: Arc<AtomicUsize>) -> StreamProviderAdapter {
+        Arc::new(move |_, _| {
+            let pulled = pulled.clone();
+            Box::pin(stream::iter(0..count).map(move |_| {
+                pulled.fetch_add(1, Ordering::SeqCst);
+                Ok(delta("once up"))
+            })) as BoxStream<'static, Result<PromptResponseDelta, String>>
+        })
+    }
+
+    #[test]
+    fn test_stream_stops_when_budget_runs_out() {
+        let budget = Arc::new(TokenBudget::new(5));
+        let pulled = Arc::new(AtomicUsize::new(0));
+
+        let deltas: Vec<_> = block_on(
+            context()
+                .send_streaming_with_budget(
+                    story_adapter(10, pulled.clone()),
+                    100,
+                    budget.clone(),
+                    Arc::new(HeuristicTokenCounter),
+                )
+                .collect(),
+        );
+        let deltas: Vec<PromptResponseDelta> = deltas.into_iter().map(Result::unwrap).collect();
+
+        // 2 + 2 + 1 granted of the third delta's 2
+        assert_eq!(deltas.len(), 3);
+        assert!(deltas[2].stop_reason == Some(StopReason::BudgetExhausted));
+        assert_eq!(pulled.load(Ordering::SeqCst), 3);
+        assert_eq!(budget.remaining(), 0);
+    }
+
+    #[test]
+    fn test_exhausted_budget_skips_the_adapter() {
+        let budget = Arc::new(TokenBudget::new(0));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_clone = calls.clone();
+        let adapter: StreamProviderAdapter = Arc::new(move |_, _| {
+            calls_clone.fetch_add(1, Ordering::SeqCst);
+            Box::pin(stream::iter(vec![Ok(delta("once up"))]))
+                as BoxStream<'static, Result<PromptResponseDelta, String>>
+        });
+
+        let deltas: Vec<_> = block_on(
+            context()
+                .send_streaming_with_budget(adapter, 100, budget, Arc::new(HeuristicTokenCounter))
+                .collect(),
+        );
+
+        assert_eq!(deltas.len(), 1);
+        let delta = deltas[0].as_ref().unwrap();
+        assert!(delta.stop_reason == Some(StopReason::BudgetExhausted));
+        assert!(delta.content.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_final_usage_reconciles_estimates() {
+        let budget = Arc::new(TokenBudget::new(100));
+        let adapter: StreamProviderAdapter = Arc::new(|_, _| {
+            Box::pin(stream::iter(vec![
+                Ok(delta("once up")),
+                Ok(delta("on a time")),
+                Ok(PromptResponseDelta {
+                    cumulative_tokens: 9,
+                    stop_reason: Some(StopReason::Stop),
+                    ..delta("")
+                }),
+            ])) as BoxStream<'static, Result<PromptResponseDelta, String>>
+        });
+
+        let deltas: Vec<_> = block_on(
+            context()
+                .send_streaming_with_budget(
+                    adapter,
+                    100,
+                    budget.clone(),
+                    Arc::new(HeuristicTokenCounter),
+                )
+                .collect(),
+        );
+
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(budget.remaining(), 91);
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_streams_share_one_budget() {
+        use std::time::Duration;
+        use steelwool::tokens::TokenCounter;
+
+        let budget = Arc::new(TokenBudget::new(21));
+        let counter = HeuristicTokenCounter;
+
+        let slow_story = |offset_ms: u64| -> StreamProviderAdapter {
+            Arc::new(move |_, _| {
+                Box::pin(stream::iter(0..50).then(move |i| async move {
+                    tokio::time::sleep(Duration::from_millis(1 + offset_ms * (i % 2))).await;
+                    Ok(delta("a bit of a story"))
+                })) as BoxStream<'static, Result<PromptResponseDelta, String>>
+            })
+        };
+
+        let run = |adapter: StreamProviderAdapter| {
+            let budget = budget.clone();
+            async move {
+                context()
+                    .send_streaming_with_budget(adapter, 1000, budget, Arc::new(counter))
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        let (a, b) = tokio::join!(run(slow_story(0)), run(slow_story(2)));
+
+        let consumed: usize = a
+            .iter()
+            .chain(b.iter())
+            .map(|d| counter.count(&d.as_ref().unwrap().content))
+            .sum();
+        let slack = counter.count("a bit of a story");
+
+        assert!(consumed <= 21 + slack, "consumed {} tokens", consumed);
+        assert_eq!(budget.remaining(), 0);
+        for deltas in [&a, &b] {
+            let last = deltas.last().unwrap().as_ref().unwrap();
+            assert!(last.stop_reason == Some(StopReason::BudgetExhausted));
+        }
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use steelwool::capabilities::{
+        MaxTokensPolicy, known_capabilities, set_global_default_max_tokens,
+    };
+
+    #[test]
+    fn test_known_models_match_longest_prefix() {
+        let mini = known_capabilities("gpt-4o-mini-2024-07-18").unwrap();
+        assert_eq!(mini.max_output_tokens, 16_384);
+        assert_eq!(
+            known_capabilities("gpt-4-turbo").unwrap().max_output_tokens,
+            4_096
+        );
+        assert_eq!(
+            known_capabilities("gpt-4-0613").unwrap().max_output_tokens,
+            8_192
+        );
+        assert_eq!(
+            known_capabilities("llama3:8b").unwrap().context_length,
+            8_192
+        );
+        assert!(known_capabilities("my-finetune").is_none());
+    }
+
+    #[test]
+    fn test_clamping_and_strict_mode() {
+        let lenient = MaxTokensPolicy::default();
+        assert_eq!(
+            lenient.resolve("gpt-4-turbo", 10_000).unwrap(),
+            (
+                4_096,
+                Some(
+                    "clamped max_tokens 10000 to the gpt-4-turbo output limit of 4096".to_string()
+                )
+            )
+        );
+        assert_eq!(
+            lenient.resolve("gpt-4-turbo", 1_000).unwrap(),
+            (1_000, None)
+        );
+        assert_eq!(
+            lenient.resolve("my-finetune", 50_000).unwrap(),
+            (50_000, None)
+        );
+
+        let strict = MaxTokensPolicy {
+            strict: true,
+            ..MaxTokensPolicy::default()
+        };
+        assert_eq!(
+            strict.resolve("gpt-4-turbo", 10_000).unwrap_err(),
+            "max_tokens 10000 exceeds the gpt-4-turbo output limit of 4096"
+        );
+        assert_eq!(strict.resolve("gpt-4-turbo", 4_096).unwrap(), (4_096, None));
+    }
+
+    #[test]
+    fn test_defaults_apply_when_no_max_tokens_given() {
+        let adapter_default = MaxTokensPolicy {
+            default_max_tokens: Some(800),
+            strict: false,
+        };
+        assert_eq!(adapter_default.resolve("gpt-4o", 0).unwrap(), (800, None));
+        // An oversized default is clamped like any other request
+        let oversized = MaxTokensPolicy {
+            default_max_tokens: Some(20_000),
+            strict: false,
+        };
+        assert_eq!(oversized.resolve("gpt-4", 0).unwrap().0, 8_192);
+
+        // Falls back to the model's maximum, and errors when that's unknown
+        let none = MaxTokensPolicy::default();
+        assert_eq!(none.resolve("gpt-4", 0).unwrap(), (8_192, None));
+        assert!(none.resolve("my-finetune", 0).is_err());
+
+        set_global_default_max_tokens(300);
+        assert_eq!(none.resolve("my-finetune", 0).unwrap(), (300, None));
+        assert_eq!(
+            adapter_default.resolve("my-finetune", 0).unwrap(),
+            (800, None)
+        );
+        set_global_default_max_tokens(0);
+        assert!(none.resolve("my-finetune", 0).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::channel::oneshot;
+    use futures::future::{FutureExt, Shared, join3};
+    use steelwool::ab_test::CONVERSATION_ID;
+    use steelwool::coalesce::{COALESCED, coalescing_adapter};
+    use steelwool::providers::mock::mock_text_response;
+    use steelwool::{ContentType, ContextBuilder, Message, MessageRole, ProviderAdapter};
+
+    fn question(content: &str) -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    /// Answers "reply N" for the Nth call, once `release` fires
+    fn slow_adapter(
+        release: Shared<oneshot::Receiver<()>>,
+        calls: Arc<AtomicUsize>,
+    ) -> ProviderAdapter {
+        Arc::new(move |_, _| {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let release = release.clone();
+            Box::pin(async move {
+                let _ = release.await;
+                Ok(mock_text_response(&format!("reply {}", n)))
+            })
+        })
+    }
+
+    /// Send `first` and `second` through one coalescing adapter at once,
+    /// returning both replies and how often the inner adapter ran
+    async fn send_together(
+        first: ContextBuilder,
+        second: ContextBuilder,
+    ) -> (String, String, usize) {
+        let (release, released) = oneshot::channel();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let adapter = coalescing_adapter(slow_adapter(released.shared(), calls.clone()));
+
+        let (a, b, _) = join3(
+            first.send(adapter.clone(), 100),
+            second.send(adapter, 100),
+            async move { release.send(()).unwrap() },
+        )
+        .await;
+        (
+            a.prompt_response.message.content,
+            b.prompt_response.message.content,
+            calls.load(Ordering::SeqCst),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_identical_concurrent_sends_share_one_request() {
+        let (release, released) = oneshot::channel();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let adapter = coalescing_adapter(slow_adapter(released.shared(), calls.clone()));
+
+        let (first, second, _) = join3(
+            question("Capital of Norway?").send(adapter.clone(), 100),
+            question("Capital of Norway?").send(adapter.clone(), 100),
+            async move { release.send(()).unwrap() },
+        )
+        .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let (first, second) = (first.prompt_response, second.prompt_response);
+        assert_eq!(first.message.content, "reply 1");
+        assert_eq!(second.message.content, "reply 1");
+        assert!(first.provenance.details_from(COALESCED).is_empty());
+        assert_eq!(
+            second.provenance.details_from(COALESCED),
+            vec!["joined an identical request in flight"]
+        );
+
+        // Finished requests aren't reused
+        let later = question("Capital of Norway?").send(adapter, 100).await;
+        assert_eq!(later.prompt_response.message.content, "reply 2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_different_requests_are_not_coalesced() {
+        let (_, _, calls) =
+            send_together(question("Capital of Norway?"), question("Of Sweden?")).await;
+        assert_eq!(calls, 2);
+
+        let (_, _, calls) = send_together(
+            question("Capital of Norway?").with_metadata(CONVERSATION_ID, "conv-1"),
+            question("Capital of Norway?").with_metadata(CONVERSATION_ID, "conv-2"),
+        )
+        .await;
+        assert_eq!(calls, 2);
+
+        let mut hotter = question("Capital of Norway?");
+        hotter.params.temperature = Some(1.2);
+        let (_, _, calls) = send_together(question("Capital of Norway?"), hotter).await;
+        assert_eq!(calls, 2);
+
+        let (_, _, calls) = send_together(
+            question("Capital of Norway?").with_metadata(CONVERSATION_ID, "conv-1"),
+            question("Capital of Norway?").with_metadata(CONVERSATION_ID, "conv-1"),
+        )
+        .await;
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_skip_coalescing_always_sends() {
+        let mut opted_out = question("Capital of Norway?");
+        opted_out.params.skip_coalescing = true;
+
+        let (first, second, calls) = send_together(question("Capital of Norway?"), opted_out).await;
+        assert_eq!(calls, 2);
+        assert_eq!((first.as_str(), second.as_str()), ("reply 1", "reply 2"));
+    }
+
+    #[cfg(all(feature = "tokio-runtime", not(feature = "agnostic-runtime")))]
+    mod timing {
+        use std::time::Duration;
+
+        use super::*;
+        use steelwool::coalesce::{CoalesceOptions, coalescing_adapter_with_options};
+
+        #[tokio::test(start_paused = true)]
+        async fn test_waiters_give_up_after_max_wait() {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let calls_clone = calls.clone();
+            let slow: ProviderAdapter = Arc::new(move |_, _| {
+                let n = calls_clone.fetch_add(1, Ordering::SeqCst) + 1;
+                Box::pin(async move {
+                    tokio::time::sleep(Duration::from_secs(n as u64 * 10)).await;
+                    Ok(mock_text_response(&format!("reply {}", n)))
+                })
+            });
+            let adapter = coalescing_adapter_with_options(
+                slow,
+                CoalesceOptions {
+                    max_wait: Duration::from_secs(1),
+                },
+            );
+
+            let (first, second) = tokio::join!(
+                question("Capital of Norway?").send(adapter.clone(), 100),
+                question("Capital of Norway?").send(adapter, 100),
+            );
+
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+            assert_eq!(first.prompt_response.message.content, "reply 1");
+            assert_eq!(second.prompt_response.message.content, "reply 2");
+            assert!(second.prompt_response.provenance.notes.is_empty());
+        }
+    }
+}
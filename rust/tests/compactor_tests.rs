@@ -0,0 +1,172 @@
+#[cfg(all(test, feature = "tokio-runtime"))]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use steelwool::compactor::{CompactionPolicy, Compactor, SUMMARY_HEADER};
+    use steelwool::providers::mock::{mock_message, mock_text_response};
+    use steelwool::tool_gc::UNKNOWN_TOOL;
+    use steelwool::{ContentType, ContextBuilder, MessageRole, ProviderAdapter, ToolCall};
+
+    fn conversation(turns: usize) -> ContextBuilder {
+        (0..turns).fold(
+            ContextBuilder::new().add_message(mock_message(MessageRole::System, "Be brief.")),
+            |context, i| {
+                context.add_message(mock_message(MessageRole::User, &format!("turn {}", i)))
+            },
+        )
+    }
+
+    /// Summarizer that takes five seconds and counts its calls
+    fn slow_summarizer() -> (ProviderAdapter, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let adapter: ProviderAdapter = Arc::new(move |_, _| {
+            let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(mock_text_response(&format!("summary {}", call)))
+            })
+        });
+        (adapter, calls)
+    }
+
+    fn policy() -> CompactionPolicy {
+        CompactionPolicy {
+            max_messages: 8,
+            keep_recent: 4,
+            max_concurrency: 1,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_swap_in_only_once_ready() {
+        let (summarizer, _) = slow_summarizer();
+        let compactor = Compactor::spawn(policy(), summarizer);
+        let context = conversation(12);
+        let mut compacted = compactor.submit("a", context.clone());
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let current = compactor.swap_in_if_ready("a", context.clone(), &mut compacted);
+        assert!(current.history == context.history);
+        assert!(compactor.is_compacting("a"));
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let current = compactor.swap_in_if_ready("a", context.clone(), &mut compacted);
+        assert_eq!(current.len(), 6);
+        assert_eq!(current.history[0].content, "Be brief.");
+        assert_eq!(
+            current.history[1].content,
+            format!("{}\nsummary 1", SUMMARY_HEADER)
+        );
+        assert_eq!(current.history[5].content, "turn 11");
+        assert!(!compactor.is_compacting("a"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_resubmit_mid_compaction_requeues() {
+        let (summarizer, calls) = slow_summarizer();
+        let compactor = Compactor::spawn(policy(), summarizer);
+        let mut compacted = compactor.submit("a", conversation(12));
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let advanced = conversation(13);
+        compactor.submit("a", advanced.clone());
+
+        // The first compaction finishes but its source is stale
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert!(!compacted.has_changed().unwrap());
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let current = compactor.swap_in_if_ready("a", advanced, &mut compacted);
+        assert_eq!(current.history.last().unwrap().content, "turn 12");
+        assert!(current.history[1].content.ends_with("summary 2"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_advanced_source_is_not_clobbered() {
+        let (summarizer, calls) = slow_summarizer();
+        let compactor = Compactor::spawn(policy(), summarizer);
+        let mut compacted = compactor.submit("a", conversation(12));
+        tokio::time::sleep(Duration::from_secs(6)).await;
+
+        // A turn landed without being resubmitted
+        let advanced = conversation(13);
+        let current = compactor.swap_in_if_ready("a", advanced.clone(), &mut compacted);
+        assert!(current.history == advanced.history);
+        assert!(compactor.is_compacting("a"));
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let current = compactor.swap_in_if_ready("a", advanced, &mut compacted);
+        assert_eq!(current.len(), 6);
+        assert_eq!(current.history.last().unwrap().content, "turn 12");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bounded_concurrency_and_abort() {
+        let (summarizer, calls) = slow_summarizer();
+        let compactor = Compactor::spawn(policy(), summarizer);
+        let first = compactor.submit("a", conversation(12));
+        let second = compactor.submit("b", conversation(12));
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert!(first.has_changed().unwrap());
+        assert!(!second.has_changed().unwrap());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        compactor.abort("b");
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(second.has_changed().is_err());
+        assert!(!compactor.is_compacting("b"));
+
+        // Short conversations are left alone
+        let short = compactor.submit("c", conversation(3));
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(!short.has_changed().unwrap());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_repairs_split_tool_pairs() {
+        let context = conversation(1)
+            .add_tool_use_message(ToolCall {
+                id: "call_1".to_string(),
+                name: "get_weather".to_string(),
+                arguments: serde_json::json!({}),
+            })
+            .add_tool_result_message("call_1", "4C".to_string(), false)
+            .add_message(mock_message(MessageRole::Model, "It's 4C."));
+        let policy = CompactionPolicy {
+            keep_recent: 2,
+            ..CompactionPolicy::default()
+        };
+        let summarizer = || {
+            let adapter: ProviderAdapter =
+                Arc::new(|_, _| Box::pin(async { Ok(mock_text_response("asked about weather")) }));
+            adapter
+        };
+
+        // The summary swallows the call but keeps its result
+        let compacted = policy.compact(&context, summarizer()).await.unwrap();
+        assert_eq!(compacted.len(), 5);
+        assert!(matches!(
+            &compacted.history[2].content_type,
+            ContentType::ToolUse(call) if call.name == UNKNOWN_TOOL
+        ));
+
+        let untouched = CompactionPolicy {
+            tool_gc: None,
+            ..policy
+        };
+        let compacted = untouched.compact(&context, summarizer()).await.unwrap();
+        assert_eq!(compacted.len(), 4);
+        assert!(matches!(
+            compacted.history[2].content_type,
+            ContentType::ToolResult(_)
+        ));
+    }
+}
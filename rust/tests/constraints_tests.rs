@@ -0,0 +1,183 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use steelwool::constraints::{
+        ConstraintViolation, LangCode, ResponseConstraints, ResponseFormat, detect_language,
+    };
+    use steelwool::providers::mock::{mock_adapter_factory, mock_message, mock_text_response};
+    use steelwool::{ContextBuilder, MessageRole, ProviderAdapter};
+
+    const GERMAN_BULLETS: &str = "- Die Lieferung ist unterwegs und kommt morgen an.\n\
+        - Das Paket ist versichert.\n\
+        - Bei Fragen schreiben Sie uns einfach.";
+    const ENGLISH_PREAMBLE: &str = "Sure! Here is the answer you asked for:\n\
+        - The delivery is on the way and should arrive tomorrow.\n\
+        - The parcel is insured.\n\
+        - If you have any questions, just write to us.\n\
+        - As an AI, I cannot track it myself.";
+
+    fn constraints() -> ResponseConstraints {
+        ResponseConstraints {
+            language: Some(LangCode::De),
+            max_words: Some(30),
+            format: Some(ResponseFormat::Bullets { max_items: Some(3) }),
+            forbidden_phrases: vec!["as an AI".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_rendered_instructions_join_the_system_prompt() {
+        let context = ContextBuilder::new()
+            .add_message(mock_message(
+                MessageRole::System,
+                "You are a courier assistant.",
+            ))
+            .add_message(mock_message(MessageRole::User, "Where is my parcel?"))
+            .with_constraints(&constraints());
+
+        assert_eq!(context.history.len(), 2);
+        assert_eq!(
+            context.history[0].content,
+            "You are a courier assistant.\n\n\
+             Response requirements:\n\
+             - Answer in German.\n\
+             - Use at most 30 words.\n\
+             - Answer only with bullet points (at most 3), with no introduction or closing remarks.\n\
+             - Never use these phrases: \"as an AI\"."
+        );
+
+        let bare = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::User, "Hi"))
+            .with_constraints(&ResponseConstraints {
+                format: Some(ResponseFormat::Table),
+                ..ResponseConstraints::default()
+            });
+        assert!(bare.history[0].role == MessageRole::System);
+        assert_eq!(
+            bare.history[0].content,
+            "Response requirements:\n- Answer with a Markdown table."
+        );
+    }
+
+    #[test]
+    fn test_validator_judgments() {
+        assert_eq!(detect_language(GERMAN_BULLETS), Some(LangCode::De));
+        assert_eq!(detect_language(ENGLISH_PREAMBLE), Some(LangCode::En));
+        assert_eq!(
+            detect_language("Le colis est en route et il arrive demain chez vous."),
+            Some(LangCode::Fr)
+        );
+        assert_eq!(detect_language("Okay."), None);
+
+        assert!(constraints().violations(GERMAN_BULLETS).is_empty());
+
+        let violations = constraints().violations(ENGLISH_PREAMBLE);
+        assert_eq!(
+            violations,
+            vec![
+                ConstraintViolation::WrongLanguage {
+                    expected: LangCode::De,
+                    detected: LangCode::En
+                },
+                ConstraintViolation::TooManyWords {
+                    limit: 30,
+                    found: 39
+                },
+                ConstraintViolation::WrongFormat(
+                    "Answer only with bullet points; remove the 1 line(s) that aren't bullets."
+                        .to_string()
+                ),
+                ConstraintViolation::WrongFormat(
+                    "Use at most 3 bullet points; the reply had 4.".to_string()
+                ),
+                ConstraintViolation::ForbiddenPhrase("as an AI".to_string()),
+            ]
+        );
+        assert_eq!(
+            violations[0].feedback(),
+            "Answer in German; the reply was in English."
+        );
+
+        let table = ResponseConstraints {
+            format: Some(ResponseFormat::Table),
+            ..ResponseConstraints::default()
+        };
+        assert!(
+            table
+                .violations("| a | b |\n|---|---|\n| 1 | 2 |")
+                .is_empty()
+        );
+        assert_eq!(table.violations("a, b, 1, 2").len(), 1);
+
+        let prose = ResponseConstraints {
+            format: Some(ResponseFormat::Paragraph),
+            ..ResponseConstraints::default()
+        };
+        assert!(prose.violations("Just one paragraph.").is_empty());
+        assert_eq!(prose.violations("1. first\n2. second").len(), 1);
+    }
+
+    #[test]
+    fn test_retry_loop_complies_on_second_attempt() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen_clone = seen.clone();
+        let replies = mock_adapter_factory(vec![
+            Ok(mock_text_response(ENGLISH_PREAMBLE)),
+            Ok(mock_text_response(GERMAN_BULLETS)),
+        ]);
+        let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, max_tokens| {
+            seen_clone.lock().unwrap().push(context.history.clone());
+            replies(context, max_tokens)
+        });
+
+        let context = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::User, "Where is my parcel?"))
+            .with_constraints(&constraints());
+        let response =
+            block_on(context.validate_and_retry(adapter, 200, &constraints().validator(), 3))
+                .ok()
+                .unwrap();
+
+        assert_eq!(response.prompt_response.message.content, GERMAN_BULLETS);
+        assert_eq!(response.context_builder.len(), 2);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        let retry = &seen[1];
+        assert_eq!(retry.len(), 4);
+        assert!(retry[2].role == MessageRole::Model);
+        assert!(retry[3].content.starts_with(
+            "Your reply didn't meet the requirements:\n- Answer in German; the reply was in English."
+        ));
+        assert!(
+            retry[3]
+                .content
+                .contains("- Do not use the phrase \"as an AI\".")
+        );
+    }
+
+    #[test]
+    fn test_retry_loop_gives_up() {
+        let adapter = mock_adapter_factory(vec![
+            Ok(mock_text_response("As an AI, no.")),
+            Ok(mock_text_response("As an AI, still no.")),
+        ]);
+        let constraints = ResponseConstraints {
+            forbidden_phrases: vec!["as an ai".to_string()],
+            ..ResponseConstraints::default()
+        };
+
+        let result = block_on(
+            ContextBuilder::new()
+                .add_message(mock_message(MessageRole::User, "Help"))
+                .validate_and_retry(adapter, 100, &constraints.validator(), 2),
+        );
+
+        assert_eq!(
+            result.err().unwrap(),
+            "Reply still invalid after 2 attempts: - Do not use the phrase \"as an ai\"."
+        );
+    }
+}
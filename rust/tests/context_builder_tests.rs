@@ -0,0 +1,1094 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+    use futures::executor::block_on;
+    use futures::stream;
+    use steelwool::providers::mock::{
+        counting_adapter, failing_streaming_adapter_factory, mock_adapter_factory,
+        mock_streaming_adapter_factory, mock_text_response,
+    };
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, PromptResponseDelta,
+        ProviderAdapter, StopReason, StreamProviderAdapter, TokenLogprob, ToolCall, ToolResult,
+    };
+
+    fn user_context(content: &str) -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    #[test]
+    fn test_send_n_returns_every_result() {
+        let adapter = mock_adapter_factory(vec![
+            Ok(mock_text_response("a")),
+            Err("boom".to_string()),
+            Ok(mock_text_response("ccc")),
+        ]);
+
+        let results = block_on(user_context("hi").send_n(adapter, 100, 3));
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().message.content, "a");
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().message.content, "ccc");
+    }
+
+    #[test]
+    fn test_send_n_concurrent_resolves_each_reply() {
+        let (adapter, calls) = counting_adapter(mock_adapter_factory(vec![
+            Ok(mock_text_response("a")),
+            Err("boom".to_string()),
+            Ok(mock_text_response("c")),
+        ]));
+
+        let results = block_on(user_context("hi").send_n_concurrent(adapter, 100, 3));
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(results[1].as_ref().err().unwrap(), "boom");
+        let replies: Vec<String> = results
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|response| {
+                response
+                    .resolve_without()
+                    .history
+                    .last()
+                    .unwrap()
+                    .content
+                    .clone()
+            })
+            .collect();
+        assert_eq!(replies, vec!["a", "c"]);
+    }
+
+    #[test]
+    fn test_send_n_and_pick_chooses_from_successes() {
+        let adapter = mock_adapter_factory(vec![
+            Ok(mock_text_response("short")),
+            Err("boom".to_string()),
+            Ok(mock_text_response("the longest reply")),
+            Ok(mock_text_response("medium one")),
+        ]);
+
+        let unresolved =
+            block_on(
+                user_context("hi").send_n_and_pick(adapter, 100, 4, |responses| {
+                    assert_eq!(responses.len(), 3);
+                    responses
+                        .into_iter()
+                        .max_by_key(|r| r.message.content.len())
+                        .unwrap()
+                }),
+            );
+
+        let context = unresolved.resolve_without();
+        assert_eq!(context.history.len(), 2);
+        assert_eq!(context.history[1].content, "the longest reply");
+    }
+
+    #[test]
+    fn test_send_n_and_pick_applies_prefill_once() {
+        let adapter = mock_adapter_factory(vec![Ok(mock_text_response(" 4}"))]);
+
+        let unresolved = block_on(
+            user_context("2+2?")
+                .with_prefill("{\"answer\":")
+                .send_n_and_pick(adapter, 100, 2, |mut responses| responses.remove(0)),
+        );
+
+        assert_eq!(
+            unresolved.prompt_response.message.content,
+            "{\"answer\": 4}"
+        );
+        assert!(unresolved.context_builder.params.prefill.is_none());
+    }
+
+    #[test]
+    fn test_send_all_and_join_appends_every_reply() {
+        let adapters = vec![
+            mock_adapter_factory(vec![Ok(mock_text_response("Oslo"))]),
+            mock_adapter_factory(vec![Err("offline".to_string())]),
+            mock_adapter_factory(vec![Ok(mock_text_response("Bergen"))]),
+        ];
+
+        let context = block_on(
+            user_context("Capital of Norway?")
+                .with_prefill("Answer: ")
+                .send_all_and_join(adapters, 100),
+        );
+
+        let contents: Vec<&str> = context.history.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec!["Capital of Norway?", "Answer: Oslo", "Answer: Bergen"]
+        );
+        assert!(
+            context.history[1..]
+                .iter()
+                .all(|m| m.role == MessageRole::Model)
+        );
+        assert!(context.params.prefill.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to get PromptResponse: offline")]
+    fn test_send_all_and_join_panics_when_every_adapter_fails() {
+        let adapters = vec![mock_adapter_factory(vec![Err("offline".to_string())])];
+        block_on(user_context("hi").send_all_and_join(adapters, 100));
+    }
+
+    #[test]
+    fn test_promote_adds_response_then_follow_up() {
+        let adapter = mock_adapter_factory(vec![Ok(mock_text_response("A long answer"))]);
+        let unresolved = block_on(user_context("hi").send(adapter, 100));
+
+        let follow_up = user_context("Now make it shorter.").history[0].clone();
+        let context = unresolved.clone().promote(Some(follow_up));
+        let contents: Vec<&str> = context.history.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            vec!["hi", "A long answer", "Now make it shorter."]
+        );
+        assert!(context.history[2].role == MessageRole::User);
+
+        assert_eq!(unresolved.promote(None).history.len(), 2);
+    }
+
+    #[test]
+    fn test_try_parse_tool_calls_from_content() {
+        let parse = |content: &str| {
+            let adapter = mock_adapter_factory(vec![Ok(mock_text_response(content))]);
+            block_on(user_context("Weather in Oslo?").send(adapter, 100))
+                .try_parse_tool_calls_from_content()
+                .prompt_response
+        };
+
+        let response = parse(
+            "```json\n[{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Oslo\"}},\n\
+             {\"id\": \"t2\", \"name\": \"get_time\", \"parameters\": {\"tz\": \"CET\"}}]\n```",
+        );
+        assert!(response.stop_reason == StopReason::ToolCalls);
+        let calls = response.tool_calls.unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_0");
+        assert_eq!(calls[0].arg_str("city"), Ok("Oslo".to_string()));
+        assert_eq!(calls[1].id, "t2");
+        assert_eq!(calls[1].arg_str("tz"), Ok("CET".to_string()));
+        assert!(response.message.assistant_tool_calls.is_some());
+
+        // A single call object works too
+        let response = parse(r#"{"name": "get_weather"}"#);
+        assert_eq!(
+            response.tool_calls.unwrap()[0].arguments,
+            serde_json::json!({})
+        );
+
+        // Prose, other JSON, and objects without a name are left alone
+        for content in ["It's sunny.", "[1, 2]", "[]", r#"[{"city": "Oslo"}]"#] {
+            let response = parse(content);
+            assert!(response.stop_reason == StopReason::Stop, "{}", content);
+            assert!(response.tool_calls.is_none());
+            assert_eq!(response.message.content, content);
+        }
+    }
+
+    #[test]
+    fn test_to_summary_string_does_not_touch_context() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen_clone = seen.clone();
+        let summarizer: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            *seen_clone.lock().unwrap() = context.history.clone();
+            Box::pin(async move { Ok(mock_text_response("They said hi.")) })
+        });
+
+        let context = user_context("hi");
+        let summary = block_on(context.to_summary_string(
+            summarizer,
+            100,
+            "Summarize the above conversation in 3 sentences.",
+        ));
+
+        assert_eq!(summary.unwrap(), "They said hi.");
+        assert_eq!(context.history.len(), 1);
+
+        let sent = seen.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[1].role == MessageRole::User);
+        assert_eq!(
+            sent[1].content,
+            "Summarize the above conversation in 3 sentences."
+        );
+    }
+
+    #[test]
+    fn test_to_summary_string_surfaces_errors() {
+        let summarizer = mock_adapter_factory(vec![Err("offline".to_string())]);
+        let summary = block_on(user_context("hi").to_summary_string(summarizer, 100, "Sum up"));
+
+        assert_eq!(summary.unwrap_err(), "offline");
+    }
+
+    #[test]
+    fn test_to_summary_string_on_finalized_context() {
+        use steelwool::finalize::FinalizeOpts;
+
+        let mut context = user_context("hi");
+        block_on(context.finalize(FinalizeOpts::default())).unwrap();
+
+        let summarizer = mock_adapter_factory(vec![Ok(mock_text_response("They said hi."))]);
+        let summary = block_on(context.to_summary_string(summarizer, 100, "Sum up"));
+        assert_eq!(summary.unwrap(), "They said hi.");
+    }
+
+    #[test]
+    fn test_to_summary_string_does_not_spill_to_store() {
+        use steelwool::store::{ConversationStore, InMemoryConversationStore};
+
+        let store = Arc::new(InMemoryConversationStore::new());
+        let context = user_context("hi").with_backing_store(store.clone(), 1);
+        assert!(store.list().unwrap().is_empty());
+
+        let summarizer = mock_adapter_factory(vec![Ok(mock_text_response("They said hi."))]);
+        block_on(context.to_summary_string(summarizer, 100, "Sum up")).unwrap();
+
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_summarize_to_system_message_replaces_history() {
+        let summarizer = mock_adapter_factory(vec![Ok(mock_text_response("They said hi twice."))]);
+        let context = user_context("hi")
+            .add_message(Message {
+                role: MessageRole::Model,
+                content: "hello".to_string(),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            })
+            .with_metadata("conversation_id", "conv-1");
+
+        let summarized = block_on(context.summarize_to_system_message(summarizer, 100));
+
+        assert_eq!(summarized.history.len(), 1);
+        assert!(summarized.history[0].role == MessageRole::System);
+        assert_eq!(summarized.history[0].content, "They said hi twice.");
+        assert_eq!(summarized.metadata["conversation_id"], "conv-1");
+    }
+
+    #[test]
+    fn test_or_else_falls_back_on_empty_or_null_responses() {
+        let fallback = || user_context("fallback");
+
+        let empty = mock_adapter_factory(vec![Ok(mock_text_response(""))]);
+        let context = block_on(user_context("hi").send(empty, 100)).or_else(fallback);
+        assert_eq!(context.history[0].content, "fallback");
+
+        let null = mock_adapter_factory(vec![Ok(PromptResponse {
+            stop_reason: StopReason::Null,
+            ..mock_text_response("partial")
+        })]);
+        let context = block_on(user_context("hi").send(null, 100)).or_else(fallback);
+        assert_eq!(context.history[0].content, "fallback");
+
+        let ok = mock_adapter_factory(vec![Ok(mock_text_response("hello"))]);
+        let context = block_on(user_context("hi").send(ok, 100)).or_else(fallback);
+        assert_eq!(context.history.len(), 2);
+        assert_eq!(context.history[1].content, "hello");
+    }
+
+    #[test]
+    fn test_conditional_send_skips_provider_when_false() {
+        let (adapter, calls) =
+            counting_adapter(mock_adapter_factory(vec![Ok(mock_text_response("real"))]));
+
+        let skipped = block_on(user_context("hi").conditional_send(adapter.clone(), 100, false));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(skipped.prompt_response.stop_reason == StopReason::Stop);
+        assert!(skipped.prompt_response.message.content.is_empty());
+        assert_eq!(skipped.context_builder.history.len(), 1);
+
+        let sent = block_on(user_context("hi").conditional_send(adapter, 100, true));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(sent.prompt_response.message.content, "real");
+    }
+
+    #[test]
+    fn test_message_at_is_bounds_checked() {
+        let context = user_context("hi");
+
+        assert_eq!(context.len(), 1);
+        assert_eq!(context.message_at(0).unwrap().content, "hi");
+        assert!(context.message_at(1).is_none());
+        assert!(ContextBuilder::new().is_empty());
+    }
+
+    fn numbered(n: usize) -> ContextBuilder {
+        (0..n).fold(ContextBuilder::new(), |ctx, i| {
+            ctx.add_message(Message {
+                role: MessageRole::User,
+                content: i.to_string(),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            })
+        })
+    }
+
+    fn contents(context: &ContextBuilder) -> Vec<&str> {
+        context.history.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_rotate_messages_wraps() {
+        assert_eq!(
+            contents(&numbered(4).rotate_messages(1)),
+            vec!["1", "2", "3", "0"]
+        );
+        assert_eq!(
+            contents(&numbered(4).rotate_messages(6)),
+            vec!["2", "3", "0", "1"]
+        );
+        assert!(ContextBuilder::new().rotate_messages(3).is_empty());
+    }
+
+    #[test]
+    fn test_reverse_messages() {
+        assert_eq!(contents(&numbered(3).reverse()), vec!["2", "1", "0"]);
+        assert_eq!(
+            contents(&numbered(3).reverse().reverse()),
+            vec!["0", "1", "2"]
+        );
+        assert!(ContextBuilder::new().reverse().is_empty());
+    }
+
+    #[test]
+    fn test_map_content() {
+        assert_eq!(
+            contents(&numbered(3).map_content(|c| format!(" {}\r\n", c))),
+            vec![" 0\r\n", " 1\r\n", " 2\r\n"]
+        );
+        assert_eq!(
+            contents(
+                &numbered(2)
+                    .map_content(|c| format!("  {} ", c))
+                    .map_content(|c| c.trim().to_string())
+            ),
+            vec!["0", "1"]
+        );
+        assert!(
+            ContextBuilder::new()
+                .map_content(str::to_uppercase)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_messages_in_range_clamps() {
+        assert_eq!(
+            contents(&numbered(5).messages_in_range(1, 3)),
+            vec!["1", "2"]
+        );
+        assert_eq!(
+            contents(&numbered(5).messages_in_range(3, 99)),
+            vec!["3", "4"]
+        );
+        assert!(numbered(5).messages_in_range(4, 2).is_empty());
+        assert!(numbered(5).messages_in_range(7, 9).is_empty());
+    }
+
+    #[test]
+    fn test_zip_with_pads_shorter_side() {
+        let longer = numbered(3);
+        let shorter = numbered(1);
+
+        let pairs: Vec<(Option<&str>, Option<&str>)> = longer
+            .zip_with(&shorter)
+            .map(|(a, b)| (a.map(|m| m.content.as_str()), b.map(|m| m.content.as_str())))
+            .collect();
+
+        assert_eq!(
+            pairs,
+            vec![(Some("0"), Some("0")), (Some("1"), None), (Some("2"), None)]
+        );
+        assert_eq!(shorter.zip_with(&longer).count(), 3);
+        assert_eq!(
+            ContextBuilder::new()
+                .zip_with(&ContextBuilder::new())
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_last_n_by_role_keeps_order() {
+        let context = ["q1", "a1", "q2", "a2", "q3", "a3"]
+            .iter()
+            .enumerate()
+            .fold(ContextBuilder::new(), |ctx, (i, content)| {
+                ctx.add_message(Message {
+                    role: if i % 2 == 0 {
+                        MessageRole::User
+                    } else {
+                        MessageRole::Model
+                    },
+                    content: content.to_string(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                })
+            });
+
+        let replies: Vec<&str> = context
+            .last_n_by_role(MessageRole::Model, 2)
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect();
+        assert_eq!(replies, vec!["a2", "a3"]);
+        assert_eq!(context.last_n_by_role(MessageRole::User, 10).len(), 3);
+        assert!(context.last_n_by_role(MessageRole::Tool, 2).is_empty());
+    }
+
+    #[test]
+    fn test_messages_between_roles() {
+        let script = [
+            (MessageRole::System, "sys"),
+            (MessageRole::User, "u1"),
+            (MessageRole::Model, "m1"),
+            (MessageRole::Tool, "t1"),
+            (MessageRole::Model, "m2"),
+            (MessageRole::User, "u2"),
+            (MessageRole::User, "u3"),
+            (MessageRole::Tool, "t2"),
+            (MessageRole::User, "u4"),
+            (MessageRole::Model, "m3"),
+        ];
+        let context = script
+            .into_iter()
+            .fold(ContextBuilder::new(), |ctx, (role, content)| {
+                ctx.add_message(Message {
+                    role,
+                    content: content.to_string(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                })
+            });
+
+        let segments: Vec<&str> = context
+            .messages_between_roles(MessageRole::User, MessageRole::Tool)
+            .iter()
+            .map(|msg| msg.content.as_str())
+            .collect();
+        assert_eq!(segments, vec!["u1", "m1", "t1", "u3", "t2"]);
+
+        assert!(
+            context
+                .messages_between_roles(MessageRole::Tool, MessageRole::System)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_inject_before_last_user_message() {
+        let message = |role: MessageRole, content: &str| Message {
+            role,
+            content: content.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        };
+        let contents = |context: &ContextBuilder| -> Vec<String> {
+            context
+                .history
+                .iter()
+                .map(|msg| msg.content.clone())
+                .collect()
+        };
+
+        let context = ContextBuilder::new()
+            .add_message(message(MessageRole::User, "u1"))
+            .add_message(message(MessageRole::Model, "m1"))
+            .add_message(message(MessageRole::User, "u2"))
+            .inject_before_last_user_message(message(MessageRole::System, "docs"));
+        assert_eq!(contents(&context), vec!["u1", "m1", "docs", "u2"]);
+        assert!(context.history[2].pinned);
+
+        // Trailing non-user messages stay after the injected one
+        let context = context
+            .add_message(message(MessageRole::Model, "m2"))
+            .inject_before_last_user_message(message(MessageRole::Tool, "pre"));
+        assert_eq!(
+            contents(&context),
+            vec!["u1", "m1", "docs", "pre", "u2", "m2"]
+        );
+
+        let context = ContextBuilder::new()
+            .add_message(message(MessageRole::System, "sys"))
+            .inject_before_last_user_message(message(MessageRole::Model, "hi"));
+        assert_eq!(contents(&context), vec!["sys", "hi"]);
+    }
+
+    #[test]
+    fn test_clear_keeps_configuration() {
+        let context = numbered(3).with_prefill("{").save_branch("before").clear();
+
+        assert!(context.is_empty());
+        assert_eq!(context.params.prefill.as_deref(), Some("{"));
+        assert_eq!(context.branch_names(), vec!["before"]);
+        assert_eq!(
+            contents(&context.add_message(numbered(1).history[0].clone())),
+            vec!["0"]
+        );
+    }
+
+    #[test]
+    fn test_contains_role() {
+        let context = user_context("hi");
+
+        assert!(context.contains_role(MessageRole::User));
+        assert!(!context.contains_role(MessageRole::Model));
+        assert!(!ContextBuilder::new().contains_role(MessageRole::User));
+    }
+
+    #[test]
+    fn test_has_consecutive_same_role() {
+        let alternating = user_context("hi").add_message(Message {
+            role: MessageRole::Model,
+            content: "hello".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        });
+        assert!(!alternating.has_consecutive_same_role());
+        assert!(
+            alternating
+                .clone()
+                .add_message(Message {
+                    role: MessageRole::Model,
+                    content: "again".to_string(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                })
+                .has_consecutive_same_role()
+        );
+        assert!(numbered(3).has_consecutive_same_role());
+        assert!(!user_context("hi").has_consecutive_same_role());
+        assert!(!ContextBuilder::new().has_consecutive_same_role());
+    }
+
+    #[test]
+    fn test_merge_consecutive_same_role() {
+        let text = |role: MessageRole, content: &str| Message {
+            role,
+            content: content.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        };
+        let context = ContextBuilder::new()
+            .add_message(text(MessageRole::System, "s1"))
+            .add_message(text(MessageRole::System, "s2"))
+            .add_message(text(MessageRole::User, "u1"))
+            .add_message(text(MessageRole::User, "u2"))
+            .add_message(text(MessageRole::Model, "m1"))
+            .add_message(text(MessageRole::User, "u3"))
+            .pin_message(3);
+
+        let merged = context.merge_consecutive_same_role();
+        assert_eq!(contents(&merged), vec!["s1", "s2", "u1\n\nu2", "m1", "u3"]);
+        assert!(merged.history[2].pinned);
+        assert!(!merged.messages_in_range(1, 5).has_consecutive_same_role());
+
+        let tools = ContextBuilder::new()
+            .add_tool_result_message("call_1", "4C".to_string(), false)
+            .add_tool_result_message("call_2", "5C".to_string(), false)
+            .merge_consecutive_same_role();
+        assert_eq!(tools.len(), 2);
+    }
+
+    #[test]
+    fn test_from_plain_text() {
+        let plain = ContextBuilder::from_plain_text(
+            "First paragraph\nstill first.\n\n\n  \nSecond.\n",
+            MessageRole::User,
+        );
+        assert_eq!(
+            contents(&plain),
+            vec!["First paragraph\nstill first.", "Second."]
+        );
+        assert!(plain.history.iter().all(|m| m.role == MessageRole::User));
+
+        let transcript = ContextBuilder::from_plain_text(
+            "System: Be brief.\nUser: Hi\nthere\nAssistant: Hello!\n\nA note.",
+            MessageRole::Tool,
+        );
+        assert_eq!(
+            contents(&transcript),
+            vec!["Be brief.", "Hi\nthere", "Hello!", "A note."]
+        );
+        let roles: Vec<MessageRole> = transcript.history.iter().map(|m| m.role.clone()).collect();
+        assert!(
+            roles
+                == vec![
+                    MessageRole::System,
+                    MessageRole::User,
+                    MessageRole::Model,
+                    MessageRole::Tool
+                ]
+        );
+        assert!(ContextBuilder::from_plain_text("\n\n", MessageRole::User).is_empty());
+    }
+
+    #[test]
+    fn test_swap_messages() {
+        assert_eq!(
+            contents(&numbered(4).swap_messages(0, 3)),
+            vec!["3", "1", "2", "0"]
+        );
+        assert_eq!(
+            contents(&numbered(3).swap_messages(1, 1)),
+            vec!["0", "1", "2"]
+        );
+        assert_eq!(
+            contents(&numbered(3).swap_messages(0, 3)),
+            vec!["0", "1", "2"]
+        );
+    }
+
+    #[test]
+    fn test_extend_from_context_borrows() {
+        let prefix = numbered(2);
+        let a = user_context("a").extend_from_context(&prefix);
+        let b = user_context("b").extend_from_context(&prefix);
+
+        assert_eq!(contents(&a), vec!["a", "0", "1"]);
+        assert_eq!(contents(&b), vec!["b", "0", "1"]);
+        assert_eq!(contents(&prefix), vec!["0", "1"]);
+    }
+
+    #[test]
+    fn test_add_tool_use_message() {
+        let call = ToolCall {
+            id: "toolu_01".to_string(),
+            name: "get_weather".to_string(),
+            arguments: serde_json::Value::from(r#"{"location": "Oslo"}"#),
+        };
+        let context = user_context("Weather in Oslo?").add_tool_use_message(call.clone());
+
+        let msg = context.history.last().unwrap();
+        assert!(msg.role == MessageRole::Model);
+        assert!(msg.content_type == ContentType::ToolUse(call));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&msg.content).unwrap(),
+            serde_json::json!({
+                "type": "tool_use",
+                "id": "toolu_01",
+                "name": "get_weather",
+                "input": { "location": "Oslo" }
+            })
+        );
+
+        let json = serde_json::to_string(&context).unwrap();
+        let restored: ContextBuilder = serde_json::from_str(&json).unwrap();
+        assert!(restored.history == context.history);
+    }
+
+    #[test]
+    fn test_add_tool_result_message() {
+        let context = user_context("Weather in Oslo?").add_tool_result_message(
+            "toolu_01",
+            "Service unavailable".to_string(),
+            true,
+        );
+
+        let msg = context.history.last().unwrap();
+        assert!(msg.role == MessageRole::Tool);
+        assert_eq!(msg.content, "Service unavailable");
+        assert!(
+            msg.content_type
+                == ContentType::ToolResult(ToolResult {
+                    tool_call_id: "toolu_01".to_string(),
+                    result: "Service unavailable".to_string(),
+                    error: true,
+                    simulated: false,
+                })
+        );
+    }
+
+    #[test]
+    fn test_tool_call_typed_argument_accessors() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            // Sent as a JSON string, the way OpenAI does
+            arguments: serde_json::json!(
+                "{\"location\":\"Oslo\",\"days\":3,\"metric\":true,\"ratio\":1.5}"
+            ),
+        };
+
+        assert_eq!(call.arg_str("location"), Ok("Oslo".to_string()));
+        assert_eq!(call.arg_i64("days"), Ok(3));
+        assert_eq!(call.arg_bool("metric"), Ok(true));
+        assert_eq!(
+            call.arg_str("country"),
+            Err("Missing argument country for get_weather".to_string())
+        );
+        assert_eq!(
+            call.arg_i64("ratio"),
+            Err("Argument ratio for get_weather should be an integer, got 1.5".to_string())
+        );
+        assert_eq!(
+            call.arg_bool("location"),
+            Err("Argument location for get_weather should be a boolean, got \"Oslo\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tool_descriptor_from_json_schema() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"]
+        });
+        let tool = steelwool::ToolDescriptor::from_json_schema(
+            "get_weather".to_string(),
+            "Current weather for a city".to_string(),
+            schema.clone(),
+        );
+        assert_eq!(tool.name, "get_weather");
+        assert_eq!(tool.description, "Current weather for a city");
+        assert_eq!(tool.schema, schema);
+        assert!(!tool.required);
+    }
+
+    fn delta(content: &str, stop_reason: Option<StopReason>) -> PromptResponseDelta {
+        PromptResponseDelta {
+            content: content.to_string(),
+            stop_reason,
+            tool_calls: None,
+            cumulative_tokens: 0,
+            token_delta: 0,
+            logprobs: None,
+        }
+    }
+
+    #[test]
+    fn test_token_delta_defaults_when_absent() {
+        let older: PromptResponseDelta = serde_json::from_str(
+            r#"{"content":"hi","stop_reason":null,"tool_calls":null,"cumulative_tokens":12}"#,
+        )
+        .unwrap();
+
+        assert_eq!((older.cumulative_tokens, older.token_delta), (12, 0));
+    }
+
+    /// Streams "reply N" in two deltas, where N is the number of messages it
+    /// was sent, recording each context
+    fn echo_streaming_adapter(seen: Arc<Mutex<Vec<ContextBuilder>>>) -> StreamProviderAdapter {
+        Arc::new(move |context: ContextBuilder, _| {
+            let n = context.history.len();
+            seen.lock().unwrap().push(context);
+            Box::pin(stream::iter(vec![
+                Ok(delta("reply ", None)),
+                Ok(delta(&n.to_string(), Some(StopReason::Stop))),
+            ]))
+        })
+    }
+
+    #[test]
+    fn test_send_reactive_streaming_streams_every_reply() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let replies = Arc::new(Mutex::new(vec![]));
+        let replies_clone = replies.clone();
+
+        let deltas = user_context("count").send_reactive_streaming(
+            echo_streaming_adapter(seen.clone()),
+            move |response: PromptResponse| {
+                replies_clone.lock().unwrap().push(response.message.content);
+                Some(Message {
+                    role: MessageRole::User,
+                    content: "again".to_string(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                })
+            },
+            100,
+            3,
+        );
+        let text: Vec<String> = block_on(deltas.map(|d| d.unwrap().content).collect());
+
+        assert_eq!(text.concat(), "reply 1reply 3reply 5");
+        assert_eq!(
+            *replies.lock().unwrap(),
+            vec!["reply 1", "reply 3", "reply 5"]
+        );
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 3);
+        let last = &seen[2].history;
+        assert_eq!(last[1].content, "reply 1");
+        assert!(last[1].role == MessageRole::Model);
+        assert_eq!(last[4].content, "again");
+    }
+
+    #[test]
+    fn test_send_streaming_with_progress_reports_running_count() {
+        let progress = Arc::new(Mutex::new(vec![]));
+        let progress_clone = progress.clone();
+        let adapter: StreamProviderAdapter = Arc::new(|_, _| {
+            Box::pin(stream::iter(vec![
+                Ok(PromptResponseDelta {
+                    token_delta: 2,
+                    ..delta("Hello ", None)
+                }),
+                Err("hiccup".to_string()),
+                Ok(PromptResponseDelta {
+                    token_delta: 3,
+                    ..delta("there", None)
+                }),
+                // The provider's final count wins over the running sum
+                Ok(PromptResponseDelta {
+                    cumulative_tokens: 7,
+                    ..delta("", Some(StopReason::Stop))
+                }),
+            ]))
+        });
+
+        let deltas = user_context("hi").send_streaming_with_progress(
+            adapter,
+            100,
+            move |tokens, stop_reason| {
+                progress_clone.lock().unwrap().push((tokens, stop_reason));
+            },
+        );
+        let results: Vec<_> = block_on(deltas.collect());
+
+        assert_eq!(results.len(), 4);
+        let progress = progress.lock().unwrap();
+        let counts: Vec<usize> = progress.iter().map(|(tokens, _)| *tokens).collect();
+        assert_eq!(counts, vec![2, 5, 7]);
+        assert!(progress[1].1.is_none());
+        assert!(progress[2].1 == Some(StopReason::Stop));
+    }
+
+    #[test]
+    fn test_send_streaming_with_callback_keeps_partial_on_error() {
+        let tool_call = ToolCall {
+            id: "call_1".to_string(),
+            name: "lookup".to_string(),
+            arguments: serde_json::json!({ "q": "rust" }),
+        };
+        let adapter = failing_streaming_adapter_factory(
+            vec![
+                delta("one ", None),
+                delta("two ", None),
+                PromptResponseDelta {
+                    tool_calls: Some(vec![tool_call]),
+                    ..delta("three ", None)
+                },
+                delta("four", None),
+                delta(" never sent", Some(StopReason::Stop)),
+            ],
+            4,
+        );
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen_clone = seen.clone();
+
+        let failure = block_on(user_context("count").send_streaming_with_callback(
+            adapter,
+            100,
+            move |result| seen_clone.lock().unwrap().push(result.is_ok()),
+        ))
+        .err()
+        .unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), vec![true, true, true, true, false]);
+        assert_eq!(failure.error, "Injected stream failure after 4 deltas");
+        assert_eq!(failure.deltas, 4);
+        let partial = &failure.partial.prompt_response;
+        assert_eq!(partial.message.content, "one two three four");
+        assert!(partial.stop_reason == StopReason::Interrupted);
+        assert_eq!(partial.tool_calls.as_ref().unwrap()[0].name, "lookup");
+
+        // The partial turn can still be kept
+        let context = failure.partial.resolve_without();
+        assert_eq!(context.len(), 2);
+        assert_eq!(context.history[1].content, "one two three four");
+    }
+
+    #[test]
+    fn test_send_streaming_with_callback_fails_on_first_delta() {
+        let adapter = failing_streaming_adapter_factory(vec![delta("never sent", None)], 0);
+
+        let failure = block_on(
+            user_context("hi")
+                .with_prefill("{")
+                .send_streaming_with_callback(adapter, 100, |_| {}),
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(failure.error, "Injected stream failure after 0 deltas");
+        assert_eq!(failure.deltas, 0);
+        let partial = &failure.partial.prompt_response;
+        assert_eq!(partial.message.content, "{");
+        assert!(partial.tool_calls.is_none());
+        assert!(partial.stop_reason == StopReason::Interrupted);
+        assert_eq!(failure.partial.context_builder.len(), 1);
+        assert_eq!(
+            String::from(failure),
+            "Injected stream failure after 0 deltas"
+        );
+    }
+
+    #[test]
+    fn test_streamed_logprobs_are_collected() {
+        let token = |token: &str, logprob: f32| TokenLogprob {
+            token: token.to_string(),
+            logprob,
+            top_alternatives: vec![(token.to_string(), logprob), (" Rome".to_string(), -4.0)],
+        };
+        let deltas = vec![
+            PromptResponseDelta {
+                logprobs: Some(vec![token("The", -0.1), token(" capital", -0.2)]),
+                ..delta("The capital", None)
+            },
+            // Usage-only chunks carry none
+            delta("", None),
+            PromptResponseDelta {
+                logprobs: Some(vec![token(" Paris", -2.5)]),
+                ..delta(" Paris", Some(StopReason::Stop))
+            },
+        ];
+
+        let response = block_on(user_context("hi").send_streaming_with_callback(
+            mock_streaming_adapter_factory(deltas.iter().cloned().map(Ok).collect()),
+            100,
+            |_| {},
+        ))
+        .ok()
+        .unwrap()
+        .prompt_response;
+        let logprobs = response.logprobs.clone().unwrap();
+        assert_eq!(logprobs.len(), 3);
+        assert_eq!(logprobs[2].top_alternatives[1], (" Rome".to_string(), -4.0));
+        assert_eq!(response.low_confidence_spans(-1.0), vec![11..17]);
+
+        let replies = Arc::new(Mutex::new(vec![]));
+        let replies_clone = replies.clone();
+        let streamed = user_context("hi").send_reactive_streaming(
+            mock_streaming_adapter_factory(deltas.into_iter().map(Ok).collect()),
+            move |response: PromptResponse| {
+                replies_clone.lock().unwrap().push(response.logprobs);
+                None
+            },
+            100,
+            1,
+        );
+        block_on(streamed.collect::<Vec<_>>());
+        assert_eq!(replies.lock().unwrap()[0].as_ref().map(Vec::len), Some(3));
+
+        let plain = block_on(user_context("hi").send_streaming_with_callback(
+            mock_streaming_adapter_factory(vec![Ok(delta("Paris", Some(StopReason::Stop)))]),
+            100,
+            |_| {},
+        ))
+        .ok()
+        .unwrap();
+        assert!(plain.prompt_response.logprobs.is_none());
+    }
+
+    #[test]
+    fn test_send_reactive_streaming_stops_when_handler_declines() {
+        let seen = Arc::new(Mutex::new(vec![]));
+
+        let deltas = user_context("once")
+            .with_prefill("> ")
+            .send_reactive_streaming(echo_streaming_adapter(seen.clone()), |_| None, 100, 5);
+        let text: Vec<String> = block_on(deltas.map(|d| d.unwrap().content).collect());
+
+        assert_eq!(text.concat(), "> reply 1");
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_send_reactive_streaming_ends_on_error() {
+        let calls = Arc::new(Mutex::new(0));
+        let calls_clone = calls.clone();
+        let adapter: StreamProviderAdapter = Arc::new(move |_, _| {
+            *calls_clone.lock().unwrap() += 1;
+            Box::pin(stream::iter(vec![
+                Ok(delta("partial", None)),
+                Err("connection reset".to_string()),
+            ]))
+        });
+
+        let results: Vec<_> = block_on(
+            user_context("hi")
+                .send_reactive_streaming(adapter, |_| panic!("reply never completed"), 100, 3)
+                .collect(),
+        );
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].as_ref().err().unwrap(), "connection reset");
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    #[tokio::test]
+    async fn test_send_streaming_to_channel_forwards_every_delta() {
+        let adapter: StreamProviderAdapter = Arc::new(|_, _| {
+            Box::pin(stream::iter(vec![
+                Ok(delta("Hel", None)),
+                Ok(delta("lo", Some(StopReason::Stop))),
+                Err("connection reset".to_string()),
+            ]))
+        });
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+
+        let task = tokio::spawn(
+            user_context("hi")
+                .with_prefill("> ")
+                .send_streaming_to_channel(adapter, 100, sender),
+        );
+        let mut received = vec![];
+        while let Some(delta) = receiver.recv().await {
+            received.push(delta.map(|d| d.content));
+        }
+        task.await.unwrap();
+
+        assert_eq!(
+            received,
+            vec![
+                Ok("> ".to_string()),
+                Ok("Hel".to_string()),
+                Ok("lo".to_string()),
+                Err("connection reset".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    #[tokio::test]
+    async fn test_send_streaming_to_channel_stops_when_receiver_drops() {
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        drop(receiver);
+
+        // Would never finish if it kept polling the endless stream
+        let adapter: StreamProviderAdapter =
+            Arc::new(|_, _| Box::pin(stream::repeat_with(|| Ok(delta("tok", None)))));
+        user_context("hi")
+            .send_streaming_to_channel(adapter, 100, sender)
+            .await;
+    }
+}
@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod tests {
+    use steelwool::dedup::{BLOB_REF_PREFIX, BlobStrategy};
+    use steelwool::providers::mock::mock_message;
+    use steelwool::tokens::HeuristicTokenCounter;
+    use steelwool::{ContentType, ContextBuilder, MessageRole, ToolCall};
+
+    fn schema() -> String {
+        "Schema: orders(id INTEGER PRIMARY KEY, customer TEXT, total REAL). ".repeat(20)
+    }
+
+    fn pipeline() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(
+                MessageRole::User,
+                &format!("{}\n\nCount the orders.", schema()),
+            ))
+            .add_message(mock_message(
+                MessageRole::Model,
+                "SELECT COUNT(*) FROM orders;",
+            ))
+            .add_message(mock_message(
+                MessageRole::User,
+                &format!("{}\n\nSum the totals.", schema()),
+            ))
+    }
+
+    fn contents(context: &ContextBuilder) -> Vec<&str> {
+        context
+            .history
+            .iter()
+            .map(|msg| msg.content.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_blobs_below_min_len_are_left_alone() {
+        let context = pipeline().deduplicate_blobs(schema().len() + 1, BlobStrategy::KeepFirst);
+
+        assert_eq!(contents(&context), contents(&pipeline()));
+        assert!(context.metadata.is_empty());
+    }
+
+    #[test]
+    fn test_keep_first_references_the_earliest_copy() {
+        let context = pipeline().deduplicate_blobs(256, BlobStrategy::KeepFirst);
+
+        assert_eq!(
+            context.history[0].content,
+            format!("[REF-1]\n{}\n\nCount the orders.", schema())
+        );
+        assert_eq!(
+            context.history[2].content,
+            "(see the text provided earlier as [REF-1])\n\nSum the totals."
+        );
+        assert_eq!(
+            context.metadata.get(&format!("{}REF-1", BLOB_REF_PREFIX)),
+            Some(&schema())
+        );
+    }
+
+    #[test]
+    fn test_keep_last_references_the_latest_copy() {
+        let context = pipeline().deduplicate_blobs(256, BlobStrategy::KeepLast);
+
+        assert_eq!(
+            context.history[0].content,
+            "(see the text provided later as [REF-1])\n\nCount the orders."
+        );
+        assert_eq!(
+            context.history[2].content,
+            format!("[REF-1]\n{}\n\nSum the totals.", schema())
+        );
+    }
+
+    #[test]
+    fn test_report_counts_replacements_and_tokens_saved() {
+        let context = pipeline().add_message(mock_message(MessageRole::User, &schema()));
+        let (deduped, report) = context.clone().deduplicate_blobs_with_report(
+            256,
+            BlobStrategy::KeepFirst,
+            &HeuristicTokenCounter,
+        );
+
+        assert_eq!(report.references, vec!["REF-1".to_string()]);
+        assert_eq!(report.replaced, 2);
+
+        let tokens = |context: &ContextBuilder| -> usize {
+            context
+                .history
+                .iter()
+                .map(|msg| msg.content.len().div_ceil(4))
+                .sum()
+        };
+        assert!(report.tokens_saved > 0);
+        assert_eq!(report.tokens_saved, tokens(&context) - tokens(&deduped));
+    }
+
+    #[test]
+    fn test_tool_call_arguments_are_never_rewritten() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "run_query".to_string(),
+            arguments: serde_json::json!({ "schema": schema() }),
+        };
+        let context = pipeline().add_tool_use_message(call.clone());
+        let tool_use = context.history[3].content.clone();
+
+        let context = context.deduplicate_blobs(256, BlobStrategy::KeepFirst);
+
+        assert_eq!(context.history[3].content, tool_use);
+        assert!(context.history[3].content_type == ContentType::ToolUse(call));
+    }
+
+    #[test]
+    fn test_tool_results_are_deduplicated() {
+        let context = pipeline().add_tool_result_message("call_1", schema(), false);
+        let context = context.deduplicate_blobs(256, BlobStrategy::KeepFirst);
+
+        let marker = "(see the text provided earlier as [REF-1])";
+        assert_eq!(context.history[3].content, marker);
+        match &context.history[3].content_type {
+            ContentType::ToolResult(result) => assert_eq!(result.result, marker),
+            _ => panic!("expected a tool result"),
+        }
+    }
+
+    #[test]
+    fn test_expand_restores_the_original_history() {
+        for strategy in [BlobStrategy::KeepFirst, BlobStrategy::KeepLast] {
+            let expanded = pipeline()
+                .deduplicate_blobs(256, strategy)
+                .expand_blob_refs();
+
+            assert_eq!(contents(&expanded), contents(&pipeline()));
+            assert!(
+                expanded
+                    .metadata
+                    .keys()
+                    .all(|key| !key.starts_with(BLOB_REF_PREFIX))
+            );
+        }
+    }
+
+    #[test]
+    fn test_later_passes_number_new_references() {
+        let context = pipeline()
+            .deduplicate_blobs(256, BlobStrategy::KeepFirst)
+            .add_message(mock_message(
+                MessageRole::User,
+                &"Another long note. ".repeat(20),
+            ))
+            .add_message(mock_message(
+                MessageRole::User,
+                &"Another long note. ".repeat(20),
+            ));
+
+        let (context, report) = context.deduplicate_blobs_with_report(
+            256,
+            BlobStrategy::KeepFirst,
+            &HeuristicTokenCounter,
+        );
+
+        assert_eq!(report.references, vec!["REF-2".to_string()]);
+        assert!(context.history[3].content.starts_with("[REF-2]\n"));
+    }
+}
@@ -0,0 +1,161 @@
+#[cfg(test)]
+mod tests {
+    use steelwool::delta::{ContextDelta, DeltaConflict, MessageId};
+    use steelwool::providers::mock::mock_message;
+    use steelwool::{ContextBuilder, MessageRole};
+
+    fn context(contents: &[&str]) -> ContextBuilder {
+        contents
+            .iter()
+            .fold(ContextBuilder::new(), |context, content| {
+                context.add_message(mock_message(MessageRole::User, content))
+            })
+    }
+
+    fn contents(context: &ContextBuilder) -> Vec<&str> {
+        context.history.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    /// Round-trip through JSON, as a delta would travel
+    fn over_the_wire(delta: ContextDelta) -> ContextDelta {
+        serde_json::from_str(&serde_json::to_string(&delta).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_ids_chain_over_history() {
+        let ab = context(&["a", "b"]);
+        let ids = ab.message_ids();
+
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[0], MessageId::ROOT);
+        assert_eq!(ab.head(), ids[2]);
+        assert_eq!(context(&["a"]).head(), ids[1]);
+        assert_ne!(context(&["b", "a"]).head(), ab.head());
+        assert_eq!(ContextBuilder::new().head(), MessageId::ROOT);
+    }
+
+    #[test]
+    fn test_pinning_keeps_the_head() {
+        let ab = context(&["a", "b"]);
+        let pinned = ab.clone().pin_message(0);
+
+        assert!(pinned.history[0].pinned);
+        assert_eq!(pinned.head(), ab.head());
+        assert_eq!(pinned.message_ids(), ab.message_ids());
+    }
+
+    #[test]
+    fn test_clean_apply() {
+        let server = context(&["a", "b", "c"]);
+        let mut client = context(&["a"]);
+
+        let delta = over_the_wire(server.diff_since(client.head()));
+        assert!(delta.appended.as_ref().is_some_and(|m| m.len() == 2));
+        assert!(client.apply_delta(delta.clone()).is_ok());
+        assert_eq!(contents(&client), ["a", "b", "c"]);
+        assert_eq!(client.head(), server.head());
+
+        // Applying again changes nothing
+        assert!(client.apply_delta(delta).is_ok());
+        assert_eq!(client.head(), server.head());
+    }
+
+    #[test]
+    fn test_delta_is_compact() {
+        let server = context(&["a", "b", "c"]);
+        let base = server.message_ids()[2];
+        let wire = serde_json::to_string(&server.diff_since(base)).unwrap();
+
+        assert!(!wire.contains("\"a\""));
+        assert!(wire.contains(&format!("\"{:016x}\"", base.0)));
+
+        let behind = serde_json::to_string(&context(&["x"]).diff_since(base)).unwrap();
+        assert!(!behind.contains("appended"));
+    }
+
+    #[test]
+    fn test_both_sides_appending_conflicts() {
+        let base = context(&["a"]);
+        let server = base
+            .clone()
+            .add_message(mock_message(MessageRole::Model, "b"));
+        let mut client = base
+            .clone()
+            .add_message(mock_message(MessageRole::User, "c"));
+
+        let conflict = client.apply_delta(server.diff_since(base.head()));
+        assert!(
+            conflict
+                == Err(DeltaConflict::Diverged {
+                    ours: vec![mock_message(MessageRole::User, "c")],
+                    theirs: vec![mock_message(MessageRole::Model, "b")],
+                })
+        );
+        assert_eq!(contents(&client), ["a", "c"]);
+    }
+
+    #[test]
+    fn test_tampered_deltas_are_rejected() {
+        let server = context(&["a", "b", "c"]);
+        let mut client = context(&["a"]);
+        let delta = server.diff_since(client.head());
+
+        let bogus = MessageId(42);
+        let tampered_base = ContextDelta {
+            base: bogus,
+            ..delta.clone()
+        };
+        assert!(client.apply_delta(tampered_base) == Err(DeltaConflict::UnknownBase(bogus)));
+
+        let mut tampered_messages = delta.clone();
+        tampered_messages.appended.as_mut().unwrap()[0].content = "B".to_string();
+        assert!(matches!(
+            client.apply_delta(tampered_messages),
+            Err(DeltaConflict::HeadMismatch { .. })
+        ));
+        assert_eq!(contents(&client), ["a"]);
+    }
+
+    #[test]
+    fn test_edits_require_resync() {
+        let client = context(&["a", "b"]);
+        let edited_server = context(&["a", "B", "c"]);
+
+        let delta = edited_server.diff_since(client.head());
+        assert!(delta.appended.is_none());
+        assert!(client.clone().apply_delta(delta) == Err(DeltaConflict::ResyncRequired));
+    }
+
+    #[test]
+    fn test_client_server_sync_over_three_turns() {
+        let mut server =
+            ContextBuilder::new().add_message(mock_message(MessageRole::System, "Hi."));
+        let mut client = server.clone();
+
+        for turn in 0..3 {
+            // The client appends a user message and sends only that
+            let synced = client.head();
+            client = client.add_message(mock_message(
+                MessageRole::User,
+                &format!("question {}", turn),
+            ));
+            let upstream = over_the_wire(client.diff_since(synced));
+            assert_eq!(upstream.appended.as_ref().map(Vec::len), Some(1));
+            assert!(server.apply_delta(upstream).is_ok());
+
+            // The server answers and sends only the reply back
+            let synced = server.head();
+            server = server.add_message(mock_message(
+                MessageRole::Model,
+                &format!("answer {}", turn),
+            ));
+            let downstream = over_the_wire(server.diff_since(synced));
+            assert_eq!(downstream.appended.as_ref().map(Vec::len), Some(1));
+            assert!(client.apply_delta(downstream).is_ok());
+
+            assert_eq!(client.head(), server.head());
+        }
+        assert_eq!(client.len(), 7);
+        assert_eq!(contents(&client)[6], "answer 2");
+    }
+}
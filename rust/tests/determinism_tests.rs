@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use serde_json::json;
+    use steelwool::determinism::Determinism;
+    use steelwool::providers::mock::mock_text_response;
+    use steelwool::{ContentType, ContextBuilder, Message, MessageRole, ProviderAdapter};
+
+    /// Replies "option-N" to every call, logging the call
+    fn scripted_adapter(log: Arc<Mutex<Vec<String>>>) -> ProviderAdapter {
+        let calls = Arc::new(AtomicUsize::new(0));
+        Arc::new(move |context: ContextBuilder, _| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            log.lock().unwrap().push(
+                json!({"event": "send", "call": call, "prompt": context.history.last().unwrap().content})
+                    .to_string(),
+            );
+            Box::pin(async move { Ok(mock_text_response(&format!("option-{}", call % 3))) })
+        })
+    }
+
+    /// Few-shot shuffle, three tied best-of-3 rounds and a retry delay,
+    /// as a JSONL event log
+    fn run_pipeline(determinism: Determinism) -> String {
+        let log = Arc::new(Mutex::new(vec![]));
+        let adapter = scripted_adapter(log.clone());
+
+        let mut examples = vec!["ex-a", "ex-b", "ex-c", "ex-d", "ex-e"];
+        determinism.shuffle(&mut examples);
+        log.lock()
+            .unwrap()
+            .push(json!({"event": "few_shot", "order": examples}).to_string());
+
+        let mut context = ContextBuilder::new();
+        for round in 0..3 {
+            context = context.add_message(Message {
+                role: MessageRole::User,
+                content: format!("question {}", round),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            });
+            let replies = block_on(context.send_n_with(adapter.clone(), 50, 3, &determinism))
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+            let picked = determinism.pick_best_by(|r| r.message.content.len() as f64)(replies);
+            log.lock()
+                .unwrap()
+                .push(json!({"event": "pick", "content": picked.message.content}).to_string());
+            context = context.add_message(picked.message);
+        }
+
+        let delay = determinism.jitter(Duration::from_millis(1000), 0.5);
+        log.lock()
+            .unwrap()
+            .push(json!({"event": "retry_delay_ms", "ms": delay.as_millis() as u64}).to_string());
+
+        log.lock().unwrap().join("\n")
+    }
+
+    #[test]
+    fn test_same_seed_gives_identical_event_log() {
+        let first = run_pipeline(Determinism::seeded(7));
+        let second = run_pipeline(Determinism::seeded(7));
+        let other = run_pipeline(Determinism::seeded(8));
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        // Sequential fan-out keeps calls in declaration order
+        let calls: Vec<u64> = first
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter_map(|event| event["call"].as_u64())
+            .collect();
+        assert_eq!(calls, (0..9).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_helpers_stay_in_range() {
+        let determinism = Determinism::default();
+        assert!(!determinism.is_sequential());
+        for _ in 0..100 {
+            assert!(determinism.below(3) < 3);
+            let delay = determinism.jitter(Duration::from_millis(100), 0.2);
+            assert!(delay >= Duration::from_millis(80) && delay <= Duration::from_millis(120));
+        }
+        assert_eq!(determinism.below(0), 0);
+
+        let mut items = vec![1, 2, 3, 4];
+        determinism.shuffle(&mut items);
+        items.sort();
+        assert_eq!(items, vec![1, 2, 3, 4]);
+    }
+}
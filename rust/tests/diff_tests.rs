@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests {
+    use steelwool::diff::MessageDiff;
+    use steelwool::providers::mock::mock_message;
+    use steelwool::{ContextBuilder, MessageRole};
+
+    fn context(contents: &[&str]) -> ContextBuilder {
+        contents
+            .iter()
+            .fold(ContextBuilder::new(), |context, content| {
+                context.add_message(mock_message(MessageRole::User, content))
+            })
+    }
+
+    fn contents(context: &ContextBuilder) -> Vec<&str> {
+        context.history.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_diff_reports_edits() {
+        let old = context(&["a", "b", "c", "d"]);
+        let new = context(&["a", "B", "c", "d", "e"]);
+
+        let edits = old.diff(&new);
+
+        assert_eq!(edits.len(), 2);
+        assert!(
+            edits[0]
+                == MessageDiff::Changed {
+                    index: 1,
+                    before: mock_message(MessageRole::User, "b"),
+                    after: mock_message(MessageRole::User, "B"),
+                }
+        );
+        assert!(
+            edits[1]
+                == MessageDiff::Added {
+                    index: 4,
+                    message: mock_message(MessageRole::User, "e"),
+                }
+        );
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_apply_diff_round_trips() {
+        let pairs: [(&[&str], &[&str]); 5] = [
+            (&["a", "b", "c", "d"], &["a", "B", "c", "d", "e"]),
+            (&["a", "b", "c"], &["c"]),
+            (&[], &["x", "y"]),
+            (
+                &["s", "u1", "m1", "u2", "m2"],
+                &["s", "u0", "u1", "m1x", "m2"],
+            ),
+            (&["a", "b"], &["b", "a", "b", "a"]),
+        ];
+
+        for (from, to) in pairs {
+            let (old, new) = (context(from), context(to));
+            let patched = old.clone().apply_diff(old.diff(&new));
+            assert_eq!(contents(&patched), to.to_vec(), "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn test_apply_handwritten_diff() {
+        let patched = context(&["a", "b", "c"]).apply_diff(vec![
+            MessageDiff::Removed {
+                index: 0,
+                message: mock_message(MessageRole::User, "a"),
+            },
+            MessageDiff::Added {
+                index: 0,
+                message: mock_message(MessageRole::System, "rules"),
+            },
+            MessageDiff::Added {
+                index: 10,
+                message: mock_message(MessageRole::User, "last"),
+            },
+            MessageDiff::Changed {
+                index: 2,
+                before: mock_message(MessageRole::User, "c"),
+                after: mock_message(MessageRole::User, "C"),
+            },
+            MessageDiff::Removed {
+                index: 7,
+                message: mock_message(MessageRole::User, "gone"),
+            },
+        ]);
+
+        assert_eq!(contents(&patched), vec!["rules", "b", "C", "last"]);
+        assert!(patched.history[0].role == MessageRole::System);
+    }
+}
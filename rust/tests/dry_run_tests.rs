@@ -0,0 +1,161 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::executor::block_on;
+    use steelwool::capabilities::{Capabilities, Pricing};
+    use steelwool::dry_run::{DryRunOptions, DryRunReport, dry_run_adapter};
+    use steelwool::tokens::HeuristicTokenCounter;
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, StopReason, ToolDescriptor, ToolExecuter,
+    };
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            context_length: 1000,
+            max_output_tokens: 500,
+            supports_tools: true,
+            strict_tool_schemas: true,
+        }
+    }
+
+    fn weather_tool() -> ToolDescriptor {
+        ToolDescriptor {
+            name: "get_weather".to_string(),
+            description: "Get the weather".to_string(),
+            schema: serde_json::json!({
+                "type": "object",
+                "properties": { "location": { "type": "string" } },
+                "required": ["location"],
+                "additionalProperties": false
+            }),
+            required: true,
+        }
+    }
+
+    fn user(content: &str) -> Message {
+        Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        }
+    }
+
+    /// send -> execute tools -> send again until the model stops calling tools
+    fn run_agent(context: ContextBuilder, adapter: steelwool::ProviderAdapter) -> ContextBuilder {
+        let executer: ToolExecuter = Arc::new(|_| Box::pin(async { Ok("sunny".to_string()) }));
+        let mut context = context;
+
+        loop {
+            let response = block_on(context.send(adapter.clone(), 100));
+            if response.prompt_response.stop_reason != StopReason::ToolCalls {
+                return response.resolve_without();
+            }
+            context = block_on(response.resolve(executer.clone()));
+        }
+    }
+
+    #[test]
+    fn test_agent_pipeline_dry_run_report() {
+        let report = DryRunReport::new();
+        let adapter = dry_run_adapter(
+            capabilities(),
+            Pricing {
+                prompt_per_million: 1_000_000.0,
+                completion_per_million: 2_000_000.0,
+            },
+            Arc::new(HeuristicTokenCounter),
+            DryRunOptions {
+                canned_content: "It is sunny.".to_string(),
+                assumed_completion_tokens: 50,
+                tools: vec![weather_tool()],
+                assumed_tool_turns: 2,
+            },
+            report.clone(),
+        );
+
+        let context = run_agent(
+            ContextBuilder::new().add_message(user("Weather in Oslo?")),
+            adapter,
+        );
+
+        assert_eq!(report.call_count(), 3);
+        assert_eq!(context.history.last().unwrap().content, "It is sunny.");
+        assert!(report.validation_errors().is_empty());
+
+        // Completion length is capped by max_tokens (100) in every call
+        assert_eq!(report.total_completion_tokens(), 150);
+        let calls = report.calls();
+        assert!(calls[0].prompt_tokens < calls[1].prompt_tokens);
+        assert!(calls[1].prompt_tokens < calls[2].prompt_tokens);
+        assert_eq!(
+            report.total_prompt_tokens(),
+            calls.iter().map(|c| c.prompt_tokens).sum::<u32>()
+        );
+        assert_eq!(
+            report.total_cost(),
+            report.total_prompt_tokens() as f64 + 2.0 * 150.0
+        );
+    }
+
+    #[test]
+    fn test_oversized_prompt_is_a_validation_error() {
+        let report = DryRunReport::new();
+        let adapter = dry_run_adapter(
+            capabilities(),
+            Pricing::default(),
+            Arc::new(HeuristicTokenCounter),
+            DryRunOptions::default(),
+            report.clone(),
+        );
+
+        let huge = "word ".repeat(1000);
+        let response = block_on(
+            ContextBuilder::new()
+                .add_message(user(&huge))
+                .send(adapter, 100),
+        );
+
+        assert_eq!(response.prompt_response.message.content, "[dry run]");
+        let errors = report.validation_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("exceeds context length 1000"));
+    }
+
+    #[test]
+    fn test_non_strict_tool_schema_is_flagged() {
+        let report = DryRunReport::new();
+        let mut loose = weather_tool();
+        loose.schema["additionalProperties"] = serde_json::Value::Bool(true);
+
+        let adapter = dry_run_adapter(
+            Capabilities {
+                supports_tools: false,
+                ..capabilities()
+            },
+            Pricing::default(),
+            Arc::new(HeuristicTokenCounter),
+            DryRunOptions {
+                tools: vec![loose],
+                ..DryRunOptions::default()
+            },
+            report.clone(),
+        );
+        block_on(
+            ContextBuilder::new()
+                .add_message(user("hi"))
+                .send(adapter, 600),
+        );
+
+        assert_eq!(
+            report.validation_errors(),
+            vec![
+                "max_tokens 600 exceeds model output limit 500".to_string(),
+                "Model does not support tools".to_string(),
+                "Tool get_weather schema must set additionalProperties: false".to_string(),
+            ]
+        );
+    }
+}
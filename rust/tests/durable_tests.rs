@@ -0,0 +1,147 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::FutureExt;
+    use futures::executor::block_on;
+    use futures::stream::{self, BoxStream, StreamExt};
+    use steelwool::durable::{FsyncPolicy, GENERATION_INTERRUPTED, StreamJournal};
+    use steelwool::providers::mock::mock_streaming_adapter_factory;
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, StopReason,
+        StreamProviderAdapter,
+    };
+
+    fn journal_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("steelwool-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn delta(content: &str) -> PromptResponseDelta {
+        PromptResponseDelta {
+            content: content.to_string(),
+            stop_reason: None,
+            tool_calls: None,
+            cumulative_tokens: 0,
+            token_delta: 0,
+            logprobs: None,
+        }
+    }
+
+    fn context() -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Tell me a story".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    /// Yields `deltas`, then hangs as if the provider stalled
+    fn stalling_adapter(deltas: Vec<&str>) -> StreamProviderAdapter {
+        let deltas: Vec<PromptResponseDelta> = deltas.into_iter().map(delta).collect();
+        Arc::new(move |_, _| {
+            Box::pin(stream::iter(deltas.clone().into_iter().map(Ok)).chain(stream::pending()))
+                as BoxStream<'static, Result<PromptResponseDelta, String>>
+        })
+    }
+
+    #[test]
+    fn test_recovery_reproduces_delivered_prefix() {
+        for policy in [
+            FsyncPolicy::EveryDelta,
+            FsyncPolicy::Interval(Duration::from_secs(60)),
+            FsyncPolicy::OnCompletion,
+        ] {
+            let dir = journal_dir("crash");
+            let journal = StreamJournal::new(&dir, policy);
+            let delivered = Arc::new(Mutex::new(String::new()));
+            let delivered_clone = delivered.clone();
+
+            let writer = journal.writer("conv-1").unwrap();
+            let turn = context().send_streaming_durable(
+                stalling_adapter(vec!["Once ", "upon ", "a time, ", "\"quoted\"\n"]),
+                100,
+                &writer,
+                move |delta| {
+                    delivered_clone
+                        .lock()
+                        .unwrap()
+                        .push_str(&delta.unwrap().content)
+                },
+            );
+            // Poll until the stream stalls, then drop everything: the "crash"
+            assert!(turn.now_or_never().is_none());
+            drop(writer);
+
+            let recovered = journal.recover_partial("conv-1").unwrap().unwrap();
+            assert_eq!(recovered.content, *delivered.lock().unwrap());
+            assert_eq!(recovered.content, "Once upon a time, \"quoted\"\n");
+            assert_eq!(recovered.deltas, 4);
+
+            let restored = context().restore_partial(recovered);
+            assert!(restored.history[1].role == MessageRole::Model);
+            assert_eq!(
+                restored.history[1].content,
+                "Once upon a time, \"quoted\"\n"
+            );
+            assert_eq!(
+                restored
+                    .metadata
+                    .get(GENERATION_INTERRUPTED)
+                    .map(String::as_str),
+                Some("4")
+            );
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+    }
+
+    #[test]
+    fn test_completion_finalizes_entry() {
+        let dir = journal_dir("complete");
+        let journal = StreamJournal::new(&dir, FsyncPolicy::default());
+        let adapter = mock_streaming_adapter_factory(vec![
+            Ok(delta("Hello")),
+            Ok(PromptResponseDelta {
+                stop_reason: Some(StopReason::Stop),
+                ..delta(" world")
+            }),
+        ]);
+
+        let writer = journal.writer("conv-2").unwrap();
+        let response =
+            block_on(context().send_streaming_durable(adapter, 100, &writer, |_| {})).unwrap();
+
+        assert_eq!(response.prompt_response.message.content, "Hello world");
+        assert!(journal.recover_partial("conv-2").unwrap().is_none());
+        assert!(writer.append(&delta("late")).is_err());
+        assert!(journal.recover_partial("never-started").unwrap().is_none());
+        assert!(journal.writer("../escape").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_torn_last_line_is_ignored() {
+        let dir = journal_dir("torn");
+        let journal = StreamJournal::new(&dir, FsyncPolicy::EveryDelta);
+        let writer = journal.writer("conv-3").unwrap();
+        writer.append(&delta("kept")).unwrap();
+        drop(writer);
+
+        let path = dir.join("conv-3.wal");
+        let mut wal = std::fs::read_to_string(&path).unwrap();
+        wal.push_str("{\"delta\":{\"content\":\"half");
+        std::fs::write(&path, wal).unwrap();
+
+        let recovered = journal.recover_partial("conv-3").unwrap().unwrap();
+        assert_eq!((recovered.content.as_str(), recovered.deltas), ("kept", 1));
+
+        journal.discard("conv-3").unwrap();
+        assert!(journal.recover_partial("conv-3").unwrap().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
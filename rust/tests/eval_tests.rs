@@ -0,0 +1,284 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use regex::Regex;
+    use steelwool::capabilities::Pricing;
+    use steelwool::eval::{EvalCase, EvalOptions, Expectation, ReproducibilityDrift, run_eval};
+    use steelwool::providers::mock::{mock_adapter_factory, mock_text_response};
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, Provenance, ProviderAdapter,
+        ReproducibilityInfo,
+    };
+
+    fn case(name: &str, question: &str, expected: Expectation) -> EvalCase {
+        EvalCase {
+            name: name.to_string(),
+            context: ContextBuilder::new().add_message(Message {
+                role: MessageRole::User,
+                content: question.to_string(),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            }),
+            expected,
+        }
+    }
+
+    /// Answers each question from a fixed table
+    fn subject() -> ProviderAdapter {
+        Arc::new(|context: ContextBuilder, _| {
+            let reply = match context.history[0].content.as_str() {
+                "Capital of Norway?" => "The capital of Norway is Oslo.",
+                "Order as JSON" => r#"{"order": {"items": [{"sku": "A1", "qty": 2}]}}"#,
+                "Say hi" => "Hello there!",
+                _ => "I don't know.",
+            };
+            Box::pin(async move {
+                Ok(steelwool::PromptResponse {
+                    token_usage: 10,
+                    ..mock_text_response(reply)
+                })
+            })
+        })
+    }
+
+    #[test]
+    fn test_each_deterministic_expectation() {
+        let cases = vec![
+            case(
+                "contains",
+                "Capital of Norway?",
+                Expectation::ContainsAll(vec!["Oslo".to_string(), "Norway".to_string()]),
+            ),
+            case(
+                "contains-missing",
+                "Capital of Norway?",
+                Expectation::ContainsAll(vec!["Bergen".to_string()]),
+            ),
+            case(
+                "regex",
+                "Say hi",
+                Expectation::MatchesRegex(Regex::new(r"^Hello\b").unwrap()),
+            ),
+            case(
+                "json",
+                "Order as JSON",
+                Expectation::JsonFieldEquals {
+                    path: "order.items.0.qty".to_string(),
+                    value: serde_json::json!(2),
+                },
+            ),
+            case(
+                "json-not-json",
+                "Say hi",
+                Expectation::JsonFieldEquals {
+                    path: "order".to_string(),
+                    value: serde_json::json!(null),
+                },
+            ),
+        ];
+
+        let report = block_on(run_eval(
+            cases,
+            subject(),
+            &EvalOptions {
+                concurrency: 3,
+                ..EvalOptions::default()
+            },
+        ));
+
+        let outcomes: Vec<(&str, bool)> = report
+            .results
+            .iter()
+            .map(|r| (r.name.as_str(), r.passed))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("contains", true),
+                ("contains-missing", false),
+                ("regex", true),
+                ("json", true),
+                ("json-not-json", false)
+            ]
+        );
+        assert_eq!(report.results[1].detail.as_deref(), Some("Missing: Bergen"));
+        assert!(
+            report.results[4]
+                .detail
+                .as_ref()
+                .unwrap()
+                .starts_with("Reply is not JSON")
+        );
+        assert_eq!(report.total_tokens(), 50);
+        assert_eq!(report.pass_rate(), 0.6);
+        assert!(report.total_cost().is_none());
+    }
+
+    #[test]
+    fn test_judge_scores_and_cost() {
+        let prompts = Arc::new(Mutex::new(vec![]));
+        let prompts_clone = prompts.clone();
+        let scores = mock_adapter_factory(vec![
+            Ok(mock_text_response(
+                r#"```json
+{"score": 8.5, "reasoning": "Correct and concise."}
+```"#,
+            )),
+            Ok(mock_text_response(
+                r#"{"score": 3, "reasoning": "Unhelpful."}"#,
+            )),
+            Ok(mock_text_response("Looks fine to me")),
+        ]);
+        let judge: ProviderAdapter = Arc::new(move |context: ContextBuilder, max_tokens| {
+            prompts_clone
+                .lock()
+                .unwrap()
+                .push(context.history[1].content.clone());
+            scores(context, max_tokens)
+        });
+        let judged = |name, question| {
+            case(
+                name,
+                question,
+                Expectation::JudgeScoresAtLeast {
+                    rubric: "Answers the question directly".to_string(),
+                    min: 7.0,
+                    judge: judge.clone(),
+                },
+            )
+        };
+
+        let report = block_on(run_eval(
+            vec![
+                judged("good", "Capital of Norway?"),
+                judged("bad", "Capital of Peru?"),
+                judged("unparseable", "Say hi"),
+            ],
+            subject(),
+            &EvalOptions {
+                pricing: Some(Pricing {
+                    prompt_per_million: 1_000_000.0,
+                    completion_per_million: 1_000_000.0,
+                }),
+                ..EvalOptions::default()
+            },
+        ));
+
+        let good = &report.results[0];
+        assert!(good.passed);
+        assert_eq!(good.judge_score, Some(8.5));
+        assert_eq!(good.detail.as_deref(), Some("Correct and concise."));
+        assert!(good.cost.unwrap() > 0.0);
+
+        let bad = &report.results[1];
+        assert!(!bad.passed);
+        assert_eq!(bad.judge_score, Some(3.0));
+        assert_eq!(
+            bad.detail.as_deref(),
+            Some("Judge scored 3 (< 7): Unhelpful.")
+        );
+
+        let unparseable = &report.results[2];
+        assert!(!unparseable.passed && unparseable.judge_score.is_none());
+        assert!(
+            unparseable
+                .detail
+                .as_ref()
+                .unwrap()
+                .starts_with("Judge reply has no JSON verdict")
+        );
+
+        let first_prompt = prompts.lock().unwrap()[0].clone();
+        assert!(first_prompt.contains("Answers the question directly"));
+        assert!(first_prompt.contains("User: Capital of Norway?"));
+        assert!(first_prompt.contains("The capital of Norway is Oslo."));
+        assert!(report.total_cost().is_some());
+    }
+
+    #[test]
+    fn test_adapter_errors_fail_the_case() {
+        let adapter = mock_adapter_factory(vec![Err("rate limited".to_string())]);
+
+        let report = block_on(run_eval(
+            vec![case("any", "Say hi", Expectation::ContainsAll(vec![]))],
+            adapter,
+            &EvalOptions::default(),
+        ));
+
+        assert_eq!(report.passed(), 0);
+        assert_eq!(
+            report.failed()[0].detail.as_deref(),
+            Some("Adapter failed: rate limited")
+        );
+    }
+
+    /// Replies "Hi" reporting a seeded backend with `fingerprint`
+    fn seeded_adapter(fingerprint: Option<&str>, claims: bool) -> ProviderAdapter {
+        mock_adapter_factory(vec![Ok(steelwool::PromptResponse {
+            provenance: Provenance {
+                reproducibility: Some(ReproducibilityInfo {
+                    seed_sent: Some(42),
+                    fingerprint: fingerprint.map(str::to_string),
+                    backend_claims_determinism: claims,
+                }),
+                ..Provenance::default()
+            },
+            ..mock_text_response("Hi")
+        })])
+    }
+
+    #[test]
+    fn test_reproducibility_drift_flags_changed_backends() {
+        let run = |adapter| {
+            block_on(run_eval(
+                vec![case("hi", "Say hi", Expectation::ContainsAll(vec![]))],
+                adapter,
+                &EvalOptions::default(),
+            ))
+        };
+        let baseline = run(seeded_adapter(Some("fp_a"), true));
+        assert_eq!(
+            baseline.results[0]
+                .reproducibility
+                .as_ref()
+                .unwrap()
+                .fingerprint
+                .as_deref(),
+            Some("fp_a")
+        );
+
+        assert!(
+            run(seeded_adapter(Some("fp_a"), true))
+                .reproducibility_drift(&baseline)
+                .is_empty()
+        );
+        assert_eq!(
+            run(seeded_adapter(Some("fp_b"), true)).reproducibility_drift(&baseline),
+            vec![ReproducibilityDrift {
+                name: "hi".to_string(),
+                detail: "Backend fingerprint changed from fp_a to fp_b".to_string(),
+            }]
+        );
+        assert_eq!(
+            run(seeded_adapter(Some("fp_a"), false)).reproducibility_drift(&baseline)[0].detail,
+            "Backend no longer claims determinism"
+        );
+        assert_eq!(
+            run(mock_adapter_factory(vec![Ok(mock_text_response("Hi"))]))
+                .reproducibility_drift(&baseline)[0]
+                .detail,
+            "No reproducibility info reported"
+        );
+
+        // Nothing was promised by a backend that ignores seeds
+        let unseeded = run(seeded_adapter(Some("fp_a"), false));
+        assert!(
+            run(seeded_adapter(Some("fp_b"), false))
+                .reproducibility_drift(&unseeded)
+                .is_empty()
+        );
+    }
+}
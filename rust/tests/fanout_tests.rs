@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use futures::StreamExt;
+    use steelwool::fanout::{fanout_streaming, fanout_streaming_merged};
+    use steelwool::providers::mock::mock_streaming_adapter_factory;
+    use steelwool::{ContextBuilder, PromptResponseDelta, StreamProviderAdapter};
+
+    /// Drive `future` on whichever executor the runtime backend expects
+    fn run<F: Future>(future: F) -> F::Output {
+        #[cfg(all(feature = "tokio-runtime", not(feature = "agnostic-runtime")))]
+        return tokio::runtime::Runtime::new().unwrap().block_on(future);
+
+        #[cfg(any(not(feature = "tokio-runtime"), feature = "agnostic-runtime"))]
+        return futures::executor::block_on(future);
+    }
+
+    fn delta(content: &str) -> Result<PromptResponseDelta, String> {
+        Ok(PromptResponseDelta {
+            content: content.to_string(),
+            stop_reason: None,
+            tool_calls: None,
+            cumulative_tokens: 0,
+            token_delta: 0,
+            logprobs: None,
+        })
+    }
+
+    fn models() -> Vec<StreamProviderAdapter> {
+        vec![
+            mock_streaming_adapter_factory(vec![delta("Par"), delta("is")]),
+            mock_streaming_adapter_factory(vec![delta("Paris"), Err("cut off".to_string())]),
+            mock_streaming_adapter_factory(vec![delta("Lyon")]),
+        ]
+    }
+
+    fn prompt() -> ContextBuilder {
+        ContextBuilder::new().with_prefill("A: ")
+    }
+
+    #[test]
+    fn test_one_stream_per_pair() {
+        let replies: Vec<Vec<Result<String, String>>> = run(async {
+            let streams = fanout_streaming(vec![prompt(), prompt()], models(), 100);
+            let mut replies = vec![];
+            for stream in streams {
+                replies.push(stream.map(|d| d.map(|d| d.content)).collect().await);
+            }
+            replies
+        });
+
+        // The third model has no context to pair with
+        assert_eq!(
+            replies,
+            vec![
+                vec![
+                    Ok("A: ".to_string()),
+                    Ok("Par".to_string()),
+                    Ok("is".to_string())
+                ],
+                vec![
+                    Ok("A: ".to_string()),
+                    Ok("Paris".to_string()),
+                    Err("cut off".to_string())
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merged_stream_tags_each_delta() {
+        let tagged: Vec<(usize, Result<PromptResponseDelta, String>)> = run(async {
+            fanout_streaming_merged(vec![prompt(), prompt(), prompt()], models(), 100)
+                .collect()
+                .await
+        });
+
+        let reply = |index: usize| -> String {
+            tagged
+                .iter()
+                .filter(|(i, _)| *i == index)
+                .filter_map(|(_, delta)| delta.as_ref().ok())
+                .map(|delta| delta.content.as_str())
+                .collect()
+        };
+        assert_eq!(tagged.len(), 8);
+        assert_eq!(reply(0), "A: Paris");
+        assert_eq!(reply(1), "A: Paris");
+        assert_eq!(reply(2), "A: Lyon");
+    }
+}
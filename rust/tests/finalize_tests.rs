@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+    use futures::executor::block_on;
+    use steelwool::budget::TokenBudget;
+    use steelwool::finalize::{CONVERSATION_FINALIZED, FinalizeHook, FinalizeOpts};
+    use steelwool::providers::mock::{
+        counting_adapter, mock_adapter_factory, mock_message, mock_streaming_adapter_factory,
+        mock_text_response,
+    };
+    use steelwool::store::{ConversationStore, InMemoryConversationStore};
+    use steelwool::{ContextBuilder, MessageRole, StreamProviderAdapter};
+
+    fn conversation() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(MessageRole::User, "Book a table for two."))
+            .add_message(mock_message(MessageRole::Model, "Done, 7pm at Luigi's."))
+    }
+
+    #[test]
+    fn test_every_sink_sees_finalization_once() {
+        let store = Arc::new(InMemoryConversationStore::new());
+        let budget = Arc::new(TokenBudget::new(1_000));
+        let logged = Arc::new(Mutex::new(vec![]));
+        let usage = Arc::new(AtomicUsize::new(0));
+        let (summarizer, summaries) = counting_adapter(mock_adapter_factory(vec![Ok(
+            mock_text_response("Dinner booking\nA table for two was booked at 7pm."),
+        )]));
+
+        let logger: FinalizeHook = {
+            let logged = logged.clone();
+            Arc::new(move |context: &ContextBuilder, _: &_| {
+                logged.lock().unwrap().push(context.len());
+                Ok(())
+            })
+        };
+        let tracker: FinalizeHook = {
+            let usage = usage.clone();
+            Arc::new(move |_: &_, report: &_| {
+                usage.fetch_add(report.stats.message_count, Ordering::SeqCst);
+                Ok(())
+            })
+        };
+        let opts = FinalizeOpts {
+            store: Some((store.clone(), "conv-1".to_string())),
+            summarizer: Some((summarizer, 100)),
+            release: Some((budget.clone(), 250)),
+            hooks: vec![logger, tracker],
+            ..FinalizeOpts::default()
+        };
+
+        let mut context = conversation();
+        let report = block_on(context.finalize(opts.clone())).unwrap();
+        assert_eq!(report.title.as_deref(), Some("Dinner booking"));
+        assert_eq!(
+            report.summary.as_deref(),
+            Some("A table for two was booked at 7pm.")
+        );
+        assert_eq!(report.stored_as.as_deref(), Some("conv-1"));
+        assert_eq!(report.stats.message_count, 2);
+
+        // Finalizing again returns the cached report without side effects
+        let again = block_on(context.finalize(opts)).unwrap();
+        assert_eq!(again, report);
+        assert_eq!(context.final_report(), Some(&report));
+
+        assert_eq!(summaries.load(Ordering::SeqCst), 1);
+        assert_eq!(*logged.lock().unwrap(), vec![2]);
+        assert_eq!(usage.load(Ordering::SeqCst), 2);
+        assert_eq!(budget.remaining(), 1_250);
+        assert_eq!(store.list().unwrap(), vec!["conv-1".to_string()]);
+        assert!(store.is_finalized("conv-1").unwrap());
+        assert!(!store.is_finalized("conv-2").unwrap());
+    }
+
+    #[test]
+    fn test_finalized_conversation_is_read_only() {
+        let mut context = conversation();
+        block_on(context.finalize(FinalizeOpts::default())).unwrap();
+        assert!(context.is_finalized());
+
+        let added = context
+            .clone()
+            .try_add_message(mock_message(MessageRole::User, "Make it three."));
+        assert_eq!(added.err().as_deref(), Some(CONVERSATION_FINALIZED));
+
+        let adapter = mock_adapter_factory(vec![Ok(mock_text_response("Sure."))]);
+        let sent = block_on(context.clone().try_send(adapter, 100));
+        assert_eq!(sent.err().as_deref(), Some(CONVERSATION_FINALIZED));
+
+        // The flag survives a round trip through a store
+        let store = InMemoryConversationStore::new();
+        store.save("conv-1", &context).unwrap();
+        assert!(store.load("conv-1").unwrap().unwrap().is_finalized());
+    }
+
+    #[test]
+    #[should_panic(expected = "ConversationFinalized")]
+    fn test_add_message_panics_once_finalized() {
+        let mut context = conversation();
+        block_on(context.finalize(FinalizeOpts::default())).unwrap();
+        let _ = context.add_message(mock_message(MessageRole::User, "Make it three."));
+    }
+
+    fn finalized() -> ContextBuilder {
+        let mut context = conversation();
+        block_on(context.finalize(FinalizeOpts::default())).unwrap();
+        context
+    }
+
+    #[test]
+    fn test_finalized_conversation_refuses_streaming_sends() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let adapter: StreamProviderAdapter = {
+            let calls = calls.clone();
+            let deltas = mock_streaming_adapter_factory(vec![]);
+            Arc::new(move |context, max_tokens| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                deltas(context, max_tokens)
+            })
+        };
+
+        let deltas: Vec<_> = block_on(
+            finalized()
+                .send_streaming(adapter.clone(), 100)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(
+            deltas[0].as_ref().err().map(String::as_str),
+            Some(CONVERSATION_FINALIZED)
+        );
+
+        let failure = block_on(finalized().send_streaming_with_callback(adapter, 100, |_| {}))
+            .err()
+            .unwrap();
+        assert_eq!(failure.error, CONVERSATION_FINALIZED);
+        assert_eq!(failure.deltas, 0);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_finalized_conversation_refuses_send_n() {
+        let (adapter, calls) =
+            counting_adapter(mock_adapter_factory(vec![Ok(mock_text_response("Sure."))]));
+
+        let results = block_on(finalized().send_n(adapter.clone(), 100, 2));
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(
+            |result| result.as_ref().err().map(String::as_str) == Some(CONVERSATION_FINALIZED)
+        ));
+
+        let results = block_on(finalized().send_n_concurrent(adapter.clone(), 100, 2));
+        assert!(results.iter().all(Result::is_err));
+
+        let json = block_on(finalized().send_with_json_mode(adapter, 100));
+        assert_eq!(json.err().as_deref(), Some(CONVERSATION_FINALIZED));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ConversationFinalized")]
+    fn test_history_edits_panic_once_finalized() {
+        let _ = finalized().pin_message(0);
+    }
+
+    #[test]
+    fn test_finalized_conversation_takes_no_deltas() {
+        use steelwool::delta::DeltaConflict;
+
+        let server = conversation().add_message(mock_message(MessageRole::User, "Make it three."));
+        let delta = server.diff_since(conversation().head());
+
+        let mut client = finalized();
+        assert!(client.apply_delta(delta) == Err(DeltaConflict::Finalized));
+        assert_eq!(client.len(), 2);
+    }
+
+    #[test]
+    fn test_failed_hook_leaves_conversation_open() {
+        let failing: FinalizeHook = Arc::new(|_: &_, _: &_| Err("logger offline".to_string()));
+        let mut context = conversation();
+
+        let result = block_on(context.finalize(FinalizeOpts {
+            hooks: vec![failing],
+            ..FinalizeOpts::default()
+        }));
+        assert_eq!(result.err().as_deref(), Some("logger offline"));
+        assert!(!context.is_finalized());
+        assert!(
+            context
+                .try_add_message(mock_message(MessageRole::User, "Still there?"))
+                .is_ok()
+        );
+    }
+}
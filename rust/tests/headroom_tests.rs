@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use serde_json::json;
+    use steelwool::capabilities::Capabilities;
+    use steelwool::headroom::{HeadroomWarning, headroom_warning_adapter};
+    use steelwool::providers::mock::{mock_adapter_factory, mock_message, mock_text_response};
+    use steelwool::tokens::{HeuristicTokenCounter, TokenCounter, estimate_prompt_tokens};
+    use steelwool::{ContextBuilder, MessageRole, PromptResponse, ProviderAdapter, ToolDescriptor};
+
+    fn conversation() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(
+                MessageRole::System,
+                "You are a travel assistant. Answer briefly and cite the tools you used.",
+            ))
+            .add_message(mock_message(
+                MessageRole::User,
+                "I'm flying from Oslo to Lisbon next Thursday. What will the weather be like when I land?",
+            ))
+            .add_message(mock_message(
+                MessageRole::Model,
+                "Let me check the forecast for Lisbon on Thursday afternoon.",
+            ))
+    }
+
+    fn weather_tool() -> ToolDescriptor {
+        ToolDescriptor::from_json_schema(
+            "get_forecast".to_string(),
+            "Daily weather forecast for a city".to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "city": { "type": "string" },
+                    "date": { "type": "string", "description": "ISO date" }
+                },
+                "required": ["city", "date"]
+            }),
+        )
+    }
+
+    fn capabilities(context_length: u32) -> Capabilities {
+        Capabilities {
+            context_length,
+            max_output_tokens: 4096,
+            supports_tools: true,
+            strict_tool_schemas: false,
+        }
+    }
+
+    /// Stand-in for a real tokenizer: a token per word and per punctuation mark
+    struct WordTokenizer;
+
+    impl TokenCounter for WordTokenizer {
+        fn count(&self, text: &str) -> usize {
+            let words = text
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .count();
+            let marks = text.chars().filter(|c| c.is_ascii_punctuation()).count();
+            words + marks
+        }
+    }
+
+    #[test]
+    fn test_estimate_tracks_reported_usage() {
+        // Mock provider that reports the prompt size by its own tokenizer
+        let tools = vec![weather_tool()];
+        let provider_tools = tools.clone();
+        let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            let usage = estimate_prompt_tokens(&context, &provider_tools, &WordTokenizer);
+            Box::pin(async move {
+                Ok(PromptResponse {
+                    token_usage: usage as u32,
+                    ..mock_text_response("")
+                })
+            })
+        });
+
+        let context = conversation();
+        let headroom = context.headroom(&capabilities(8192), &tools, &HeuristicTokenCounter, 512);
+        let reported = block_on(context.clone().send(adapter, 512))
+            .prompt_response
+            .token_usage as f64;
+
+        let error = (headroom.prompt_tokens as f64 - reported).abs() / reported;
+        assert!(
+            error < 0.25,
+            "estimate {} vs usage {}",
+            headroom.prompt_tokens,
+            reported
+        );
+        assert_eq!(headroom.remaining, 8192 - 512 - headroom.prompt_tokens);
+
+        // Tool descriptors count toward the prompt
+        let bare = context.headroom(&capabilities(8192), &[], &HeuristicTokenCounter, 512);
+        assert!(bare.prompt_tokens < headroom.prompt_tokens);
+    }
+
+    #[test]
+    fn test_would_truncate_and_prompt_budget() {
+        let context = conversation();
+        let prompt = estimate_prompt_tokens(&context, &[], &HeuristicTokenCounter) as u32;
+
+        let headroom =
+            context.headroom(&capabilities(prompt + 99), &[], &HeuristicTokenCounter, 100);
+        assert!(headroom.would_truncate);
+        assert_eq!(headroom.remaining, 0);
+
+        let truncated = context
+            .truncate_to_fit(headroom.prompt_budget(), &HeuristicTokenCounter)
+            .unwrap();
+        assert!(
+            !truncated
+                .headroom(&capabilities(prompt + 99), &[], &HeuristicTokenCounter, 100)
+                .would_truncate
+        );
+    }
+
+    #[test]
+    fn test_warning_fires_below_threshold() {
+        let warnings: Arc<Mutex<Vec<HeadroomWarning>>> = Arc::new(Mutex::new(vec![]));
+        let tools = vec![weather_tool()];
+        let prompt = estimate_prompt_tokens(&conversation(), &tools, &HeuristicTokenCounter) as u32;
+
+        let send = |context_length: u32| {
+            let sink = warnings.clone();
+            let adapter = headroom_warning_adapter(
+                mock_adapter_factory(vec![Ok(mock_text_response("Sunny"))]),
+                capabilities(context_length),
+                tools.clone(),
+                Arc::new(HeuristicTokenCounter),
+                1000,
+                Arc::new(move |warning| sink.lock().unwrap().push(warning)),
+            );
+            block_on(conversation().send(adapter, 256))
+        };
+
+        // Exactly the threshold left
+        send(prompt + 256 + 1000);
+        assert!(warnings.lock().unwrap().is_empty());
+
+        // One token short
+        let response = send(prompt + 256 + 999);
+        assert_eq!(response.prompt_response.message.content, "Sunny");
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].headroom.remaining, 999);
+        assert_eq!(warnings[0].headroom.reserved_for_completion, 256);
+        assert_eq!(warnings[0].threshold, 1000);
+    }
+}
@@ -0,0 +1,227 @@
+#[cfg(all(test, feature = "tokio-runtime", not(feature = "agnostic-runtime")))]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use futures::stream;
+    use steelwool::hedge::{HEDGE, HedgeStats, hedged_adapter, hedged_streaming_adapter};
+    use steelwool::providers::mock::mock_text_response;
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, ProviderAdapter,
+        StreamProviderAdapter,
+    };
+    use tokio::time::Instant;
+
+    fn context() -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "What's the capital of Norway?".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    /// Records whether a request was dropped before it finished
+    struct Backend {
+        calls: AtomicUsize,
+        cancelled: AtomicBool,
+    }
+
+    struct CancelGuard {
+        backend: Arc<Backend>,
+        finished: bool,
+    }
+
+    impl CancelGuard {
+        fn new(backend: Arc<Backend>) -> Self {
+            CancelGuard {
+                backend,
+                finished: false,
+            }
+        }
+
+        fn finish(&mut self) {
+            self.finished = true;
+        }
+    }
+
+    impl Drop for CancelGuard {
+        fn drop(&mut self) {
+            if !self.finished {
+                self.backend.cancelled.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn backend() -> Arc<Backend> {
+        Arc::new(Backend {
+            calls: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+        })
+    }
+
+    /// Answers `reply` after `delay`, reporting `tokens` of usage
+    fn delayed(
+        delay: Duration,
+        reply: &'static str,
+        tokens: u32,
+    ) -> (ProviderAdapter, Arc<Backend>) {
+        let state = backend();
+        let seen = state.clone();
+        let adapter: ProviderAdapter = Arc::new(move |_, _| {
+            seen.calls.fetch_add(1, Ordering::SeqCst);
+            let mut guard = CancelGuard::new(seen.clone());
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                guard.finish();
+                let mut response = mock_text_response(reply);
+                response.token_usage = tokens;
+                Ok(response)
+            })
+        });
+        (adapter, state)
+    }
+
+    fn delta(content: &str, cumulative_tokens: u32) -> PromptResponseDelta {
+        PromptResponseDelta {
+            content: content.to_string(),
+            stop_reason: None,
+            tool_calls: None,
+            cumulative_tokens,
+            token_delta: 0,
+            logprobs: None,
+        }
+    }
+
+    /// Streams `reply` word by word once `delay` has passed
+    fn delayed_stream(
+        delay: Duration,
+        reply: &'static str,
+    ) -> (StreamProviderAdapter, Arc<Backend>) {
+        let state = backend();
+        let seen = state.clone();
+        let adapter: StreamProviderAdapter = Arc::new(move |_, _| {
+            seen.calls.fetch_add(1, Ordering::SeqCst);
+            let guard = Arc::new(std::sync::Mutex::new(CancelGuard::new(seen.clone())));
+            let words: Vec<&'static str> = reply.split_inclusive(' ').collect();
+            let count = words.len();
+            Box::pin(
+                stream::once(tokio::time::sleep(delay))
+                    .flat_map(move |_| stream::iter(words.clone()).enumerate())
+                    .map(move |(i, word)| {
+                        if i + 1 == count {
+                            guard.lock().unwrap().finish();
+                        }
+                        Ok(delta(word, i as u32 + 1))
+                    }),
+            )
+        });
+        (adapter, state)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fast_primary_never_hedges() {
+        let (primary, _) = delayed(Duration::from_millis(100), "Oslo", 12);
+        let (secondary, secondary_state) = delayed(Duration::from_millis(10), "Oslo!", 30);
+        let hedged = hedged_adapter(primary, secondary, Duration::from_millis(500));
+
+        let response = (hedged.adapter)(context(), 100).await.unwrap();
+
+        assert_eq!(response.message.content, "Oslo");
+        assert_eq!(
+            response.provenance.details_from(HEDGE),
+            vec!["not fired; primary won"]
+        );
+        assert_eq!(secondary_state.calls.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            hedged.counters.snapshot(),
+            HedgeStats {
+                requests: 1,
+                primary_wins: 1,
+                token_usage: 12,
+                ..HedgeStats::default()
+            }
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_primary_loses_to_hedge() {
+        let (primary, primary_state) = delayed(Duration::from_secs(5), "Oslo", 12);
+        let (secondary, _) = delayed(Duration::from_millis(100), "Oslo!", 30);
+        let hedged = hedged_adapter(primary, secondary, Duration::from_millis(500));
+
+        let started = Instant::now();
+        let response = (hedged.adapter)(context(), 100).await.unwrap();
+
+        assert_eq!(started.elapsed(), Duration::from_millis(600));
+        assert_eq!(response.message.content, "Oslo!");
+        assert_eq!(
+            response.provenance.details_from(HEDGE),
+            vec!["fired after 500ms; secondary won"]
+        );
+        assert!(primary_state.cancelled.load(Ordering::SeqCst));
+
+        let stats = hedged.counters.snapshot();
+        assert_eq!(stats.hedges_fired, 1);
+        assert_eq!(stats.secondary_wins, 1);
+        assert_eq!(stats.token_usage, 30);
+        assert!(stats.wasted_tokens > 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_both_slow_takes_the_first_to_finish() {
+        let (primary, _) = delayed(Duration::from_secs(2), "Oslo", 12);
+        let (secondary, secondary_state) = delayed(Duration::from_secs(3), "Oslo!", 30);
+        let hedged = hedged_adapter(primary, secondary, Duration::from_millis(500));
+
+        let started = Instant::now();
+        let response = (hedged.adapter)(context(), 100).await.unwrap();
+
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+        assert_eq!(response.message.content, "Oslo");
+        assert_eq!(
+            response.provenance.details_from(HEDGE),
+            vec!["fired after 500ms; primary won"]
+        );
+        assert_eq!(secondary_state.calls.load(Ordering::SeqCst), 1);
+        assert!(secondary_state.cancelled.load(Ordering::SeqCst));
+        assert_eq!(hedged.counters.snapshot().token_usage, 12);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_streams_race_to_first_content() {
+        let collect = |adapter: StreamProviderAdapter| async move {
+            let deltas: Vec<_> = adapter(context(), 100).collect().await;
+            deltas
+                .into_iter()
+                .map(|d| d.unwrap().content)
+                .collect::<String>()
+        };
+
+        // Primary first: no hedge
+        let (primary, _) = delayed_stream(Duration::from_millis(100), "It is Oslo");
+        let (secondary, secondary_state) = delayed_stream(Duration::ZERO, "Oslo");
+        let hedged = hedged_streaming_adapter(primary, secondary, Duration::from_millis(500));
+        assert_eq!(collect(hedged.adapter.clone()).await, "It is Oslo");
+        assert_eq!(secondary_state.calls.load(Ordering::SeqCst), 0);
+        assert_eq!(hedged.counters.snapshot().token_usage, 3);
+
+        // Primary stalls: the hedge wins and the primary is dropped
+        let (primary, primary_state) = delayed_stream(Duration::from_secs(5), "It is Oslo");
+        let (secondary, _) = delayed_stream(Duration::from_millis(100), "Oslo, Norway");
+        let hedged = hedged_streaming_adapter(primary, secondary, Duration::from_millis(500));
+        let started = Instant::now();
+        assert_eq!(collect(hedged.adapter.clone()).await, "Oslo, Norway");
+        assert_eq!(started.elapsed(), Duration::from_millis(600));
+        assert!(primary_state.cancelled.load(Ordering::SeqCst));
+
+        let stats = hedged.counters.snapshot();
+        assert_eq!(stats.hedges_fired, 1);
+        assert_eq!(stats.secondary_wins, 1);
+        assert_eq!(stats.token_usage, 2);
+        assert!(stats.wasted_tokens > 0);
+    }
+}
@@ -0,0 +1,159 @@
+#[cfg(all(test, feature = "macros"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures::executor::block_on;
+    use serde_json::json;
+    use steelwool::{IntoTool, Tool, ToolCall, tool};
+
+    /// Forecast unit
+    #[allow(dead_code)]
+    #[derive(Tool)]
+    struct Units {
+        /// "metric" or "imperial"
+        system: String,
+    }
+
+    /// Get the weather forecast
+    /// for a city
+    #[allow(dead_code)]
+    #[derive(Tool)]
+    struct GetWeather {
+        /// City name, e.g. "Oslo"
+        city: String,
+        /// Days ahead
+        days: Option<u8>,
+        hourly: bool,
+        thresholds: Vec<f64>,
+        tags: HashMap<String, i64>,
+        units: Units,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Tool)]
+    #[tool(name = "search")]
+    struct SearchArgs {
+        query: String,
+    }
+
+    #[test]
+    fn test_derive_tool_descriptor() {
+        let tool = GetWeather::tool_descriptor();
+        assert_eq!(tool.name, "get_weather");
+        assert_eq!(tool.description, "Get the weather forecast for a city");
+        assert!(!tool.required);
+        assert_eq!(
+            tool.schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "city": { "type": "string", "description": "City name, e.g. \"Oslo\"" },
+                    "days": { "type": "integer", "description": "Days ahead" },
+                    "hourly": { "type": "boolean" },
+                    "thresholds": { "type": "array", "items": { "type": "number" } },
+                    "tags": { "type": "object", "additionalProperties": { "type": "integer" } },
+                    "units": {
+                        "type": "object",
+                        "properties": {
+                            "system": { "type": "string", "description": "\"metric\" or \"imperial\"" }
+                        },
+                        "required": ["system"],
+                        "additionalProperties": false
+                    }
+                },
+                "required": ["city", "hourly", "thresholds", "tags", "units"],
+                "additionalProperties": false
+            })
+        );
+    }
+
+    #[test]
+    fn test_tool_name_override() {
+        let tool = SearchArgs::tool_descriptor();
+        assert_eq!(tool.name, "search");
+        assert_eq!(tool.description, "");
+        assert_eq!(tool.schema["required"], json!(["query"]));
+    }
+
+    /// Current weather for a city
+    ///
+    /// # Arguments
+    /// * `city` - City name, e.g. "Oslo"
+    /// * `days`: Days ahead
+    #[tool]
+    async fn get_forecast(city: String, days: Option<u8>) -> Result<String, String> {
+        match city.is_empty() {
+            true => Err("no city".to_string()),
+            false => Ok(format!("{} for {} days", city, days.unwrap_or(1))),
+        }
+    }
+
+    #[tool(name = "add")]
+    fn add_numbers(a: i64, b: i64) -> Result<i64, String> {
+        Ok(a + b)
+    }
+
+    fn call(name: &str, arguments: serde_json::Value) -> ToolCall {
+        ToolCall {
+            id: "call_1".to_string(),
+            name: name.to_string(),
+            arguments,
+        }
+    }
+
+    #[test]
+    fn test_tool_attribute_descriptor() {
+        let tool = GetForecastTool::as_tool_descriptor();
+        assert_eq!(tool.name, "get_forecast");
+        assert_eq!(tool.description, "Current weather for a city");
+        assert_eq!(
+            tool.schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "city": { "type": "string", "description": "City name, e.g. \"Oslo\"" },
+                    "days": { "type": "integer", "description": "Days ahead" }
+                },
+                "required": ["city"],
+                "additionalProperties": false
+            })
+        );
+        assert_eq!(AddNumbersTool::tool_descriptor().name, "add");
+    }
+
+    #[test]
+    fn test_tool_attribute_executer() {
+        let forecast = GetForecastTool::as_tool_executer();
+        assert_eq!(
+            block_on(forecast(call(
+                "get_forecast",
+                json!({ "city": "Oslo", "days": 3 })
+            )))
+            .unwrap(),
+            "Oslo for 3 days"
+        );
+        // OpenAI sends arguments as a JSON string
+        assert_eq!(
+            block_on(forecast(call("get_forecast", json!(r#"{"city": "Oslo"}"#)))).unwrap(),
+            "Oslo for 1 days"
+        );
+        assert_eq!(
+            block_on(forecast(call("get_forecast", json!({ "city": "" }))))
+                .err()
+                .unwrap(),
+            "no city"
+        );
+        assert!(
+            block_on(forecast(call("get_forecast", json!({ "days": 2 }))))
+                .err()
+                .unwrap()
+                .starts_with("Invalid argument city for get_forecast")
+        );
+
+        let add = AddNumbersTool::as_tool_executer();
+        assert_eq!(
+            block_on(add(call("add", json!({ "a": 2, "b": 40 })))).unwrap(),
+            "42"
+        );
+    }
+}
@@ -1,6 +1,8 @@
 #[cfg(all(test, feature = "tokio-runtime"))]
 mod tests {
+    #[cfg(feature = "ollama")]
     use futures::StreamExt;
+    #[cfg(feature = "ollama")]
     use std::sync::{Arc, Mutex};
     use tokio;
 
@@ -17,16 +19,20 @@ mod tests {
             "You are a helpful, concise assistant. Keep your answers brief.".to_string();
         let adapter = ollama_adapter_factory(model_name, None);
 
-        let context = ContextBuilder { history: vec![] }
+        let context = ContextBuilder::new()
             .add_message(Message {
                 role: MessageRole::System,
                 content: system_message,
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             })
             .add_message(Message {
                 role: MessageRole::User,
                 content: "Explain quantum computing in 3 simple sentences.".to_string(),
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             });
 
         let response = context.send(adapter, 1000).await.resolve_without();
@@ -52,16 +58,20 @@ mod tests {
             "You are a helpful, concise assistant. Keep your answers brief.".to_string();
         let streaming_adapter = ollama_streaming_adapter_factory(model_name.clone(), None);
 
-        let context = ContextBuilder { history: vec![] }
+        let context = ContextBuilder::new()
             .add_message(Message {
                 role: MessageRole::System,
                 content: system_message.clone(),
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             })
             .add_message(Message {
                 role: MessageRole::User,
                 content: "Explain quantum computing in 3 simple sentences.".to_string(),
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             });
 
         // Test 1: Basic streaming with DIRECT stream consumption
@@ -134,4 +144,32 @@ mod tests {
         assert!(!final_content.is_empty(), "Callback should collect content");
         println!("Callback was called {} times", final_count);
     }
+
+    #[tokio::test]
+    #[cfg(feature = "ollama")]
+    async fn test_ollama_rejects_seed_wider_than_32_bits() {
+        let mut context = ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Pick a number".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        });
+        context.params.seed = Some(u64::from(u32::MAX));
+        let expected = format!(
+            "Ollama takes a seed of at most {}, got {}",
+            i32::MAX,
+            u32::MAX
+        );
+
+        // Rejected before any request reaches the server
+        let adapter = ollama_adapter_factory("llama3.2".to_string(), None);
+        let error = adapter(context.clone(), 50).await.err();
+        assert_eq!(error, Some(expected.clone()));
+
+        let adapter = ollama_streaming_adapter_factory("llama3.2".to_string(), None);
+        let deltas: Vec<_> = adapter(context, 50).collect().await;
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].as_ref().err(), Some(&expected));
+    }
 }
@@ -0,0 +1,245 @@
+#[cfg(all(test, feature = "openai"))]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    use async_openai::Client;
+    use async_openai::config::OpenAIConfig;
+    use async_openai::types::{Image, ImagesResponse};
+    use futures::executor::block_on;
+    use serde_json::{Value, json};
+    use steelwool::providers::mock::{mock_adapter_factory, mock_text_response};
+    use steelwool::providers::openai_images::{
+        ImageDelivery, ImageGenerationConfig, ImageGenerator, image_generation_executer,
+        image_generation_tool, openai_image_generator,
+    };
+    use steelwool::tool_loop::ParamsSchedule;
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, RequestParams,
+        StopReason, ToolCall,
+    };
+
+    /// Serves one HTTP request with `status` and `body`, returning the
+    /// request body it received
+    fn stub_server(status: &str, body: Value) -> (String, JoinHandle<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let status = status.to_string();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut request = vec![0; length];
+            reader.read_exact(&mut request).unwrap();
+
+            let body = body.to_string();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            serde_json::from_slice(&request).unwrap()
+        });
+        (base, server)
+    }
+
+    fn client(base: &str) -> Client<OpenAIConfig> {
+        Client::with_config(
+            OpenAIConfig::new()
+                .with_api_base(base)
+                .with_api_key("test-key"),
+        )
+    }
+
+    fn draw_call(arguments: &str) -> ToolCall {
+        ToolCall {
+            id: "call_1".to_string(),
+            name: "generate_image".to_string(),
+            arguments: Value::from(arguments),
+        }
+    }
+
+    #[test]
+    fn test_tool_descriptor() {
+        let tool = image_generation_tool(&ImageGenerationConfig {
+            model: "dall-e-2".to_string(),
+            ..ImageGenerationConfig::default()
+        });
+        assert_eq!(tool.name, "generate_image");
+        assert_eq!(tool.schema["required"], json!(["prompt"]));
+        assert_eq!(
+            tool.schema["properties"]["size"]["enum"],
+            json!(["256x256", "512x512", "1024x1024"])
+        );
+        assert!(tool.schema["properties"]["style"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_request_and_base64_response_mapping() {
+        let (base, server) = stub_server(
+            "200 OK",
+            json!({
+                "created": 1700000000,
+                "data": [{ "b64_json": "iVBORw0KGgo=", "revised_prompt": "A tidy diagram" }]
+            }),
+        );
+        let executer = image_generation_executer(
+            openai_image_generator(client(&base)),
+            ImageGenerationConfig {
+                delivery: ImageDelivery::Base64,
+                ..ImageGenerationConfig::default()
+            },
+        );
+
+        let result = executer(draw_call(
+            r#"{"prompt": "Architecture diagram", "size": "1792x1024", "style": "natural"}"#,
+        ))
+        .await
+        .unwrap();
+
+        let request = server.join().unwrap();
+        assert_eq!(request["prompt"], "Architecture diagram");
+        assert_eq!(request["model"], "dall-e-3");
+        assert_eq!(request["size"], "1792x1024");
+        assert_eq!(request["style"], "natural");
+        assert_eq!(request["response_format"], "b64_json");
+        assert_eq!(request["n"], 1);
+
+        let result: Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(
+            result,
+            json!({
+                "type": "image",
+                "mime_type": "image/png",
+                "b64_json": "iVBORw0KGgo=",
+                "revised_prompt": "A tidy diagram"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_content_policy_error_is_structured() {
+        let (base, server) = stub_server(
+            "400 Bad Request",
+            json!({
+                "error": {
+                    "message": "Your request was rejected as a result of our safety system.",
+                    "type": "invalid_request_error",
+                    "param": null,
+                    "code": "content_policy_violation"
+                }
+            }),
+        );
+        let executer = image_generation_executer(
+            openai_image_generator(client(&base)),
+            ImageGenerationConfig::default(),
+        );
+
+        let error = executer(draw_call(r#"{"prompt": "Something forbidden"}"#))
+            .await
+            .err()
+            .unwrap();
+        server.join().unwrap();
+
+        let error: Value = serde_json::from_str(&error).unwrap();
+        assert_eq!(
+            error,
+            json!({
+                "error": {
+                    "kind": "content_policy",
+                    "message": "Your request was rejected as a result of our safety system.",
+                    "retryable": false
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_size_fails_without_a_request() {
+        let never: ImageGenerator = Arc::new(|_| panic!("no request expected"));
+        let executer = image_generation_executer(never, ImageGenerationConfig::default());
+
+        let error = block_on(executer(draw_call(
+            r#"{"prompt": "A cat", "size": "256x256"}"#,
+        )))
+        .err()
+        .unwrap();
+        let error: Value = serde_json::from_str(&error).unwrap();
+        assert_eq!(error["error"]["kind"], "invalid_request");
+        assert_eq!(
+            error["error"]["message"],
+            "Size 256x256 isn't supported by dall-e-3; use one of 1024x1024, 1792x1024, 1024x1792"
+        );
+    }
+
+    #[test]
+    fn test_agent_loop_with_canned_image() {
+        let canned: ImageGenerator = Arc::new(|request| {
+            assert_eq!(request.prompt, "Architecture diagram");
+            Box::pin(async {
+                Ok(ImagesResponse {
+                    created: 0,
+                    data: vec![Arc::new(Image::Url {
+                        url: "https://images.example/diagram.png".to_string(),
+                        revised_prompt: None,
+                    })],
+                })
+            })
+        });
+        let executer = image_generation_executer(canned, ImageGenerationConfig::default());
+        let adapter = mock_adapter_factory(vec![
+            Ok(PromptResponse {
+                stop_reason: StopReason::ToolCalls,
+                tool_calls: Some(vec![draw_call(r#"{"prompt": "Architecture diagram"}"#)]),
+                ..mock_text_response("")
+            }),
+            Ok(mock_text_response("Here is your diagram.")),
+        ]);
+
+        let context = block_on(
+            ContextBuilder::new()
+                .add_message(Message {
+                    role: MessageRole::User,
+                    content: "Draw a diagram of this architecture".to_string(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                })
+                .send_tool_loop(
+                    adapter,
+                    executer,
+                    100,
+                    3,
+                    &ParamsSchedule::Constant(RequestParams::default()),
+                ),
+        )
+        .unwrap();
+
+        let tool_message = &context.history[2];
+        assert!(tool_message.role == MessageRole::Tool);
+        let result: Value = serde_json::from_str(tool_message.content.trim()).unwrap();
+        assert_eq!(
+            result,
+            json!({ "type": "image", "url": "https://images.example/diagram.png" })
+        );
+        assert_eq!(
+            context.history.last().unwrap().content,
+            "Here is your diagram."
+        );
+    }
+}
@@ -1,6 +1,8 @@
 #[cfg(all(test, feature = "tokio-runtime"))]
 mod tests {
+    #[cfg(feature = "openai")]
     use futures::StreamExt;
+    #[cfg(feature = "openai")]
     use std::sync::{Arc, Mutex};
     use tokio;
 
@@ -17,16 +19,20 @@ mod tests {
             "You are a helpful, concise assistant. Keep your answers brief.".to_string();
         let adapter = openai_adapter_factory(model_name, None);
 
-        let context = ContextBuilder { history: vec![] }
+        let context = ContextBuilder::new()
             .add_message(Message {
                 role: MessageRole::System,
                 content: system_message,
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             })
             .add_message(Message {
                 role: MessageRole::User,
                 content: "Explain quantum computing in 3 simple sentences.".to_string(),
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             });
 
         let response = context.send(adapter, 1000).await.resolve_without();
@@ -52,16 +58,20 @@ mod tests {
             "You are a helpful, concise assistant. Keep your answers brief.".to_string();
         let streaming_adapter = openai_streaming_adapter_factory(model_name.clone(), None);
 
-        let context = ContextBuilder { history: vec![] }
+        let context = ContextBuilder::new()
             .add_message(Message {
                 role: MessageRole::System,
                 content: system_message,
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             })
             .add_message(Message {
                 role: MessageRole::User,
                 content: "Explain quantum computing in 3 simple sentences.".to_string(),
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             });
 
         // Test 1: Basic streaming with DIRECT stream consumption
@@ -183,16 +193,20 @@ mod tests {
         let tools = Some(vec![weather_tool]);
         let adapter = openai_adapter_factory(model_name, tools);
 
-        let context = ContextBuilder { history: vec![] }
+        let context = ContextBuilder::new()
             .add_message(Message {
                 role: MessageRole::System,
                 content: system_message,
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             })
             .add_message(Message {
                 role: MessageRole::User,
                 content: "What's the weather like in Seattle?".to_string(),
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             });
 
         let response = context.send(adapter, 1000).await;
@@ -249,16 +263,20 @@ mod tests {
         let tools = Some(vec![weather_tool]);
         let streaming_adapter = openai_streaming_adapter_factory(model_name.clone(), tools);
 
-        let context = ContextBuilder { history: vec![] }
+        let context = ContextBuilder::new()
             .add_message(Message {
                 role: MessageRole::System,
                 content: system_message,
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             })
             .add_message(Message {
                 role: MessageRole::User,
                 content: "What's the weather like in Seattle?".to_string(),
                 content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
             });
 
         // Test 1: Basic streaming with DIRECT stream consumption
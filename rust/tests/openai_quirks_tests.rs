@@ -0,0 +1,235 @@
+#[cfg(all(test, feature = "openai"))]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    use async_openai::config::OpenAIConfig;
+    use futures::StreamExt;
+    use serde_json::{Value, json};
+    use steelwool::providers::mock::mock_message;
+    use steelwool::providers::openai::{
+        OpenAIAdapterOptions, QuirkPreset, QuirkProfile, build_chat_completion_request,
+        openai_adapter_factory_with_options, openai_streaming_adapter_factory_with_options,
+    };
+    use steelwool::tokens::{HeuristicTokenCounter, estimate_prompt_tokens};
+    use steelwool::{ContextBuilder, MessageRole, ToolDescriptor};
+
+    /// Serves one HTTP request with `content_type` and `body`, returning the
+    /// request body it received
+    fn stub_server(content_type: &str, body: String) -> (String, JoinHandle<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let content_type = content_type.to_string();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut request = vec![0; length];
+            reader.read_exact(&mut request).unwrap();
+
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            )
+            .unwrap();
+            serde_json::from_slice(&request).unwrap()
+        });
+        (base, server)
+    }
+
+    fn conversation() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(MessageRole::System, "Be brief."))
+            .add_message(mock_message(MessageRole::User, "Hi"))
+            .add_message(mock_message(MessageRole::System, "Answer in English."))
+    }
+
+    fn tools() -> Option<Vec<ToolDescriptor>> {
+        Some(vec![ToolDescriptor::from_json_schema(
+            "get_time".to_string(),
+            "Current time".to_string(),
+            json!({ "type": "object", "properties": {}, "additionalProperties": false }),
+        )])
+    }
+
+    fn body(model: &str, quirks: QuirkProfile, stream: bool) -> Value {
+        let options = OpenAIAdapterOptions {
+            quirks,
+            ..OpenAIAdapterOptions::default()
+        };
+        build_chat_completion_request(model, &conversation(), tools(), 256, &options, stream)
+            .unwrap()
+    }
+
+    fn options(base: &str, quirks: QuirkProfile) -> OpenAIAdapterOptions {
+        OpenAIAdapterOptions {
+            quirks,
+            client_config: Some(
+                OpenAIConfig::new()
+                    .with_api_base(base)
+                    .with_api_key("test-key"),
+            ),
+            ..OpenAIAdapterOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_generic_preset_matches_openai() {
+        let generic = QuirkPreset::Generic.into();
+        let streamed = body("gpt-4o", generic, true);
+        assert_eq!(streamed["stream_options"], json!({ "include_usage": true }));
+        assert_eq!(streamed["max_tokens"], 256);
+        assert_eq!(streamed["tools"][0]["function"]["strict"], true);
+        assert_eq!(streamed["messages"].as_array().unwrap().len(), 3);
+
+        let reasoning = body("o3-mini", generic, false);
+        assert_eq!(reasoning["max_completion_tokens"], 256);
+        assert!(reasoning.get("max_tokens").is_none());
+    }
+
+    #[test]
+    fn test_lm_studio_preset() {
+        let streamed = body("qwen2.5-7b", QuirkPreset::LmStudio.into(), true);
+        assert_eq!(streamed["stream"], true);
+        assert!(streamed.get("stream_options").is_none());
+        assert!(streamed["tools"][0]["function"].get("strict").is_none());
+        assert_eq!(streamed["messages"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_vllm_preset() {
+        let reasoning = body("o1-mini", QuirkPreset::Vllm.into(), false);
+        assert_eq!(reasoning["max_tokens"], 256);
+        assert!(reasoning.get("max_completion_tokens").is_none());
+        assert_eq!(reasoning["tools"][0]["function"]["strict"], true);
+    }
+
+    #[test]
+    fn test_llama_cpp_server_preset() {
+        let request = body("llama3.1-8b", QuirkPreset::LlamaCppServer.into(), true);
+        assert_eq!(
+            request["messages"],
+            json!([
+                { "role": "system", "content": "Be brief.\n\nAnswer in English." },
+                { "role": "user", "content": "Hi" }
+            ])
+        );
+        assert!(request["tools"][0]["function"].get("strict").is_none());
+        assert_eq!(request["stream_options"], json!({ "include_usage": true }));
+    }
+
+    #[test]
+    fn test_profiles_compose() {
+        let quirks = QuirkProfile::from(QuirkPreset::LmStudio).union(QuirkPreset::Vllm.into());
+        assert_eq!(
+            quirks,
+            QuirkProfile {
+                omit_stream_options: true,
+                force_max_tokens_field: true,
+                no_strict_tools: true,
+                ..QuirkProfile::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_usage_synthesized_when_server_reports_none() {
+        let completion = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "local",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hello there, friend" },
+                "finish_reason": "stop",
+                "logprobs": null
+            }]
+        })
+        .to_string();
+        let quirks = QuirkProfile {
+            synthesize_usage_from_content: true,
+            ..QuirkProfile::default()
+        };
+
+        let (base, server) = stub_server("application/json", completion.clone());
+        let adapter = openai_adapter_factory_with_options(
+            "local".to_string(),
+            None,
+            options(&base, QuirkProfile::default()),
+        );
+        let response = adapter(conversation(), 256).await.unwrap();
+        server.join().unwrap();
+        assert_eq!(response.token_usage, 0);
+
+        let (base, server) = stub_server("application/json", completion);
+        let adapter =
+            openai_adapter_factory_with_options("local".to_string(), None, options(&base, quirks));
+        let response = adapter(conversation(), 256).await.unwrap();
+        server.join().unwrap();
+
+        // The prompt estimate plus 5 tokens out
+        let prompt = estimate_prompt_tokens(&conversation(), &[], &HeuristicTokenCounter);
+        assert_eq!(response.token_usage as usize, prompt + 5);
+        assert_eq!(
+            response.provenance.details_from("usage"),
+            vec!["estimated from content; server reported none"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamed_usage_synthesized_without_usage_chunk() {
+        let chunk = |delta: Value, finish_reason: Value| {
+            json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "local",
+                "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+            })
+        };
+        let events = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk(
+                json!({ "role": "assistant", "content": "Hello there, friend" }),
+                Value::Null
+            ),
+            chunk(json!({}), json!("stop"))
+        );
+        let (base, server) = stub_server("text/event-stream", events);
+        let quirks = QuirkProfile::from(QuirkPreset::LmStudio).union(QuirkProfile {
+            synthesize_usage_from_content: true,
+            ..QuirkProfile::default()
+        });
+        let adapter = openai_streaming_adapter_factory_with_options(
+            "local".to_string(),
+            None,
+            options(&base, quirks),
+        );
+
+        let deltas: Vec<_> = adapter(conversation(), 256)
+            .map(|delta| delta.unwrap())
+            .collect()
+            .await;
+        let request = server.join().unwrap();
+
+        assert!(request.get("stream_options").is_none());
+        let content: String = deltas.iter().map(|d| d.content.as_str()).collect();
+        assert_eq!(content, "Hello there, friend");
+        assert_eq!(deltas.last().unwrap().cumulative_tokens, 5);
+    }
+}
@@ -0,0 +1,506 @@
+#[cfg(all(test, feature = "openai"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures::executor::block_on;
+    use serde_json::json;
+    use steelwool::providers::mock::{
+        mock_adapter_factory, mock_message, mock_text_response, stub_tool_executer,
+    };
+    use steelwool::providers::openai::{
+        LogprobOptions, OpenAIAdapterOptions, PrefillPolicy, QuirkProfile, RoleMapping, RoleTarget,
+        UnsupportedRolePolicy, build_chat_completion_message_history,
+        build_chat_completion_request, map_logprobs, reproducibility_info,
+    };
+    use steelwool::{ContextBuilder, Message, MessageRole, PromptResponse, StopReason, ToolCall};
+
+    fn tool_conversation() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(MessageRole::System, "Be brief."))
+            .add_message(mock_message(MessageRole::User, "Weather in Seattle?"))
+            .add_message(mock_message(MessageRole::Model, "Checking."))
+            .add_message(mock_message(MessageRole::Tool, "52F and raining"))
+    }
+
+    fn bot_human_mapping(policy: UnsupportedRolePolicy) -> RoleMapping {
+        RoleMapping {
+            user: RoleTarget::send("human"),
+            model: RoleTarget::send("bot"),
+            tool: RoleTarget::Unsupported(policy.clone()),
+            function: RoleTarget::Unsupported(policy),
+            ..RoleMapping::default()
+        }
+    }
+
+    fn roles_and_contents(messages: &[serde_json::Value]) -> Vec<(String, String)> {
+        messages
+            .iter()
+            .map(|m| {
+                (
+                    m["role"].as_str().unwrap().to_string(),
+                    m["content"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_default_mapping_matches_openai_roles() {
+        let messages =
+            build_chat_completion_message_history(&tool_conversation(), &RoleMapping::default())
+                .unwrap();
+
+        assert_eq!(
+            roles_and_contents(&messages),
+            vec![
+                ("system".to_string(), "Be brief.".to_string()),
+                ("user".to_string(), "Weather in Seattle?".to_string()),
+                ("assistant".to_string(), "Checking.".to_string()),
+                ("tool".to_string(), "52F and raining".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_result_message_carries_call_id() {
+        let context =
+            tool_conversation().add_tool_result_message("call_7", "timeout".to_string(), true);
+        let messages =
+            build_chat_completion_message_history(&context, &RoleMapping::default()).unwrap();
+
+        assert_eq!(
+            messages[4],
+            json!({ "role": "tool", "content": "timeout", "tool_call_id": "call_7" })
+        );
+    }
+
+    #[test]
+    fn test_resolved_tool_calls_each_get_a_tool_message() {
+        let calls = vec![
+            ToolCall {
+                id: "call_1".to_string(),
+                name: "get_weather".to_string(),
+                arguments: json!(r#"{"city":"Seattle"}"#),
+            },
+            ToolCall {
+                id: "call_2".to_string(),
+                name: "get_time".to_string(),
+                arguments: json!({ "zone": "PST" }),
+            },
+        ];
+        let mut response = mock_text_response("");
+        response.message.assistant_tool_calls = Some(calls.clone());
+        let adapter = mock_adapter_factory(vec![Ok(PromptResponse {
+            stop_reason: StopReason::ToolCalls,
+            tool_calls: Some(calls),
+            ..response
+        })]);
+        let executer = stub_tool_executer(HashMap::from([(
+            "get_weather".to_string(),
+            "52F".to_string(),
+        )]));
+
+        let context = block_on(async {
+            ContextBuilder::new()
+                .add_message(mock_message(MessageRole::User, "Weather in Seattle?"))
+                .send(adapter, 100)
+                .await
+                .resolve(executer)
+                .await
+        });
+        let messages =
+            build_chat_completion_message_history(&context, &RoleMapping::default()).unwrap();
+
+        let call_ids: Vec<&str> = messages[1]["tool_calls"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|call| call["id"].as_str().unwrap())
+            .collect();
+        let result_ids: Vec<&str> = messages[2..]
+            .iter()
+            .map(|m| {
+                assert_eq!(m["role"], "tool");
+                m["tool_call_id"].as_str().unwrap()
+            })
+            .collect();
+        assert_eq!(call_ids, vec!["call_1", "call_2"]);
+        assert_eq!(result_ids, call_ids);
+        assert_eq!(messages[2]["content"], "52F");
+        assert!(
+            messages[3]["content"]
+                .as_str()
+                .unwrap()
+                .contains("unknown tool: get_time")
+        );
+    }
+
+    #[test]
+    fn test_assistant_message_carries_tool_calls() {
+        let calls = vec![
+            ToolCall {
+                id: "call_1".to_string(),
+                name: "get_weather".to_string(),
+                arguments: json!(r#"{"city":"Seattle"}"#),
+            },
+            ToolCall {
+                id: "call_2".to_string(),
+                name: "get_time".to_string(),
+                arguments: json!({ "zone": "PST" }),
+            },
+        ];
+        let mut checking = mock_message(MessageRole::Model, "Checking.");
+        checking.assistant_tool_calls = Some(calls.clone());
+        let mut silent = mock_message(MessageRole::Model, "");
+        silent.assistant_tool_calls = Some(calls);
+        let context = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::User, "Weather in Seattle?"))
+            .add_message(checking)
+            .add_tool_result_message("call_1", "52F".to_string(), false)
+            .add_tool_result_message("call_2", "9am".to_string(), false)
+            .add_message(silent);
+
+        let messages =
+            build_chat_completion_message_history(&context, &RoleMapping::default()).unwrap();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[1]["content"], "Checking.");
+        assert_eq!(
+            messages[1]["tool_calls"],
+            json!([
+                {
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": r#"{"city":"Seattle"}"# },
+                },
+                {
+                    "id": "call_2",
+                    "type": "function",
+                    "function": { "name": "get_time", "arguments": r#"{"zone":"PST"}"# },
+                },
+            ])
+        );
+        assert!(messages[4].get("content").is_none_or(|c| c.is_null()));
+        assert_eq!(messages[4]["tool_calls"].as_array().unwrap().len(), 2);
+
+        // Plain assistant messages don't grow a tool_calls field
+        let plain =
+            build_chat_completion_message_history(&tool_conversation(), &RoleMapping::default())
+                .unwrap();
+        assert!(plain[2].get("tool_calls").is_none());
+    }
+
+    #[test]
+    fn test_bot_human_mapping_flattens_tool_output() {
+        let mapping = bot_human_mapping(UnsupportedRolePolicy::FlattenIntoUserText);
+        let messages =
+            build_chat_completion_message_history(&tool_conversation(), &mapping).unwrap();
+
+        assert_eq!(
+            roles_and_contents(&messages),
+            vec![
+                ("system".to_string(), "Be brief.".to_string()),
+                ("human".to_string(), "Weather in Seattle?".to_string()),
+                ("bot".to_string(), "Checking.".to_string()),
+                (
+                    "human".to_string(),
+                    "Tool result: 52F and raining".to_string()
+                ),
+            ]
+        );
+        assert!(messages[3].get("tool_call_id").is_none());
+    }
+
+    #[test]
+    fn test_bot_human_mapping_remaps_tool_role() {
+        let mapping = bot_human_mapping(UnsupportedRolePolicy::RemapTo(MessageRole::Model));
+        let messages =
+            build_chat_completion_message_history(&tool_conversation(), &mapping).unwrap();
+
+        assert_eq!(
+            roles_and_contents(&messages)[3],
+            ("bot".to_string(), "52F and raining".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bot_human_mapping_errors_on_tool_role() {
+        let mapping = bot_human_mapping(UnsupportedRolePolicy::Error);
+        let result = build_chat_completion_message_history(&tool_conversation(), &mapping);
+
+        assert_eq!(
+            result.unwrap_err(),
+            "Backend does not accept Tool result messages"
+        );
+    }
+
+    #[test]
+    fn test_remap_cycle_is_an_error() {
+        let mapping = RoleMapping {
+            tool: RoleTarget::Unsupported(UnsupportedRolePolicy::RemapTo(MessageRole::Function)),
+            function: RoleTarget::Unsupported(UnsupportedRolePolicy::RemapTo(MessageRole::Tool)),
+            ..RoleMapping::default()
+        };
+
+        assert!(build_chat_completion_message_history(&tool_conversation(), &mapping).is_err());
+    }
+
+    #[test]
+    fn test_request_body_uses_mapping() {
+        let options = OpenAIAdapterOptions {
+            role_mapping: bot_human_mapping(UnsupportedRolePolicy::FlattenIntoUserText),
+            ..OpenAIAdapterOptions::default()
+        };
+        let body = build_chat_completion_request(
+            "local-model",
+            &tool_conversation(),
+            None,
+            256,
+            &options,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(body["model"], json!("local-model"));
+        assert_eq!(body["stream"], json!(true));
+        assert_eq!(body["messages"][1]["role"], json!("human"));
+        assert_eq!(body["messages"][2]["role"], json!("bot"));
+    }
+
+    #[test]
+    fn test_prefill_policy() {
+        let context = tool_conversation().with_prefill("{");
+
+        let prepend = OpenAIAdapterOptions::default();
+        let body =
+            build_chat_completion_request("gpt-4o", &context, None, 256, &prepend, false).unwrap();
+        assert_eq!(body["messages"].as_array().unwrap().len(), 4);
+
+        let strict = OpenAIAdapterOptions {
+            prefill_policy: PrefillPolicy::Error,
+            ..OpenAIAdapterOptions::default()
+        };
+        assert_eq!(
+            build_chat_completion_request("gpt-4o", &context, None, 256, &strict, false)
+                .unwrap_err(),
+            "OpenAI chat completions do not support assistant prefill"
+        );
+        assert!(
+            build_chat_completion_request(
+                "gpt-4o",
+                &tool_conversation(),
+                None,
+                256,
+                &strict,
+                false
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_sampling_params_reach_the_body() {
+        let mut context = tool_conversation();
+        context.params.temperature = Some(0.25);
+
+        let options = OpenAIAdapterOptions::default();
+        let body =
+            build_chat_completion_request("gpt-4o", &context, None, 256, &options, false).unwrap();
+        assert_eq!(body["temperature"], json!(0.25));
+        assert!(body.get("top_p").is_none());
+        assert!(body.get("response_format").is_none());
+
+        context.params.json_mode = true;
+        let body =
+            build_chat_completion_request("gpt-4o", &context, None, 256, &options, false).unwrap();
+        assert_eq!(body["response_format"], json!({ "type": "json_object" }));
+    }
+
+    #[test]
+    fn test_seed_and_fingerprint_capture() {
+        let mut context = tool_conversation();
+        let options = OpenAIAdapterOptions::default();
+        let body =
+            build_chat_completion_request("gpt-4o", &context, None, 256, &options, false).unwrap();
+        assert!(body.get("seed").is_none());
+
+        context.params.seed = Some(42);
+        let body =
+            build_chat_completion_request("gpt-4o", &context, None, 256, &options, false).unwrap();
+        assert_eq!(body["seed"], json!(42));
+
+        let fixture = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4o",
+            "system_fingerprint": "fp_44709d6fcb",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi"},
+                "finish_reason": "stop"
+            }]
+        });
+        let parsed: async_openai::types::CreateChatCompletionResponse =
+            serde_json::from_value(fixture).unwrap();
+        let info = reproducibility_info(
+            Some(42),
+            parsed.system_fingerprint.clone(),
+            &QuirkProfile::default(),
+        );
+        assert_eq!(info.fingerprint.as_deref(), Some("fp_44709d6fcb"));
+        assert!(info.backend_claims_determinism);
+
+        let ignoring = QuirkProfile {
+            ignores_seed: true,
+            ..QuirkProfile::default()
+        };
+        assert!(!reproducibility_info(Some(42), None, &ignoring).backend_claims_determinism);
+        assert!(
+            !reproducibility_info(None, parsed.system_fingerprint, &QuirkProfile::default())
+                .backend_claims_determinism
+        );
+    }
+
+    #[test]
+    fn test_max_tokens_policy_clamps_request_body() {
+        let options = OpenAIAdapterOptions::default();
+        let (max_tokens, clamped) = options.max_tokens.resolve("gpt-4-turbo", 9_000).unwrap();
+
+        let body = build_chat_completion_request(
+            "gpt-4-turbo",
+            &tool_conversation(),
+            None,
+            max_tokens,
+            &options,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(body["max_tokens"], json!(4096));
+        assert!(clamped.unwrap().starts_with("clamped max_tokens 9000"));
+    }
+
+    fn logprob_token(token: &str, logprob: f32) -> serde_json::Value {
+        json!({
+            "token": token,
+            "logprob": logprob,
+            "bytes": token.as_bytes(),
+            "top_logprobs": [
+                {"token": token, "logprob": logprob, "bytes": null},
+                {"token": " London", "logprob": -3.5, "bytes": null}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_logprobs_fixture_and_spans() {
+        let options = OpenAIAdapterOptions {
+            logprobs: Some(LogprobOptions { top_logprobs: 2 }),
+            ..OpenAIAdapterOptions::default()
+        };
+        let body = build_chat_completion_request(
+            "gpt-4o",
+            &tool_conversation(),
+            None,
+            50,
+            &options,
+            false,
+        )
+        .unwrap();
+        assert_eq!(body["logprobs"], json!(true));
+        assert_eq!(body["top_logprobs"], json!(2));
+
+        let fixture = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "The capital is Paris."},
+                "finish_reason": "stop",
+                "logprobs": {
+                    "content": [
+                        logprob_token("The", -0.01),
+                        logprob_token(" capital", -0.02),
+                        logprob_token(" is", -0.05),
+                        logprob_token(" Par", -2.25),
+                        logprob_token("is", -1.5),
+                        logprob_token(".", -0.5)
+                    ],
+                    "refusal": null
+                }
+            }]
+        });
+        let parsed: async_openai::types::CreateChatCompletionResponse =
+            serde_json::from_value(fixture).unwrap();
+        let logprobs = map_logprobs(parsed.choices[0].logprobs.as_ref().unwrap()).unwrap();
+        assert_eq!(logprobs.len(), 6);
+        assert_eq!(
+            logprobs[3].top_alternatives[1],
+            (" London".to_string(), -3.5)
+        );
+
+        let response = PromptResponse {
+            logprobs: Some(logprobs),
+            ..mock_text_response("The capital is Paris.")
+        };
+        assert_eq!(response.low_confidence_spans(-1.0), vec![14..20]);
+        assert_eq!(&response.message.content[14..20], " Paris");
+        assert_eq!(response.low_confidence_spans(-0.03), vec![11..21]);
+        assert!((response.mean_token_logprob().unwrap() - (-4.33 / 6.0)).abs() < 1e-5);
+
+        // A prefill prepended by the send methods doesn't shift the spans
+        let prefilled = PromptResponse {
+            message: Message {
+                content: format!(">> {}", response.message.content),
+                ..response.message.clone()
+            },
+            ..response
+        };
+        assert_eq!(prefilled.low_confidence_spans(-1.0), vec![17..23]);
+        assert!(mock_text_response("x").mean_token_logprob().is_none());
+    }
+
+    #[test]
+    fn test_streaming_logprobs_fixture() {
+        let options = OpenAIAdapterOptions {
+            logprobs: Some(LogprobOptions { top_logprobs: 2 }),
+            ..OpenAIAdapterOptions::default()
+        };
+        let body =
+            build_chat_completion_request("gpt-4o", &tool_conversation(), None, 50, &options, true)
+                .unwrap();
+        assert_eq!(body["stream"], json!(true));
+        assert_eq!(body["top_logprobs"], json!(2));
+
+        let chunk = |content: &str, tokens: Vec<serde_json::Value>| {
+            let chunk: async_openai::types::CreateChatCompletionStreamResponse =
+                serde_json::from_value(json!({
+                    "id": "chatcmpl-1",
+                    "object": "chat.completion.chunk",
+                    "created": 0,
+                    "model": "gpt-4o",
+                    "choices": [{
+                        "index": 0,
+                        "delta": {"content": content},
+                        "finish_reason": null,
+                        "logprobs": {"content": tokens, "refusal": null}
+                    }]
+                }))
+                .unwrap();
+            chunk.choices[0].logprobs.as_ref().and_then(map_logprobs)
+        };
+        let first = chunk(
+            "The capital",
+            vec![
+                logprob_token("The", -0.01),
+                logprob_token(" capital", -0.02),
+            ],
+        )
+        .unwrap();
+        let second = chunk(" Paris", vec![logprob_token(" Paris", -2.25)]).unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(second[0].top_alternatives[1], (" London".to_string(), -3.5));
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use steelwool::persistence::{FORMAT_VERSION, MigrationReport};
+    use steelwool::store::{ConversationStore, InMemoryConversationStore};
+    use steelwool::{ContentType, ContextBuilder, Message, MessageRole};
+
+    /// Bare `ContextBuilder` JSON as written before the envelope and
+    /// `content_type` existed, with a field this version never had
+    const OLD_FORMAT: &str = r#"{
+        "history": [
+            { "role": "User", "content": "Hi", "timestamp": 1700000000 },
+            { "role": "Model", "content": "Hello!", "timestamp": 1700000001 }
+        ],
+        "session": "abc"
+    }"#;
+
+    fn conversation() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(Message {
+                role: MessageRole::User,
+                content: "Hi".to_string(),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            })
+            .add_message(Message {
+                role: MessageRole::Model,
+                content: "Hello!".to_string(),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            })
+    }
+
+    #[test]
+    fn test_current_format_loads_clean() {
+        let json = conversation()
+            .save_branch("draft")
+            .to_versioned_json()
+            .unwrap();
+
+        let (context, report) = ContextBuilder::load_with_report(&json).unwrap();
+
+        assert_eq!(report, MigrationReport::current());
+        assert!(report.is_clean());
+        assert!(context.history == conversation().history);
+        assert_eq!(context.branch_names(), vec!["draft"]);
+    }
+
+    #[test]
+    fn test_old_format_reports_defaults_and_ignored_fields() {
+        let (context, report) = ContextBuilder::load_with_report(OLD_FORMAT).unwrap();
+
+        assert!(context.history == conversation().history);
+        assert_eq!(
+            report,
+            MigrationReport {
+                source_version: 0,
+                defaulted_fields: vec!["history[].content_type".to_string()],
+                ignored_fields: vec!["history[].timestamp".to_string(), "session".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_envelope_paths_and_version_checks() {
+        let json = format!(
+            r#"{{"format_version": 1, "context": {}, "written_by": "0.0.9"}}"#,
+            OLD_FORMAT
+        );
+        let (_, report) = ContextBuilder::load_with_report(&json).unwrap();
+        assert_eq!(report.source_version, 1);
+        assert_eq!(
+            report.defaulted_fields,
+            vec!["context.history[].content_type"]
+        );
+        assert_eq!(
+            report.ignored_fields,
+            vec![
+                "context.history[].timestamp",
+                "context.session",
+                "written_by"
+            ]
+        );
+
+        let future = format!(
+            r#"{{"format_version": {}, "context": {{"history": []}}}}"#,
+            FORMAT_VERSION + 1
+        );
+        let error = ContextBuilder::load_with_report(&future).err().unwrap();
+        assert!(error.contains("newer than supported"));
+    }
+
+    #[test]
+    fn test_store_load_surfaces_a_report() {
+        let store: Arc<dyn ConversationStore> = Arc::new(InMemoryConversationStore::new());
+        store.save("chat", &conversation()).unwrap();
+
+        let (context, report) = store.load_with_report("chat").unwrap().unwrap();
+        assert_eq!(context.len(), 2);
+        assert_eq!(report, MigrationReport::current());
+        assert!(store.load_with_report("missing").unwrap().is_none());
+    }
+}
@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use steelwool::pinning::PINNED_EXCEED_BUDGET;
+    use steelwool::providers::mock::mock_message;
+    use steelwool::store::InMemoryConversationStore;
+    use steelwool::tokens::{HeuristicTokenCounter, MESSAGE_OVERHEAD_TOKENS};
+    use steelwool::{ContentType, ContextBuilder, Message, MessageRole};
+
+    /// Each message costs 1 token of content plus the framing overhead
+    const COST: usize = 1 + MESSAGE_OVERHEAD_TOKENS;
+
+    fn message(i: usize) -> Message {
+        let role = match i % 2 {
+            0 => MessageRole::User,
+            _ => MessageRole::Model,
+        };
+        mock_message(role, &i.to_string())
+    }
+
+    fn numbered(n: usize) -> ContextBuilder {
+        (0..n).fold(ContextBuilder::new(), |ctx, i| ctx.add_message(message(i)))
+    }
+
+    fn contents(context: &ContextBuilder) -> Vec<&str> {
+        context.history.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_pins_straddling_the_boundary_survive() {
+        // Room for 5 messages: pins 1 and 4 plus the newest 3 unpinned
+        let context = numbered(8)
+            .pin_message(1)
+            .pin_message(4)
+            .truncate_to_fit(5 * COST, &HeuristicTokenCounter)
+            .unwrap();
+        assert_eq!(contents(&context), vec!["1", "4", "5", "6", "7"]);
+        assert!(context.history[0].pinned && context.history[1].pinned);
+
+        let context = numbered(4)
+            .pin_last()
+            .truncate_to_fit(2 * COST, &HeuristicTokenCounter)
+            .unwrap();
+        assert_eq!(contents(&context), vec!["2", "3"]);
+    }
+
+    #[test]
+    fn test_pins_over_budget_is_an_error() {
+        let error = numbered(5)
+            .pin_message(0)
+            .pin_message(1)
+            .pin_message(2)
+            .truncate_to_fit(2 * COST, &HeuristicTokenCounter)
+            .err()
+            .unwrap();
+        assert!(error.starts_with(PINNED_EXCEED_BUDGET));
+        assert_eq!(
+            error,
+            format!(
+                "{}: pinned messages need {} tokens, over the budget of {}",
+                PINNED_EXCEED_BUDGET,
+                3 * COST,
+                2 * COST
+            )
+        );
+    }
+
+    #[test]
+    fn test_truncate_to_last_n_skips_pinned_messages() {
+        let context = numbered(8).pin_message(2).truncate_to_last_n(3);
+        assert_eq!(contents(&context), vec!["2", "5", "6", "7"]);
+
+        assert_eq!(numbered(3).truncate_to_last_n(10).len(), 3);
+        assert_eq!(
+            contents(&numbered(3).truncate_to_last_n(0)),
+            Vec::<&str>::new()
+        );
+    }
+
+    #[test]
+    fn test_system_messages_are_pinned_on_add() {
+        let system = Message {
+            role: MessageRole::System,
+            ..message(0)
+        };
+        let context = ContextBuilder::new()
+            .add_message(system)
+            .add_message(message(1))
+            .add_message(message(2))
+            .truncate_to_last_n(1);
+
+        assert_eq!(contents(&context), vec!["0", "2"]);
+        assert!(context.history[0].pinned);
+        assert!(!context.history[1].pinned);
+    }
+
+    #[test]
+    fn test_bulk_pinning() {
+        let pinned = |context: &ContextBuilder| -> Vec<bool> {
+            context.history.iter().map(|m| m.pinned).collect()
+        };
+
+        let context = numbered(4).pin_by_role(MessageRole::Model);
+        assert_eq!(pinned(&context), vec![false, true, false, true]);
+
+        let context = context.unpin_all();
+        assert_eq!(pinned(&context), vec![false; 4]);
+
+        let context = context.pin_all();
+        assert_eq!(pinned(&context), vec![true; 4]);
+        assert_eq!(context.truncate_to_last_n(1).len(), 4);
+    }
+
+    #[test]
+    fn test_spill_keeps_pinned_messages_in_memory() {
+        let store = Arc::new(InMemoryConversationStore::new());
+        let context = (0..10).fold(
+            ContextBuilder::new().with_backing_store(store, 4),
+            |ctx, i| match i {
+                2 => ctx.add_message(message(i)).pin_last(),
+                _ => ctx.add_message(message(i)),
+            },
+        );
+
+        // Marker, the pinned message, then the newest unpinned messages
+        assert!(matches!(
+            context.history[0].content_type,
+            ContentType::ArchivedRange(_)
+        ));
+        assert_eq!(contents(&context)[1..], ["2", "7", "8", "9"]);
+
+        let hydrated = context.hydrate().unwrap();
+        assert_eq!(
+            contents(&hydrated),
+            vec!["0", "1", "3", "4", "5", "6", "2", "7", "8", "9"]
+        );
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    #[test]
+    fn test_compaction_keeps_pinned_messages() {
+        use futures::executor::block_on;
+        use steelwool::compactor::CompactionPolicy;
+        use steelwool::providers::mock::{mock_adapter_factory, mock_text_response};
+
+        let policy = CompactionPolicy {
+            keep_recent: 2,
+            ..CompactionPolicy::default()
+        };
+        let summarizer = mock_adapter_factory(vec![Ok(mock_text_response("earlier turns"))]);
+        let compacted = block_on(policy.compact(&numbered(8).pin_message(3), summarizer)).unwrap();
+
+        assert_eq!(compacted.len(), 4);
+        assert!(compacted.history[0].content.ends_with("earlier turns"));
+        assert_eq!(contents(&compacted)[1..], ["3", "6", "7"]);
+    }
+}
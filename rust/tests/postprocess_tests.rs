@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use serde_json::json;
+    use steelwool::postprocess::{as_markdown_table, numbered_list, post_process_results};
+    use steelwool::sandbox::{SandboxPolicy, SandboxRun, sandbox};
+    use steelwool::{ToolCall, ToolExecuter};
+
+    fn call(name: &str) -> ToolCall {
+        ToolCall {
+            id: "call_1".to_string(),
+            name: name.to_string(),
+            arguments: json!({}),
+        }
+    }
+
+    /// Answers each tool with the raw output registered for its name
+    fn raw(outputs: &[(&str, &str)]) -> ToolExecuter {
+        let outputs: HashMap<String, String> = outputs
+            .iter()
+            .map(|(name, output)| (name.to_string(), output.to_string()))
+            .collect();
+        Arc::new(move |call: ToolCall| {
+            let output = outputs[&call.name].clone();
+            Box::pin(async move { Ok(output) })
+        })
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let table = as_markdown_table(2)(json!({
+            "rows": [
+                { "id": 1, "name": "Ada" },
+                { "id": 2, "name": "Grace | Hopper", "team": null },
+                { "id": 3, "name": "Alan" }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            table,
+            "| id | name | team |\n\
+             | --- | --- | --- |\n\
+             | 1 | Ada |  |\n\
+             | 2 | Grace \\| Hopper | null |\n\
+             \n_1 more rows not shown_\n"
+        );
+        assert_eq!(as_markdown_table(5)(json!([])).unwrap(), "_No rows_");
+        assert!(as_markdown_table(5)(json!("text")).is_err());
+    }
+
+    #[test]
+    fn test_numbered_list_dedups() {
+        let list = numbered_list("title", "url")(json!([
+            { "title": "Edition guide", "url": "https://a.example" },
+            { "title": "Edition guide (mirror)", "url": "https://a.example" },
+            { "title": "Release notes", "url": "https://b.example" },
+            { "url": "https://c.example" }
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            list,
+            "1. Edition guide (https://a.example)\n\
+             2. Release notes (https://b.example)\n\
+             3. {\"url\":\"https://c.example\"}"
+        );
+    }
+
+    #[test]
+    fn test_per_tool_processing_and_non_json_bypass() {
+        let warnings = Arc::new(Mutex::new(vec![]));
+        let sink = warnings.clone();
+        let executer = post_process_results(
+            raw(&[
+                ("run_sql", r#"[{"n": 1}]"#),
+                ("search", "no results, try again"),
+                ("clock", r#"{"time": "14:00"}"#),
+            ]),
+            HashMap::from([
+                ("run_sql".to_string(), as_markdown_table(10)),
+                ("search".to_string(), numbered_list("title", "url")),
+            ]),
+            Some(Arc::new(move |call: &ToolCall, warning: &str| {
+                sink.lock()
+                    .unwrap()
+                    .push(format!("{}: {}", call.name, warning))
+            })),
+        );
+
+        assert_eq!(
+            block_on(executer(call("run_sql"))).unwrap(),
+            "| n |\n| --- |\n| 1 |\n"
+        );
+        assert_eq!(
+            block_on(executer(call("clock"))).unwrap(),
+            r#"{"time": "14:00"}"#
+        );
+        assert_eq!(
+            block_on(executer(call("search"))).unwrap(),
+            "no results, try again"
+        );
+
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("search: Output isn't JSON, so it wasn't post-processed"));
+    }
+
+    #[test]
+    fn test_size_limit_applies_to_processed_output() {
+        let rows: Vec<_> = (0..50).map(|i| json!({ "id": i })).collect();
+        let rows = serde_json::to_string(&rows).unwrap();
+        let policy = SandboxPolicy {
+            max_result_bytes: Some(100),
+            ..SandboxPolicy::default()
+        };
+        let processed = |max_rows| {
+            post_process_results(
+                raw(&[("run_sql", rows.as_str())]),
+                HashMap::from([("run_sql".to_string(), as_markdown_table(max_rows))]),
+                None,
+            )
+        };
+
+        // 3 rows fit in 100 bytes; all 50 don't
+        let run = SandboxRun::new();
+        let table = block_on(sandbox(processed(3), policy.clone(), &run)(call("run_sql"))).unwrap();
+        assert!(table.ends_with("_47 more rows not shown_\n"));
+
+        let error = block_on(sandbox(processed(50), policy, &run)(call("run_sql"))).unwrap_err();
+        assert!(error.starts_with("Sandbox violation (max_result_bytes)"));
+    }
+}
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+    use futures::executor::block_on;
+    use steelwool::providers::mock::{
+        mock_adapter_factory, mock_streaming_adapter_factory, mock_text_response,
+    };
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, ProviderAdapter,
+    };
+
+    fn question() -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Give me the weather as JSON".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    /// Replies with the continuation and remembers the prefill it was sent
+    fn continuing_adapter(seen: Arc<Mutex<Option<String>>>) -> ProviderAdapter {
+        Arc::new(move |context: ContextBuilder, _| {
+            *seen.lock().unwrap() = context.params.prefill.clone();
+            Box::pin(async { Ok(mock_text_response("\"temp\": 12}")) })
+        })
+    }
+
+    #[test]
+    fn test_prefill_is_part_of_the_reply() {
+        let seen = Arc::new(Mutex::new(None));
+
+        let context = block_on(
+            question()
+                .with_prefill("{")
+                .send(continuing_adapter(seen.clone()), 100),
+        )
+        .resolve_without();
+
+        assert_eq!(seen.lock().unwrap().as_deref(), Some("{"));
+        assert_eq!(context.history.last().unwrap().content, "{\"temp\": 12}");
+        assert!(context.params.prefill.is_none());
+    }
+
+    #[test]
+    fn test_streamed_prefill_comes_first() {
+        let adapter = mock_streaming_adapter_factory(vec![Ok(PromptResponseDelta {
+            content: "\"temp\": 12}".to_string(),
+            stop_reason: None,
+            tool_calls: None,
+            cumulative_tokens: 0,
+            token_delta: 0,
+            logprobs: None,
+        })]);
+
+        let deltas: Vec<String> = block_on(
+            question()
+                .with_prefill("{")
+                .send_streaming(adapter.clone(), 100)
+                .map(|delta| delta.unwrap().content)
+                .collect(),
+        );
+        assert_eq!(deltas, vec!["{", "\"temp\": 12}"]);
+
+        let response = block_on(question().with_prefill("{").send_streaming_with_callback(
+            adapter,
+            100,
+            |_| {},
+        ))
+        .unwrap();
+        assert_eq!(response.prompt_response.message.content, "{\"temp\": 12}");
+    }
+
+    #[test]
+    fn test_json_mode_parses_the_reply() {
+        let json_mode = Arc::new(Mutex::new(false));
+        let seen = json_mode.clone();
+        let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            *seen.lock().unwrap() = context.params.json_mode;
+            Box::pin(async { Ok(mock_text_response("\"temp\": 12}")) })
+        });
+
+        let value = block_on(
+            question()
+                .with_prefill("{")
+                .send_with_json_mode(adapter, 100),
+        )
+        .unwrap();
+        assert!(*json_mode.lock().unwrap());
+        assert_eq!(value, serde_json::json!({ "temp": 12 }));
+
+        let prose = mock_adapter_factory(vec![Ok(mock_text_response("It's 12 degrees."))]);
+        let error = block_on(question().send_with_json_mode(prose, 100)).unwrap_err();
+        assert!(error.starts_with("Model returned invalid JSON"));
+    }
+
+    #[cfg(feature = "ollama")]
+    #[test]
+    fn test_ollama_prompt_ends_with_prefill() {
+        use steelwool::providers::ollama::format_ollama_prompt;
+
+        let prompt: serde_json::Value =
+            serde_json::from_str(&format_ollama_prompt(&question().with_prefill("{"), &None))
+                .unwrap();
+
+        assert_eq!(
+            prompt["messages"],
+            serde_json::json!([
+                { "role": "user", "content": "Give me the weather as JSON" },
+                { "role": "assistant", "content": "{" }
+            ])
+        );
+    }
+}
@@ -0,0 +1,179 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::executor::block_on;
+    use steelwool::rag::chunking::{
+        Boundary, ChunkPolicy, Document, IngestOptions, chunk_document, chunk_text, ingest,
+    };
+    use steelwool::rag::{EmbeddingAdapter, InMemoryVectorStore, VectorStore};
+    use steelwool::tokens::HeuristicTokenCounter;
+
+    /// Embeds each text as (length, vowel count); fails the `fail_on`th call
+    fn embedder(fail_on: Option<usize>) -> EmbeddingAdapter {
+        let calls = Arc::new(AtomicUsize::new(0));
+        Arc::new(move |texts: Vec<String>| {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                if Some(call) == fail_on {
+                    return Err("503 from embedding service".to_string());
+                }
+                Ok(texts
+                    .iter()
+                    .map(|t| {
+                        let vowels = t.chars().filter(|c| "aeiou".contains(*c)).count();
+                        vec![t.len() as f32, vowels as f32]
+                    })
+                    .collect())
+            })
+        })
+    }
+
+    #[test]
+    fn test_boundary_selection() {
+        let text = "First sentence here. Second one follows!\n\nNew paragraph starts. Ends here.";
+        let counter = HeuristicTokenCounter;
+        let policy = |boundary| ChunkPolicy {
+            max_tokens: 12,
+            overlap_tokens: 0,
+            boundary,
+        };
+
+        let sentences = chunk_text(text, &policy(Boundary::Sentence), &counter);
+        let texts: Vec<&str> = sentences.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "First sentence here. Second one follows!",
+                "New paragraph starts. Ends here."
+            ]
+        );
+        for chunk in &sentences {
+            assert_eq!(&text[chunk.byte_range.clone()], chunk.text);
+        }
+
+        let paragraphs = chunk_text(text, &policy(Boundary::Paragraph), &counter);
+        assert_eq!(paragraphs.len(), 2);
+        assert!(paragraphs[0].text.ends_with("follows!"));
+
+        // Smaller than a chunk: one chunk, whatever the boundary
+        let small = chunk_text("Tiny.", &policy(Boundary::Fixed), &counter);
+        assert_eq!(small.len(), 1);
+        assert_eq!(small[0].id, "0-5");
+        assert!(chunk_text("  \n", &policy(Boundary::Sentence), &counter).is_empty());
+
+        // No boundaries at all: falls back to character cuts
+        let blob = "x".repeat(100);
+        let pieces = chunk_text(&blob, &policy(Boundary::Sentence), &counter);
+        assert_eq!(pieces.len(), 3);
+        assert!(pieces.iter().all(|c| c.text.len() <= 48));
+        assert_eq!(pieces.iter().map(|c| c.text.len()).sum::<usize>(), 100);
+    }
+
+    #[test]
+    fn test_overlap_in_tokens() {
+        let text = (0..20)
+            .map(|i| format!("word{:02}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let counter = HeuristicTokenCounter;
+        let policy = ChunkPolicy {
+            max_tokens: 10,
+            overlap_tokens: 4,
+            boundary: Boundary::Fixed,
+        };
+
+        let chunks = chunk_text(&text, &policy, &counter);
+        assert!(chunks.len() > 2);
+        for pair in chunks.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            assert!(next.byte_range.start > prev.byte_range.start);
+            // The shared region fits in the overlap budget and isn't empty
+            assert!(next.byte_range.start < prev.byte_range.end);
+            let shared = &text[next.byte_range.start..prev.byte_range.end];
+            assert!(shared.chars().count().div_ceil(4) <= 4, "{:?}", shared);
+        }
+        assert!(
+            chunks
+                .iter()
+                .all(|c| c.text.chars().count().div_ceil(4) <= 10)
+        );
+        assert_eq!(chunks.last().unwrap().byte_range.end, text.len());
+    }
+
+    #[test]
+    fn test_ingest_is_idempotent_and_reports_failures() {
+        let store = InMemoryVectorStore::new();
+        let docs = vec![
+            Document {
+                id: "guide".to_string(),
+                text: "Install it. Configure it. Run it. Monitor it. Upgrade it.".to_string(),
+                metadata: BTreeMap::from([("source".to_string(), "docs".to_string())]),
+            },
+            Document {
+                id: "faq".to_string(),
+                text: "Why? Because.".to_string(),
+                metadata: BTreeMap::new(),
+            },
+        ];
+        let policy = ChunkPolicy {
+            max_tokens: 5,
+            overlap_tokens: 0,
+            boundary: Boundary::Sentence,
+        };
+        let counter = HeuristicTokenCounter;
+        let options = IngestOptions {
+            batch_size: 2,
+            concurrency: 2,
+        };
+
+        let expected = chunk_document(&docs[0], &policy, &counter).len()
+            + chunk_document(&docs[1], &policy, &counter).len();
+        let first = block_on(ingest(
+            &store,
+            embedder(None),
+            docs.clone(),
+            &policy,
+            &counter,
+            &options,
+        ));
+        assert!(first.is_complete());
+        assert_eq!(first.upserted.len(), expected);
+        assert_eq!(store.len().unwrap(), expected);
+
+        let record = store.get("guide:0-11").unwrap().unwrap();
+        assert_eq!(record.text, "Install it.");
+        assert_eq!(record.metadata["source"], "docs");
+        assert_eq!(record.metadata["chunk_index"], "0");
+
+        // Second batch fails mid-ingest; the others still land
+        let second = block_on(ingest(
+            &store,
+            embedder(Some(1)),
+            docs,
+            &policy,
+            &counter,
+            &options,
+        ));
+        assert_eq!(second.failed.len(), 2);
+        assert_eq!(second.upserted.len(), expected - 2);
+        assert_eq!(
+            second.failed[0].1,
+            "Embedding failed: 503 from embedding service"
+        );
+        assert_eq!(
+            first.upserted[2..4],
+            second
+                .failed
+                .iter()
+                .map(|f| f.0.clone())
+                .collect::<Vec<_>>()[..]
+        );
+        assert_eq!(store.len().unwrap(), expected);
+
+        let nearest = store.search(&[11.0, 4.0], 1).unwrap();
+        assert_eq!(nearest.len(), 1);
+    }
+}
@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+
+    use futures::executor::block_on;
+    use steelwool::providers::mock::{counting_adapter, mock_adapter_factory, mock_text_response};
+    use steelwool::refusal::{CONTENT_FILTER, FALLBACK, FallbackStep, REFUSAL};
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, ProviderAdapter,
+        StopReason,
+    };
+
+    fn context(content: &str) -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    fn filtered() -> PromptResponse {
+        let mut response = PromptResponse {
+            stop_reason: StopReason::ContentFilter,
+            ..mock_text_response("")
+        };
+        response
+            .provenance
+            .record(CONTENT_FILTER, "span: detonator");
+        response
+    }
+
+    /// Refuses while the last message mentions the flagged span
+    fn refusing() -> ProviderAdapter {
+        Arc::new(|context: ContextBuilder, _| {
+            let flagged = context
+                .history
+                .last()
+                .is_some_and(|msg| msg.content.contains("detonator"));
+            Box::pin(async move {
+                Ok(match flagged {
+                    true => filtered(),
+                    false => mock_text_response("Here is general safety information."),
+                })
+            })
+        })
+    }
+
+    /// Removes the span named in the filter details from the last message
+    fn strip_flagged_span() -> FallbackStep {
+        FallbackStep::RetryWithTransformer(Arc::new(|mut context, refused| {
+            for span in refused.provenance.details_from(CONTENT_FILTER) {
+                let span = span.trim_start_matches("span: ");
+                if let Some(last) = context.history.last_mut() {
+                    last.content = last.content.replace(span, "[removed]");
+                }
+            }
+            context
+        }))
+    }
+
+    #[test]
+    fn test_transformer_recovers() {
+        let question = context("How is a detonator wired?");
+        let response = block_on(question.clone().send(refusing(), 100));
+        assert!(response.prompt_response.is_refusal());
+
+        let (adapter, calls) = counting_adapter(refusing());
+        let recovered = block_on(response.fallback_on_refusal(
+            adapter,
+            100,
+            vec![
+                strip_flagged_span(),
+                FallbackStep::StaticMessage("I can't help with that.".to_string()),
+            ],
+        ));
+
+        let reply = &recovered.prompt_response;
+        assert_eq!(reply.message.content, "Here is general safety information.");
+        assert_eq!(
+            reply.provenance.details_from(FALLBACK),
+            ["step 1 (retry with transformer): answered"]
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let original: Message =
+            serde_json::from_str(reply.provenance.details_from(REFUSAL)[0]).unwrap();
+        assert!(original == filtered().message);
+
+        // The transform only touched the retried request
+        assert!(recovered.context_builder.history == question.history);
+    }
+
+    #[test]
+    fn test_static_message_is_terminal() {
+        let response = block_on(context("detonator").send(refusing(), 100));
+        let (adapter, calls) = counting_adapter(mock_adapter_factory(vec![
+            Ok(filtered()),
+            Err("server error".to_string()),
+        ]));
+
+        let recovered = block_on(response.fallback_on_refusal(
+            adapter.clone(),
+            100,
+            vec![
+                FallbackStep::RetryWithTransformer(Arc::new(|context, _| context)),
+                FallbackStep::SwitchAdapter(adapter),
+                FallbackStep::StaticMessage("I can't help with that.".to_string()),
+                FallbackStep::StaticMessage("unreached".to_string()),
+            ],
+        ));
+
+        let reply = &recovered.prompt_response;
+        assert_eq!(reply.message.content, "I can't help with that.");
+        assert!(reply.stop_reason == StopReason::Stop);
+        assert_eq!(
+            reply.provenance.details_from(FALLBACK),
+            [
+                "step 1 (retry with transformer): refused again",
+                "step 2 (switch adapter): failed: server error",
+                "step 3 (static message): answered",
+            ]
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_exhausted_chain_keeps_refusal() {
+        let response = block_on(context("detonator").send(refusing(), 100));
+        let (adapter, calls) = counting_adapter(refusing());
+
+        let steps = vec![
+            FallbackStep::RetryWithTransformer(Arc::new(|context, _| context)),
+            FallbackStep::RetryWithTransformer(Arc::new(|context, _| context)),
+        ];
+        let result = block_on(response.fallback_on_refusal(adapter, 100, steps));
+
+        assert!(result.prompt_response.is_refusal());
+        assert_eq!(
+            result
+                .prompt_response
+                .provenance
+                .details_from(FALLBACK)
+                .len(),
+            2
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_accepted_response_is_untouched() {
+        let response = block_on(context("hello").send(refusing(), 100));
+        let (adapter, calls) = counting_adapter(refusing());
+
+        let result = block_on(response.fallback_on_refusal(
+            adapter,
+            100,
+            vec![FallbackStep::StaticMessage("unreached".to_string())],
+        ));
+        assert_eq!(
+            result.prompt_response.message.content,
+            "Here is general safety information."
+        );
+        assert!(result.prompt_response.provenance.notes.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,406 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+    use steelwool::providers::mock::{counting_adapter, mock_adapter_factory, mock_text_response};
+    use steelwool::resilience::{
+        ErrorClass, ErrorClassifier, EscalationPolicy, EscalationSignal, HeuristicErrorClassifier,
+        RetryPolicy, escalation_adapter, retry_adapter,
+    };
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, StopReason, ToolCall,
+    };
+
+    fn context() -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Give me JSON".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    fn json_validator() -> EscalationPolicy {
+        EscalationPolicy {
+            validator: Some(Arc::new(|response: &PromptResponse| {
+                serde_json::from_str::<serde_json::Value>(&response.message.content)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            })),
+            max_validation_failures: Some(2),
+            ..EscalationPolicy::default()
+        }
+    }
+
+    fn tool_response(arguments: &str) -> PromptResponse {
+        PromptResponse {
+            stop_reason: StopReason::ToolCalls,
+            tool_calls: Some(vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "lookup".to_string(),
+                arguments: serde_json::Value::from(arguments),
+            }]),
+            ..mock_text_response("")
+        }
+    }
+
+    #[test]
+    fn test_escalates_after_two_validation_failures() {
+        let (cheap, cheap_calls) = counting_adapter(mock_adapter_factory(vec![
+            Ok(mock_text_response("not json")),
+            Ok(mock_text_response("still not json")),
+        ]));
+        let (strong, strong_calls) = counting_adapter(mock_adapter_factory(vec![Ok(
+            mock_text_response("{\"ok\": true}"),
+        )]));
+
+        let adapter = escalation_adapter(
+            vec![("cheap".to_string(), cheap), ("strong".to_string(), strong)],
+            json_validator(),
+        );
+        let response = block_on(adapter(context(), 100)).unwrap();
+
+        assert_eq!(response.message.content, "{\"ok\": true}");
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(strong_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            response.provenance.details_from("escalation"),
+            vec![
+                "cheap -> strong: 2 validation failures",
+                "answered by strong after 3 attempts"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_adapter_errors_count_as_failures() {
+        let cheap = mock_adapter_factory(vec![Err("rate limited".to_string())]);
+        let strong = mock_adapter_factory(vec![Ok(mock_text_response("[]"))]);
+
+        let adapter = escalation_adapter(
+            vec![("cheap".to_string(), cheap), ("strong".to_string(), strong)],
+            json_validator(),
+        );
+        let response = block_on(adapter(context(), 100)).unwrap();
+
+        assert_eq!(
+            response.provenance.details_from("escalation")[1],
+            "answered by strong after 3 attempts"
+        );
+    }
+
+    #[test]
+    fn test_escalates_on_malformed_tool_arguments() {
+        let (cheap, cheap_calls) =
+            counting_adapter(mock_adapter_factory(vec![Ok(tool_response("{\"city\": "))]));
+        let strong = mock_adapter_factory(vec![Ok(tool_response("{\"city\": \"Oslo\"}"))]);
+
+        let adapter = escalation_adapter(
+            vec![("cheap".to_string(), cheap), ("strong".to_string(), strong)],
+            EscalationPolicy {
+                max_malformed_tool_arguments: Some(1),
+                ..EscalationPolicy::default()
+            },
+        );
+        let response = block_on(adapter(context(), 100)).unwrap();
+
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            response.provenance.details_from("escalation")[0],
+            "cheap -> strong: 1 malformed tool arguments"
+        );
+    }
+
+    #[test]
+    fn test_caller_signal_skips_a_tier() {
+        let (cheap, cheap_calls) =
+            counting_adapter(mock_adapter_factory(vec![Ok(mock_text_response("{}"))]));
+        let strong = mock_adapter_factory(vec![Ok(mock_text_response("{}"))]);
+        let signal = EscalationSignal::new();
+
+        let adapter = escalation_adapter(
+            vec![("cheap".to_string(), cheap), ("strong".to_string(), strong)],
+            EscalationPolicy {
+                signal: Some(signal.clone()),
+                ..EscalationPolicy::default()
+            },
+        );
+
+        signal.raise();
+        let response = block_on(adapter(context(), 100)).unwrap();
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(
+            response.provenance.details_from("escalation"),
+            vec![
+                "cheap -> strong: caller signal",
+                "answered by strong after 1 attempts"
+            ]
+        );
+
+        // The signal is consumed
+        block_on(adapter(context(), 100)).unwrap();
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_validate_and_retry_failures_escalate() {
+        let (cheap, cheap_calls) =
+            counting_adapter(mock_adapter_factory(vec![Ok(mock_text_response("nope"))]));
+        let (strong, strong_calls) = counting_adapter(mock_adapter_factory(vec![Ok(
+            mock_text_response("{\"ok\": true}"),
+        )]));
+        let signal = EscalationSignal::new();
+        let adapter = escalation_adapter(
+            vec![("cheap".to_string(), cheap), ("strong".to_string(), strong)],
+            EscalationPolicy {
+                max_validation_failures: Some(2),
+                signal: Some(signal.clone()),
+                ..EscalationPolicy::default()
+            },
+        );
+        let validator = json_validator().validator.unwrap();
+
+        let response = block_on(
+            context().validate_and_retry_with_escalation(adapter, 100, &validator, 5, &signal),
+        )
+        .unwrap();
+
+        // Two rejected replies from the cheap tier, then the strong one answers
+        assert_eq!(response.prompt_response.message.content, "{\"ok\": true}");
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(strong_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            response
+                .prompt_response
+                .provenance
+                .details_from("escalation"),
+            vec![
+                "cheap -> strong: 2 validation failures",
+                "answered by strong after 1 attempts"
+            ]
+        );
+        // The next turn starts on the cheap tier again
+        assert_eq!(signal.validation_failures(), 0);
+    }
+
+    #[test]
+    fn test_exhausted_tiers_return_last_error() {
+        let (cheap, cheap_calls) =
+            counting_adapter(mock_adapter_factory(vec![Ok(mock_text_response("nope"))]));
+        let (strong, strong_calls) =
+            counting_adapter(mock_adapter_factory(vec![Ok(mock_text_response("nope"))]));
+
+        let adapter = escalation_adapter(
+            vec![("cheap".to_string(), cheap), ("strong".to_string(), strong)],
+            json_validator(),
+        );
+        let error = block_on(adapter(context(), 100)).err().unwrap();
+
+        assert!(error.starts_with("All escalation tiers failed after 4 attempts"));
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(strong_calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// Drive `future` on whichever executor the runtime backend expects
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        #[cfg(all(feature = "tokio-runtime", not(feature = "agnostic-runtime")))]
+        return tokio::runtime::Runtime::new().unwrap().block_on(future);
+
+        #[cfg(any(not(feature = "tokio-runtime"), feature = "agnostic-runtime"))]
+        return block_on(future);
+    }
+
+    fn retry_policy(classifier: Arc<dyn ErrorClassifier>) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(50),
+            classifier,
+        }
+    }
+
+    /// Calls made by `retry_adapter` for an adapter failing once with `err`
+    fn calls_after_failing_with(err: &str, classifier: Arc<dyn ErrorClassifier>) -> usize {
+        let (adapter, calls) = counting_adapter(mock_adapter_factory(vec![
+            Err(err.to_string()),
+            Ok(mock_text_response("{}")),
+        ]));
+        let _ = run(retry_adapter(adapter, retry_policy(classifier))(
+            context(),
+            100,
+        ));
+        calls.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_heuristic_classifier_decides_retries() {
+        let heuristic: Arc<dyn ErrorClassifier> = Arc::new(HeuristicErrorClassifier);
+        assert_eq!(
+            heuristic.classify("HTTP 429 Too Many Requests, Retry-After: 2"),
+            ErrorClass::RateLimited {
+                retry_after: Some(Duration::from_secs(2))
+            }
+        );
+        assert_eq!(
+            calls_after_failing_with("503 Service Unavailable", heuristic.clone()),
+            2
+        );
+        assert_eq!(
+            calls_after_failing_with("401 Unauthorized", heuristic.clone()),
+            1
+        );
+        assert_eq!(calls_after_failing_with("something odd", heuristic), 1);
+
+        // Retryable errors are retried until the attempts run out
+        let (adapter, calls) = counting_adapter(mock_adapter_factory(vec![Err(
+            "request timed out".to_string(),
+        )]));
+        let error = run(retry_adapter(
+            adapter,
+            retry_policy(Arc::new(HeuristicErrorClassifier)),
+        )(context(), 100))
+        .err()
+        .unwrap();
+        assert_eq!(error, "request timed out");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_heuristic_classifier_reads_status_codes_only() {
+        let heuristic = HeuristicErrorClassifier;
+        assert_eq!(
+            heuristic.classify("upstream returned status code 502"),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            heuristic.classify("HTTP/1.1 404 while fetching the model"),
+            ErrorClass::InvalidRequest
+        );
+
+        // Numbers that aren't statuses are left alone
+        let token_count = "requested 500 tokens but the context limit is 4096";
+        assert_eq!(heuristic.classify(token_count), ErrorClass::Unknown);
+        assert_eq!(
+            calls_after_failing_with(token_count, Arc::new(heuristic)),
+            1
+        );
+    }
+
+    #[test]
+    fn test_custom_classifier_overrides_heuristic() {
+        let acme: Arc<dyn ErrorClassifier> =
+            Arc::new(|err: &str| match err.starts_with("acme: busy") {
+                true => ErrorClass::Transient,
+                false => HeuristicErrorClassifier.classify(err),
+            });
+        assert_eq!(
+            calls_after_failing_with("acme: busy (E17)", acme.clone()),
+            2
+        );
+        assert_eq!(calls_after_failing_with("acme: bad key", acme.clone()), 1);
+
+        // Escalation fails fast on errors that won't go away
+        let (cheap, cheap_calls) = counting_adapter(mock_adapter_factory(vec![Err(
+            "acme: invalid prompt".to_string(),
+        )]));
+        let strong = mock_adapter_factory(vec![Ok(mock_text_response("{}"))]);
+        let adapter = escalation_adapter(
+            vec![("cheap".to_string(), cheap), ("strong".to_string(), strong)],
+            EscalationPolicy {
+                max_validation_failures: Some(1),
+                classifier: Some(acme),
+                ..EscalationPolicy::default()
+            },
+        );
+        let error = block_on(adapter(context(), 100)).err().unwrap();
+        assert_eq!(error, "acme: invalid prompt");
+        assert_eq!(cheap_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_openai_error_classes() {
+        use steelwool::providers::openai::OpenAIErrorClassifier;
+
+        let rate_limited = r#"OpenAI request error: ApiError(ApiError { message: "Rate limit reached for gpt-4o on requests per min (RPM): Limit 3, Used 3, Requested 1. Please try again in 20ms.", type: Some("requests"), param: None, code: Some("rate_limit_exceeded") })"#;
+        let bad_key = r#"OpenAI request error: ApiError(ApiError { message: "Incorrect API key provided: sk-abc.", type: Some("invalid_request_error"), param: None, code: Some("invalid_api_key") })"#;
+        let too_long = r#"OpenAI request error: ApiError(ApiError { message: "This model's maximum context length is 128000 tokens.", type: Some("invalid_request_error"), param: Some("messages"), code: Some("context_length_exceeded") })"#;
+
+        let openai = OpenAIErrorClassifier;
+        assert_eq!(
+            openai.classify(rate_limited),
+            ErrorClass::RateLimited {
+                retry_after: Some(Duration::from_millis(20))
+            }
+        );
+        assert_eq!(openai.classify(bad_key), ErrorClass::AuthFailure);
+        assert_eq!(openai.classify(too_long), ErrorClass::InvalidRequest);
+        assert_eq!(
+            openai.classify("OpenAI streaming error: StreamError(\"connection reset\")"),
+            ErrorClass::Transient
+        );
+
+        assert_eq!(calls_after_failing_with(rate_limited, Arc::new(openai)), 2);
+        assert_eq!(calls_after_failing_with(bad_key, Arc::new(openai)), 1);
+    }
+
+    #[test]
+    fn test_send_with_fallback() {
+        let (primary, primary_calls) = counting_adapter(mock_adapter_factory(vec![
+            Ok(mock_text_response("from primary")),
+            Err("503 Service Unavailable".to_string()),
+        ]));
+        let (fallback, fallback_calls) = counting_adapter(mock_adapter_factory(vec![Ok(
+            mock_text_response("from fallback"),
+        )]));
+
+        let response =
+            block_on(context().send_with_fallback(primary.clone(), fallback.clone(), 100));
+        assert_eq!(response.prompt_response.message.content, "from primary");
+        assert!(response.prompt_response.provenance.notes.is_empty());
+        assert_eq!(fallback_calls.load(Ordering::SeqCst), 0);
+
+        let response = block_on(context().send_with_fallback(primary, fallback, 100));
+        assert_eq!(response.prompt_response.message.content, "from fallback");
+        assert_eq!(
+            response.prompt_response.provenance.details_from("fallback"),
+            vec!["primary failed: 503 Service Unavailable"]
+        );
+        assert_eq!(response.context_builder.len(), 1);
+        assert_eq!(primary_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(fallback_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_try_send_with_fallback_returns_last_error() {
+        let primary = mock_adapter_factory(vec![Err("primary down".to_string())]);
+        let fallback = mock_adapter_factory(vec![Err("fallback down".to_string())]);
+
+        let result = block_on(context().try_send_with_fallback(primary, fallback, 100));
+        assert_eq!(result.err(), Some("fallback down".to_string()));
+    }
+
+    #[cfg(feature = "ollama")]
+    #[test]
+    fn test_ollama_error_classes() {
+        use steelwool::providers::ollama::OllamaErrorClassifier;
+
+        let unreachable = r#"Ollama generation error: ReqwestError(reqwest::Error { kind: Request, url: "http://localhost:11434/api/generate", source: ConnectError("tcp connect error", Os { code: 111, kind: ConnectionRefused }) })"#;
+        let missing_model = r#"Ollama generation error: InternalError(InternalOllamaError { message: "model \"llama9\" not found, try pulling it first" })"#;
+
+        let ollama = OllamaErrorClassifier;
+        assert_eq!(ollama.classify(unreachable), ErrorClass::Transient);
+        assert_eq!(ollama.classify(missing_model), ErrorClass::InvalidRequest);
+        assert_eq!(
+            ollama.classify("Ollama generation error: Other(\"server busy, please try again\")"),
+            ErrorClass::RateLimited { retry_after: None }
+        );
+
+        assert_eq!(calls_after_failing_with(unreachable, Arc::new(ollama)), 2);
+        assert_eq!(calls_after_failing_with(missing_model, Arc::new(ollama)), 1);
+    }
+}
@@ -0,0 +1,210 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use steelwool::providers::mock::{
+        mock_adapter_factory, mock_streaming_adapter_factory, mock_text_response,
+    };
+    use steelwool::response_filter::{
+        RESPONSE_FILTER, ResponseFilter, filtering_adapter, filtering_streaming_adapter,
+    };
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, StopReason,
+    };
+
+    const QUESTION: &str = "What is the capital of Norway?";
+    const SYSTEM: &str = "You are a terse geography tutor.";
+
+    fn context() -> ContextBuilder {
+        [(MessageRole::System, SYSTEM), (MessageRole::User, QUESTION)]
+            .into_iter()
+            .fold(ContextBuilder::new(), |context, (role, content)| {
+                context.add_message(Message {
+                    role,
+                    content: content.to_string(),
+                    content_type: ContentType::Text,
+                    pinned: false,
+                    assistant_tool_calls: None,
+                })
+            })
+    }
+
+    fn delta(
+        content: &str,
+        stop_reason: Option<StopReason>,
+    ) -> Result<PromptResponseDelta, String> {
+        Ok(PromptResponseDelta {
+            content: content.to_string(),
+            stop_reason,
+            tool_calls: None,
+            cumulative_tokens: 0,
+            token_delta: 0,
+            logprobs: None,
+        })
+    }
+
+    /// Content and notes of a filtered non-streaming reply
+    fn filtered(reply: &str) -> (String, Vec<String>) {
+        let adapter = filtering_adapter(
+            mock_adapter_factory(vec![Ok(mock_text_response(reply))]),
+            ResponseFilter::template_artifacts(),
+        );
+        let response = block_on(context().send(adapter, 100)).prompt_response;
+        let notes = response
+            .provenance
+            .details_from(RESPONSE_FILTER)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        (response.message.content, notes)
+    }
+
+    /// Content the callback sees, and the final content, when `chunks` are
+    /// streamed through the filter; the last chunk carries the stop reason
+    fn streamed(chunks: &[&str], filter: ResponseFilter) -> (String, String) {
+        let deltas = chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let stop = (i + 1 == chunks.len()).then_some(StopReason::Stop);
+                delta(chunk, stop)
+            })
+            .collect();
+        let adapter = filtering_streaming_adapter(mock_streaming_adapter_factory(deltas), filter);
+
+        let seen = Arc::new(Mutex::new(String::new()));
+        let seen_clone = seen.clone();
+        let response = block_on(context().send_streaming_with_callback(
+            adapter,
+            100,
+            move |delta| seen_clone.lock().unwrap().push_str(&delta.unwrap().content),
+        ))
+        .unwrap();
+        let seen = seen.lock().unwrap().clone();
+        (seen, response.prompt_response.message.content)
+    }
+
+    #[test]
+    fn test_leading_turn_markers_are_stripped() {
+        let (content, notes) = filtered("<|im_start|>assistant\nOslo.");
+        assert_eq!(content, "Oslo.");
+        assert_eq!(notes, vec!["leading artifact \"<|im_start|>assistant\\n\""]);
+
+        let (content, notes) = filtered("Assistant:\nOslo.");
+        assert_eq!(content, "Oslo.");
+        assert_eq!(notes.len(), 1);
+
+        let (content, _) = filtered("<|start_header_id|>assistant<|end_header_id|>\n\nOslo.");
+        assert_eq!(content, "Oslo.");
+    }
+
+    #[test]
+    fn test_trailing_end_tokens_are_stripped() {
+        let (content, notes) = filtered("Oslo.<|im_end|>\n");
+        assert_eq!(content, "Oslo.");
+        assert_eq!(notes, vec!["trailing artifact \"<|im_end|>\\n\""]);
+
+        let (content, notes) = filtered("Oslo. <|eot_id|></s>");
+        assert_eq!(content, "Oslo.");
+        assert_eq!(notes.len(), 2);
+    }
+
+    #[test]
+    fn test_echoed_prompts_are_stripped() {
+        let (content, notes) = filtered("What is the capital of Norway?\nOslo.");
+        assert_eq!(content, "Oslo.");
+        assert!(notes[0].starts_with("echoed prompt"));
+
+        // Near-copies count, case and a dropped character aside
+        let (content, _) = filtered("what is the capital of Norway\n\nOslo.");
+        assert_eq!(content, "Oslo.");
+
+        let (content, _) =
+            filtered("<|im_start|>assistant\nYou are a terse geography tutor. Oslo.");
+        assert_eq!(content, "Oslo.");
+
+        let (content, notes) = filtered("What is the capital of Norway? It's Oslo.");
+        assert_eq!(content, "It's Oslo.");
+        assert_eq!(notes.len(), 1);
+
+        // Replies that only share some words are left alone
+        let (content, notes) = filtered("The capital of Norway is Oslo.");
+        assert_eq!(content, "The capital of Norway is Oslo.");
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn test_noop_filter_leaves_replies_alone() {
+        let reply = "<|im_start|>assistant\nOslo.<|im_end|>";
+        let adapter = filtering_adapter(
+            mock_adapter_factory(vec![Ok(mock_text_response(reply))]),
+            ResponseFilter::default(),
+        );
+        let response = block_on(context().send(adapter, 100)).prompt_response;
+        assert_eq!(response.message.content, reply);
+        assert!(response.provenance.details_from(RESPONSE_FILTER).is_empty());
+
+        let (seen, content) = streamed(&[reply], ResponseFilter::default());
+        assert_eq!(seen, reply);
+        assert_eq!(content, reply);
+    }
+
+    #[test]
+    fn test_streamed_artifacts_never_reach_the_callback() {
+        let filter = ResponseFilter::template_artifacts();
+
+        let (seen, content) = streamed(&["<|im_start|>assistant\n", "Oslo", "."], filter.clone());
+        assert_eq!((seen.as_str(), content.as_str()), ("Oslo.", "Oslo."));
+
+        let (seen, content) = streamed(&["Oslo.", "<|im_", "end|>"], filter.clone());
+        assert_eq!((seen.as_str(), content.as_str()), ("Oslo.", "Oslo."));
+
+        let (seen, _) = streamed(
+            &["What is the capital", " of Norway?\n", "Oslo."],
+            filter.clone(),
+        );
+        assert_eq!(seen, "Oslo.");
+    }
+
+    #[test]
+    fn test_streamed_prefix_split_across_deltas() {
+        let filter = ResponseFilter {
+            stream_prefix_chars: 24,
+            stream_suffix_chars: 4,
+            ..ResponseFilter::template_artifacts()
+        };
+        let chunks = [
+            "<|im",
+            "_sta",
+            "rt|>assis",
+            "tant\nThe capital ",
+            "is Oslo",
+            ".",
+        ];
+        let (seen, content) = streamed(&chunks, filter);
+        assert_eq!(seen, "The capital is Oslo.");
+        assert_eq!(content, "The capital is Oslo.");
+    }
+
+    #[test]
+    fn test_held_content_is_flushed_without_stop_reason() {
+        let adapter = filtering_streaming_adapter(
+            mock_streaming_adapter_factory(vec![
+                delta("<|im_start|>assistant\n", None),
+                delta("Oslo.<|im_end|>", None),
+            ]),
+            ResponseFilter::template_artifacts(),
+        );
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_clone = seen.clone();
+        block_on(
+            context().send_streaming_with_callback(adapter, 100, move |delta| {
+                seen_clone.lock().unwrap().push(delta.unwrap().content)
+            }),
+        )
+        .unwrap();
+        assert_eq!(seen.lock().unwrap().concat(), "Oslo.");
+    }
+}
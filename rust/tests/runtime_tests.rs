@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::time::{Duration, Instant};
+
+    use futures::{SinkExt, StreamExt};
+    use steelwool::runtime::{mpsc, sleep, spawn, timeout};
+
+    /// Drive `future` on whichever executor the runtime backend expects
+    fn run<F: Future>(future: F) -> F::Output {
+        #[cfg(all(feature = "tokio-runtime", not(feature = "agnostic-runtime")))]
+        return tokio::runtime::Runtime::new().unwrap().block_on(future);
+
+        #[cfg(any(not(feature = "tokio-runtime"), feature = "agnostic-runtime"))]
+        return futures::executor::block_on(future);
+    }
+
+    #[test]
+    fn test_sleep_waits() {
+        let started = Instant::now();
+        run(sleep(Duration::from_millis(20)));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let fast = run(timeout(Duration::from_millis(200), async { 7 }));
+        assert_eq!(fast.unwrap(), 7);
+
+        let slow = run(timeout(
+            Duration::from_millis(10),
+            sleep(Duration::from_secs(5)),
+        ));
+        assert_eq!(slow.unwrap_err(), "Timed out after 10ms");
+    }
+
+    #[test]
+    fn test_spawned_tasks_feed_a_channel() {
+        let received: Vec<usize> = run(async {
+            let (tx, rx) = mpsc::channel(4);
+            let handles: Vec<_> = (0..3)
+                .map(|i| {
+                    let mut tx = tx.clone();
+                    spawn(async move {
+                        sleep(Duration::from_millis(5 * (3 - i as u64))).await;
+                        tx.send(i).await.unwrap();
+                        i * 10
+                    })
+                })
+                .collect();
+            drop(tx);
+
+            let mut outputs = vec![];
+            for handle in handles {
+                outputs.push(handle.await.unwrap());
+            }
+            assert_eq!(outputs, vec![0, 10, 20]);
+
+            let mut received: Vec<usize> = rx.collect().await;
+            received.sort();
+            received
+        });
+
+        assert_eq!(received, vec![0, 1, 2]);
+    }
+}
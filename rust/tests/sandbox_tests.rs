@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::executor::block_on;
+    use steelwool::providers::mock::mock_text_response;
+    use steelwool::sandbox::{SandboxPolicy, SandboxRun, sandbox};
+    use steelwool::{
+        ContextBuilder, PromptResponse, StopReason, ToolCall, ToolExecuter, UnresolvedResponse,
+    };
+
+    fn fetch_call(id: &str, url: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: "fetch".to_string(),
+            arguments: serde_json::json!({ "url": url }),
+        }
+    }
+
+    /// Pretend fetcher that reports each request to the run
+    fn fetcher(run: &SandboxRun) -> ToolExecuter {
+        let run = run.clone();
+        Arc::new(move |call: ToolCall| {
+            run.record_outbound_request();
+            let body = format!("<html>{}</html>", call.arguments["url"].as_str().unwrap());
+            Box::pin(async move { Ok(body) })
+        })
+    }
+
+    fn tool_turn(calls: Vec<ToolCall>) -> UnresolvedResponse {
+        UnresolvedResponse {
+            prompt_response: PromptResponse {
+                stop_reason: StopReason::ToolCalls,
+                tool_calls: Some(calls),
+                ..mock_text_response("")
+            },
+            context_builder: ContextBuilder::new(),
+        }
+    }
+
+    #[test]
+    fn test_third_call_in_run_is_a_violation() {
+        let run = SandboxRun::new();
+        let executer = sandbox(
+            fetcher(&run),
+            SandboxPolicy {
+                max_calls_per_run: Some(2),
+                ..SandboxPolicy::default()
+            },
+            &run,
+        );
+
+        let context = block_on(
+            tool_turn(vec![
+                fetch_call("call_1", "a.com"),
+                fetch_call("call_2", "b.com"),
+                fetch_call("call_3", "c.com"),
+            ])
+            .resolve(executer),
+        );
+
+        let results = run.results();
+        assert_eq!(results.len(), 3);
+        assert!(!results[0].error && !results[1].error);
+        assert_eq!(results[0].result, "<html>a.com</html>");
+        assert!(results[2].error);
+        assert_eq!(results[2].tool_call_id, "call_3");
+        assert_eq!(
+            results[2].result,
+            "Sandbox violation (max_calls_per_run): call 3 exceeds the limit of 2"
+        );
+
+        let stats = run.stats();
+        assert_eq!(
+            (stats.calls, stats.outbound_requests, stats.violations),
+            (2, 2, 1)
+        );
+        assert!(
+            context
+                .history
+                .last()
+                .unwrap()
+                .content
+                .contains("Error in tool call call_3 of fetch: Sandbox violation")
+        );
+    }
+
+    #[test]
+    fn test_result_size_and_outbound_limits() {
+        let run = SandboxRun::new();
+        let executer = sandbox(
+            fetcher(&run),
+            SandboxPolicy {
+                max_result_bytes: Some(20),
+                max_outbound_requests: Some(2),
+                ..SandboxPolicy::default()
+            },
+            &run,
+        );
+
+        let short = block_on(executer(fetch_call("call_1", "a.com")));
+        let long = block_on(executer(fetch_call("call_2", "a-very-long-domain.com")));
+        let blocked = block_on(executer(fetch_call("call_3", "b.com")));
+
+        assert_eq!(short.unwrap(), "<html>a.com</html>");
+        assert_eq!(
+            long.unwrap_err(),
+            "Sandbox violation (max_result_bytes): result of 35 bytes exceeds 20"
+        );
+        assert_eq!(
+            blocked.unwrap_err(),
+            "Sandbox violation (max_outbound_requests): run has made 2 of 2 requests"
+        );
+
+        run.reset();
+        assert!(block_on(executer(fetch_call("call_4", "b.com"))).is_ok());
+    }
+
+    #[test]
+    fn test_veto_sees_run_stats() {
+        let run = SandboxRun::new();
+        let executer = sandbox(
+            fetcher(&run),
+            SandboxPolicy {
+                veto: Some(Arc::new(|call, stats| {
+                    let url = call.arguments["url"].as_str().unwrap_or_default();
+                    match url.ends_with(".internal") && stats.calls > 0 {
+                        true => Err(format!("{} blocked after external fetches", url)),
+                        false => Ok(()),
+                    }
+                })),
+                ..SandboxPolicy::default()
+            },
+            &run,
+        );
+
+        assert!(block_on(executer(fetch_call("call_1", "db.internal"))).is_ok());
+        assert!(block_on(executer(fetch_call("call_2", "a.com"))).is_ok());
+        assert_eq!(
+            block_on(executer(fetch_call("call_3", "db.internal"))).unwrap_err(),
+            "Sandbox violation (veto): db.internal blocked after external fetches"
+        );
+        assert_eq!(run.stats().calls, 2);
+    }
+}
@@ -0,0 +1,128 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use futures::future::join;
+    use steelwool::providers::mock::mock_text_response;
+    use steelwool::scratchpad::SharedScratchpad;
+    use steelwool::{
+        ContextBuilder, PromptResponse, ProviderAdapter, StopReason, ToolCall, ToolExecuter,
+        UnresolvedResponse,
+    };
+
+    fn select_call(id: &str, account: u64) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: "select_account".to_string(),
+            arguments: serde_json::json!({ "account_id": account }),
+        }
+    }
+
+    /// Stores the chosen account in the scratchpad
+    fn select_account_tool(shared: SharedScratchpad) -> ToolExecuter {
+        Arc::new(move |call: ToolCall| {
+            let result = shared
+                .set(&call.id, "account_id", &call.arguments["account_id"])
+                .map(|_| "selected".to_string());
+            Box::pin(async move { result })
+        })
+    }
+
+    #[test]
+    fn test_typed_access_and_persistence_round_trip() {
+        let mut context = ContextBuilder::new();
+        context.scratchpad.set("account_id", 42u64).unwrap();
+        context
+            .scratchpad
+            .set("draft", vec!["intro", "body"])
+            .unwrap();
+
+        assert_eq!(context.scratchpad.get_as::<u64>("account_id"), Ok(Some(42)));
+        assert_eq!(context.scratchpad.get_as::<u64>("missing"), Ok(None));
+        assert!(context.scratchpad.get_as::<String>("account_id").is_err());
+
+        let json = context.to_versioned_json().unwrap();
+        let (loaded, report) = ContextBuilder::load_with_report(&json).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(loaded.scratchpad, context.scratchpad);
+        assert_eq!(
+            loaded.scratchpad.get_as::<Vec<String>>("draft").unwrap(),
+            Some(vec!["intro".to_string(), "body".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_scratchpad_is_not_sent_to_adapters() {
+        let seen = Arc::new(Mutex::new(None));
+        let seen_clone = seen.clone();
+        let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            *seen_clone.lock().unwrap() = Some(context.scratchpad.is_empty());
+            Box::pin(async { Ok(mock_text_response("ok")) })
+        });
+
+        let mut context = ContextBuilder::new();
+        context
+            .scratchpad
+            .set("secret_plan", "world domination")
+            .unwrap();
+        let response = block_on(context.send(adapter, 100));
+
+        assert_eq!(*seen.lock().unwrap(), Some(true));
+        assert!(!response.context_builder.scratchpad.is_empty());
+    }
+
+    #[test]
+    fn test_tool_write_is_read_by_later_transformer() {
+        let context = ContextBuilder::new();
+        let shared = context.scratchpad.share();
+        let response = UnresolvedResponse {
+            prompt_response: PromptResponse {
+                stop_reason: StopReason::ToolCalls,
+                tool_calls: Some(vec![select_call("call_1", 7)]),
+                ..mock_text_response("")
+            },
+            context_builder: context,
+        };
+
+        let context = block_on(response.resolve(select_account_tool(shared.clone())))
+            .merge_scratchpad(&shared)
+            .transform_with(|context| {
+                let account: u64 = context.scratchpad.get_as("account_id").unwrap().unwrap();
+                context.add_tool_result_message("note", format!("Using account {}", account), false)
+            });
+
+        assert_eq!(context.history.last().unwrap().content, "Using account 7");
+        assert!(shared.conflicts().is_empty());
+    }
+
+    #[test]
+    fn test_concurrent_writes_are_last_write_wins_with_conflict() {
+        let mut context = ContextBuilder::new();
+        context.scratchpad.set("untouched", true).unwrap();
+        let shared = context.scratchpad.share();
+        let tool = select_account_tool(shared.clone());
+
+        let (first, second) = block_on(join(
+            tool(select_call("call_1", 1)),
+            tool(select_call("call_2", 2)),
+        ));
+        assert!(first.is_ok() && second.is_ok());
+
+        let writes = shared.writes();
+        assert_eq!(writes.len(), 2);
+        assert_eq!(writes[0].conflicts_with, None);
+
+        let conflicts = shared.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].call_id, "call_2");
+        assert_eq!(conflicts[0].conflicts_with.as_deref(), Some("call_1"));
+
+        let context = context.merge_scratchpad(&shared);
+        assert_eq!(context.scratchpad.get_as::<u64>("account_id"), Ok(Some(2)));
+        assert_eq!(
+            context.scratchpad.get_as::<bool>("untouched"),
+            Ok(Some(true))
+        );
+    }
+}
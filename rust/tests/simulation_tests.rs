@@ -0,0 +1,190 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use steelwool::providers::mock::mock_text_response;
+    use steelwool::simulation::{
+        SIMULATED_TOOLS, SimulationRun, SimulationSource, simulated_executer,
+    };
+    use steelwool::{
+        ContentType, ContextBuilder, ProviderAdapter, ToolCall, ToolCallLog, ToolDescriptor,
+    };
+
+    fn send_email_call(id: &str, to: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: "send_email".to_string(),
+            arguments: serde_json::json!({ "to": to, "body": "Hi" }),
+        }
+    }
+
+    fn log(call_id: &str, arguments: serde_json::Value, result: &str) -> ToolCallLog {
+        ToolCallLog {
+            call_id: call_id.to_string(),
+            tool_name: "send_email".to_string(),
+            arguments,
+            result: Ok(result.to_string()),
+            duration_ms: 120,
+        }
+    }
+
+    #[test]
+    fn test_canned_results_by_tool_name() {
+        let run = SimulationRun::new();
+        let executer = simulated_executer(
+            SimulationSource::Canned(HashMap::from([(
+                "send_email".to_string(),
+                "queued".to_string(),
+            )])),
+            &run,
+        );
+
+        assert_eq!(
+            block_on(executer(send_email_call("1", "a@example.com"))),
+            Ok("queued".to_string())
+        );
+        let unknown = ToolCall {
+            name: "delete_account".to_string(),
+            ..send_email_call("2", "a@example.com")
+        };
+        assert!(block_on(executer(unknown)).is_err());
+
+        let results = run.results();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.simulated));
+        assert!(results[1].error);
+        assert!(run.is_simulated("1"));
+        assert!(!run.is_simulated("3"));
+    }
+
+    #[test]
+    fn test_llm_is_prompted_with_descriptor_and_arguments() {
+        let prompts = Arc::new(Mutex::new(vec![]));
+        let seen = prompts.clone();
+        let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            let prompt: Vec<String> = context.history.iter().map(|m| m.content.clone()).collect();
+            seen.lock().unwrap().push(prompt);
+            Box::pin(async { Ok(mock_text_response("{\"status\":\"sent\"}")) })
+        });
+
+        let run = SimulationRun::new();
+        let executer = simulated_executer(
+            SimulationSource::Llm {
+                adapter,
+                tools: vec![ToolDescriptor {
+                    name: "send_email".to_string(),
+                    description: "Send an email".to_string(),
+                    schema: serde_json::json!({ "type": "object" }),
+                    required: false,
+                }],
+                max_tokens: 200,
+            },
+            &run,
+        );
+
+        let result = block_on(executer(send_email_call("1", "a@example.com")));
+
+        assert_eq!(result, Ok("{\"status\":\"sent\"}".to_string()));
+        let prompts = prompts.lock().unwrap();
+        assert!(prompts[0][0].contains("`send_email`: Send an email"));
+        assert!(prompts[0][1].contains("a@example.com"));
+        assert!(run.results()[0].simulated);
+    }
+
+    #[test]
+    fn test_recording_prefers_matching_arguments() {
+        let run = SimulationRun::new();
+        let executer = simulated_executer(
+            SimulationSource::Recording(vec![
+                log(
+                    "a",
+                    serde_json::json!({ "to": "a@example.com", "body": "Hi" }),
+                    "sent to a",
+                ),
+                // Logged as a JSON string, the way OpenAI sends arguments
+                log(
+                    "b",
+                    serde_json::json!("{\"to\":\"b@example.com\",\"body\":\"Hi\"}"),
+                    "sent to b",
+                ),
+            ]),
+            &run,
+        );
+
+        assert_eq!(
+            block_on(executer(send_email_call("1", "b@example.com"))),
+            Ok("sent to b".to_string())
+        );
+        assert_eq!(
+            block_on(executer(send_email_call("2", "c@example.com"))),
+            Ok("sent to a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_recording_from_log_file() {
+        let path =
+            std::env::temp_dir().join(format!("steelwool-simulation-{}.jsonl", std::process::id()));
+        let lines: Vec<String> = [
+            log("a", serde_json::json!({ "to": "a@example.com" }), "sent"),
+            log("b", serde_json::json!({ "to": "b@example.com" }), "bounced"),
+        ]
+        .iter()
+        .map(|l| serde_json::to_string(l).unwrap())
+        .collect();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let source = SimulationSource::from_log_file(&path).unwrap();
+        let SimulationSource::Recording(logs) = &source else {
+            panic!("expected a recording");
+        };
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[1].result, Ok("bounced".to_string()));
+
+        std::fs::write(&path, "not json\n").unwrap();
+        let err = SimulationSource::from_log_file(&path).err().unwrap();
+        assert!(err.contains("line 1"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_annotate_simulated_flags_transcript() {
+        let run = SimulationRun::new();
+        let executer = simulated_executer(
+            SimulationSource::Canned(HashMap::from([(
+                "send_email".to_string(),
+                "queued".to_string(),
+            )])),
+            &run,
+        );
+
+        let result = block_on(executer(send_email_call("1", "a@example.com"))).unwrap();
+        let context = ContextBuilder::new()
+            .add_tool_result_message("1", result, false)
+            .add_tool_result_message("real", "42".to_string(), false)
+            .annotate_simulated(&run);
+
+        let flags: Vec<(String, bool)> = context
+            .history
+            .iter()
+            .filter_map(|m| match &m.content_type {
+                ContentType::ToolResult(r) => Some((r.tool_call_id.clone(), r.simulated)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            flags,
+            vec![("1".to_string(), true), ("real".to_string(), false)]
+        );
+        assert_eq!(
+            context.metadata.get(SIMULATED_TOOLS).map(String::as_str),
+            Some("true")
+        );
+
+        // Nothing simulated, nothing tagged
+        let untouched = ContextBuilder::new().annotate_simulated(&SimulationRun::new());
+        assert!(!untouched.metadata.contains_key(SIMULATED_TOOLS));
+    }
+}
@@ -0,0 +1,188 @@
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use futures::executor::block_on;
+    use futures::stream;
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, StopReason,
+        StreamProviderAdapter,
+    };
+
+    /// Counts allocations made on the current thread, so tests running in
+    /// parallel don't skew each other
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations_during<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let result = f();
+        (result, ALLOCATIONS.with(Cell::get) - before)
+    }
+
+    fn context() -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Count to a thousand".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    fn deltas(n: usize) -> Vec<PromptResponseDelta> {
+        (0..n)
+            .map(|i| PromptResponseDelta {
+                content: "tok ".to_string(),
+                stop_reason: (i + 1 == n).then_some(StopReason::Stop),
+                tool_calls: None,
+                cumulative_tokens: i as u32 + 1,
+                token_delta: 1,
+                logprobs: None,
+            })
+            .collect()
+    }
+
+    /// Streams deltas built ahead of time, so the provider side allocates
+    /// nothing while it's measured
+    fn prebuilt(deltas: Vec<PromptResponseDelta>) -> StreamProviderAdapter {
+        let deltas = Mutex::new(Some(deltas));
+        Arc::new(move |_, _| {
+            let deltas = deltas.lock().unwrap().take().unwrap_or_default();
+            Box::pin(stream::iter(deltas.into_iter().map(Ok)))
+        })
+    }
+
+    #[test]
+    fn test_sinks_receive_all_content() {
+        let mut text = String::new();
+        let response = block_on(context().with_prefill("> ").send_streaming_into(
+            prebuilt(deltas(3)),
+            100,
+            &mut text,
+        ))
+        .unwrap();
+        assert_eq!(text, "> tok tok tok ");
+        assert_eq!(response.prompt_response.message.content, text);
+        assert!(response.prompt_response.stop_reason == StopReason::Stop);
+        assert_eq!(response.prompt_response.token_usage, 3);
+
+        let mut bytes: Vec<u8> = vec![];
+        block_on(context().send_streaming_into(prebuilt(deltas(2)), 100, &mut bytes)).unwrap();
+        assert_eq!(bytes, b"tok tok ");
+    }
+
+    #[test]
+    fn test_errors_end_the_stream() {
+        let adapter: StreamProviderAdapter = Arc::new(|_, _| {
+            Box::pin(stream::iter(vec![
+                Ok(deltas(1).remove(0)),
+                Err("connection reset".to_string()),
+            ]))
+        });
+        let mut text = String::new();
+        let result = block_on(context().send_streaming_into(adapter, 100, &mut text));
+        assert!(result.is_err());
+        assert_eq!(text, "tok ");
+    }
+
+    #[test]
+    fn test_allocations_do_not_grow_with_deltas() {
+        let context = context();
+        let mut sink = String::with_capacity(4096);
+        let adapter = prebuilt(deltas(1000));
+
+        let (response, into_allocations) = allocations_during(|| {
+            block_on(
+                context
+                    .clone()
+                    .send_streaming_into(adapter, 1000, &mut sink),
+            )
+        });
+        assert_eq!(
+            response.unwrap().prompt_response.message.content.len(),
+            4000
+        );
+        assert_eq!(sink.len(), 4000);
+        assert!(into_allocations < 32, "{} allocations", into_allocations);
+
+        // The callback path clones every delta
+        let adapter = prebuilt(deltas(1000));
+        let (_, callback_allocations) = allocations_during(|| {
+            block_on(context.send_streaming_with_callback(adapter, 1000, |_| {}))
+        });
+        assert!(callback_allocations >= 1000);
+    }
+
+    #[cfg(feature = "tokio-runtime")]
+    #[tokio::test]
+    async fn test_async_write_sink() {
+        use steelwool::sink::AsyncWriteSink;
+
+        let mut sink = AsyncWriteSink::new(Vec::<u8>::new());
+        context()
+            .send_streaming_into(prebuilt(deltas(3)), 100, &mut sink)
+            .await
+            .unwrap();
+        assert_eq!(sink.into_inner(), b"tok tok tok ");
+    }
+
+    /// `cargo test --test sink_tests -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_send_streaming_into() {
+        const RUNS: usize = 200;
+
+        let time = |into: bool| {
+            let adapters: Vec<_> = (0..RUNS).map(|_| prebuilt(deltas(1000))).collect();
+            let started = Instant::now();
+            for adapter in adapters {
+                match into {
+                    true => {
+                        let mut sink = String::new();
+                        block_on(context().send_streaming_into(adapter, 1000, &mut sink)).unwrap();
+                    }
+                    false => {
+                        block_on(context().send_streaming_with_callback(adapter, 1000, |_| {}))
+                            .unwrap();
+                    }
+                }
+            }
+            started.elapsed() / RUNS as u32
+        };
+
+        println!(
+            "send_streaming_into:           {:?} per 1000 deltas",
+            time(true)
+        );
+        println!(
+            "send_streaming_with_callback:  {:?} per 1000 deltas",
+            time(false)
+        );
+    }
+}
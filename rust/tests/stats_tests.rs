@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use steelwool::providers::mock::mock_message;
+    use steelwool::stats::ConversationStats;
+    use steelwool::store::{ConversationStore, InMemoryConversationStore};
+    use steelwool::tokens::{HeuristicTokenCounter, estimate_prompt_tokens};
+    use steelwool::{ContextBuilder, MessageRole, ToolCallLog};
+
+    #[test]
+    fn test_stats_counts_roles_and_tokens() {
+        let context = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::System, "Be helpful."))
+            .add_message(mock_message(MessageRole::User, "Weather in Oslo?"))
+            .add_message(mock_message(MessageRole::Model, "Checking."))
+            .add_message(mock_message(MessageRole::Tool, "{\"temp\": 4}"))
+            .add_message(mock_message(MessageRole::Function, "{\"wind\": 7}"))
+            .add_message(mock_message(MessageRole::Model, "4 degrees and windy."))
+            .add_message(mock_message(MessageRole::User, "Thanks"));
+
+        let stats = context.stats();
+
+        assert_eq!(
+            stats,
+            ConversationStats {
+                message_count: 7,
+                user_turns: 2,
+                model_turns: 2,
+                tool_calls: 2,
+                system_messages: 1,
+                estimated_tokens: estimate_prompt_tokens(&context, &[], &HeuristicTokenCounter),
+                avg_model_response_chars: Some(14.5),
+                tool_usage: None,
+            }
+        );
+        assert_eq!(ContextBuilder::new().stats(), ConversationStats::default());
+    }
+
+    fn tool_log(name: &str) -> ToolCallLog {
+        ToolCallLog {
+            call_id: "call_1".to_string(),
+            tool_name: name.to_string(),
+            arguments: serde_json::json!({}),
+            result: Ok("ok".to_string()),
+            duration_ms: 5,
+        }
+    }
+
+    fn transcript() -> ContextBuilder {
+        ContextBuilder::new()
+            .add_message(mock_message(MessageRole::User, "Weather and time in Oslo?"))
+            .add_message(mock_message(MessageRole::Model, "Checking."))
+            .add_message(mock_message(MessageRole::Tool, "4 degrees\n14:00\n"))
+            .add_message(mock_message(MessageRole::Model, "4 degrees at 14:00."))
+    }
+
+    #[test]
+    fn test_tool_usage_needs_logs() {
+        let stats = transcript().stats();
+        assert_eq!(stats.avg_model_response_chars, Some(14.0));
+        assert_eq!(stats.tool_usage, None);
+
+        let stats = stats.with_tool_logs(&[tool_log("weather"), tool_log("clock")]);
+        assert_eq!(
+            stats.tool_usage,
+            Some(BTreeMap::from([
+                ("clock".to_string(), 1),
+                ("weather".to_string(), 1)
+            ]))
+        );
+
+        let silent = ContextBuilder::new().add_message(mock_message(MessageRole::User, "Hello?"));
+        assert_eq!(silent.stats().avg_model_response_chars, None);
+    }
+
+    #[test]
+    fn test_merge_aggregates() {
+        let logged = transcript()
+            .stats()
+            .with_tool_logs(&[tool_log("weather"), tool_log("clock")]);
+        let short = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::User, "Hi"))
+            .add_message(mock_message(MessageRole::Model, "Hello!"))
+            .stats()
+            .with_tool_logs(&[tool_log("weather")]);
+
+        let total = logged.merge(&short);
+        assert_eq!(total.message_count, 6);
+        assert_eq!(total.model_turns, 3);
+        assert_eq!(
+            total.estimated_tokens,
+            logged.estimated_tokens + short.estimated_tokens
+        );
+        // (9 + 19 + 6) / 3
+        assert!((total.avg_model_response_chars.unwrap() - 34.0 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            total.tool_usage,
+            Some(BTreeMap::from([
+                ("clock".to_string(), 1),
+                ("weather".to_string(), 2)
+            ]))
+        );
+
+        // A side without logs makes the usage unknown rather than undercounted
+        assert_eq!(total.merge(&transcript().stats()).tool_usage, None);
+        assert_eq!(
+            ConversationStats::default()
+                .merge(&short)
+                .avg_model_response_chars,
+            Some(6.0)
+        );
+
+        let round_trip: ConversationStats =
+            serde_json::from_str(&serde_json::to_string(&total).unwrap()).unwrap();
+        assert_eq!(round_trip, total);
+    }
+
+    #[test]
+    fn test_store_lists_stats_from_save() {
+        let store = InMemoryConversationStore::new();
+        store.save("b", &transcript()).unwrap();
+        store.save("a", &ContextBuilder::new()).unwrap();
+
+        let listed = store.list_with_stats().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(
+            listed[0],
+            ("a".to_string(), Some(ConversationStats::default()))
+        );
+        assert_eq!(listed[1], ("b".to_string(), Some(transcript().stats())));
+    }
+}
@@ -0,0 +1,191 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use futures::executor::block_on;
+    use steelwool::providers::mock::{mock_message, mock_text_response};
+    use steelwool::store::{ConversationStore, InMemoryConversationStore, SPILL_FAILED};
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, ProviderAdapter,
+    };
+
+    fn is_marker(msg: &Message) -> bool {
+        matches!(msg.content_type, ContentType::ArchivedRange(_))
+    }
+
+    /// 1 system prompt + 200 conversation messages, with a tool result after
+    /// every 10th model reply
+    fn long_conversation() -> Vec<Message> {
+        // Pinned, as `add_message` pins system messages
+        let mut history = vec![Message {
+            pinned: true,
+            ..mock_message(MessageRole::System, "Be helpful.")
+        }];
+        for i in 0..200 {
+            let role = if i % 10 == 8 {
+                MessageRole::Tool
+            } else if i % 2 == 0 {
+                MessageRole::User
+            } else {
+                MessageRole::Model
+            };
+            history.push(mock_message(role, &format!("message {}", i)));
+        }
+        history
+    }
+
+    #[test]
+    fn test_spill_keeps_window_and_hydrates_exactly() {
+        let store = Arc::new(InMemoryConversationStore::new());
+        let original = long_conversation();
+
+        let context = original.iter().cloned().fold(
+            ContextBuilder::new().with_backing_store(store.clone(), 50),
+            |ctx, msg| ctx.add_message(msg),
+        );
+
+        // System prompt, one marker, then the in-memory window
+        assert!(context.history[0].content == "Be helpful.");
+        assert!(is_marker(&context.history[1]));
+        assert_eq!(context.history.iter().filter(|m| is_marker(m)).count(), 1);
+        assert!(context.history.len() - 2 <= 50);
+        assert_eq!(store.list().unwrap().len(), 1);
+
+        let hydrated = context.hydrate().unwrap();
+        assert_eq!(hydrated.history.len(), original.len());
+        assert!(hydrated.history == original);
+    }
+
+    #[test]
+    fn test_spill_never_splits_tool_pairs() {
+        let store = Arc::new(InMemoryConversationStore::new());
+
+        for keep in 1..12 {
+            let context = long_conversation().into_iter().fold(
+                ContextBuilder::new().with_backing_store(store.clone(), keep),
+                |ctx, msg| ctx.add_message(msg),
+            );
+
+            let first_in_window = &context.history[2];
+            assert!(
+                first_in_window.role != MessageRole::Tool,
+                "window for keep={} starts with an orphaned tool result",
+                keep
+            );
+        }
+    }
+
+    #[test]
+    fn test_markers_serialize_and_rehydrate_with_store() {
+        let store = Arc::new(InMemoryConversationStore::new());
+        let context = long_conversation().into_iter().fold(
+            ContextBuilder::new().with_backing_store(store.clone(), 20),
+            |ctx, msg| ctx.add_message(msg),
+        );
+
+        let json = serde_json::to_string(&context).unwrap();
+        let restored: ContextBuilder = serde_json::from_str(&json).unwrap();
+
+        assert!(restored.history == context.history);
+        assert!(restored.hydrate().is_err());
+
+        let reattached = restored.with_backing_store(store, 20);
+        assert!(reattached.hydrate().unwrap().history == long_conversation());
+    }
+
+    #[test]
+    fn test_sends_exclude_archive_markers() {
+        let store = Arc::new(InMemoryConversationStore::new());
+        let seen = Arc::new(std::sync::Mutex::new(vec![]));
+        let seen_clone = seen.clone();
+
+        let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            *seen_clone.lock().unwrap() = context.history.clone();
+            Box::pin(async move { Ok::<PromptResponse, String>(mock_text_response("ok")) })
+        });
+
+        let context = long_conversation().into_iter().fold(
+            ContextBuilder::new().with_backing_store(store, 10),
+            |ctx, msg| ctx.add_message(msg),
+        );
+        block_on(context.send(adapter, 100));
+
+        let sent = seen.lock().unwrap();
+        assert!(!sent.iter().any(is_marker));
+        assert_eq!(sent.len(), 11);
+    }
+
+    #[test]
+    fn test_hydrate_keeps_the_rest_of_the_builder() {
+        let store = Arc::new(InMemoryConversationStore::new());
+        let context = long_conversation().into_iter().fold(
+            ContextBuilder::new()
+                .with_metadata("conversation_id", "conv-1")
+                .with_prefill("{")
+                .with_backing_store(store, 20),
+            |ctx, msg| ctx.add_message(msg),
+        );
+
+        let hydrated = context.hydrate().unwrap();
+        assert!(hydrated.history == long_conversation());
+        assert_eq!(
+            hydrated.metadata.get("conversation_id").map(String::as_str),
+            Some("conv-1")
+        );
+        assert_eq!(hydrated.params.prefill.as_deref(), Some("{"));
+
+        // Detached: adding more doesn't spill again
+        let grown = hydrated.add_message(mock_message(MessageRole::User, "more"));
+        assert_eq!(grown.len(), long_conversation().len() + 1);
+    }
+
+    /// `InMemoryConversationStore` whose writes fail while `failing` is set
+    struct FlakyStore {
+        inner: InMemoryConversationStore,
+        failing: AtomicBool,
+    }
+
+    impl ConversationStore for FlakyStore {
+        fn save(&self, key: &str, context: &ContextBuilder) -> Result<(), String> {
+            match self.failing.load(Ordering::SeqCst) {
+                true => Err("disk full".to_string()),
+                false => self.inner.save(key, context),
+            }
+        }
+        fn load(&self, key: &str) -> Result<Option<ContextBuilder>, String> {
+            self.inner.load(key)
+        }
+        fn delete(&self, key: &str) -> Result<(), String> {
+            self.inner.delete(key)
+        }
+        fn list(&self) -> Result<Vec<String>, String> {
+            self.inner.list()
+        }
+    }
+
+    #[test]
+    fn test_failed_spill_is_reported() {
+        let store = Arc::new(FlakyStore {
+            inner: InMemoryConversationStore::new(),
+            failing: AtomicBool::new(true),
+        });
+        let context = long_conversation().into_iter().take(30).fold(
+            ContextBuilder::new().with_backing_store(store.clone(), 10),
+            |ctx, msg| ctx.add_message(msg),
+        );
+
+        assert_eq!(context.spill_error(), Some("disk full"));
+        assert_eq!(
+            context.metadata.get(SPILL_FAILED).map(String::as_str),
+            Some("disk full")
+        );
+        assert_eq!(context.len(), 30);
+        assert!(!context.history.iter().any(is_marker));
+
+        store.failing.store(false, Ordering::SeqCst);
+        let context = context.add_message(mock_message(MessageRole::User, "again"));
+        assert_eq!(context.spill_error(), None);
+        assert!(is_marker(&context.history[1]));
+    }
+}
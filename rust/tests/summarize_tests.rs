@@ -0,0 +1,212 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+
+    use futures::StreamExt;
+    use steelwool::providers::mock::{
+        counting_adapter, mock_adapter_factory, mock_message, mock_streaming_adapter_factory,
+        mock_text_response,
+    };
+    use steelwool::summarize::SUMMARY_HEADER;
+    use steelwool::{
+        ContextBuilder, Message, MessageRole, PromptResponseDelta, ProviderAdapter,
+        StreamProviderAdapter,
+    };
+
+    /// Replies "summary N" and records the history it was asked to summarize
+    fn recording_summarizer(seen: Arc<Mutex<Vec<Vec<String>>>>) -> ProviderAdapter {
+        Arc::new(move |context: ContextBuilder, _| {
+            let mut seen = seen.lock().unwrap();
+            seen.push(context.history.iter().map(|m| m.content.clone()).collect());
+            let reply = mock_text_response(&format!("summary {}", seen.len()));
+            Box::pin(async move { Ok(reply) })
+        })
+    }
+
+    fn contents(context: &ContextBuilder) -> Vec<&str> {
+        context.history.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_summarizes_all_but_window_past_threshold() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut context = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::System, "Be brief."))
+            .with_rolling_summary_window(recording_summarizer(seen.clone()), 200, 2, 4);
+        for turn in ["u1", "m1", "u2"] {
+            context = context.add_message(mock_message(MessageRole::User, turn));
+        }
+        let context = block_on(context.apply_rolling_summary()).unwrap();
+        assert_eq!(context.len(), 4);
+        assert!(seen.lock().unwrap().is_empty());
+
+        let context = context.add_message(mock_message(MessageRole::Model, "m2"));
+        assert_eq!(context.len(), 5);
+        let context = block_on(context.apply_rolling_summary()).unwrap();
+
+        let first_summary = format!("{}\nsummary 1", SUMMARY_HEADER);
+        assert_eq!(
+            contents(&context),
+            vec!["Be brief.", first_summary.as_str(), "u2", "m2"]
+        );
+        assert!(context.history[1].role == MessageRole::System);
+        let prompt = seen.lock().unwrap()[0].clone();
+        assert_eq!(prompt[..2], ["u1", "m1"]);
+        assert_eq!(prompt.len(), 3);
+
+        // The next roll folds the earlier summary into a new one
+        let context = context.add_message(mock_message(MessageRole::User, "u3"));
+        let context = block_on(context.apply_rolling_summary()).unwrap();
+        let second_summary = format!("{}\nsummary 2", SUMMARY_HEADER);
+        assert_eq!(
+            contents(&context),
+            vec!["Be brief.", second_summary.as_str(), "m2", "u3"]
+        );
+        assert_eq!(
+            seen.lock().unwrap()[1][..2],
+            [first_summary, "u2".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_failed_summary_is_returned_and_leaves_history() {
+        let (summarizer, calls) =
+            counting_adapter(mock_adapter_factory(vec![Err("overloaded".to_string())]));
+        let mut context = ContextBuilder::new().with_rolling_summary_window(summarizer, 200, 1, 2);
+        for turn in ["u1", "m1", "u2"] {
+            context = context.add_message(mock_message(MessageRole::User, turn));
+        }
+        // Adding messages never calls the summarizer
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let result = block_on(context.clone().apply_rolling_summary());
+        assert_eq!(result.err(), Some("overloaded".to_string()));
+        assert_eq!(contents(&context), vec!["u1", "m1", "u2"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(context.summarize_strategy.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_send_summarizes_on_a_current_thread_runtime() {
+        // A summarizer that needs the runtime it was called from
+        let summarizer: ProviderAdapter = Arc::new(|_, _| {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                Ok(mock_text_response("the story so far"))
+            })
+        });
+        let mut context = ContextBuilder::new().with_rolling_summary_window(summarizer, 200, 1, 2);
+        for turn in ["u1", "m1", "u2"] {
+            context = context.add_message(mock_message(MessageRole::User, turn));
+        }
+
+        let model = mock_adapter_factory(vec![Ok(mock_text_response("m2"))]);
+        let context = context.send(model, 100).await.resolve_without();
+
+        let summary = format!("{}\nthe story so far", SUMMARY_HEADER);
+        assert_eq!(contents(&context), vec![summary.as_str(), "u2", "m2"]);
+    }
+
+    #[test]
+    fn test_pinned_and_system_messages_are_kept() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let context = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::User, "u1"))
+            .add_message(Message {
+                pinned: true,
+                ..mock_message(MessageRole::User, "my name is Ada")
+            })
+            .add_message(mock_message(MessageRole::System, "late rule"))
+            .add_message(mock_message(MessageRole::Model, "m1"))
+            .with_rolling_summary_window(recording_summarizer(seen.clone()), 200, 1, 3);
+        let context = block_on(context.apply_rolling_summary()).unwrap();
+
+        let summary = format!("{}\nsummary 1", SUMMARY_HEADER);
+        assert_eq!(
+            contents(&context),
+            vec!["my name is Ada", "late rule", summary.as_str(), "m1"]
+        );
+        assert_eq!(seen.lock().unwrap()[0][0], "u1");
+        assert_eq!(seen.lock().unwrap()[0].len(), 2);
+    }
+
+    /// Three turns past a threshold of 2, summarized down to the last one
+    fn overgrown(summarizer: ProviderAdapter) -> ContextBuilder {
+        let mut context = ContextBuilder::new().with_rolling_summary_window(summarizer, 200, 1, 2);
+        for turn in ["u1", "m1", "u2"] {
+            context = context.add_message(mock_message(MessageRole::User, turn));
+        }
+        context
+    }
+
+    #[test]
+    fn test_streaming_sends_summarize_first() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let model: StreamProviderAdapter = {
+            let sent = sent.clone();
+            let deltas = mock_streaming_adapter_factory(vec![Ok(PromptResponseDelta {
+                content: "m2".to_string(),
+                stop_reason: None,
+                tool_calls: None,
+                cumulative_tokens: 0,
+                token_delta: 0,
+                logprobs: None,
+            })]);
+            Arc::new(move |context: ContextBuilder, max_tokens| {
+                sent.lock().unwrap().push(contents(&context).join("|"));
+                deltas(context, max_tokens)
+            })
+        };
+        let summary = format!("{}\nsummary 1", SUMMARY_HEADER);
+        let expected = format!("{}|u2", summary);
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let deltas: Vec<_> = block_on(
+            overgrown(recording_summarizer(seen.clone()))
+                .send_streaming(model.clone(), 100)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(seen.lock().unwrap().len(), 1);
+
+        let seen = Arc::new(Mutex::new(vec![]));
+        let response = block_on(
+            overgrown(recording_summarizer(seen.clone())).send_streaming_with_callback(
+                model,
+                100,
+                |_| {},
+            ),
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(
+            contents(&response.resolve_without()),
+            vec![summary.as_str(), "u2", "m2"]
+        );
+        assert_eq!(*sent.lock().unwrap(), vec![expected.clone(), expected]);
+    }
+
+    #[test]
+    fn test_send_n_summarizes_once_and_surfaces_errors() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let model = mock_adapter_factory(vec![Ok(mock_text_response("m2"))]);
+        let results = block_on(
+            overgrown(recording_summarizer(seen.clone())).send_n_concurrent(model.clone(), 100, 2),
+        );
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        let context = &results[0].as_ref().ok().unwrap().context_builder;
+        assert_eq!(context.len(), 2);
+
+        let failing = mock_adapter_factory(vec![Err("overloaded".to_string())]);
+        let results = block_on(overgrown(failing).send_n(model, 100, 2));
+        assert!(
+            results
+                .iter()
+                .all(|result| result.as_ref().err().map(String::as_str) == Some("overloaded"))
+        );
+    }
+}
@@ -0,0 +1,209 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+    use futures::executor::block_on;
+    use steelwool::anonymize::AnonymizerRules;
+    use steelwool::budget::TokenBudget;
+    use steelwool::providers::mock::{mock_streaming_adapter_factory, mock_text_response};
+    use steelwool::store::{ConversationStore, InMemoryConversationStore};
+    use steelwool::tenant::{NamespacedStore, StaticTenantResolver, TENANT, TenantContext};
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, ProviderAdapter,
+    };
+
+    fn conversation(tenant: &str, content: &str) -> ContextBuilder {
+        ContextBuilder::new()
+            .with_metadata(TENANT, tenant)
+            .add_message(Message {
+                role: MessageRole::User,
+                content: content.to_string(),
+                content_type: ContentType::Text,
+                pinned: false,
+                assistant_tool_calls: None,
+            })
+    }
+
+    /// Replies `reply` using `tokens`, recording each prompt it's sent
+    fn adapter(reply: &'static str, tokens: u32) -> (ProviderAdapter, Arc<Mutex<Vec<String>>>) {
+        let prompts = Arc::new(Mutex::new(vec![]));
+        let seen = prompts.clone();
+        let adapter: ProviderAdapter = Arc::new(move |context: ContextBuilder, _| {
+            seen.lock()
+                .unwrap()
+                .push(context.history.last().unwrap().content.clone());
+            let mut response = mock_text_response(reply);
+            response.token_usage = tokens;
+            Box::pin(async move { Ok(response) })
+        });
+        (adapter, prompts)
+    }
+
+    fn tenant(
+        adapter: ProviderAdapter,
+        budget: u64,
+        shared: &Arc<InMemoryConversationStore>,
+        namespace: &str,
+    ) -> TenantContext {
+        TenantContext {
+            adapter,
+            streaming_adapter: None,
+            budget: Arc::new(TokenBudget::new(budget)),
+            store: Arc::new(NamespacedStore::new(shared.clone(), namespace)),
+            redaction: None,
+        }
+    }
+
+    #[test]
+    fn test_tenants_are_isolated() {
+        let shared = Arc::new(InMemoryConversationStore::new());
+        let (acme_adapter, acme_prompts) = adapter("Hello from Acme", 40);
+        let (globex_adapter, globex_prompts) = adapter("Hello from Globex", 70);
+        let acme = tenant(acme_adapter, 1000, &shared, "acme");
+        let globex = tenant(globex_adapter, 500, &shared, "globex");
+        let resolver = StaticTenantResolver::new()
+            .with_tenant("acme", acme.clone())
+            .with_tenant("globex", globex.clone());
+
+        let acme_reply = block_on(conversation("acme", "hi").send_for_tenant(&resolver, 100))
+            .unwrap()
+            .resolve_without();
+        let globex_reply = block_on(conversation("globex", "hey").send_for_tenant(&resolver, 100))
+            .unwrap()
+            .resolve_without();
+
+        assert_eq!(acme_reply.history[1].content, "Hello from Acme");
+        assert_eq!(globex_reply.history[1].content, "Hello from Globex");
+        assert_eq!(*acme_prompts.lock().unwrap(), vec!["hi"]);
+        assert_eq!(*globex_prompts.lock().unwrap(), vec!["hey"]);
+
+        // Each tenant pays only for its own usage
+        assert_eq!(acme.budget.remaining(), 960);
+        assert_eq!(globex.budget.remaining(), 430);
+
+        // Same key, separate conversations
+        acme_reply.save_for_tenant(&resolver, "chat").unwrap();
+        globex_reply.save_for_tenant(&resolver, "chat").unwrap();
+        let loaded = ContextBuilder::load_for_tenant(&resolver, "acme", "chat")
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.history[1].content, "Hello from Acme");
+        assert_eq!(acme.store.list().unwrap(), vec!["chat"]);
+        assert_eq!(globex.store.list().unwrap(), vec!["chat"]);
+
+        let mut keys = shared.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["acme/chat", "globex/chat"]);
+
+        globex.store.delete("chat").unwrap();
+        assert!(acme.store.load("chat").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_unknown_or_missing_tenant_is_an_error() {
+        let shared = Arc::new(InMemoryConversationStore::new());
+        let (acme_adapter, prompts) = adapter("Hello", 10);
+        let resolver = StaticTenantResolver::new()
+            .with_tenant("acme", tenant(acme_adapter, 100, &shared, "acme"));
+
+        let err = block_on(conversation("initech", "hi").send_for_tenant(&resolver, 50))
+            .err()
+            .unwrap();
+        assert_eq!(err, "Unknown tenant: initech");
+
+        let untagged = ContextBuilder::new();
+        assert!(block_on(untagged.send_for_tenant(&resolver, 50)).is_err());
+        assert!(
+            ContextBuilder::new()
+                .save_for_tenant(&resolver, "chat")
+                .is_err()
+        );
+        assert!(prompts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_budget_caps_and_exhausts() {
+        let shared = Arc::new(InMemoryConversationStore::new());
+        let max_tokens = Arc::new(Mutex::new(vec![]));
+        let seen = max_tokens.clone();
+        let capped: ProviderAdapter = Arc::new(move |_, max_tokens| {
+            seen.lock().unwrap().push(max_tokens);
+            let mut response = mock_text_response("ok");
+            response.token_usage = max_tokens;
+            Box::pin(async move { Ok(response) })
+        });
+        let acme = tenant(capped, 150, &shared, "acme");
+        let resolver = StaticTenantResolver::new().with_tenant("acme", acme.clone());
+
+        assert!(block_on(conversation("acme", "hi").send_for_tenant(&resolver, 100)).is_ok());
+        assert!(block_on(conversation("acme", "hi").send_for_tenant(&resolver, 100)).is_ok());
+        let err = block_on(conversation("acme", "hi").send_for_tenant(&resolver, 100))
+            .err()
+            .unwrap();
+
+        assert_eq!(*max_tokens.lock().unwrap(), vec![100, 50]);
+        assert_eq!(err, "Budget exhausted for tenant: acme");
+        assert_eq!(acme.budget.remaining(), 0);
+    }
+
+    #[test]
+    fn test_redaction_rules_apply_per_tenant() {
+        let shared = Arc::new(InMemoryConversationStore::new());
+        let (echo, prompts) = adapter("Sure, I'll email <EMAIL_1>.", 5);
+        let mut acme = tenant(echo, 100, &shared, "acme");
+        acme.redaction = Some(
+            AnonymizerRules::new()
+                .pattern("EMAIL", r"[\w.+-]+@[\w-]+\.[\w.]+")
+                .unwrap(),
+        );
+        let resolver = StaticTenantResolver::new().with_tenant("acme", acme);
+
+        let response =
+            block_on(conversation("acme", "Email ada@example.com").send_for_tenant(&resolver, 50))
+                .unwrap();
+
+        assert_eq!(*prompts.lock().unwrap(), vec!["Email <EMAIL_1>"]);
+        assert_eq!(
+            response.prompt_response.message.content,
+            "Sure, I'll email ada@example.com."
+        );
+    }
+
+    #[test]
+    fn test_streaming_spends_tenant_budget() {
+        let shared = Arc::new(InMemoryConversationStore::new());
+        let (unused, _) = adapter("", 0);
+        let mut acme = tenant(unused.clone(), 1000, &shared, "acme");
+        acme.streaming_adapter = Some(mock_streaming_adapter_factory(vec![Ok(
+            PromptResponseDelta {
+                content: "Hi there".to_string(),
+                stop_reason: None,
+                tool_calls: None,
+                cumulative_tokens: 12,
+                token_delta: 12,
+                logprobs: None,
+            },
+        )]));
+        let globex = tenant(unused, 1000, &shared, "globex");
+        let resolver = StaticTenantResolver::new()
+            .with_tenant("acme", acme.clone())
+            .with_tenant("globex", globex.clone());
+
+        let deltas: Vec<_> = block_on(
+            conversation("acme", "hi")
+                .send_streaming_for_tenant(&resolver, 100)
+                .unwrap()
+                .collect(),
+        );
+
+        assert_eq!(deltas[0].as_ref().unwrap().content, "Hi there");
+        assert_eq!(acme.budget.remaining(), 988);
+        assert_eq!(globex.budget.remaining(), 1000);
+        assert!(
+            conversation("globex", "hi")
+                .send_streaming_for_tenant(&resolver, 100)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,74 @@
+#[cfg(all(test, feature = "tiktoken"))]
+mod tests {
+    use steelwool::providers::mock::mock_message;
+    use steelwool::{ContextBuilder, Message, MessageRole};
+
+    fn message(content: &str) -> Message {
+        mock_message(MessageRole::User, content)
+    }
+
+    #[test]
+    fn test_tokens_per_message() {
+        let context = ContextBuilder::new()
+            .add_message(message("hello world"))
+            .add_message(message(""));
+
+        let tokens = context.to_tiktoken_tokens("gpt-4").unwrap();
+
+        assert_eq!(tokens, vec![vec![15339, 1917], vec![]]);
+    }
+
+    #[test]
+    fn test_unknown_model_is_an_error() {
+        let result = ContextBuilder::new().to_tiktoken_tokens("not-a-model");
+        assert!(result.unwrap_err().starts_with("No tiktoken encoding"));
+    }
+
+    #[test]
+    fn test_split_repeats_system_head() {
+        // "hello world" is 2 tokens under cl100k
+        let context = (1..=5).fold(
+            ContextBuilder::new().add_message(mock_message(MessageRole::System, "hello world")),
+            |ctx, _| ctx.add_message(message("hello world")),
+        );
+
+        let chunks = context.split_by_token_limit("gpt-4", 6).unwrap();
+
+        let shapes: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+        assert_eq!(shapes, vec![3, 3, 2]);
+        assert!(
+            chunks
+                .iter()
+                .all(|c| c.history[0].role == MessageRole::System)
+        );
+    }
+
+    #[test]
+    fn test_split_rejects_what_cannot_fit() {
+        let context = ContextBuilder::new()
+            .add_message(mock_message(MessageRole::System, "hello world"))
+            .add_message(message("hello world hello world"));
+
+        assert!(
+            context
+                .split_by_token_limit("gpt-4", 1)
+                .err()
+                .unwrap()
+                .starts_with("System messages alone are 2 tokens")
+        );
+        assert!(
+            context
+                .split_by_token_limit("gpt-4", 4)
+                .err()
+                .unwrap()
+                .starts_with("Message 1 is 4 tokens")
+        );
+        assert_eq!(
+            ContextBuilder::new()
+                .split_by_token_limit("gpt-4", 4)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,311 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use futures::executor::block_on;
+    use steelwool::providers::mock::{
+        mock_adapter_factory, mock_message, mock_text_response, stub_tool_executer,
+    };
+    use steelwool::tool_gc::{RESULT_UNAVAILABLE, ToolGcPolicy, UNKNOWN_TOOL};
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, StopReason, ToolCall,
+    };
+
+    fn call(id: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            name: "get_weather".to_string(),
+            arguments: serde_json::json!({ "city": "Oslo" }),
+        }
+    }
+
+    fn question() -> ContextBuilder {
+        ContextBuilder::new().add_message(mock_message(MessageRole::User, "Weather in Oslo?"))
+    }
+
+    fn answer(context: ContextBuilder) -> ContextBuilder {
+        context.add_message(mock_message(MessageRole::Model, "It's 4C and raining."))
+    }
+
+    /// Each way a history ends up with half a pair
+    fn orphans() -> Vec<(&'static str, ContextBuilder)> {
+        vec![
+            (
+                "missing result",
+                answer(question().add_tool_use_message(call("call_1"))),
+            ),
+            (
+                "missing call",
+                answer(question().add_tool_result_message("call_1", "4C".to_string(), false)),
+            ),
+            (
+                "id mismatch",
+                answer(
+                    question()
+                        .add_tool_use_message(call("call_1"))
+                        .add_tool_result_message("call_2", "4C".to_string(), false),
+                ),
+            ),
+        ]
+    }
+
+    /// A tool turn as `send` and `resolve` record it: the calls on the model
+    /// message's `assistant_tool_calls`, then a result per call
+    fn resolved_tool_turn() -> ContextBuilder {
+        let calls = vec![call("call_1"), call("call_2")];
+        let mut response = mock_text_response("");
+        response.message.assistant_tool_calls = Some(calls.clone());
+        let model = mock_adapter_factory(vec![Ok(PromptResponse {
+            stop_reason: StopReason::ToolCalls,
+            tool_calls: Some(calls),
+            ..response
+        })]);
+        let tools = stub_tool_executer(HashMap::from([(
+            "get_weather".to_string(),
+            "4C".to_string(),
+        )]));
+
+        answer(block_on(
+            block_on(question().send(model, 100)).resolve(tools),
+        ))
+    }
+
+    fn kinds(context: &ContextBuilder) -> Vec<&'static str> {
+        context
+            .history
+            .iter()
+            .map(|msg| match &msg.content_type {
+                ContentType::ToolUse(_) => "use",
+                ContentType::ToolResult(_) => "result",
+                _ => "text",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_complete_pairs_are_untouched() {
+        let context = answer(
+            question()
+                .add_tool_use_message(call("call_1"))
+                .add_tool_use_message(call("call_2"))
+                .add_tool_result_message("call_1", "4C".to_string(), false)
+                .add_tool_result_message("call_2", "5C".to_string(), false),
+        );
+
+        for policy in [
+            ToolGcPolicy::Drop,
+            ToolGcPolicy::Stub,
+            ToolGcPolicy::Narrate,
+        ] {
+            let collected = context.clone().gc_tool_messages(policy);
+            assert_eq!(
+                kinds(&collected),
+                ["text", "use", "use", "result", "result", "text"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolved_tool_turn_is_untouched() {
+        let context = resolved_tool_turn();
+        assert_eq!(
+            kinds(&context),
+            ["text", "text", "result", "result", "text"]
+        );
+
+        for policy in [
+            ToolGcPolicy::Drop,
+            ToolGcPolicy::Stub,
+            ToolGcPolicy::Narrate,
+        ] {
+            let collected = context.clone().gc_tool_messages(policy);
+            assert!(collected.history == context.history, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn test_unanswered_assistant_tool_calls_are_repaired() {
+        let mut context = resolved_tool_turn();
+        context.history.remove(3);
+        let call_ids = |msg: &Message| -> Vec<String> {
+            msg.assistant_tool_calls
+                .iter()
+                .flatten()
+                .map(|call| call.id.clone())
+                .collect()
+        };
+
+        let dropped = context.clone().gc_tool_messages(ToolGcPolicy::Drop);
+        assert_eq!(kinds(&dropped), ["text", "text", "result", "text"]);
+        assert_eq!(call_ids(&dropped.history[1]), ["call_1"]);
+
+        let stubbed = context.clone().gc_tool_messages(ToolGcPolicy::Stub);
+        assert_eq!(
+            kinds(&stubbed),
+            ["text", "text", "result", "result", "text"]
+        );
+        assert_eq!(call_ids(&stubbed.history[1]), ["call_1", "call_2"]);
+        assert!(matches!(
+            &stubbed.history[2].content_type,
+            ContentType::ToolResult(result) if result.tool_call_id == "call_2" && result.error
+        ));
+
+        let narrated = context.clone().gc_tool_messages(ToolGcPolicy::Narrate);
+        assert_eq!(kinds(&narrated), ["text", "text", "result", "text"]);
+        assert_eq!(call_ids(&narrated.history[1]), ["call_1"]);
+        assert!(narrated.history[1].content.contains("get_weather"));
+
+        // With every call unanswered, an empty turn has nothing left to keep
+        context.history.remove(2);
+        let dropped = context.gc_tool_messages(ToolGcPolicy::Drop);
+        assert_eq!(kinds(&dropped), ["text", "text"]);
+    }
+
+    #[test]
+    fn test_drop_removes_orphans() {
+        for (name, context) in orphans() {
+            let collected = context.gc_tool_messages(ToolGcPolicy::Drop);
+            assert_eq!(kinds(&collected), ["text", "text"], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_stub_adds_missing_halves() {
+        let expected = [
+            vec!["text", "use", "result", "text"],
+            vec!["text", "use", "result", "text"],
+            vec!["text", "use", "result", "use", "result", "text"],
+        ];
+
+        for ((name, context), expected) in orphans().into_iter().zip(expected) {
+            let collected = context.gc_tool_messages(ToolGcPolicy::Stub);
+            assert_eq!(kinds(&collected), expected, "{}", name);
+        }
+
+        let (_, missing_result) = orphans().remove(0);
+        let collected = missing_result.gc_tool_messages(ToolGcPolicy::Stub);
+        assert_eq!(collected.history[2].content, RESULT_UNAVAILABLE);
+        assert!(matches!(
+            &collected.history[2].content_type,
+            ContentType::ToolResult(result) if result.tool_call_id == "call_1" && result.error
+        ));
+
+        let (_, missing_call) = orphans().remove(1);
+        let collected = missing_call.gc_tool_messages(ToolGcPolicy::Stub);
+        assert!(matches!(
+            &collected.history[1].content_type,
+            ContentType::ToolUse(call) if call.id == "call_1" && call.name == UNKNOWN_TOOL
+        ));
+    }
+
+    #[test]
+    fn test_narrate_rewrites_orphans_as_text() {
+        let (_, mismatch) = orphans().remove(2);
+        let collected = mismatch
+            .pin_message(2)
+            .gc_tool_messages(ToolGcPolicy::Narrate);
+
+        assert_eq!(kinds(&collected), ["text", "text", "text", "text"]);
+        assert!(collected.history[1].role == MessageRole::Model);
+        assert!(collected.history[1].content.contains("get_weather"));
+        assert!(collected.history[2].content.contains("call_2"));
+        assert!(collected.history[2].content.contains("4C"));
+        assert!(collected.history[2].pinned);
+    }
+
+    #[cfg(feature = "openai")]
+    mod openai {
+        use super::*;
+        use steelwool::providers::openai::{RoleMapping, build_chat_completion_message_history};
+
+        /// OpenAI wants each assistant turn with `tool_calls` followed by a
+        /// tool message per call, and no tool message that isn't one of those
+        fn validate(context: &ContextBuilder) -> Result<(), String> {
+            let messages = build_chat_completion_message_history(context, &RoleMapping::default())?;
+            let mut pending: Vec<String> = vec![];
+
+            for msg in &messages {
+                if msg["role"] == "tool" {
+                    let id = msg["tool_call_id"].as_str().unwrap_or_default().to_string();
+                    let position = pending.iter().position(|p| *p == id);
+                    match position {
+                        Some(position) => pending.remove(position),
+                        None => return Err(format!("tool message {} answers no call", id)),
+                    };
+                    continue;
+                }
+                if !pending.is_empty() {
+                    return Err(format!("calls {:?} have no result", pending));
+                }
+                if let Some(calls) = msg["tool_calls"].as_array() {
+                    pending = calls
+                        .iter()
+                        .map(|c| c["id"].as_str().unwrap().to_string())
+                        .collect();
+                }
+            }
+            match pending.is_empty() {
+                true => Ok(()),
+                false => Err(format!("calls {:?} have no result", pending)),
+            }
+        }
+
+        #[test]
+        fn test_orphans_fail_validation() {
+            for (name, context) in orphans() {
+                assert!(validate(&context).is_err(), "{}", name);
+            }
+        }
+
+        #[test]
+        fn test_resolved_tool_turn_validates_under_every_policy() {
+            let mut broken = resolved_tool_turn();
+            broken.history.remove(3);
+            assert!(validate(&broken).is_err());
+
+            for policy in [
+                ToolGcPolicy::Drop,
+                ToolGcPolicy::Stub,
+                ToolGcPolicy::Narrate,
+            ] {
+                let collected = resolved_tool_turn().gc_tool_messages(policy);
+                assert_eq!(validate(&collected), Ok(()), "complete under {:?}", policy);
+                let collected = broken.clone().gc_tool_messages(policy);
+                assert_eq!(validate(&collected), Ok(()), "broken under {:?}", policy);
+            }
+        }
+
+        #[test]
+        fn test_every_policy_output_validates() {
+            for policy in [
+                ToolGcPolicy::Drop,
+                ToolGcPolicy::Stub,
+                ToolGcPolicy::Narrate,
+            ] {
+                for (name, context) in orphans() {
+                    let collected = context.gc_tool_messages(policy);
+                    assert_eq!(validate(&collected), Ok(()), "{} under {:?}", name, policy);
+                }
+            }
+        }
+
+        #[test]
+        fn test_parallel_calls_share_one_assistant_turn() {
+            let context = question()
+                .add_tool_use_message(call("call_1"))
+                .add_tool_use_message(call("call_2"))
+                .add_tool_result_message("call_1", "4C".to_string(), false)
+                .add_tool_result_message("call_2", "5C".to_string(), false);
+
+            let messages =
+                build_chat_completion_message_history(&context, &RoleMapping::default()).unwrap();
+            assert_eq!(messages.len(), 4);
+            assert_eq!(messages[1]["tool_calls"].as_array().unwrap().len(), 2);
+            assert_eq!(
+                messages[1]["tool_calls"][0]["function"]["arguments"],
+                r#"{"city":"Oslo"}"#
+            );
+            assert_eq!(validate(&context), Ok(()));
+        }
+    }
+}
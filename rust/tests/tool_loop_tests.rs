@@ -0,0 +1,541 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use futures::executor::block_on;
+    use steelwool::providers::mock::{
+        mock_adapter_factory, mock_text_response, stub_tool_executer,
+    };
+    use steelwool::tool_loop::{ParamsSchedule, SpeculationPolicy, TurnInfo};
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponse, ProviderAdapter,
+        RequestParams, StopReason, ToolCall, ToolCallLog, ToolExecuter, UnresolvedResponse,
+    };
+
+    fn weather_call(id: &str) -> PromptResponse {
+        PromptResponse {
+            stop_reason: StopReason::ToolCalls,
+            tool_calls: Some(vec![ToolCall {
+                id: id.to_string(),
+                name: "get_weather".to_string(),
+                arguments: serde_json::json!({ "location": "Oslo" }),
+            }]),
+            ..mock_text_response("")
+        }
+    }
+
+    /// Replays `script` and records the temperature each call was sent with
+    fn recording_adapter(
+        script: Vec<PromptResponse>,
+        seen: Arc<Mutex<Vec<Option<f32>>>>,
+    ) -> ProviderAdapter {
+        Arc::new(move |context: ContextBuilder, _| {
+            let mut seen = seen.lock().unwrap();
+            seen.push(context.params.temperature);
+            let response = script[(seen.len() - 1).min(script.len() - 1)].clone();
+            Box::pin(async move { Ok(response) })
+        })
+    }
+
+    fn executer() -> ToolExecuter {
+        stub_tool_executer(HashMap::from([(
+            "get_weather".to_string(),
+            "sunny".to_string(),
+        )]))
+    }
+
+    fn question() -> ContextBuilder {
+        ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Weather in Oslo?".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        })
+    }
+
+    fn temperature(t: f32) -> RequestParams {
+        RequestParams {
+            temperature: Some(t),
+            ..RequestParams::default()
+        }
+    }
+
+    #[test]
+    fn test_tool_phase_then_creative() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let adapter = recording_adapter(
+            vec![weather_call("call_1"), mock_text_response("Sunny!")],
+            seen.clone(),
+        );
+        let schedule = ParamsSchedule::ToolPhaseThenCreative {
+            tool_opts: temperature(0.0),
+            answer_opts: temperature(0.9),
+        };
+
+        let context =
+            block_on(question().send_tool_loop(adapter, executer(), 100, 5, &schedule)).unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), vec![Some(0.0), Some(0.9)]);
+        assert_eq!(context.history.last().unwrap().content, "Sunny!");
+        assert!(context.params == RequestParams::default());
+    }
+
+    #[test]
+    fn test_custom_schedule_layers_over_context_params() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let adapter = recording_adapter(
+            vec![
+                weather_call("call_1"),
+                weather_call("call_2"),
+                mock_text_response("Sunny!"),
+            ],
+            seen.clone(),
+        );
+        let turns = Arc::new(Mutex::new(vec![]));
+        let turns_clone = turns.clone();
+        let schedule = ParamsSchedule::Custom(Arc::new(move |turn: &TurnInfo| {
+            turns_clone.lock().unwrap().push(turn.turn_index);
+            match turn.turn_index {
+                0 => RequestParams::default(),
+                i => temperature(i as f32 / 10.0),
+            }
+        }));
+
+        let mut context = question();
+        context.params = temperature(0.5);
+        block_on(context.send_tool_loop(adapter, executer(), 100, 5, &schedule)).unwrap();
+
+        assert_eq!(*turns.lock().unwrap(), vec![0, 1, 2]);
+        assert_eq!(*seen.lock().unwrap(), vec![Some(0.5), Some(0.1), Some(0.2)]);
+    }
+
+    #[test]
+    fn test_loop_gives_up_after_max_turns() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let adapter = recording_adapter(vec![weather_call("call_1")], seen.clone());
+
+        let result = block_on(question().send_tool_loop(
+            adapter,
+            executer(),
+            100,
+            3,
+            &ParamsSchedule::default(),
+        ));
+
+        assert_eq!(
+            result.err().unwrap(),
+            "Tool loop still calling tools after 3 turns"
+        );
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_adapter_errors_are_returned() {
+        let adapter = mock_adapter_factory(vec![
+            Ok(weather_call("call_1")),
+            Err("503 Service Unavailable".to_string()),
+        ]);
+        let result = block_on(question().send_tool_loop(
+            adapter,
+            executer(),
+            100,
+            3,
+            &ParamsSchedule::default(),
+        ));
+        assert_eq!(result.err().unwrap(), "503 Service Unavailable");
+
+        let adapter = mock_adapter_factory(vec![Err("503 Service Unavailable".to_string())]);
+        let result = block_on(question().send_tool_loop_speculative(
+            adapter,
+            executer(),
+            100,
+            3,
+            &ParamsSchedule::default(),
+            &SpeculationPolicy::disabled(),
+        ));
+        assert_eq!(result.err().unwrap(), "503 Service Unavailable");
+    }
+
+    #[test]
+    fn test_stub_executer_looks_up_by_name() {
+        let call = |name: &str| ToolCall {
+            id: "call_1".to_string(),
+            name: name.to_string(),
+            arguments: serde_json::json!({}),
+        };
+
+        assert_eq!(block_on(executer()(call("get_weather"))).unwrap(), "sunny");
+        assert_eq!(
+            block_on(executer()(call("get_time"))).unwrap_err(),
+            "unknown tool: get_time"
+        );
+    }
+
+    #[test]
+    fn test_exec_tool_calls_with_log() {
+        let logs: Arc<Mutex<Vec<ToolCallLog>>> = Arc::new(Mutex::new(vec![]));
+        let logs_clone = logs.clone();
+        let mut response = weather_call("call_1");
+        response.tool_calls.as_mut().unwrap().push(ToolCall {
+            id: "call_2".to_string(),
+            name: "get_time".to_string(),
+            arguments: serde_json::json!({ "zone": "CET" }),
+        });
+
+        let resolved = block_on(
+            UnresolvedResponse {
+                prompt_response: response,
+                context_builder: question(),
+            }
+            .exec_tool_calls_with_log(
+                executer(),
+                Some(Arc::new(move |log| logs_clone.lock().unwrap().push(log))),
+            ),
+        );
+
+        let logs = logs.lock().unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(
+            (logs[0].call_id.as_str(), logs[0].tool_name.as_str()),
+            ("call_1", "get_weather")
+        );
+        assert_eq!(logs[0].arguments, serde_json::json!({ "location": "Oslo" }));
+        assert_eq!(logs[0].result, Ok("sunny".to_string()));
+        assert_eq!(logs[1].result, Err("unknown tool: get_time".to_string()));
+        assert_eq!(resolved.context_builder.len(), 4);
+    }
+
+    /* ------------------------------ Speculation ----------------------------- */
+
+    #[cfg(feature = "tokio-runtime")]
+    mod speculation {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        use steelwool::tool_loop::{SpeculationPolicy, SpeculationReport};
+        use tokio::time::{Instant, sleep};
+
+        use super::*;
+
+        fn call(id: &str, name: &str) -> ToolCall {
+            ToolCall {
+                id: id.to_string(),
+                name: name.to_string(),
+                arguments: serde_json::json!({}),
+            }
+        }
+
+        /// Asks for a quick lookup and a slow save, then answers after two
+        /// seconds by quoting the tool messages, 10 tokens per reply
+        fn model(calls: Arc<AtomicUsize>) -> ProviderAdapter {
+            Arc::new(move |context: ContextBuilder, _| {
+                let turn = calls.fetch_add(1, Ordering::SeqCst);
+                let tools: Vec<String> = context
+                    .history
+                    .iter()
+                    .filter(|msg| msg.role == MessageRole::Tool)
+                    .map(|msg| msg.content.clone())
+                    .collect();
+                Box::pin(async move {
+                    if turn == 0 {
+                        return Ok(PromptResponse {
+                            stop_reason: StopReason::ToolCalls,
+                            tool_calls: Some(vec![
+                                call("call_1", "lookup"),
+                                call("call_2", "save"),
+                            ]),
+                            token_usage: 10,
+                            ..mock_text_response("")
+                        });
+                    }
+                    sleep(Duration::from_secs(2)).await;
+                    Ok(PromptResponse {
+                        token_usage: 10,
+                        ..mock_text_response(&format!("Done: {}", tools.join(", ")))
+                    })
+                })
+            })
+        }
+
+        /// `lookup` takes a second; `save` takes five and returns `saved`
+        fn tools(saved: &'static str) -> ToolExecuter {
+            Arc::new(move |call: ToolCall| {
+                Box::pin(async move {
+                    match call.name.as_str() {
+                        "lookup" => {
+                            sleep(Duration::from_secs(1)).await;
+                            Ok("found".to_string())
+                        }
+                        _ => {
+                            sleep(Duration::from_secs(5)).await;
+                            Ok(saved.to_string())
+                        }
+                    }
+                })
+            })
+        }
+
+        fn predict_saved() -> SpeculationPolicy {
+            SpeculationPolicy::new(Arc::new(|call: &ToolCall| {
+                (call.name == "save").then(|| "saved".to_string())
+            }))
+        }
+
+        async fn run(
+            saved: &'static str,
+            speculation: SpeculationPolicy,
+        ) -> (ContextBuilder, SpeculationReport, usize, Duration) {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let started = Instant::now();
+            let (context, report) = question()
+                .send_tool_loop_speculative(
+                    model(calls.clone()),
+                    tools(saved),
+                    100,
+                    5,
+                    &ParamsSchedule::default(),
+                    &speculation,
+                )
+                .await
+                .unwrap();
+            (
+                context,
+                report,
+                calls.load(Ordering::SeqCst),
+                started.elapsed(),
+            )
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn test_hit_reuses_the_early_response() {
+            let (context, report, calls, elapsed) = run("saved", predict_saved()).await;
+
+            // The follow-up ran from 1s to 3s, hidden behind the 5s save
+            assert_eq!(elapsed, Duration::from_secs(5));
+            assert_eq!(calls, 2);
+            assert_eq!(
+                context.history.last().unwrap().content,
+                "Done: found, saved"
+            );
+            assert_eq!((report.hits, report.misses), (1, 0));
+            assert_eq!((report.tokens_used, report.tokens_wasted), (20, 0));
+            assert_eq!(
+                report.provenance.details_from("speculation"),
+                vec!["hit on save call call_2"]
+            );
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn test_miss_resends_with_the_real_result() {
+            let (context, report, calls, elapsed) = run("disk full", predict_saved()).await;
+
+            assert_eq!(elapsed, Duration::from_secs(7));
+            assert_eq!(calls, 3);
+            assert_eq!(
+                context.history.last().unwrap().content,
+                "Done: found, disk full"
+            );
+            assert_eq!(context.history[2].content, "found");
+            assert_eq!(context.history[3].content, "disk full");
+            assert_eq!((report.hits, report.misses), (0, 1));
+            assert_eq!((report.tokens_used, report.tokens_wasted), (20, 10));
+            assert_eq!(
+                report.provenance.details_from("speculation"),
+                vec!["miss on save call call_2: 10 tokens discarded"]
+            );
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn test_disabled_waits_for_every_tool() {
+            let (context, report, calls, elapsed) =
+                run("saved", SpeculationPolicy::disabled()).await;
+
+            assert_eq!(elapsed, Duration::from_secs(7));
+            assert_eq!(calls, 2);
+            assert_eq!(
+                context.history.last().unwrap().content,
+                "Done: found, saved"
+            );
+            assert_eq!((report.hits, report.misses), (0, 0));
+            assert_eq!(report.tokens_used, 20);
+            assert!(report.provenance.notes.is_empty());
+        }
+    }
+
+    mod streaming {
+        use super::*;
+        use futures::StreamExt;
+        use futures::stream::{self, BoxStream};
+        use steelwool::providers::mock::{failing_streaming_adapter_factory, mock_adapter_factory};
+        use steelwool::tool_loop::{StreamingLoopAdapters, ToolLoopEvent};
+        use steelwool::{PromptResponseDelta, StreamProviderAdapter};
+
+        fn delta(content: &str, stop_reason: Option<StopReason>) -> PromptResponseDelta {
+            PromptResponseDelta {
+                content: content.to_string(),
+                stop_reason,
+                tool_calls: None,
+                cumulative_tokens: 0,
+                token_delta: 0,
+                logprobs: None,
+            }
+        }
+
+        /// Streams a `get_weather` call with no content, then the answer
+        /// once the tool result is in
+        fn streamed_model() -> StreamProviderAdapter {
+            Arc::new(|context: ContextBuilder, _| {
+                let deltas = match context.history.last().unwrap().role {
+                    MessageRole::Tool => vec![
+                        delta("It's ", None),
+                        delta("", None),
+                        delta("sunny.", Some(StopReason::Stop)),
+                    ],
+                    _ => vec![
+                        delta("", None),
+                        PromptResponseDelta {
+                            tool_calls: weather_call("call_1").tool_calls,
+                            ..delta("", Some(StopReason::ToolCalls))
+                        },
+                    ],
+                };
+                Box::pin(stream::iter(deltas.into_iter().map(Ok)))
+                    as BoxStream<'static, Result<PromptResponseDelta, String>>
+            })
+        }
+
+        fn erroring_stream(err: &'static str) -> StreamProviderAdapter {
+            Arc::new(move |_, _| {
+                Box::pin(stream::iter(vec![Err(err.to_string())]))
+                    as BoxStream<'static, Result<PromptResponseDelta, String>>
+            })
+        }
+
+        fn label(event: &ToolLoopEvent) -> String {
+            match event {
+                ToolLoopEvent::Content {
+                    turn_index,
+                    content,
+                } => format!("{} content {}", turn_index, content),
+                ToolLoopEvent::ToolCall { turn_index, call } => {
+                    format!("{} call {}", turn_index, call.id)
+                }
+                ToolLoopEvent::TurnEnd {
+                    turn_index,
+                    stop_reason,
+                } => format!(
+                    "{} end {}",
+                    turn_index,
+                    serde_json::to_string(stop_reason).unwrap()
+                ),
+                ToolLoopEvent::ToolResult {
+                    turn_index,
+                    call_id,
+                    result,
+                } => format!("{} result {} {:?}", turn_index, call_id, result),
+                ToolLoopEvent::FellBack { turn_index, error } => {
+                    format!("{} fell back: {}", turn_index, error)
+                }
+            }
+        }
+
+        fn run(adapters: StreamingLoopAdapters) -> (Result<ContextBuilder, String>, Vec<String>) {
+            let events = Arc::new(Mutex::new(vec![]));
+            let events_clone = events.clone();
+            let result = block_on(question().send_tool_loop_streaming(
+                adapters,
+                executer(),
+                100,
+                5,
+                &ParamsSchedule::default(),
+                move |event| events_clone.lock().unwrap().push(label(&event)),
+            ));
+            let events = events.lock().unwrap().clone();
+            (result, events)
+        }
+
+        #[test]
+        fn test_two_turn_loop_streams_events_in_order() {
+            let unused = mock_adapter_factory(vec![Err("not called".to_string())]);
+            let (context, events) = run(StreamingLoopAdapters::new(streamed_model(), unused));
+
+            assert_eq!(
+                events,
+                vec![
+                    "0 call call_1",
+                    "0 end \"ToolCalls\"",
+                    "0 result call_1 Ok(\"sunny\")",
+                    "1 content It's ",
+                    "1 content sunny.",
+                    "1 end \"Stop\"",
+                ]
+            );
+            let context = context.unwrap();
+            assert_eq!(context.history.len(), 4);
+            assert_eq!(context.history.last().unwrap().content, "It's sunny.");
+        }
+
+        #[test]
+        fn test_trailing_usage_delta_keeps_the_tool_turn() {
+            // Some adapters end every stream with a usage-only delta that
+            // says `Stop`, after the finish reason
+            let inner = streamed_model();
+            let with_usage: StreamProviderAdapter = Arc::new(move |context, max_tokens| {
+                let usage = PromptResponseDelta {
+                    cumulative_tokens: 12,
+                    token_delta: 12,
+                    ..delta("", Some(StopReason::Stop))
+                };
+                Box::pin(inner(context, max_tokens).chain(stream::iter(vec![Ok(usage)])))
+                    as BoxStream<'static, Result<PromptResponseDelta, String>>
+            });
+            let unused = mock_adapter_factory(vec![Err("not called".to_string())]);
+            let (context, events) = run(StreamingLoopAdapters::new(with_usage, unused));
+
+            assert_eq!(events[1], "0 end \"ToolCalls\"");
+            assert_eq!(events[2], "0 result call_1 Ok(\"sunny\")");
+            let context = context.unwrap();
+            assert_eq!(context.history.len(), 4);
+            assert_eq!(context.history.last().unwrap().content, "It's sunny.");
+        }
+
+        #[test]
+        fn test_stream_failing_before_content_falls_back() {
+            let broken = erroring_stream("connection reset by peer");
+            let fallback = mock_adapter_factory(vec![Ok(mock_text_response("Sunny!"))]);
+            let (context, events) = run(StreamingLoopAdapters::new(broken, fallback));
+
+            assert_eq!(
+                events,
+                vec![
+                    "0 fell back: connection reset by peer",
+                    "0 content Sunny!",
+                    "0 end \"Stop\"",
+                ]
+            );
+            assert_eq!(context.unwrap().history.last().unwrap().content, "Sunny!");
+        }
+
+        #[test]
+        fn test_failures_the_fallback_cannot_fix_are_returned() {
+            let fallback = || mock_adapter_factory(vec![Ok(mock_text_response("Sunny!"))]);
+
+            // Content was already shown, so the turn can't be sent again
+            let partial = failing_streaming_adapter_factory(vec![delta("It's", None)], 1);
+            let (result, events) = run(StreamingLoopAdapters::new(partial, fallback()));
+            assert_eq!(
+                result.err().unwrap(),
+                "Injected stream failure after 1 deltas"
+            );
+            assert_eq!(events, vec!["0 content It's"]);
+
+            // Not worth sending again
+            let unauthorized = erroring_stream("401 Unauthorized");
+            let (result, events) = run(StreamingLoopAdapters::new(unauthorized, fallback()));
+            assert_eq!(result.err().unwrap(), "401 Unauthorized");
+            assert!(events.is_empty());
+        }
+    }
+}
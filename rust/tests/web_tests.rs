@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use futures::executor::block_on;
+    use steelwool::providers::mock::mock_streaming_adapter_factory;
+    use steelwool::web::{WsFrame, parse_ws_frame, sse_events, ws_frames};
+    use steelwool::{
+        ContentType, ContextBuilder, Message, MessageRole, PromptResponseDelta, StopReason,
+        ToolCall,
+    };
+
+    fn delta(content: &str) -> PromptResponseDelta {
+        PromptResponseDelta {
+            content: content.to_string(),
+            stop_reason: None,
+            tool_calls: None,
+            cumulative_tokens: 0,
+            token_delta: 0,
+            logprobs: None,
+        }
+    }
+
+    fn run(deltas: Vec<Result<PromptResponseDelta, String>>) -> Vec<WsFrame> {
+        let adapter = mock_streaming_adapter_factory(deltas);
+        let context = ContextBuilder::new().add_message(Message {
+            role: MessageRole::User,
+            content: "Weather in Oslo?".to_string(),
+            content_type: ContentType::Text,
+            pinned: false,
+            assistant_tool_calls: None,
+        });
+
+        let frames: Vec<String> =
+            block_on(ws_frames(context.send_streaming(adapter, 100)).collect());
+        frames
+            .iter()
+            .map(|frame| parse_ws_frame(frame).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_tool_run_round_trips_through_frames() {
+        let frames = run(vec![
+            Ok(delta("Let me check.")),
+            Ok(PromptResponseDelta {
+                tool_calls: Some(vec![ToolCall {
+                    id: "call_1".to_string(),
+                    name: "get_weather".to_string(),
+                    arguments: serde_json::json!({"location": "Oslo"}),
+                }]),
+                stop_reason: Some(StopReason::ToolCalls),
+                ..delta("")
+            }),
+            Ok(PromptResponseDelta {
+                cumulative_tokens: 17,
+                ..delta("")
+            }),
+        ]);
+
+        assert!(
+            frames
+                == vec![
+                    WsFrame::Delta {
+                        content: "Let me check.".to_string()
+                    },
+                    WsFrame::ToolCall {
+                        id: "call_1".to_string(),
+                        name: "get_weather".to_string(),
+                        arguments: serde_json::json!({"location": "Oslo"}),
+                    },
+                    WsFrame::Usage {
+                        cumulative_tokens: 17
+                    },
+                    WsFrame::Done {
+                        stop_reason: Some(StopReason::ToolCalls)
+                    },
+                ]
+        );
+    }
+
+    #[test]
+    fn test_trailing_stop_keeps_tool_calls_in_done() {
+        // OpenAI ends a tool-call turn with a usage delta that says `Stop`
+        let frames = run(vec![
+            Ok(PromptResponseDelta {
+                tool_calls: Some(vec![ToolCall {
+                    id: "call_1".to_string(),
+                    name: "get_weather".to_string(),
+                    arguments: serde_json::json!({"location": "Oslo"}),
+                }]),
+                stop_reason: Some(StopReason::ToolCalls),
+                ..delta("")
+            }),
+            Ok(PromptResponseDelta {
+                cumulative_tokens: 17,
+                stop_reason: Some(StopReason::Stop),
+                ..delta("")
+            }),
+        ]);
+
+        assert!(
+            frames.last()
+                == Some(&WsFrame::Done {
+                    stop_reason: Some(StopReason::ToolCalls)
+                })
+        );
+    }
+
+    #[test]
+    fn test_done_is_last_after_mid_stream_error() {
+        let frames = run(vec![
+            Ok(delta("It is ")),
+            Err("connection reset".to_string()),
+            Ok(delta("never sent")),
+        ]);
+
+        assert!(
+            frames
+                == vec![
+                    WsFrame::Delta {
+                        content: "It is ".to_string()
+                    },
+                    WsFrame::Error {
+                        message: "connection reset".to_string()
+                    },
+                    WsFrame::Done { stop_reason: None },
+                ]
+        );
+    }
+
+    #[test]
+    fn test_frame_schema_is_tagged_by_type() {
+        assert_eq!(
+            WsFrame::Delta {
+                content: "hi".to_string()
+            }
+            .to_json(),
+            r#"{"type":"delta","content":"hi"}"#
+        );
+        assert_eq!(
+            WsFrame::Done {
+                stop_reason: Some(StopReason::Stop)
+            }
+            .to_json(),
+            r#"{"type":"done","stop_reason":"Stop"}"#
+        );
+        assert!(parse_ws_frame(r#"{"type":"nope"}"#).is_err());
+    }
+
+    #[test]
+    fn test_sse_events_name_each_frame() {
+        let adapter = mock_streaming_adapter_factory(vec![
+            Ok(delta("Sunny")),
+            Ok(PromptResponseDelta {
+                stop_reason: Some(StopReason::Stop),
+                ..delta("")
+            }),
+        ]);
+        let events: Vec<String> =
+            block_on(sse_events(ContextBuilder::new().send_streaming(adapter, 100)).collect());
+
+        assert_eq!(
+            events,
+            vec![
+                "event: delta\ndata: {\"type\":\"delta\",\"content\":\"Sunny\"}\n\n",
+                "event: done\ndata: {\"type\":\"done\",\"stop_reason\":\"Stop\"}\n\n",
+            ]
+        );
+
+        // Newlines in content stay escaped inside the single data line
+        let event = WsFrame::Error {
+            message: "line one\nline two".to_string(),
+        }
+        .to_sse();
+        assert_eq!(event.matches('\n').count(), 3);
+        assert!(event.starts_with("event: error\n"));
+    }
+}